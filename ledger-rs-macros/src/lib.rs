@@ -11,35 +11,45 @@ pub fn ledger(attr: TokenStream, item: TokenStream) -> TokenStream {
     let args = parse_macro_input!(attr with Punctuated::<Meta, Token![,]>::parse_terminated);
     //
     let mut rows_per_page: u32 = DEFAULT_ROWS_PER_PAGE;
+    let mut audit = false;
+    let mut ttl_field: Option<String> = None;
+    let mut ttl_secs: Option<u64> = None;
     //
     for meta in args {
-        match meta {
-            Meta::NameValue(nv) => {
-                let name = match nv.path.get_ident() {
-                    Some(v) => v,
-                    None => {
-                        panic!("bad attr");
-                    }
+        if let Meta::Path(path) = &meta
+            && path.is_ident("audit")
+        {
+            audit = true;
+            continue;
+        }
+        if let Meta::NameValue(nv) = meta {
+            let name = match nv.path.get_ident() {
+                Some(v) => v,
+                None => {
+                    panic!("bad attr");
                 }
-                .to_string();
+            }
+            .to_string();
+            //
+            if name == "page_size"
+                && let Expr::Lit(v) = nv.value
+            {
+                let Some(rows_per_page_str) = v.lit.span().source_text() else {
+                    continue;
+                };
                 //
+                rows_per_page = rows_per_page_str.parse::<u32>().unwrap()
+            } else if let Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Str(v),
+                ..
+            }) = &nv.value
+            {
                 match name.as_str() {
-                    "page_size" => {
-                        match nv.value {
-                            Expr::Lit(v) => {
-                                let Some(rows_per_page_str) = v.lit.span().source_text() else {
-                                    continue;
-                                };
-                                //
-                                rows_per_page = u32::from_str_radix(&rows_per_page_str, 10).unwrap()
-                            }
-                            _ => {}
-                        }
-                    }
+                    "ttl_field" => ttl_field = Some(v.value()),
+                    "ttl" => ttl_secs = Some(parse_ttl(&v.value())),
                     _ => {}
                 }
             }
-            _ => {}
         };
     }
 
@@ -64,11 +74,46 @@ pub fn ledger(attr: TokenStream, item: TokenStream) -> TokenStream {
     let mut constructor_init = Vec::new();
     let mut default_init = Vec::new();
 
+    // Index declarations and the `<Struct>LedgerExt` methods that query them
+    let mut fulltext_fields = Vec::new();
+    let mut fulltext_arms = Vec::new();
+    let mut bitmap_fields = Vec::new();
+    let mut bitmap_arms = Vec::new();
+    let mut validations = Vec::new();
+    let mut ext_signatures = Vec::new();
+    let mut ext_methods = Vec::new();
+    let mut ttl_timestamp = None;
+    // Export columns and the logical value of each field
+    let mut field_names = Vec::new();
+    let mut field_values = Vec::new();
+    // `new(...)` arguments read from an imported record
+    let mut record_args = Vec::new();
+    let mut column_types = Vec::new();
+    // `SCHEMA`, where each field sits in the stored row
+    let mut schema_fields = Vec::new();
+    // serde presents the logical view: `Serialize` writes what the getters return and
+    // `Deserialize` goes through a private struct of the original field types
+    let mut serialize_fields = Vec::new();
+    let mut logical_fields = Vec::new();
+    let mut logical_checks = Vec::new();
+    let mut logical_args = Vec::new();
+    // `<Struct>Owned`, the struct as written, and the conversions to and from it
+    let mut owned_fields = Vec::new();
+    let mut owned_from_row = Vec::new();
+    let mut owned_checks = Vec::new();
+    let mut owned_args = Vec::new();
+
     for field in fields {
         let field_name = field.ident.as_ref().unwrap();
         let field_type = &field.ty;
+        let field_vis = &field.vis;
+        field_names.push(field_name.to_string());
+        owned_fields.push(quote! { #field_vis #field_name: #field_type });
 
         let mut max_len_val: Option<usize> = None;
+        let mut is_fulltext = false;
+        let mut vector_metric: Option<proc_macro2::TokenStream> = None;
+        let mut is_bitmap_index = false;
 
         for attr in &field.attrs {
             if attr.path().is_ident("max_len") {
@@ -76,9 +121,145 @@ pub fn ledger(attr: TokenStream, item: TokenStream) -> TokenStream {
                     .parse_args()
                     .expect("max_len requires an integer, e.g., #[max_len(32)]");
                 max_len_val = Some(lit.base10_parse::<usize>().unwrap());
+            } else if attr.path().is_ident("fulltext") {
+                is_fulltext = true;
+            } else if attr.path().is_ident("vector") {
+                vector_metric = Some(parse_vector_metric(attr, field_name));
+            } else if attr.path().is_ident("bitmap_index") {
+                is_bitmap_index = true;
             }
         }
 
+        if ttl_field.as_deref() == Some(field_name.to_string().as_str()) {
+            let type_str = match field_type {
+                syn::Type::Path(type_path) => type_path
+                    .path
+                    .get_ident()
+                    .map(|i| i.to_string())
+                    .unwrap_or_default(),
+                _ => String::new(),
+            };
+            // unix seconds, anything before the epoch counts as 0
+            ttl_timestamp = Some(match type_str.as_str() {
+                "u8" => quote! { self.#field_name as u64 },
+                "i8" => quote! { self.#field_name.max(0) as u64 },
+                "u16" | "u32" | "u64" => quote! { self.#field_name() as u64 },
+                "i16" | "i32" | "i64" => quote! { self.#field_name().max(0) as u64 },
+                _ => panic!(
+                    "ttl_field '{}' must be an integer type up to 64 bits holding unix seconds.",
+                    field_name
+                ),
+            });
+        }
+
+        if is_bitmap_index {
+            let type_str = match field_type {
+                syn::Type::Path(type_path) => type_path
+                    .path
+                    .get_ident()
+                    .map(|i| i.to_string())
+                    .unwrap_or_default(),
+                _ => String::new(),
+            };
+
+            // u8/i8 are stored as-is, wider ints sit behind their _le getter
+            let native = match type_str.as_str() {
+                "u8" | "i8" => quote! { self.#field_name },
+                "u16" | "u32" | "u64" | "i16" | "i32" | "i64" => quote! { self.#field_name() },
+                _ => panic!(
+                    "Field '{}' has #[bitmap_index] but is not an integer type up to 64 bits.",
+                    field_name
+                ),
+            };
+            let to_key = |v: proc_macro2::TokenStream| {
+                if type_str.starts_with('i') {
+                    quote! { (#v as i64) as u64 }
+                } else {
+                    quote! { #v as u64 }
+                }
+            };
+
+            let field_str = field_name.to_string();
+            let rows_where_name = format_ident!("rows_where_{}_eq", field_name);
+            let row_key = to_key(native);
+            let query_key = to_key(quote! { value });
+
+            bitmap_fields.push(quote! { #field_str });
+            bitmap_arms.push(quote! {
+                #field_str => Some(#row_key)
+            });
+
+            ext_signatures.push(quote! {
+                fn #rows_where_name(&self, value: #field_type) -> Result<::ledger_rs::bitmap::RowSet, ::ledger_rs::utils::DatastoreError>
+            });
+            ext_methods.push(quote! {
+                fn #rows_where_name(&self, value: #field_type) -> Result<::ledger_rs::bitmap::RowSet, ::ledger_rs::utils::DatastoreError> {
+                    self.rows_where_eq(#field_str, #query_key)
+                }
+            });
+        }
+
+        if let Some(metric) = vector_metric {
+            let is_f32_array = if let syn::Type::Array(type_array) = field_type {
+                matches!(&*type_array.elem, syn::Type::Path(p) if p.path.is_ident("f32"))
+            } else {
+                false
+            };
+
+            if !is_f32_array {
+                panic!(
+                    "Field '{}' has #[vector] but is not an `[f32; N]` array.",
+                    field_name
+                );
+            }
+
+            let nearest_name = format_ident!("nearest_{}", field_name);
+
+            ext_signatures.push(quote! {
+                fn #nearest_name(
+                    &self,
+                    query: &#field_type,
+                    k: usize,
+                ) -> Result<Vec<::ledger_rs::vector::Neighbor>, ::ledger_rs::utils::DatastoreError>
+            });
+            ext_methods.push(quote! {
+                fn #nearest_name(
+                    &self,
+                    query: &#field_type,
+                    k: usize,
+                ) -> Result<Vec<::ledger_rs::vector::Neighbor>, ::ledger_rs::utils::DatastoreError> {
+                    let metric = #metric;
+                    self.nearest_by(k, |row| metric.distance(query, &row.#field_name()))
+                }
+            });
+        }
+
+        if is_fulltext {
+            if max_len_val.is_none() {
+                panic!(
+                    "Field '{}' has #[fulltext] but is not a #[max_len] `String`.",
+                    field_name
+                );
+            }
+
+            let field_str = field_name.to_string();
+            let search_name = format_ident!("search_{}", field_name);
+
+            fulltext_fields.push(quote! { #field_str });
+            fulltext_arms.push(quote! {
+                #field_str => self.#field_name().ok()
+            });
+
+            ext_signatures.push(quote! {
+                fn #search_name(&self, query: &str) -> Result<Vec<::ledger_rs::ledger::RowId>, ::ledger_rs::utils::DatastoreError>
+            });
+            ext_methods.push(quote! {
+                fn #search_name(&self, query: &str) -> Result<Vec<::ledger_rs::ledger::RowId>, ::ledger_rs::utils::DatastoreError> {
+                    self.search(#field_str, query)
+                }
+            });
+        }
+
         if let Some(max_len) = max_len_val {
             let is_string = if let syn::Type::Path(type_path) = field_type {
                 type_path.path.is_ident("String")
@@ -96,6 +277,60 @@ pub fn ledger(attr: TokenStream, item: TokenStream) -> TokenStream {
             let len_field_name = format_ident!("{}_len", field_name);
             let setter_name = format_ident!("set_{}", field_name);
 
+            let field_str = field_name.to_string();
+            record_args.push(quote! { &record.string(#field_str, #max_len)? });
+            column_types.push(quote! {
+                ::ledger_rs::export::ColumnType::Str { max_len: #max_len }
+            });
+            schema_fields.push(quote! {
+                ::ledger_rs::schema::FieldSchema {
+                    name: ::std::borrow::Cow::Borrowed(#field_str),
+                    column_type: ::ledger_rs::export::ColumnType::Str { max_len: #max_len },
+                    offset: ::core::mem::offset_of!(Self, #field_name),
+                    size: #max_len,
+                    max_len: Some(#max_len),
+                    len_offset: Some(::core::mem::offset_of!(Self, #len_field_name)),
+                }
+            });
+            serialize_fields.push(quote! {
+                state.serialize_field(
+                    #field_str,
+                    self.#field_name().map_err(::ledger_rs::serde::ser::Error::custom)?,
+                )?;
+            });
+            logical_fields.push(quote! { #field_name: ::std::string::String });
+            logical_checks.push(quote! {
+                if logical.#field_name.len() > #max_len {
+                    return Err(::ledger_rs::serde::de::Error::custom(format!(
+                        "{} is {} bytes, max_len is {}",
+                        #field_str,
+                        logical.#field_name.len(),
+                        #max_len
+                    )));
+                }
+            });
+            logical_args.push(quote! { &logical.#field_name });
+            owned_from_row.push(quote! {
+                #field_name: ::std::string::String::from_utf8_lossy(
+                    &row.#field_name[0..(row.#len_field_name as usize).min(#max_len)]
+                ).into_owned()
+            });
+            owned_checks.push(quote! {
+                if owned.#field_name.len() > #max_len {
+                    return Err(::ledger_rs::utils::DatastoreError::MaxLen {
+                        field: #field_str,
+                        len: owned.#field_name.len(),
+                        max_len: #max_len,
+                    });
+                }
+            });
+            owned_args.push(quote! { &owned.#field_name });
+            field_values.push(quote! {
+                ::ledger_rs::export::FieldValue::Str(::std::string::String::from_utf8_lossy(
+                    &self.#field_name[0..(self.#len_field_name as usize).min(#max_len)]
+                ))
+            });
+
             // 1. Struct Fields
             new_struct_fields.push(quote! {
                 #len_field_name: u8,
@@ -126,6 +361,19 @@ pub fn ledger(attr: TokenStream, item: TokenStream) -> TokenStream {
                 #field_name: [b' '; #max_len]
             });
 
+            validations.push(quote! {
+                if self.#len_field_name as usize > #max_len {
+                    return Err(::ledger_rs::utils::PageError::Error(
+                        concat!(stringify!(#field_name), " length out of bounds").into()
+                    ));
+                }
+                if std::str::from_utf8(&self.#field_name[0..self.#len_field_name as usize]).is_err() {
+                    return Err(::ledger_rs::utils::PageError::Error(
+                        concat!(stringify!(#field_name), " is not valid utf8").into()
+                    ));
+                }
+            });
+
             // 3. Getters & Setters
             generated_methods.push(quote! {
                 pub fn #field_name<'a>(&'a self) -> Result<&'a str, ::ledger_rs::utils::DatastoreError> {
@@ -151,6 +399,7 @@ pub fn ledger(attr: TokenStream, item: TokenStream) -> TokenStream {
         } else {
             let mut is_mapped = false;
             let mut mapped_type = quote! { #field_type };
+            let mut mapped_array: Option<(syn::Ident, proc_macro2::TokenStream)> = None;
 
             if let syn::Type::Path(type_path) = field_type {
                 if let Some(ident) = type_path.path.get_ident() {
@@ -220,6 +469,17 @@ pub fn ledger(attr: TokenStream, item: TokenStream) -> TokenStream {
                                 field_name, ident_str
                             );
                         }
+
+                        // Multi-byte elements get the same _le treatment as scalar fields.
+                        // rkyv only has Portable for their rend types, so `[u32; N]` and the
+                        // like never compiled as native arrays and no stored row has them in
+                        // another layout. `[u32_le; N]` has the size and alignment of
+                        // `[u32; N]`. The getter and setter hand out native arrays
+                        if let Some(elem_le) = array_elem_le_type(&ident_str) {
+                            let array_len = &type_array.len;
+                            mapped_type = quote! { [#elem_le; #array_len] };
+                            mapped_array = Some((ident.clone(), elem_le));
+                        }
                     } else {
                         panic!("Field '{}' has an invalid array element type.", field_name);
                     }
@@ -234,6 +494,57 @@ pub fn ledger(attr: TokenStream, item: TokenStream) -> TokenStream {
             new_struct_fields.push(quote! {
                  #field_name: #mapped_type
             });
+            field_values.push(field_value(field_name, field_type));
+            record_args.push(record_arg(field_name, field_type));
+            column_types.push(column_type(field_type));
+            let field_str = field_name.to_string();
+            let schema_column_type = column_type(field_type);
+            schema_fields.push(quote! {
+                ::ledger_rs::schema::FieldSchema {
+                    name: ::std::borrow::Cow::Borrowed(#field_str),
+                    column_type: #schema_column_type,
+                    offset: ::core::mem::offset_of!(Self, #field_name),
+                    size: ::core::mem::size_of::<#mapped_type>(),
+                    max_len: None,
+                    len_offset: None,
+                }
+            });
+
+            // arrays go through slices and Vecs, serde only has impls for arrays up to 32
+            let native = if is_mapped || mapped_array.is_some() {
+                quote! { self.#field_name() }
+            } else {
+                quote! { self.#field_name }
+            };
+            owned_from_row.push(if is_mapped || mapped_array.is_some() {
+                quote! { #field_name: row.#field_name() }
+            } else {
+                quote! { #field_name: row.#field_name }
+            });
+            owned_args.push(quote! { owned.#field_name });
+            if let syn::Type::Array(type_array) = field_type {
+                let (elem, len) = (&type_array.elem, &type_array.len);
+                serialize_fields.push(quote! {
+                    state.serialize_field(#field_str, &#native[..])?;
+                });
+                logical_fields.push(quote! { #field_name: ::std::vec::Vec<#elem> });
+                logical_args.push(quote! {
+                    <#field_type>::try_from(logical.#field_name).map_err(|v| {
+                        ::ledger_rs::serde::de::Error::custom(format!(
+                            "{} has {} elements, expected {}",
+                            #field_str,
+                            v.len(),
+                            #len
+                        ))
+                    })?
+                });
+            } else {
+                serialize_fields.push(quote! {
+                    state.serialize_field(#field_str, &#native)?;
+                });
+                logical_fields.push(quote! { #field_name: #field_type });
+                logical_args.push(quote! { logical.#field_name });
+            }
 
             // Ask the user for the standard type (e.g., u64)
             constructor_params.push(quote! { #field_name: #field_type });
@@ -259,12 +570,33 @@ pub fn ledger(attr: TokenStream, item: TokenStream) -> TokenStream {
                     }
                 });
                 // ------------------------------------------------
+            } else if let Some((elem_type, elem_le)) = &mapped_array {
+                constructor_init.push(quote! {
+                    #field_name: #field_name.map(<#elem_le>::from_native)
+                });
+                default_init.push(quote! {
+                    #field_name: ::core::array::from_fn(|_| <#elem_le>::from_native(Default::default()))
+                });
+
+                let setter_name = format_ident!("set_{}", field_name);
+                generated_methods.push(quote! {
+                    pub fn #field_name(&self) -> #field_type {
+                        self.#field_name.map(|v| -> #elem_type { v.to_native() })
+                    }
+
+                    pub fn #setter_name(&mut self, val: #field_type) {
+                        self.#field_name = val.map(<#elem_le>::from_native);
+                    }
+                });
             } else {
                 constructor_init.push(quote! {
                     #field_name
                 });
-                default_init.push(quote! {
-                    #field_name: Default::default()
+                // arrays only implement Default up to 32
+                default_init.push(if matches!(field_type, syn::Type::Array(_)) {
+                    quote! { #field_name: ::core::array::from_fn(|_| Default::default()) }
+                } else {
+                    quote! { #field_name: Default::default() }
                 });
             }
 
@@ -287,7 +619,140 @@ pub fn ledger(attr: TokenStream, item: TokenStream) -> TokenStream {
         }
     }
 
+    // #[ledger(audit)] rows carry a hidden hash chain link, filled in by the ledger on insert
+    let (chain_field, chain_init, chain_offset) = if audit {
+        generated_methods.push(quote! {
+            pub fn chain_link(&self) -> &[u8; 32] {
+                &self._chain
+            }
+        });
+        (
+            quote! { _chain: [u8; 32], },
+            quote! { _chain: [0; 32], },
+            quote! {
+                const CHAIN_OFFSET: Option<usize> = Some(::core::mem::offset_of!(Self, _chain));
+            },
+        )
+    } else {
+        (quote! {}, quote! {}, quote! {})
+    };
+
+    // #[ledger(ttl_field = "created_at", ttl = "30d")] gives the ledger a default retention policy
+    let (ttl_const, ttl_fn) = match (&ttl_field, ttl_secs) {
+        (Some(field), Some(secs)) => {
+            let Some(timestamp) = ttl_timestamp else {
+                panic!("ttl_field '{}' is not a field of {}", field, struct_name);
+            };
+            (
+                quote! {
+                    const TTL: Option<u64> = Some(#secs);
+                },
+                quote! {
+                    fn ttl_timestamp(&self) -> Option<u64> {
+                        Some(#timestamp)
+                    }
+                },
+            )
+        }
+        (None, None) => (quote! {}, quote! {}),
+        _ => panic!("#[ledger] needs both `ttl_field` and `ttl` for retention"),
+    };
+
+    let struct_str = struct_name.to_string();
+    let logical_name = format_ident!("{}Logical", struct_name);
+    let field_count = field_names.len();
+    let serde_impls = quote! {
+        impl ::ledger_rs::serde::Serialize for #struct_name {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: ::ledger_rs::serde::Serializer,
+            {
+                use ::ledger_rs::serde::ser::SerializeStruct;
+                let mut state = serializer.serialize_struct(#struct_str, #field_count)?;
+                #(#serialize_fields)*
+                state.end()
+            }
+        }
+
+        const _: () = {
+            #[derive(::ledger_rs::serde::Deserialize)]
+            #[serde(crate = "::ledger_rs::serde", rename = #struct_str)]
+            struct #logical_name {
+                #(#logical_fields,)*
+            }
+
+            impl<'de> ::ledger_rs::serde::Deserialize<'de> for #struct_name {
+                fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+                where
+                    D: ::ledger_rs::serde::Deserializer<'de>,
+                {
+                    let logical = <#logical_name as ::ledger_rs::serde::Deserialize>::deserialize(
+                        deserializer,
+                    )?;
+                    #(#logical_checks)*
+                    Ok(Self::new(#(#logical_args),*))
+                }
+            }
+        };
+    };
+
+    let struct_vis = &input.vis;
+    let owned_name = format_ident!("{}Owned", struct_name);
+    let owned_type = quote! {
+        #[derive(Clone, Debug, PartialEq)]
+        #struct_vis struct #owned_name {
+            #(#owned_fields,)*
+        }
+
+        impl From<&#struct_name> for #owned_name {
+            fn from(row: &#struct_name) -> Self {
+                Self {
+                    #(#owned_from_row,)*
+                }
+            }
+        }
+
+        impl From<#struct_name> for #owned_name {
+            fn from(row: #struct_name) -> Self {
+                Self::from(&row)
+            }
+        }
+
+        impl TryFrom<&#owned_name> for #struct_name {
+            type Error = ::ledger_rs::utils::DatastoreError;
+
+            fn try_from(owned: &#owned_name) -> Result<Self, Self::Error> {
+                #(#owned_checks)*
+                Ok(Self::new(#(#owned_args),*))
+            }
+        }
+
+        impl TryFrom<#owned_name> for #struct_name {
+            type Error = ::ledger_rs::utils::DatastoreError;
+
+            fn try_from(owned: #owned_name) -> Result<Self, Self::Error> {
+                Self::try_from(&owned)
+            }
+        }
+    };
+
     let ledger_alias_name = format_ident!("{}Ledger", struct_name);
+    let ledger_ext_name = format_ident!("{}LedgerExt", struct_name);
+    let segmented_alias_name = format_ident!("{}SegmentedLedger", struct_name);
+
+    let ledger_ext = if ext_methods.is_empty() {
+        quote! {}
+    } else {
+        quote! {
+            pub trait #ledger_ext_name {
+                #(#ext_signatures;)*
+            }
+
+            impl #ledger_ext_name for #ledger_alias_name {
+                #(#ext_methods)*
+            }
+        }
+    };
 
     let expanded = quote! {
         #[derive(
@@ -302,6 +767,7 @@ pub fn ledger(attr: TokenStream, item: TokenStream) -> TokenStream {
         #[repr(C)]
         pub struct #struct_name {
             #(#new_struct_fields,)*
+             #chain_field
              _pad: [u8; 2],
         }
 
@@ -314,12 +780,26 @@ pub fn ledger(attr: TokenStream, item: TokenStream) -> TokenStream {
             { <#struct_name as ::ledger_rs::page::PageSchema>::ROWS_PER_PAGE }
         >;
 
+        pub type #segmented_alias_name = ::ledger_rs::segmented::SegmentedLedger<
+            #struct_name,
+            { <#struct_name as ::ledger_rs::page::PageSchema>::PAGE_SZ },
+            { <#struct_name as ::ledger_rs::page::PageSchema>::ROWS_PER_PAGE }
+        >;
+
+        #ledger_ext
+
+        #serde_impls
+
+        #owned_type
+
         impl #struct_name {
+            #[allow(clippy::too_many_arguments)]
             pub fn new(#(#constructor_params),*) -> Self {
                 #(#constructor_setup)*
 
                 Self {
                     #(#constructor_init,)*
+                    #chain_init
                     _pad: [0; 2],
                 }
             }
@@ -327,7 +807,7 @@ pub fn ledger(attr: TokenStream, item: TokenStream) -> TokenStream {
             // USE default_init HERE
             pub fn default() -> Self {
                 Self {
-                    #(#default_init,)* _pad: [0; 2],
+                    #(#default_init,)* #chain_init _pad: [0; 2],
                 }
             }
 
@@ -348,12 +828,78 @@ pub fn ledger(attr: TokenStream, item: TokenStream) -> TokenStream {
                 )
             }
 
+            pub fn create_encrypted_ledger(
+                folder_path: &::std::path::Path,
+                ledger_name: &str,
+                ledger_description: &str,
+                key: &::ledger_rs::encryption::EncryptionKey,
+            ) -> Result<#ledger_alias_name, ::ledger_rs::utils::DatastoreError>
+            {
+                ::ledger_rs::ledger::DataLedgerStore::<
+                    Self,
+                    { Self::PAGE_SZ },
+                    { Self::ROWS_PER_PAGE }
+                >::open_encrypted(
+                    folder_path,
+                    ::ledger_rs::header::LedgerName::from(ledger_name),
+                    ::ledger_rs::header::LedgerDescription::new(ledger_description),
+                    key,
+                )
+            }
+
+            pub fn create_segmented_ledger(
+                folder_path: &::std::path::Path,
+                ledger_name: &str,
+                ledger_description: &str,
+                pages_per_segment: u32,
+            ) -> Result<#segmented_alias_name, ::ledger_rs::utils::DatastoreError>
+            {
+                ::ledger_rs::segmented::SegmentedLedger::<
+                    Self,
+                    { Self::PAGE_SZ },
+                    { Self::ROWS_PER_PAGE }
+                >::open(
+                    folder_path,
+                    ::ledger_rs::header::LedgerName::from(ledger_name),
+                    ::ledger_rs::header::LedgerDescription::new(ledger_description),
+                    pages_per_segment,
+                )
+            }
+
+            pub fn open_checkpoint(
+                folder_path: &::std::path::Path,
+                ledger_name: &str,
+                checkpoint_name: &str,
+            ) -> Result<#ledger_alias_name, ::ledger_rs::utils::DatastoreError>
+            {
+                ::ledger_rs::ledger::DataLedgerStore::<
+                    Self,
+                    { Self::PAGE_SZ },
+                    { Self::ROWS_PER_PAGE }
+                >::open_checkpoint(
+                    folder_path,
+                    ::ledger_rs::header::LedgerName::from(ledger_name),
+                    checkpoint_name,
+                )
+            }
+
             #(#generated_methods)*
         }
 
         impl ::ledger_rs::page::PageSchema for #struct_name {
             const ROWS_PER_PAGE: usize = #rows_per_page as usize;
             const PAGE_SZ: usize = ::ledger_rs::page::page_sz::<Self>() as usize;
+            const FULLTEXT_FIELDS: &'static [&'static str] = &[#(#fulltext_fields),*];
+            const BITMAP_FIELDS: &'static [&'static str] = &[#(#bitmap_fields),*];
+            const FIELD_NAMES: &'static [&'static str] = &[#(#field_names),*];
+            const COLUMN_TYPES: &'static [::ledger_rs::export::ColumnType] = &[#(#column_types),*];
+            const SCHEMA: ::ledger_rs::schema::LedgerSchema = ::ledger_rs::schema::LedgerSchema {
+                name: ::std::borrow::Cow::Borrowed(stringify!(#struct_name)),
+                row_size: ::core::mem::size_of::<Self>(),
+                fields: ::std::borrow::Cow::Borrowed(&[#(#schema_fields),*]),
+            };
+            #chain_offset
+            #ttl_const
 
             fn to_bytes(&self) -> Result<::rkyv::util::AlignedVec, ::rkyv::rancor::Error> {
                 ::rkyv::to_bytes::<::rkyv::rancor::Error>(self)
@@ -366,8 +912,232 @@ pub fn ledger(attr: TokenStream, item: TokenStream) -> TokenStream {
             fn deleted_row(_page_row_n: usize) -> &'static [u8] {
                 &[]
             }
+
+            fn validate(&self) -> Result<(), ::ledger_rs::utils::PageError> {
+                #(#validations)*
+                Ok(())
+            }
+
+            fn fulltext_value(&self, _field: &str) -> Option<&str> {
+                match _field {
+                    #(#fulltext_arms,)*
+                    _ => None,
+                }
+            }
+
+            fn bitmap_value(&self, _field: &str) -> Option<u64> {
+                match _field {
+                    #(#bitmap_arms,)*
+                    _ => None,
+                }
+            }
+
+            #ttl_fn
+
+            fn field_values(&self) -> Vec<::ledger_rs::export::FieldValue<'_>> {
+                vec![#(#field_values),*]
+            }
+
+            fn from_record(
+                record: &::ledger_rs::import::Record<'_>,
+            ) -> Result<Self, ::ledger_rs::import::ImportError> {
+                Ok(Self::new(#(#record_args),*))
+            }
         }
     };
 
     TokenStream::from(expanded)
 }
+
+// Element types of `[T; N]` fields that are stored as their little-endian rend equivalent, the
+// same layout the schema page describes for every multi-byte number
+fn array_elem_le_type(ident: &str) -> Option<proc_macro2::TokenStream> {
+    match ident {
+        "u16" => Some(quote! { ::rkyv::rend::u16_le }),
+        "u32" => Some(quote! { ::rkyv::rend::u32_le }),
+        "u64" => Some(quote! { ::rkyv::rend::u64_le }),
+        "u128" => Some(quote! { ::rkyv::rend::u128_le }),
+        "i16" => Some(quote! { ::rkyv::rend::i16_le }),
+        "i32" => Some(quote! { ::rkyv::rend::i32_le }),
+        "i64" => Some(quote! { ::rkyv::rend::i64_le }),
+        "i128" => Some(quote! { ::rkyv::rend::i128_le }),
+        "f32" => Some(quote! { ::rkyv::rend::f32_le }),
+        "f64" => Some(quote! { ::rkyv::rend::f64_le }),
+        _ => None,
+    }
+}
+
+// The logical value of a non-string field for exports. Multi-byte numbers are read through their
+// getter, u8/i8/bool/char are stored as-is and anything else falls back to its Debug output
+fn field_value(field_name: &syn::Ident, field_type: &syn::Type) -> proc_macro2::TokenStream {
+    let value = |ident: &str, v: proc_macro2::TokenStream| match ident {
+        "bool" => Some(quote! { ::ledger_rs::export::FieldValue::Bool(#v) }),
+        "u8" | "u16" | "u32" | "u64" | "u128" | "usize" => {
+            Some(quote! { ::ledger_rs::export::FieldValue::UInt(#v as u128) })
+        }
+        "i8" | "i16" | "i32" | "i64" | "i128" => {
+            Some(quote! { ::ledger_rs::export::FieldValue::Int(#v as i128) })
+        }
+        "f32" | "f64" => Some(quote! { ::ledger_rs::export::FieldValue::Float(#v as f64) }),
+        "char" => Some(quote! {
+            ::ledger_rs::export::FieldValue::Str(::std::borrow::Cow::Owned(#v.to_string()))
+        }),
+        _ => None,
+    };
+    let stored_as_is = |ident: &str| matches!(ident, "u8" | "i8" | "bool" | "char");
+
+    let exported = match field_type {
+        syn::Type::Path(type_path) => type_path.path.get_ident().and_then(|ident| {
+            let ident = ident.to_string();
+            match stored_as_is(&ident) {
+                true => value(&ident, quote! { self.#field_name }),
+                false => value(&ident, quote! { self.#field_name() }),
+            }
+        }),
+        syn::Type::Array(type_array) => match &*type_array.elem {
+            syn::Type::Path(elem_path) if elem_path.path.is_ident("u8") => {
+                Some(quote! { ::ledger_rs::export::FieldValue::Bytes(&self.#field_name) })
+            }
+            syn::Type::Path(elem_path) => elem_path.path.get_ident().and_then(|ident| {
+                let ident = ident.to_string();
+                let elem = value(&ident, quote! { *v })?;
+                let array = match stored_as_is(&ident) {
+                    true => quote! { self.#field_name },
+                    false => quote! { self.#field_name() },
+                };
+                Some(quote! {
+                    ::ledger_rs::export::FieldValue::Array(#array.iter().map(|v| #elem).collect())
+                })
+            }),
+            _ => None,
+        },
+        _ => None,
+    };
+    exported.unwrap_or_else(|| {
+        quote! {
+            ::ledger_rs::export::FieldValue::Str(::std::borrow::Cow::Owned(format!("{:?}", self.#field_name)))
+        }
+    })
+}
+
+// The logical type of a non-string field, `ColumnType` in ledger_rs::export
+fn column_type(field_type: &syn::Type) -> proc_macro2::TokenStream {
+    let scalar = |ident: &str| match ident {
+        "bool" => quote! { ::ledger_rs::export::ColumnType::Bool },
+        "char" => quote! { ::ledger_rs::export::ColumnType::Char },
+        "u8" => quote! { ::ledger_rs::export::ColumnType::UInt(8) },
+        "u16" => quote! { ::ledger_rs::export::ColumnType::UInt(16) },
+        "u32" => quote! { ::ledger_rs::export::ColumnType::UInt(32) },
+        "u64" | "usize" => quote! { ::ledger_rs::export::ColumnType::UInt(64) },
+        "u128" => quote! { ::ledger_rs::export::ColumnType::UInt(128) },
+        "i8" => quote! { ::ledger_rs::export::ColumnType::Int(8) },
+        "i16" => quote! { ::ledger_rs::export::ColumnType::Int(16) },
+        "i32" => quote! { ::ledger_rs::export::ColumnType::Int(32) },
+        "i64" => quote! { ::ledger_rs::export::ColumnType::Int(64) },
+        "i128" => quote! { ::ledger_rs::export::ColumnType::Int(128) },
+        "f32" => quote! { ::ledger_rs::export::ColumnType::Float(32) },
+        "f64" => quote! { ::ledger_rs::export::ColumnType::Float(64) },
+        _ => quote! { ::ledger_rs::export::ColumnType::Other },
+    };
+    match field_type {
+        syn::Type::Path(type_path) => match type_path.path.get_ident() {
+            Some(ident) => scalar(&ident.to_string()),
+            None => quote! { ::ledger_rs::export::ColumnType::Other },
+        },
+        syn::Type::Array(type_array) => {
+            let len = &type_array.len;
+            match &*type_array.elem {
+                syn::Type::Path(elem_path) if elem_path.path.is_ident("u8") => {
+                    quote! { ::ledger_rs::export::ColumnType::Bytes(#len) }
+                }
+                syn::Type::Path(elem_path) => {
+                    let elem = match elem_path.path.get_ident() {
+                        Some(ident) => scalar(&ident.to_string()),
+                        None => quote! { ::ledger_rs::export::ColumnType::Other },
+                    };
+                    quote! { ::ledger_rs::export::ColumnType::Array { elem: &#elem, len: #len } }
+                }
+                _ => quote! { ::ledger_rs::export::ColumnType::Other },
+            }
+        }
+        _ => quote! { ::ledger_rs::export::ColumnType::Other },
+    }
+}
+
+// How a non-string field is read from an imported record, the inverse of `field_value`
+fn record_arg(field_name: &syn::Ident, field_type: &syn::Type) -> proc_macro2::TokenStream {
+    let field_str = field_name.to_string();
+    let parsed = [
+        "bool", "char", "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64",
+        "i128", "f32", "f64",
+    ];
+    match field_type {
+        syn::Type::Path(type_path)
+            if type_path
+                .path
+                .get_ident()
+                .is_some_and(|ident| parsed.contains(&ident.to_string().as_str())) =>
+        {
+            quote! { record.value::<#field_type>(#field_str)? }
+        }
+        syn::Type::Array(type_array) => match &*type_array.elem {
+            syn::Type::Path(elem_path) if elem_path.path.is_ident("u8") => {
+                quote! { record.bytes(#field_str)? }
+            }
+            _ => quote! { record.array(#field_str)? },
+        },
+        _ => quote! { match record.unsupported(#field_str)? {} },
+    }
+}
+
+// ttl = "90s" | "30m" | "12h" | "30d" | "2w", a bare number is seconds
+fn parse_ttl(ttl: &str) -> u64 {
+    let ttl = ttl.trim();
+    let split = ttl.find(|c: char| !c.is_ascii_digit()).unwrap_or(ttl.len());
+    let (value, unit) = ttl.split_at(split);
+    let Ok(value) = value.parse::<u64>() else {
+        panic!("ttl '{}' must start with a number, e.g. ttl = \"30d\"", ttl);
+    };
+    let unit_secs = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => panic!(
+            "ttl '{}' has unknown unit '{}'. Expected s, m, h, d or w.",
+            ttl, unit
+        ),
+    };
+    value
+        .checked_mul(unit_secs)
+        .unwrap_or_else(|| panic!("ttl '{}' is too long", ttl))
+}
+
+// #[vector] or #[vector(metric = "cosine" | "l2" | "dot")]
+fn parse_vector_metric(attr: &syn::Attribute, field_name: &syn::Ident) -> proc_macro2::TokenStream {
+    let mut metric = String::from("cosine");
+
+    if let Meta::List(_) = attr.meta {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("metric") {
+                let lit: syn::LitStr = meta.value()?.parse()?;
+                metric = lit.value();
+                Ok(())
+            } else {
+                Err(meta.error("unsupported vector option, expected `metric`"))
+            }
+        })
+        .unwrap_or_else(|e| panic!("Field '{}': {}", field_name, e));
+    }
+
+    match metric.as_str() {
+        "cosine" => quote! { ::ledger_rs::vector::Metric::Cosine },
+        "l2" | "euclidean" => quote! { ::ledger_rs::vector::Metric::L2 },
+        "dot" => quote! { ::ledger_rs::vector::Metric::Dot },
+        other => panic!(
+            "Field '{}' has unknown vector metric '{}'. Expected \"cosine\", \"l2\" or \"dot\".",
+            field_name, other
+        ),
+    }
+}
@@ -53,7 +53,7 @@ pub fn main() -> Result<(), DatastoreError> {
             &format!("document #{n}"),
             &format!("/home/ubuntu/Documents/document_{n}.txt"),
         ))?;
-        if row_id != n {
+        if row_id.get() != n as u64 {
             panic!("row doesn't match {} != {}", row_id, n);
         }
     }
@@ -64,7 +64,7 @@ pub fn main() -> Result<(), DatastoreError> {
     //
     for n in 0..TEST_LEN {
         //
-        let Some(row) = ledger.access_row(n)? else {
+        let Some(row) = ledger.access_row(n.into())? else {
             panic!("row not found!");
         };
         //
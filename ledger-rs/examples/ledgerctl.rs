@@ -0,0 +1,333 @@
+use ledger_rs::header::{HeaderFlags, LedgerHeader};
+use ledger_rs::inspect::{RawLedger, RawSlot};
+use ledger_rs::ledger::RowId;
+use ledger_rs::utils::DatastoreError;
+
+//
+// inspects a ledger file without its row type
+//
+const USAGE: &str = "usage:
+  ledgerctl header <ledger>
+  ledgerctl schema <ledger>
+  ledgerctl pages  <ledger> [first_page] [count]
+  ledgerctl page   <ledger> <page_id>
+  ledgerctl row    <ledger> <row_id>
+  ledgerctl check  <ledger> [--json]
+  ledgerctl export <ledger> [--format csv|jsonl]
+
+options:
+  --page-size <bytes>   override the page size worked out from the file
+  --row-size <bytes>    override the row size worked out from the pages
+  --json                print the check report as json
+  --format <csv|jsonl>  export format, csv by default. rows are decoded with the ledger's
+                        schema, ledgers without one export their rows' bytes in hex
+
+check exits with 2 when it finds anomalies";
+
+pub fn main() {
+    // exit quietly when piped into `head` or `less` instead of panicking on a closed stdout
+    unsafe {
+        libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+    }
+    if let Err(e) = run(std::env::args().skip(1).collect()) {
+        eprintln!("ledgerctl: {e}");
+        std::process::exit(1);
+    }
+}
+
+fn run(args: Vec<String>) -> Result<(), DatastoreError> {
+    let mut positional = Vec::new();
+    let mut page_size = None;
+    let mut row_size = None;
+    let mut json = false;
+    let mut format = "csv".to_string();
+    //
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--page-size" => page_size = Some(parse_num(args.next(), "--page-size")?),
+            "--row-size" => row_size = Some(parse_num(args.next(), "--row-size")?),
+            "--json" => json = true,
+            "--format" => format = args.next().ok_or(format!("missing --format\n{USAGE}"))?,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            _ => positional.push(arg),
+        }
+    }
+    //
+    let [command, path, rest @ ..] = positional.as_slice() else {
+        return Err(USAGE.into());
+    };
+    let ledger = RawLedger::open_with(path, page_size, row_size)?;
+    //
+    match command.as_str() {
+        "header" => print_header(&ledger),
+        "schema" => print_schema(&ledger),
+        "pages" => {
+            let first = parse_num(rest.first().cloned(), "first_page")?;
+            let count = match rest.get(1) {
+                Some(count) => parse_num(Some(count.clone()), "count")?,
+                None => ledger.num_pages()?.saturating_sub(first),
+            };
+            print_pages(&ledger, first, count)
+        }
+        "page" => print_page(&ledger, parse_num(rest.first().cloned(), "page_id")?),
+        "row" => print_row(
+            &ledger,
+            RowId(parse_num(rest.first().cloned(), "row_id")? as u64),
+        ),
+        "check" => check(&ledger, json),
+        "export" => export(&ledger, &format),
+        _ => Err(USAGE.into()),
+    }
+}
+//
+fn parse_num(arg: Option<String>, name: &str) -> Result<usize, DatastoreError> {
+    match arg {
+        None if name == "first_page" => Ok(0),
+        None => Err(format!("missing {name}\n{USAGE}").into()),
+        Some(arg) => arg
+            .parse()
+            .map_err(|_| format!("{name} must be a number, got {arg:?}").into()),
+    }
+}
+
+//
+// ###### COMMANDS ######
+//
+
+fn print_header(ledger: &RawLedger) -> Result<(), DatastoreError> {
+    let header = ledger.header()?;
+    //
+    println!("file            {:?}", ledger.path());
+    println!("format version  {}", header.format_version());
+    println!(
+        "name            {} ({})",
+        text(header.ledger_name().as_bytes()),
+        header.ledger_name()
+    );
+    println!(
+        "description     {}",
+        text(header.ledger_description().as_bytes())
+    );
+    println!("flags           {}", flags(header));
+    println!("num_pages       {}", header.num_pages());
+    println!("page_cursor     {}", header.page_cursor());
+    println!("retired_pages   {}", header.retired_pages());
+    println!("header size     {} bytes", ledger.header_size());
+    match ledger.schema() {
+        Some(schema) => println!(
+            "schema          {} ({} fields)",
+            schema.name,
+            schema.fields.len()
+        ),
+        None => println!("schema          none"),
+    }
+    println!("page size       {} bytes", ledger.page_size());
+    match ledger.row_size() {
+        Some(row_size) => println!(
+            "row size        {} bytes ({} rows per page)",
+            row_size,
+            ledger.max_rows_per_page().unwrap_or(0)
+        ),
+        None => println!("row size        unknown"),
+    }
+    match ledger.signed_checkpoint()? {
+        Some(checkpoint) => println!(
+            "signed          {} rows at {}",
+            checkpoint.num_rows, checkpoint.timestamp
+        ),
+        None => println!("signed          no"),
+    }
+    println!("file length     {} bytes", ledger.file_len());
+    Ok(())
+}
+//
+fn print_schema(ledger: &RawLedger) -> Result<(), DatastoreError> {
+    let Some(schema) = ledger.schema() else {
+        return Err("the ledger has no schema, it predates format 2".into());
+    };
+    println!("{} ({} bytes per row)", schema.name, schema.row_size);
+    println!();
+    println!("{:>6}  {:>6}  {:<20}  type", "offset", "size", "field");
+    for field in schema.fields.iter() {
+        let column_type = match field.len_offset {
+            Some(len_offset) => format!("{:<16}  length at {len_offset}", field.column_type),
+            None => field.column_type.to_string(),
+        };
+        println!(
+            "{:>6}  {:>6}  {:<20}  {column_type}",
+            field.offset, field.size, field.name
+        );
+    }
+    Ok(())
+}
+//
+fn print_pages(ledger: &RawLedger, first: usize, count: usize) -> Result<(), DatastoreError> {
+    if ledger.is_encrypted()? {
+        return Err("pages of an encrypted ledger are sealed, only the header can be read".into());
+    }
+    let end = (first + count).min(ledger.num_pages()?);
+    //
+    println!(
+        "{:>8}  {:>10}  {:>8}  {:>10}  {:>8}  {:>10}  note",
+        "page", "slot_count", "deleted", "free_start", "free_end", "free_space"
+    );
+    for page_id in first..end {
+        let note = if ledger.is_compressed(page_id) {
+            "compressed"
+        } else {
+            ""
+        };
+        let Some(header) = ledger.page_header(page_id)? else {
+            println!(
+                "{page_id:>8}  {:>10}  {:>8}  {:>10}  {:>8}  {:>10}  hole",
+                "-", "-", "-", "-", "-"
+            );
+            continue;
+        };
+        let deleted = ledger
+            .slots(page_id)?
+            .iter()
+            .filter(|slot| **slot == RawSlot::Deleted)
+            .count();
+        println!(
+            "{page_id:>8}  {:>10}  {:>8}  {:>10}  {:>8}  {:>10}  {note}",
+            header.slot_count(),
+            deleted,
+            header.free_start(),
+            header.free_end(),
+            header.free_end().saturating_sub(header.free_start()),
+        );
+    }
+    Ok(())
+}
+//
+fn print_page(ledger: &RawLedger, page_id: usize) -> Result<(), DatastoreError> {
+    print_pages(ledger, page_id, 1)?;
+    //
+    let first_row = ledger
+        .max_rows_per_page()
+        .map(|max_rows| page_id * max_rows);
+    println!();
+    for (page_row_n, slot) in ledger.slots(page_id)?.iter().enumerate() {
+        let row_id = match first_row {
+            Some(first_row) => (first_row + page_row_n).to_string(),
+            None => "?".to_string(),
+        };
+        match slot {
+            RawSlot::Row(offset) => {
+                println!("  slot {page_row_n:>6}  row {row_id:>10}  offset {offset}")
+            }
+            RawSlot::Deleted => println!("  slot {page_row_n:>6}  row {row_id:>10}  deleted"),
+        }
+    }
+    Ok(())
+}
+//
+fn print_row(ledger: &RawLedger, row_id: RowId) -> Result<(), DatastoreError> {
+    if ledger.is_encrypted()? {
+        return Err("pages of an encrypted ledger are sealed, only the header can be read".into());
+    }
+    let (page_id, page_row_n) = ledger.locate(row_id)?;
+    println!("row {row_id}: page {page_id}, slot {page_row_n}");
+    //
+    let Some(bytes) = ledger.row_bytes(row_id)? else {
+        println!("deleted");
+        return Ok(());
+    };
+    if let Some(schema) = ledger.schema() {
+        for (field, value) in schema.fields.iter().zip(schema.decode_row(&bytes)) {
+            println!("  {:<20}  {}", field.name, value.to_text());
+        }
+        println!();
+    }
+    hexdump(&bytes);
+    Ok(())
+}
+
+//
+fn check(ledger: &RawLedger, json: bool) -> Result<(), DatastoreError> {
+    let report = ledger.verify()?;
+    //
+    if json {
+        let json = serde_json::to_string_pretty(&report).map_err(|e| e.to_string())?;
+        println!("{json}");
+    } else {
+        println!(
+            "{:?}: {} of {} pages checked, {} rows, {} deleted",
+            report.path, report.pages_checked, report.num_pages, report.rows, report.deleted_rows
+        );
+        if report.encrypted {
+            println!("encrypted, the sealed pages weren't checked");
+        }
+        for anomaly in &report.anomalies {
+            println!("  {anomaly}");
+        }
+        match report.anomalies.len() {
+            0 => println!("ok"),
+            n => println!("{n} anomalies"),
+        }
+    }
+    //
+    if !report.is_ok() {
+        std::process::exit(2);
+    }
+    Ok(())
+}
+//
+fn export(ledger: &RawLedger, format: &str) -> Result<(), DatastoreError> {
+    let stdout = std::io::stdout().lock();
+    match format {
+        "csv" => ledger.export_csv(stdout)?,
+        "jsonl" => ledger.export_jsonl(stdout)?,
+        _ => return Err(format!("unknown export format {format:?}\n{USAGE}").into()),
+    };
+    Ok(())
+}
+
+//
+// ###### FORMATTING ######
+//
+
+// 16 bytes per line, offset, hex and the printable ascii
+fn hexdump(bytes: &[u8]) {
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|b| format!("{b:02x}")).collect();
+        let ascii: String = chunk
+            .iter()
+            .map(|b| match b {
+                0x20..=0x7e => *b as char,
+                _ => '.',
+            })
+            .collect();
+        println!("{:08x}  {:<47}  |{}|", line * 16, hex.join(" "), ascii);
+    }
+}
+//
+// names and descriptions are space padded bytes
+fn text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes)
+        .trim_end_matches([' ', '\0'])
+        .to_string()
+}
+//
+fn flags(header: &LedgerHeader) -> String {
+    let set: Vec<&str> = [
+        (HeaderFlags::Private, "private"),
+        (HeaderFlags::DeleteMe, "delete_me"),
+        (HeaderFlags::Audit, "audit"),
+        (HeaderFlags::Encrypted, "encrypted"),
+    ]
+    .into_iter()
+    .filter(|(flag, _)| header.flags.is_set(flag.clone()))
+    .map(|(_, name)| name)
+    .collect();
+    //
+    match set.is_empty() {
+        true => "none".to_string(),
+        false => set.join(", "),
+    }
+}
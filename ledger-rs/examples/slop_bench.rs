@@ -7,7 +7,7 @@ use std::hint::black_box;
 use std::path::Path;
 use std::time::{Duration, Instant};
 
-/**
+/*
  *  CERTIFIED SLOP BROUGHT TO YOU BY:
  *      GEMINI 3.1 PRO   
  *
@@ -171,7 +171,7 @@ pub fn main() -> Result<(), DatastoreError> {
     for &n in &numbers {
         let iter_start = Instant::now();
 
-        let Some(row) = black_box(ledger.access_row(black_box(n))?) else {
+        let Some(row) = black_box(ledger.access_row(black_box(n.into()))?) else {
             panic!("💀 Row {} vanished into the ether!", n);
         };
 
@@ -191,7 +191,7 @@ pub fn main() -> Result<(), DatastoreError> {
         "RANDOM READ",
         &mut read_latencies,
         total_read_duration,
-        num_records as usize,
+        num_records,
     );
 
     // --- BENCHMARK: RANDOM MUTATION ---
@@ -209,7 +209,7 @@ pub fn main() -> Result<(), DatastoreError> {
         let iter_start = Instant::now();
 
         // Getting the mutable seal counts as a write access for the page
-        let Some(row_seal) = black_box(ledger.access_row_mut(black_box(n))?) else {
+        let Some(row_seal) = black_box(ledger.access_row_mut(black_box(n.into()))?) else {
             panic!("💀 Mutable row {} was swallowed by the void!", n);
         };
         let row: &mut FileManifest = row_seal.unseal();
@@ -236,7 +236,7 @@ pub fn main() -> Result<(), DatastoreError> {
         "RANDOM MUTATION",
         &mut mut_latencies,
         total_mut_duration,
-        num_records as usize,
+        num_records,
     );
 
     println!(
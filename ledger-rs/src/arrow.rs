@@ -0,0 +1,182 @@
+use crate::export::{ColumnType, FieldValue};
+use crate::ledger::RowId;
+use crate::page::PageSchema;
+use arrow_array::RecordBatch;
+use arrow_array::builder::{
+    ArrayBuilder, BooleanBuilder, Decimal128Builder, FixedSizeBinaryBuilder, FixedSizeListBuilder,
+    Float32Builder, Float64Builder, Int8Builder, Int16Builder, Int32Builder, Int64Builder,
+    StringBuilder, UInt8Builder, UInt16Builder, UInt32Builder, UInt64Builder, make_builder,
+};
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef};
+use std::sync::Arc;
+
+//
+// ###### ARROW ######
+//
+// the arrow schema of a #[ledger] struct comes from its `COLUMN_TYPES`: a `row_id` column, then
+// one column per field. fixed width ints and floats map to the arrow type of the same width,
+// #[max_len] strings to Utf8, `[u8; N]` to FixedSizeBinary(N) and other arrays to a
+// FixedSizeList. arrow has no 128 bit ints, i128 becomes Decimal128(38, 0) and u128 its 16 little
+// endian bytes in a FixedSizeBinary(16). an i128 past 38 digits keeps its bits, but a reader that
+// validates the precision rejects it. no column is nullable
+//
+pub fn arrow_schema<T: PageSchema>() -> Schema {
+    let mut fields = Vec::with_capacity(T::FIELD_NAMES.len() + 1);
+    fields.push(Field::new("row_id", DataType::UInt64, false));
+    for (name, column_type) in T::FIELD_NAMES.iter().zip(T::COLUMN_TYPES) {
+        fields.push(Field::new(*name, data_type(column_type), false));
+    }
+    Schema::new(fields)
+}
+//
+pub fn data_type(column_type: &ColumnType) -> DataType {
+    match column_type {
+        ColumnType::Bool => DataType::Boolean,
+        ColumnType::UInt(8) => DataType::UInt8,
+        ColumnType::UInt(16) => DataType::UInt16,
+        ColumnType::UInt(32) => DataType::UInt32,
+        ColumnType::UInt(64) => DataType::UInt64,
+        ColumnType::Int(8) => DataType::Int8,
+        ColumnType::Int(16) => DataType::Int16,
+        ColumnType::Int(32) => DataType::Int32,
+        ColumnType::Int(64) => DataType::Int64,
+        ColumnType::Float(32) => DataType::Float32,
+        ColumnType::Float(64) => DataType::Float64,
+        ColumnType::UInt(128) => DataType::FixedSizeBinary(16),
+        ColumnType::Int(128) => DataType::Decimal128(38, 0),
+        ColumnType::Bytes(len) => DataType::FixedSizeBinary(*len as i32),
+        ColumnType::Array { elem, len } => DataType::FixedSizeList(
+            Arc::new(Field::new("item", data_type(elem), false)),
+            *len as i32,
+        ),
+        // chars, strings and anything else
+        _ => DataType::Utf8,
+    }
+}
+
+//
+// ###### RECORD BATCHES ######
+//
+
+// collects rows column by column until `finish` turns them into a record batch
+pub(crate) struct BatchBuilder {
+    schema: SchemaRef,
+    column_types: &'static [ColumnType],
+    columns: Vec<Box<dyn ArrayBuilder>>,
+    rows: usize,
+}
+
+impl BatchBuilder {
+    pub(crate) fn new<T: PageSchema>(capacity: usize) -> Self {
+        let schema = Arc::new(arrow_schema::<T>());
+        let columns = schema
+            .fields()
+            .iter()
+            .map(|field| make_builder(field.data_type(), capacity))
+            .collect();
+        Self {
+            schema,
+            column_types: T::COLUMN_TYPES,
+            columns,
+            rows: 0,
+        }
+    }
+    //
+    #[inline(always)]
+    pub(crate) fn len(&self) -> usize {
+        self.rows
+    }
+    //
+    #[inline(always)]
+    pub(crate) fn schema(&self) -> &Schema {
+        &self.schema
+    }
+    //
+    pub(crate) fn push(
+        &mut self,
+        row_id: RowId,
+        values: &[FieldValue<'_>],
+    ) -> Result<(), ArrowError> {
+        let (row_id_column, columns) = self.columns.split_first_mut().unwrap();
+        append(
+            row_id_column.as_mut(),
+            &ColumnType::UInt(64),
+            &FieldValue::UInt(row_id.get() as u128),
+        )?;
+        for ((column, column_type), value) in columns.iter_mut().zip(self.column_types).zip(values)
+        {
+            append(column.as_mut(), column_type, value)?;
+        }
+        self.rows += 1;
+        Ok(())
+    }
+    //
+    // the rows pushed so far, the builder is empty afterwards
+    pub(crate) fn finish(&mut self) -> Result<RecordBatch, ArrowError> {
+        let columns = self
+            .columns
+            .iter_mut()
+            .map(|column| column.finish())
+            .collect();
+        self.rows = 0;
+        RecordBatch::try_new(self.schema.clone(), columns)
+    }
+}
+//
+fn append(
+    builder: &mut dyn ArrayBuilder,
+    column_type: &ColumnType,
+    value: &FieldValue<'_>,
+) -> Result<(), ArrowError> {
+    let mismatch = || {
+        ArrowError::InvalidArgumentError(format!("{value:?} doesn't fit a {column_type:?} column"))
+    };
+    // the builder make_builder created for the column's data type
+    macro_rules! builder {
+        ($builder:ty) => {
+            builder
+                .as_any_mut()
+                .downcast_mut::<$builder>()
+                .ok_or_else(mismatch)?
+        };
+    }
+    match (column_type, value) {
+        (ColumnType::Bool, FieldValue::Bool(v)) => builder!(BooleanBuilder).append_value(*v),
+        (ColumnType::UInt(8), FieldValue::UInt(v)) => builder!(UInt8Builder).append_value(*v as u8),
+        (ColumnType::UInt(16), FieldValue::UInt(v)) => {
+            builder!(UInt16Builder).append_value(*v as u16)
+        }
+        (ColumnType::UInt(32), FieldValue::UInt(v)) => {
+            builder!(UInt32Builder).append_value(*v as u32)
+        }
+        (ColumnType::UInt(64), FieldValue::UInt(v)) => {
+            builder!(UInt64Builder).append_value(*v as u64)
+        }
+        (ColumnType::Int(8), FieldValue::Int(v)) => builder!(Int8Builder).append_value(*v as i8),
+        (ColumnType::Int(16), FieldValue::Int(v)) => builder!(Int16Builder).append_value(*v as i16),
+        (ColumnType::Int(32), FieldValue::Int(v)) => builder!(Int32Builder).append_value(*v as i32),
+        (ColumnType::Int(64), FieldValue::Int(v)) => builder!(Int64Builder).append_value(*v as i64),
+        (ColumnType::Float(32), FieldValue::Float(v)) => {
+            builder!(Float32Builder).append_value(*v as f32)
+        }
+        (ColumnType::Float(64), FieldValue::Float(v)) => builder!(Float64Builder).append_value(*v),
+        (ColumnType::UInt(128), FieldValue::UInt(v)) => builder!(FixedSizeBinaryBuilder)
+            .append_value(v.to_le_bytes())
+            .map_err(|_| mismatch())?,
+        (ColumnType::Int(128), FieldValue::Int(v)) => builder!(Decimal128Builder).append_value(*v),
+        (ColumnType::Bytes(_), FieldValue::Bytes(v)) => builder!(FixedSizeBinaryBuilder)
+            .append_value(v)
+            .map_err(|_| mismatch())?,
+        (ColumnType::Array { elem, .. }, FieldValue::Array(values)) => {
+            let list = builder!(FixedSizeListBuilder<Box<dyn ArrayBuilder>>);
+            for value in values {
+                append(list.values().as_mut(), elem, value)?;
+            }
+            list.append(true);
+        }
+        (ColumnType::Array { .. } | ColumnType::Bytes(_), _) => return Err(mismatch()),
+        // everything else is a Utf8 column
+        (_, value) => builder!(StringBuilder).append_value(value.to_text()),
+    }
+    Ok(())
+}
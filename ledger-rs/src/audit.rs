@@ -0,0 +1,46 @@
+use crate::ledger::RowId;
+use sha2::{Digest, Sha256};
+
+//
+pub type ChainHash = [u8; 32];
+//
+// the link before row 0
+pub const GENESIS: ChainHash = [0; 32];
+//
+// ###### HASH CHAIN ######
+//
+// rows of a #[ledger(audit)] ledger carry a hidden 32 byte field holding
+// sha256(previous row's link || the row's own bytes with the link zeroed). changing, deleting or
+// reordering any row breaks every link after it. removing rows from the end can only be detected
+// against a chain head recorded somewhere else, see `DataLedgerStore::chain_head`.
+//
+pub fn chain_hash(prev: &ChainHash, row: &[u8], chain_offset: usize) -> ChainHash {
+    let mut hasher = Sha256::new();
+    hasher.update(prev);
+    hasher.update(&row[..chain_offset]);
+    hasher.update(GENESIS);
+    hasher.update(&row[chain_offset + 32..]);
+    hasher.finalize().into()
+}
+//
+#[inline(always)]
+pub fn stored_link(row: &[u8], chain_offset: usize) -> ChainHash {
+    row[chain_offset..chain_offset + 32].try_into().unwrap()
+}
+//
+// the first row whose stored link doesn't match the chain. `found` is None for a deleted row
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrokenLink {
+    pub row_id: RowId,
+    pub expected: ChainHash,
+    pub found: Option<ChainHash>,
+}
+
+impl std::fmt::Display for BrokenLink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.found {
+            Some(_) => write!(f, "row {} was modified", self.row_id),
+            None => write!(f, "row {} was deleted", self.row_id),
+        }
+    }
+}
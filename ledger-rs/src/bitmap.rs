@@ -0,0 +1,107 @@
+use roaring::RoaringTreemap;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+//
+const BMI_MAGIC: [u8; 4] = *b"LBMI";
+// 2: row ids are u64
+const BMI_VERSION: u8 = 2;
+//
+// a set of row ids. supports `&` and `|` for combining bitmap index lookups
+pub type RowSet = RoaringTreemap;
+//
+// ###### BITMAP INDEX ######
+//
+// one compressed bitmap of row ids per distinct value of a low cardinality integer field.
+// signed values are stored by their two's complement bit pattern.
+//
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BitmapIndex {
+    values: BTreeMap<u64, RoaringTreemap>,
+    // one past the highest row id seen. compared against the ledger on open to detect a stale index
+    watermark: u64,
+}
+
+impl BitmapIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    //
+    #[inline(always)]
+    pub fn watermark(&self) -> u64 {
+        self.watermark
+    }
+    //
+    #[inline(always)]
+    pub fn cardinality(&self) -> usize {
+        self.values.len()
+    }
+    //
+    pub fn insert(&mut self, row_id: u64, value: u64) {
+        self.values.entry(value).or_default().insert(row_id);
+        self.watermark = self.watermark.max(row_id + 1);
+    }
+    //
+    pub fn remove(&mut self, row_id: u64, value: u64) {
+        if let Some(rows) = self.values.get_mut(&value) {
+            rows.remove(row_id);
+            if rows.is_empty() {
+                self.values.remove(&value);
+            }
+        }
+    }
+    //
+    pub fn rows_eq(&self, value: u64) -> RowSet {
+        self.values.get(&value).cloned().unwrap_or_default()
+    }
+    //
+    //  ###### PERSISTENCE ######
+    //
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let mut r = BufReader::new(File::open(path)?);
+        //
+        let mut magic = [0_u8; 5];
+        r.read_exact(&mut magic)?;
+        if magic[0..4] != BMI_MAGIC || magic[4] != BMI_VERSION {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "bad bitmap index header",
+            ));
+        }
+        //
+        let mut buf = [0_u8; 8];
+        r.read_exact(&mut buf)?;
+        let watermark = u64::from_le_bytes(buf);
+        r.read_exact(&mut buf)?;
+        let num_values = u64::from_le_bytes(buf);
+        //
+        let mut values = BTreeMap::new();
+        for _ in 0..num_values {
+            r.read_exact(&mut buf)?;
+            let value = u64::from_le_bytes(buf);
+            values.insert(value, RoaringTreemap::deserialize_from(&mut r)?);
+        }
+        //
+        Ok(Self { values, watermark })
+    }
+    //
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let tmp_path = path.with_extension("tmp");
+        let mut w = BufWriter::new(File::create(&tmp_path)?);
+        //
+        w.write_all(&BMI_MAGIC)?;
+        w.write_all(&[BMI_VERSION])?;
+        w.write_all(&self.watermark.to_le_bytes())?;
+        w.write_all(&(self.values.len() as u64).to_le_bytes())?;
+        //
+        for (value, rows) in &self.values {
+            w.write_all(&value.to_le_bytes())?;
+            rows.serialize_into(&mut w)?;
+        }
+        //
+        w.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        std::fs::rename(tmp_path, path)
+    }
+}
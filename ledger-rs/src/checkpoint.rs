@@ -0,0 +1,161 @@
+use crate::transaction::sync_parent_dir;
+use crate::utils::DatastoreError;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+//
+const MANIFEST_FILE: &str = "manifest";
+//
+// ###### CHECKPOINTS ######
+//
+// named, immutable restore points of a ledger, kept in `<ledger>.checkpoints/`.
+//
+// a checkpoint is a reflink (FICLONE) of the ledger file when the filesystem supports it, which
+// shares every block with the ledger until one of them is written. otherwise it is an undo log
+// (`<name>.undo`, the transaction journal format): from then on the first write to each existing
+// page appends the page's original image to the newest log. a logged checkpoint is read back by
+// starting from the live ledger and applying the logs from the newest down to the checkpoint.
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckpointKind {
+    Reflink,
+    UndoLog,
+}
+
+impl CheckpointKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            CheckpointKind::Reflink => "reflink",
+            CheckpointKind::UndoLog => "undo",
+        }
+    }
+    //
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "reflink" => Some(CheckpointKind::Reflink),
+            "undo" => Some(CheckpointKind::UndoLog),
+            _ => None,
+        }
+    }
+}
+//
+// checkpoints in creation order, one `<kind> <name>` line each
+#[derive(Debug, Default, Clone)]
+pub struct CheckpointManifest {
+    pub entries: Vec<(CheckpointKind, String)>,
+}
+
+impl CheckpointManifest {
+    pub fn load(dir: &Path) -> Result<Self, DatastoreError> {
+        let text = match std::fs::read_to_string(dir.join(MANIFEST_FILE)) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+        //
+        let mut entries = Vec::new();
+        for line in text.lines().filter(|l| !l.is_empty()) {
+            let Some((kind, name)) = line
+                .split_once(' ')
+                .and_then(|(kind, name)| Some((CheckpointKind::parse(kind)?, name)))
+            else {
+                return Err(format!("bad checkpoint manifest line: {line:?}").into());
+            };
+            entries.push((kind, name.to_string()));
+        }
+        //
+        Ok(Self { entries })
+    }
+    //
+    pub fn save(&self, dir: &Path) -> Result<(), DatastoreError> {
+        let path = dir.join(MANIFEST_FILE);
+        let tmp_path = path.with_extension("tmp");
+        //
+        let mut f = File::create(&tmp_path)?;
+        for (kind, name) in &self.entries {
+            writeln!(f, "{} {}", kind.as_str(), name)?;
+        }
+        f.sync_all()?;
+        std::fs::rename(tmp_path, &path)?;
+        sync_parent_dir(&path)?;
+        //
+        Ok(())
+    }
+    //
+    pub fn push(&mut self, kind: CheckpointKind, name: &str) {
+        self.entries.push((kind, name.to_string()));
+    }
+    //
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.entries.iter().position(|(_, n)| n == name)
+    }
+    //
+    // the log that receives page images for writes made now
+    pub fn newest_log(&self) -> Option<&str> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|(kind, _)| *kind == CheckpointKind::UndoLog)
+            .map(|(_, name)| name.as_str())
+    }
+}
+
+// #### UTILS
+
+pub fn checkpoint_dir(ledger_path: &Path) -> PathBuf {
+    let mut name = ledger_path.as_os_str().to_os_string();
+    name.push(".checkpoints");
+    PathBuf::from(name)
+}
+//
+pub fn checkpoint_path(dir: &Path, name: &str, kind: CheckpointKind) -> PathBuf {
+    match kind {
+        CheckpointKind::Reflink => dir.join(name),
+        CheckpointKind::UndoLog => dir.join(format!("{name}.undo")),
+    }
+}
+//
+// names become file names, keep them to a portable set
+pub fn validate_name(name: &str) -> Result<(), DatastoreError> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name != MANIFEST_FILE
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err(format!("invalid checkpoint name {name:?}").into());
+    }
+    Ok(())
+}
+//
+// clones `src` into a new file at `dst`. Ok(false) when the filesystem can't share blocks
+#[cfg(target_os = "linux")]
+pub fn reflink(src: &File, dst: &Path) -> std::io::Result<bool> {
+    use std::os::fd::AsRawFd;
+    //
+    let dst_file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(dst)?;
+    //
+    let ret = unsafe { libc::ioctl(dst_file.as_raw_fd(), libc::FICLONE, src.as_raw_fd()) };
+    if ret == 0 {
+        dst_file.sync_all()?;
+        return Ok(true);
+    }
+    //
+    let e = std::io::Error::last_os_error();
+    drop(dst_file);
+    std::fs::remove_file(dst)?;
+    match e.raw_os_error() {
+        Some(libc::EOPNOTSUPP | libc::EXDEV | libc::EINVAL | libc::ENOTTY) => Ok(false),
+        _ => Err(e),
+    }
+}
+//
+#[cfg(not(target_os = "linux"))]
+pub fn reflink(_src: &File, _dst: &Path) -> std::io::Result<bool> {
+    Ok(false)
+}
@@ -0,0 +1,480 @@
+use crate::transaction::{checksum, sync_parent_dir};
+use crate::utils::FileAt;
+use rkyv::util::AlignedVec;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Read, Write};
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+//
+const SEGMENT_MAGIC: [u8; 4] = *b"LSEG";
+const SEGMENT_VERSION: u8 = 1;
+// [kind u8][page_id u64][payload len u32][payload checksum u64][checksum of the previous fields]
+const RECORD_HEADER_SZ: usize = 1 + 8 + 4 + 8 + 8;
+//
+const KIND_PAGE: u8 = 1;
+const KIND_COMMIT: u8 = 2;
+const KIND_RAW: u8 = 3;
+//
+// pages read from outside the mapping that are kept for `access_page`
+pub const PAGE_CACHE_PAGES: usize = 64;
+//
+// ###### COMPRESSED PAGES ######
+//
+// full pages (every page before the page cursor) can be moved into `<ledger>.lz4`, an append
+// only segment of lz4 compressed page images, and their range of the ledger file is punched out.
+// reads of such a page decompress it, writes first copy it back into the ledger and append a
+// `raw` record. the segment is never rewritten, so an offset read from it stays valid for as long
+// as the file exists.
+//
+// a batch of pages is only visible after its commit record, and the commit is only written
+// once the pages are on disk. the ledger file is punched after the commit.
+//
+#[derive(Debug, Default)]
+pub struct CompressedPages {
+    path: PathBuf,
+    // None until the first page is compressed
+    file: Option<File>,
+    // page id -> where its compressed image starts, its length and checksum
+    pages: HashMap<usize, Frame>,
+    len: u64,
+    // false for checkpoints, which read the live ledger's segment
+    writable: bool,
+}
+//
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    offset: u64,
+    len: u32,
+    checksum: u64,
+}
+//
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+    // pages moved into the segment by this call
+    pub pages: usize,
+    pub raw_bytes: u64,
+    pub compressed_bytes: u64,
+}
+
+impl CompressedPages {
+    pub fn open(ledger_path: &Path) -> std::io::Result<Self> {
+        Self::load(ledger_path, true)
+    }
+    //
+    pub fn open_read_only(ledger_path: &Path) -> std::io::Result<Self> {
+        Self::load(ledger_path, false)
+    }
+    //
+    fn load(ledger_path: &Path, writable: bool) -> std::io::Result<Self> {
+        let path = segment_path(ledger_path);
+        let mut segment = Self {
+            path,
+            writable,
+            ..Self::default()
+        };
+        //
+        let file = match OpenOptions::new()
+            .read(true)
+            .write(writable)
+            .open(&segment.path)
+        {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(segment),
+            Err(e) => return Err(e),
+        };
+        //
+        let file_len = file.metadata()?.len();
+        let mut r = BufReader::new(&file);
+        let mut magic = [0_u8; 5];
+        r.read_exact(&mut magic)?;
+        if magic[0..4] != SEGMENT_MAGIC || magic[4] != SEGMENT_VERSION {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "bad compressed segment header",
+            ));
+        }
+        //
+        // only the record headers are read, payloads are checked when they're decompressed
+        let mut pos = 5_u64;
+        let mut valid_len = pos;
+        let mut pending = Vec::new();
+        let mut header = [0_u8; RECORD_HEADER_SZ];
+        while pos + RECORD_HEADER_SZ as u64 <= file_len {
+            r.read_exact(&mut header)?;
+            let (body, sum) = header.split_at(RECORD_HEADER_SZ - 8);
+            if checksum(body) != u64::from_le_bytes(sum.try_into().unwrap()) {
+                break;
+            }
+            let page_id = u64::from_le_bytes(body[1..9].try_into().unwrap()) as usize;
+            let len = u32::from_le_bytes(body[9..13].try_into().unwrap());
+            let payload_checksum = u64::from_le_bytes(body[13..21].try_into().unwrap());
+            //
+            let offset = pos + RECORD_HEADER_SZ as u64;
+            if offset + len as u64 > file_len {
+                break;
+            }
+            r.seek_relative(len as i64)?;
+            pos = offset + len as u64;
+            //
+            match body[0] {
+                KIND_PAGE => pending.push((
+                    page_id,
+                    Frame {
+                        offset,
+                        len,
+                        checksum: payload_checksum,
+                    },
+                )),
+                KIND_COMMIT => {
+                    segment.pages.extend(pending.drain(..));
+                    valid_len = pos;
+                }
+                KIND_RAW => {
+                    segment.pages.remove(&page_id);
+                    valid_len = pos;
+                }
+                _ => break,
+            }
+        }
+        drop(r);
+        //
+        if valid_len < file_len && writable {
+            // an uncommitted batch, its pages were never punched out of the ledger
+            tracing::warn!(
+                "discarding uncommitted compressed pages in {:?}",
+                segment.path
+            );
+            file.set_len(valid_len)?;
+            file.sync_all()?;
+        }
+        segment.len = valid_len;
+        segment.file = Some(file);
+        //
+        Ok(segment)
+    }
+    //
+    #[inline(always)]
+    pub fn contains(&self, page_id: usize) -> bool {
+        self.pages.contains_key(&page_id)
+    }
+    //
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.pages.len()
+    }
+    //
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+    //
+    // decompresses the page into `out`, which must be exactly one page long. Ok(false) when the
+    // page isn't compressed
+    pub fn read_into(&self, page_id: usize, out: &mut [u8]) -> std::io::Result<bool> {
+        let (Some(frame), Some(file)) = (self.pages.get(&page_id), self.file.as_ref()) else {
+            return Ok(false);
+        };
+        //
+        let mut payload = vec![0_u8; frame.len as usize];
+        file.read_exact_at(&mut payload, frame.offset)?;
+        if checksum(&payload) != frame.checksum {
+            return Err(corrupt(page_id));
+        }
+        match lz4_flex::block::decompress_into(&payload, out) {
+            Ok(n) if n == out.len() => Ok(true),
+            _ => Err(corrupt(page_id)),
+        }
+    }
+    //
+    // appends and commits a batch of (page id, compressed image). once this returns the pages
+    // read from the segment and their range of the ledger file can be punched out
+    pub fn append(&mut self, pages: &[(usize, Vec<u8>)]) -> std::io::Result<()> {
+        if pages.is_empty() {
+            return Ok(());
+        }
+        let (file, end) = self.file_for_append()?;
+        //
+        let mut batch = Vec::new();
+        let mut frames = Vec::with_capacity(pages.len());
+        let mut pos = end;
+        for (page_id, compressed) in pages {
+            let frame = Frame {
+                offset: pos + RECORD_HEADER_SZ as u64,
+                len: compressed.len() as u32,
+                checksum: checksum(compressed),
+            };
+            batch.extend_from_slice(&record_header(
+                KIND_PAGE,
+                *page_id,
+                frame.len,
+                frame.checksum,
+            ));
+            batch.extend_from_slice(compressed);
+            pos = frame.offset + frame.len as u64;
+            frames.push((*page_id, frame));
+        }
+        file.write_all_at(&batch, end)?;
+        file.sync_data()?;
+        //
+        // the commit only goes out once every page it covers is durable
+        file.write_all_at(&record_header(KIND_COMMIT, 0, 0, checksum(&[])), pos)?;
+        file.sync_data()?;
+        //
+        self.len = pos + RECORD_HEADER_SZ as u64;
+        self.pages.extend(frames);
+        Ok(())
+    }
+    //
+    // the page was copied back into the ledger file, which is now the only copy that counts
+    pub fn mark_raw(&mut self, page_id: usize) -> std::io::Result<()> {
+        if !self.contains(page_id) {
+            return Ok(());
+        }
+        let (file, end) = self.file_for_append()?;
+        file.write_all_at(&record_header(KIND_RAW, page_id, 0, checksum(&[])), end)?;
+        file.sync_data()?;
+        //
+        self.len = end + RECORD_HEADER_SZ as u64;
+        self.pages.remove(&page_id);
+        Ok(())
+    }
+    //
+    // drops the page in memory only. checkpoints use it for pages their undo logs restore
+    pub fn forget(&mut self, page_id: usize) {
+        self.pages.remove(&page_id);
+    }
+    //
+    // the segment file and where the next record goes
+    fn file_for_append(&mut self) -> std::io::Result<(&File, u64)> {
+        if !self.writable {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "compressed segment is read-only",
+            ));
+        }
+        if self.file.is_none() {
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&self.path)?;
+            file.write_all(&SEGMENT_MAGIC)?;
+            file.write_all(&[SEGMENT_VERSION])?;
+            file.sync_all()?;
+            sync_parent_dir(&self.path)?;
+            //
+            self.len = 5;
+            self.file = Some(file);
+        }
+        Ok((self.file.as_ref().unwrap(), self.len))
+    }
+}
+//
+// compresses a page, None when it doesn't get smaller
+pub fn compress_page(page: &[u8]) -> Option<Vec<u8>> {
+    let compressed = lz4_flex::block::compress(page);
+    (compressed.len() < page.len()).then_some(compressed)
+}
+
+// #### UTILS
+
+fn segment_path(ledger_path: &Path) -> PathBuf {
+    let mut name = ledger_path.as_os_str().to_os_string();
+    name.push(".lz4");
+    PathBuf::from(name)
+}
+//
+fn record_header(kind: u8, page_id: usize, len: u32, payload_checksum: u64) -> Vec<u8> {
+    let mut header = Vec::with_capacity(RECORD_HEADER_SZ);
+    header.push(kind);
+    header.extend_from_slice(&(page_id as u64).to_le_bytes());
+    header.extend_from_slice(&len.to_le_bytes());
+    header.extend_from_slice(&payload_checksum.to_le_bytes());
+    header.extend_from_slice(&checksum(&header).to_le_bytes());
+    header
+}
+//
+fn corrupt(page_id: usize) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("compressed page {page_id} is corrupt"),
+    )
+}
+//
+// frees the file's blocks in the range, which then reads back as zeros. the file length is kept.
+// a no-op where the filesystem can't do it, the bytes just stay on disk
+#[cfg(target_os = "linux")]
+pub fn punch_hole(file: &File, offset: u64, len: u64) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+    //
+    let ret = unsafe {
+        libc::fallocate(
+            file.as_raw_fd(),
+            libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+            offset as libc::off_t,
+            len as libc::off_t,
+        )
+    };
+    if ret == 0 {
+        return Ok(());
+    }
+    let e = std::io::Error::last_os_error();
+    match e.raw_os_error() {
+        Some(libc::EOPNOTSUPP) => Ok(()),
+        _ => Err(e),
+    }
+}
+//
+#[cfg(not(target_os = "linux"))]
+pub fn punch_hole(_file: &File, _offset: u64, _len: u64) -> std::io::Result<()> {
+    Ok(())
+}
+
+//
+// ###### PAGE CACHE ######
+//
+// pages that aren't in the mapping (compressed, or still sealed in the file of an encrypted
+// ledger) are read into a buffer of the page cache. past PAGE_CACHE_PAGES the least recently used
+// one is dropped, a reader still holding it keeps the buffer alive through its `CacheRef`.
+//
+#[derive(Debug, Default)]
+pub(crate) struct PageCache {
+    // page id -> buffer at the page's alignment in the ledger file, last use
+    pages: HashMap<usize, (Arc<AlignedVec<16>>, u64)>,
+    tick: u64,
+}
+
+impl PageCache {
+    pub(crate) fn get(&mut self, page_id: usize) -> Option<Arc<AlignedVec<16>>> {
+        self.tick += 1;
+        let (buf, used) = self.pages.get_mut(&page_id)?;
+        *used = self.tick;
+        Some(buf.clone())
+    }
+    //
+    pub(crate) fn insert(&mut self, page_id: usize, buf: AlignedVec<16>) -> Arc<AlignedVec<16>> {
+        if self.pages.len() >= PAGE_CACHE_PAGES
+            && let Some(oldest) = self
+                .pages
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(page_id, _)| *page_id)
+        {
+            self.pages.remove(&oldest);
+        }
+        self.tick += 1;
+        let buf = Arc::new(buf);
+        self.pages.insert(page_id, (buf.clone(), self.tick));
+        buf
+    }
+    //
+    #[inline(always)]
+    pub(crate) fn len(&self) -> usize {
+        self.pages.len()
+    }
+    //
+    #[inline(always)]
+    pub(crate) fn remove(&mut self, page_id: usize) {
+        self.pages.remove(&page_id);
+    }
+    //
+    #[inline(always)]
+    pub(crate) fn clear(&mut self) {
+        self.pages.clear();
+    }
+}
+//
+// a page, or something in one, handed out by `access_page` and `access_row`. either borrowed
+// from the mapping or pointing into a page cache buffer it holds on to
+pub struct CacheRef<'a, X: ?Sized> {
+    value: *const X,
+    // None when `value` is in the mapping
+    buf: Option<Arc<AlignedVec<16>>>,
+    phantom: PhantomData<&'a X>,
+}
+
+// `value` is a shared reference into the mapping or into `buf`, which is never written to
+unsafe impl<X: ?Sized + Sync> Send for CacheRef<'_, X> {}
+unsafe impl<X: ?Sized + Sync> Sync for CacheRef<'_, X> {}
+
+impl CacheRef<'_, [u8]> {
+    #[inline(always)]
+    pub(crate) fn cached(buf: Arc<AlignedVec<16>>) -> Self {
+        Self {
+            value: buf.as_slice(),
+            buf: Some(buf),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, X: ?Sized> CacheRef<'a, X> {
+    #[inline(always)]
+    pub(crate) fn borrowed(value: &'a X) -> Self {
+        Self {
+            value,
+            buf: None,
+            phantom: PhantomData,
+        }
+    }
+    //
+    pub fn map<Y: ?Sized, F>(self, f: F) -> CacheRef<'a, Y>
+    where
+        F: for<'b> FnOnce(&'b X) -> &'b Y,
+    {
+        let value: *const Y = f(&*self);
+        CacheRef {
+            value,
+            buf: self.buf,
+            phantom: PhantomData,
+        }
+    }
+    //
+    pub fn try_map<Y: ?Sized, E, F>(self, f: F) -> Result<CacheRef<'a, Y>, E>
+    where
+        F: for<'b> FnOnce(&'b X) -> Result<&'b Y, E>,
+    {
+        let value: *const Y = f(&*self)?;
+        Ok(CacheRef {
+            value,
+            buf: self.buf,
+            phantom: PhantomData,
+        })
+    }
+    //
+    pub fn try_filter_map<Y: ?Sized, E, F>(self, f: F) -> Result<Option<CacheRef<'a, Y>>, E>
+    where
+        F: for<'b> FnOnce(&'b X) -> Result<Option<&'b Y>, E>,
+    {
+        let Some(value) = f(&*self)? else {
+            return Ok(None);
+        };
+        let value: *const Y = value;
+        Ok(Some(CacheRef {
+            value,
+            buf: self.buf,
+            phantom: PhantomData,
+        }))
+    }
+}
+
+impl<X: ?Sized> Deref for CacheRef<'_, X> {
+    type Target = X;
+
+    #[inline(always)]
+    fn deref(&self) -> &X {
+        // the mapping outlives 'a, a cached buffer lives as long as `buf`
+        unsafe { &*self.value }
+    }
+}
+
+impl<X: ?Sized + std::fmt::Debug> std::fmt::Debug for CacheRef<'_, X> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        (**self).fmt(f)
+    }
+}
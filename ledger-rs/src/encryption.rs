@@ -0,0 +1,438 @@
+use crate::transaction::{checksum, sync_parent_dir};
+use crate::utils::{DatastoreError, FileAt};
+use chacha20poly1305::aead::{AeadInPlace, KeyInit};
+use chacha20poly1305::{Key, Tag, XChaCha20Poly1305, XNonce};
+use hkdf::Hkdf;
+use memmap2::MmapMut;
+use parking_lot::Mutex;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+//
+const SEALS_MAGIC: [u8; 4] = *b"LSEA";
+const SEALS_VERSION: u8 = 1;
+const SEALS_HEADER_SZ: u64 = 5;
+//
+const SEALOG_MAGIC: [u8; 4] = *b"LSLG";
+const SEALOG_VERSION: u8 = 1;
+// magic, version and the number of writes
+const SEALOG_HEADER_SZ: usize = 9;
+// where a write goes
+const TO_LEDGER: u8 = 0;
+const TO_SEALS: u8 = 1;
+//
+const NONCE_SZ: usize = 24;
+const TAG_SZ: usize = 16;
+// what sealing adds to a page, the nonce and the tag
+pub const SEAL_SZ: usize = NONCE_SZ + TAG_SZ;
+//
+const KEY_CONTEXT: &[u8] = b"ledger-rs page key v1";
+const KEY_ID_CONTEXT: &[u8] = b"ledger-rs key id v1";
+//
+pub type EncryptionKey = [u8; 32];
+//
+// ###### PAGE ENCRYPTION ######
+//
+// every page of an encrypted ledger is sealed with XChaCha20-Poly1305 under a key derived from
+// the caller's key with HKDF-SHA256, salted with the salt in the ledger header. the page id is
+// associated data so pages can't be swapped around. the ciphertext stays at the page's offset in
+// the ledger file, the random nonce and the tag of each page go in `<ledger>.seal`.
+//
+// only the rows are confidential. in the clear are:
+// - the ledger header: name, description, page count, key id and salt
+// - the schema page: field names, types and offsets
+// - `<ledger>.merkle` and the signed checkpoints: SHA-256 hashes over the plaintext rows, enough
+//   to confirm a guessed page
+// #[fulltext] and #[bitmap_index] indexes would put field values and tokens in `.fts`/`.bmi`
+// sidecars, so an encrypted ledger keeps them in memory only and rebuilds them on open.
+//
+// the store works on a plaintext copy in anonymous memory. a page is decrypted into it the first
+// time it's written, reads of other pages go through the page cache. changed pages are sealed
+// and written back on flush. past PAGE_CACHE_PAGES resident pages the store flushes and hands
+// the clean ones back to the OS, they're read through the page cache again.
+//
+// a page and its seal are two writes to two files. flush writes them to `<ledger>.sealog` first,
+// a redo log of every write with a checksum at the end, and only then in place. a crash in
+// between leaves either an incomplete log, which is discarded with the old pages and seals still
+// matching, or a complete one that `recover` replays on the next open.
+//
+pub struct PageCipher {
+    aead: XChaCha20Poly1305,
+    seals: File,
+    sealog: File,
+    // layout of the ledger file
+    header_sz: usize,
+    page_size: usize,
+    // pages decrypted into the plaintext copy
+    resident: HashSet<usize>,
+    // resident pages changed since they were last sealed
+    dirty: Mutex<BTreeSet<usize>>,
+}
+
+impl PageCipher {
+    pub fn open(
+        ledger_path: &Path,
+        key: &EncryptionKey,
+        salt: [u8; 8],
+        header_sz: usize,
+        page_size: usize,
+    ) -> std::io::Result<Self> {
+        let path = seals_path(ledger_path);
+        let mut seals = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        if seals.metadata()?.len() < SEALS_HEADER_SZ {
+            seals.write_all(&SEALS_MAGIC)?;
+            seals.write_all(&[SEALS_VERSION])?;
+            seals.sync_all()?;
+            sync_parent_dir(&path)?;
+        } else {
+            let mut magic = [0_u8; SEALS_HEADER_SZ as usize];
+            seals.read_exact_at(&mut magic, 0)?;
+            if magic[0..4] != SEALS_MAGIC || magic[4] != SEALS_VERSION {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "bad page seal file header",
+                ));
+            }
+        }
+        let sealog = open_sealog(ledger_path)?;
+        //
+        let mut page_key = [0_u8; 32];
+        Hkdf::<Sha256>::new(Some(&salt), key)
+            .expand(KEY_CONTEXT, &mut page_key)
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+        //
+        Ok(Self {
+            aead: XChaCha20Poly1305::new(Key::from_slice(&page_key)),
+            seals,
+            sealog,
+            header_sz,
+            page_size,
+            resident: HashSet::new(),
+            dirty: Mutex::new(BTreeSet::new()),
+        })
+    }
+    //
+    #[inline(always)]
+    pub fn is_resident(&self, page_id: usize) -> bool {
+        self.resident.contains(&page_id)
+    }
+    //
+    // the page now lives in the plaintext copy
+    pub fn set_resident(&mut self, page_id: usize) {
+        self.resident.insert(page_id);
+    }
+    //
+    #[inline(always)]
+    pub fn num_resident(&self) -> usize {
+        self.resident.len()
+    }
+    //
+    // clean resident pages other than `keep`, which can leave the plaintext copy
+    pub fn evictable(&self, keep: &[usize]) -> Vec<usize> {
+        let dirty = self.dirty.lock();
+        self.resident
+            .iter()
+            .filter(|page_id| !dirty.contains(page_id) && !keep.contains(page_id))
+            .copied()
+            .collect()
+    }
+    //
+    // the page was dropped from the plaintext copy, reads decrypt it from the file again
+    pub fn set_evicted(&mut self, page_id: usize) {
+        self.resident.remove(&page_id);
+    }
+    //
+    #[inline(always)]
+    pub fn mark_dirty(&self, page_id: usize) {
+        self.dirty.lock().insert(page_id);
+    }
+    //
+    // pages past the end were truncated away
+    pub fn truncate(&mut self, num_pages: usize) {
+        self.resident.retain(|page_id| *page_id < num_pages);
+        self.dirty.get_mut().retain(|page_id| *page_id < num_pages);
+    }
+    //
+    // decrypts the page from the ledger file into `out`
+    pub fn read_page(
+        &self,
+        file: &File,
+        page_id: usize,
+        out: &mut [u8],
+    ) -> Result<(), DatastoreError> {
+        file.read_exact_at(out, self.page_offset(page_id) as u64)?;
+        let mut seal = [0_u8; SEAL_SZ];
+        self.seals
+            .read_exact_at(&mut seal, seal_offset(page_id))
+            .map_err(|_| unreadable(page_id))?;
+        self.open_in_place(page_id, &seal, out)
+    }
+    //
+    // writes the header and seals every dirty page of the plaintext copy into the ledger file.
+    // everything goes through the seal log and is fsynced before this returns
+    pub fn flush(&self, file: &File, plaintext: &[u8]) -> std::io::Result<()> {
+        let mut dirty = self.dirty.lock();
+        if dirty.is_empty() {
+            file.write_all_at(&plaintext[..self.header_sz], 0)?;
+            return file.sync_data();
+        }
+        let mut log = SealLog::default();
+        log.push(TO_LEDGER, 0, &plaintext[..self.header_sz]);
+        for page_id in dirty.iter() {
+            let start = self.page_offset(*page_id);
+            let mut buf = plaintext[start..start + self.page_size].to_vec();
+            let seal = self.seal_in_place(*page_id, &mut buf);
+            log.push(TO_LEDGER, start as u64, &buf);
+            log.push(TO_SEALS, seal_offset(*page_id), &seal);
+        }
+        // nothing is written in place until the whole log is on disk
+        let log = log.finish();
+        self.sealog.write_all_at(&log, 0)?;
+        self.sealog.set_len(log.len() as u64)?;
+        self.sealog.sync_data()?;
+        //
+        apply_sealog(&log, file, &self.seals)?;
+        // an emptied log that didn't reach the disk only replays these same writes
+        self.sealog.set_len(0)?;
+        self.sealog.sync_data()?;
+        //
+        dirty.clear();
+        Ok(())
+    }
+    //
+    // replays a complete seal log left by a crash. an incomplete one is dropped, the pages and
+    // seals in place are still those of the last flush
+    pub fn recover(ledger_path: &Path, file: &File) -> std::io::Result<()> {
+        let path = sealog_path(ledger_path);
+        if !path.exists() {
+            return Ok(());
+        }
+        let sealog = open_sealog(ledger_path)?;
+        let len = sealog.metadata()?.len() as usize;
+        if len == 0 {
+            return Ok(());
+        }
+        let mut log = vec![0_u8; len];
+        sealog.read_exact_at(&mut log, 0)?;
+        //
+        if sealog_writes(&log).is_some() {
+            tracing::warn!("replaying the seal log of {:?}", ledger_path);
+            let seals = OpenOptions::new()
+                .write(true)
+                .open(seals_path(ledger_path))?;
+            apply_sealog(&log, file, &seals)?;
+        } else {
+            tracing::warn!("discarding an incomplete seal log of {:?}", ledger_path);
+        }
+        sealog.set_len(0)?;
+        sealog.sync_data()
+    }
+    //
+    #[inline(always)]
+    fn page_offset(&self, page_id: usize) -> usize {
+        self.header_sz + page_id * self.page_size
+    }
+    //
+    // a page image for the transaction journal, which never holds plaintext:
+    // ciphertext followed by the seal
+    pub fn seal_image(&self, page_id: usize, page: &[u8]) -> Vec<u8> {
+        let mut image = Vec::with_capacity(page.len() + SEAL_SZ);
+        image.extend_from_slice(page);
+        let seal = self.seal_in_place(page_id, &mut image);
+        image.extend_from_slice(&seal);
+        image
+    }
+    //
+    pub fn open_image(&self, page_id: usize, image: &[u8]) -> Result<Vec<u8>, DatastoreError> {
+        if image.len() < SEAL_SZ {
+            return Err(unreadable(page_id));
+        }
+        let (ciphertext, seal) = image.split_at(image.len() - SEAL_SZ);
+        let mut page = ciphertext.to_vec();
+        self.open_in_place(page_id, seal, &mut page)?;
+        Ok(page)
+    }
+    //
+    fn seal_in_place(&self, page_id: usize, buf: &mut [u8]) -> [u8; SEAL_SZ] {
+        let nonce: [u8; NONCE_SZ] = rand::random();
+        let tag = self
+            .aead
+            .encrypt_in_place_detached(
+                XNonce::from_slice(&nonce),
+                &(page_id as u64).to_le_bytes(),
+                buf,
+            )
+            .expect("page fits in a single xchacha20poly1305 message");
+        //
+        let mut seal = [0_u8; SEAL_SZ];
+        seal[..NONCE_SZ].copy_from_slice(&nonce);
+        seal[NONCE_SZ..].copy_from_slice(&tag);
+        seal
+    }
+    //
+    fn open_in_place(
+        &self,
+        page_id: usize,
+        seal: &[u8],
+        buf: &mut [u8],
+    ) -> Result<(), DatastoreError> {
+        let (nonce, tag) = seal.split_at(NONCE_SZ);
+        self.aead
+            .decrypt_in_place_detached(
+                XNonce::from_slice(nonce),
+                &(page_id as u64).to_le_bytes(),
+                buf,
+                Tag::from_slice(tag),
+            )
+            .map_err(|_| unreadable(page_id))
+    }
+}
+//
+// identifies the key in the ledger header without revealing it
+pub fn key_id(key: &EncryptionKey) -> [u8; 4] {
+    let mut hasher = Sha256::new();
+    hasher.update(KEY_ID_CONTEXT);
+    hasher.update(key);
+    hasher.finalize()[..4].try_into().unwrap()
+}
+
+// #### UTILS
+
+fn seals_path(ledger_path: &Path) -> PathBuf {
+    let mut name = ledger_path.as_os_str().to_os_string();
+    name.push(".seal");
+    PathBuf::from(name)
+}
+//
+fn sealog_path(ledger_path: &Path) -> PathBuf {
+    let mut name = ledger_path.as_os_str().to_os_string();
+    name.push(".sealog");
+    PathBuf::from(name)
+}
+//
+fn open_sealog(ledger_path: &Path) -> std::io::Result<File> {
+    let path = sealog_path(ledger_path);
+    let existed = path.exists();
+    let sealog = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)?;
+    if !existed {
+        sync_parent_dir(&path)?;
+    }
+    Ok(sealog)
+}
+//
+#[inline(always)]
+fn seal_offset(page_id: usize) -> u64 {
+    SEALS_HEADER_SZ + (page_id * SEAL_SZ) as u64
+}
+//
+fn unreadable(page_id: usize) -> DatastoreError {
+    format!("encrypted page {page_id} failed authentication").into()
+}
+
+// zeroes the whole OS pages inside the range of the plaintext copy and gives their memory back.
+// a no-op elsewhere than unix, the plaintext just stays
+#[cfg(unix)]
+pub fn release_range(mmap: &MmapMut, start: usize, len: usize) -> std::io::Result<()> {
+    let os_page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    // the mapping starts on an OS page, the ledger pages around the range keep their bytes
+    let (from, to) = (
+        start.next_multiple_of(os_page),
+        (start + len) / os_page * os_page,
+    );
+    if from >= to {
+        return Ok(());
+    }
+    unsafe { mmap.unchecked_advise_range(memmap2::UncheckedAdvice::DontNeed, from, to - from) }
+}
+//
+#[cfg(not(unix))]
+pub fn release_range(_mmap: &MmapMut, _start: usize, _len: usize) -> std::io::Result<()> {
+    Ok(())
+}
+
+// #### SEAL LOG
+//
+//   magic "LSLG" | version u8 | writes u32 | { target u8 | offset u64 | len u32 | bytes } | checksum u64
+//
+// the checksum covers everything before it
+//
+#[derive(Default)]
+struct SealLog {
+    writes: u32,
+    body: Vec<u8>,
+}
+
+impl SealLog {
+    fn push(&mut self, target: u8, offset: u64, bytes: &[u8]) {
+        self.body.push(target);
+        self.body.extend_from_slice(&offset.to_le_bytes());
+        self.body
+            .extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        self.body.extend_from_slice(bytes);
+        self.writes += 1;
+    }
+    //
+    fn finish(self) -> Vec<u8> {
+        let mut log = Vec::with_capacity(SEALOG_HEADER_SZ + self.body.len() + 8);
+        log.extend_from_slice(&SEALOG_MAGIC);
+        log.push(SEALOG_VERSION);
+        log.extend_from_slice(&self.writes.to_le_bytes());
+        log.extend_from_slice(&self.body);
+        log.extend_from_slice(&checksum(&log).to_le_bytes());
+        log
+    }
+}
+//
+// the writes of a complete log, None if it was cut short or doesn't check out
+fn sealog_writes(log: &[u8]) -> Option<Vec<(u8, u64, &[u8])>> {
+    let (body, sum) = log.split_at_checked(log.len().checked_sub(8)?)?;
+    if body.len() < SEALOG_HEADER_SZ
+        || body[0..4] != SEALOG_MAGIC
+        || body[4] != SEALOG_VERSION
+        || checksum(body) != u64::from_le_bytes(sum.try_into().ok()?)
+    {
+        return None;
+    }
+    let count = u32::from_le_bytes(body[5..9].try_into().ok()?);
+    let mut writes = Vec::with_capacity(count as usize);
+    let mut rest = &body[SEALOG_HEADER_SZ..];
+    for _ in 0..count {
+        let (prefix, tail) = rest.split_at_checked(13)?;
+        if prefix[0] > TO_SEALS {
+            return None;
+        }
+        let offset = u64::from_le_bytes(prefix[1..9].try_into().ok()?);
+        let len = u32::from_le_bytes(prefix[9..13].try_into().ok()?) as usize;
+        let (bytes, tail) = tail.split_at_checked(len)?;
+        writes.push((prefix[0], offset, bytes));
+        rest = tail;
+    }
+    rest.is_empty().then_some(writes)
+}
+//
+// writes a complete log in place and syncs both files
+fn apply_sealog(log: &[u8], file: &File, seals: &File) -> std::io::Result<()> {
+    let writes = sealog_writes(log)
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "corrupt seal log"))?;
+    for (target, offset, bytes) in writes {
+        match target {
+            TO_SEALS => seals.write_all_at(bytes, offset)?,
+            _ => file.write_all_at(bytes, offset)?,
+        }
+    }
+    seals.sync_data()?;
+    file.sync_data()
+}
@@ -0,0 +1,249 @@
+use crate::ledger::RowId;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+//
+// ###### EXPORT ######
+//
+// rows are written out through `PageSchema::field_values`, which #[ledger] generates with the
+// logical value of every field: ints and floats in their native type, strings cut to their
+// length. a `row_id` column comes first. `[u8; N]` fields are written as hex, other arrays as a
+// json array in both formats.
+//
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValue<'a> {
+    Bool(bool),
+    Int(i128),
+    UInt(u128),
+    Float(f64),
+    Str(Cow<'a, str>),
+    Bytes(&'a [u8]),
+    Array(Vec<FieldValue<'a>>),
+}
+
+//
+// the logical type of a field, what its FieldValue holds. used to build columnar schemas
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Bool,
+    Char,
+    // bits
+    UInt(u8),
+    Int(u8),
+    Float(u8),
+    // #[max_len] string
+    Str {
+        max_len: usize,
+    },
+    // [u8; N]
+    Bytes(usize),
+    // [T; N] of any other primitive
+    Array {
+        elem: &'static ColumnType,
+        len: usize,
+    },
+    // written as its Debug text
+    Other,
+}
+
+// rust-like names, how schemas write them: "u32", "string(32)", "[u8; 16]", "[f32; 3]"
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColumnType::Bool => write!(f, "bool"),
+            ColumnType::Char => write!(f, "char"),
+            ColumnType::UInt(bits) => write!(f, "u{bits}"),
+            ColumnType::Int(bits) => write!(f, "i{bits}"),
+            ColumnType::Float(bits) => write!(f, "f{bits}"),
+            ColumnType::Str { max_len } => write!(f, "string({max_len})"),
+            ColumnType::Bytes(len) => write!(f, "[u8; {len}]"),
+            ColumnType::Array { elem, len } => write!(f, "[{elem}; {len}]"),
+            ColumnType::Other => write!(f, "other"),
+        }
+    }
+}
+
+impl FromStr for ColumnType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("unknown column type {s:?}");
+        if let Some(array) = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            let (elem, len) = array.split_once(';').ok_or_else(invalid)?;
+            let len = len.trim().parse().map_err(|_| invalid())?;
+            return match elem.trim() {
+                "u8" => Ok(ColumnType::Bytes(len)),
+                elem => Ok(ColumnType::Array {
+                    elem: Self::scalar(elem.parse()?).ok_or_else(invalid)?,
+                    len,
+                }),
+            };
+        }
+        if let Some(max_len) = s.strip_prefix("string(").and_then(|s| s.strip_suffix(')')) {
+            return Ok(ColumnType::Str {
+                max_len: max_len.parse().map_err(|_| invalid())?,
+            });
+        }
+        let bits = |bits: &str| match bits.parse() {
+            Ok(bits @ (8 | 16 | 32 | 64 | 128)) => Ok(bits),
+            _ => Err(invalid()),
+        };
+        match s {
+            "bool" => Ok(ColumnType::Bool),
+            "char" => Ok(ColumnType::Char),
+            "other" => Ok(ColumnType::Other),
+            "f32" => Ok(ColumnType::Float(32)),
+            "f64" => Ok(ColumnType::Float(64)),
+            _ => match s.split_at_checked(1) {
+                Some(("u", n)) => Ok(ColumnType::UInt(bits(n)?)),
+                Some(("i", n)) => Ok(ColumnType::Int(bits(n)?)),
+                _ => Err(invalid()),
+            },
+        }
+    }
+}
+
+impl ColumnType {
+    // array elements are 'static, a parsed one is swapped for the constant of the same type
+    fn scalar(column_type: ColumnType) -> Option<&'static ColumnType> {
+        match column_type {
+            ColumnType::Bool => Some(&ColumnType::Bool),
+            ColumnType::Char => Some(&ColumnType::Char),
+            ColumnType::UInt(8) => Some(&ColumnType::UInt(8)),
+            ColumnType::UInt(16) => Some(&ColumnType::UInt(16)),
+            ColumnType::UInt(32) => Some(&ColumnType::UInt(32)),
+            ColumnType::UInt(64) => Some(&ColumnType::UInt(64)),
+            ColumnType::UInt(128) => Some(&ColumnType::UInt(128)),
+            ColumnType::Int(8) => Some(&ColumnType::Int(8)),
+            ColumnType::Int(16) => Some(&ColumnType::Int(16)),
+            ColumnType::Int(32) => Some(&ColumnType::Int(32)),
+            ColumnType::Int(64) => Some(&ColumnType::Int(64)),
+            ColumnType::Int(128) => Some(&ColumnType::Int(128)),
+            ColumnType::Float(32) => Some(&ColumnType::Float(32)),
+            ColumnType::Float(64) => Some(&ColumnType::Float(64)),
+            ColumnType::Other => Some(&ColumnType::Other),
+            _ => None,
+        }
+    }
+}
+
+impl Serialize for ColumnType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ColumnType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl<'a> FieldValue<'a> {
+    // as a csv cell, before quoting
+    pub fn to_text(&self) -> Cow<'_, str> {
+        match self {
+            FieldValue::Bool(v) => Cow::Owned(v.to_string()),
+            FieldValue::Int(v) => Cow::Owned(v.to_string()),
+            FieldValue::UInt(v) => Cow::Owned(v.to_string()),
+            FieldValue::Float(v) => Cow::Owned(v.to_string()),
+            FieldValue::Str(v) => Cow::Borrowed(v),
+            FieldValue::Bytes(v) => Cow::Owned(hex(v)),
+            FieldValue::Array(_) => Cow::Owned(self.to_json().to_string()),
+        }
+    }
+    //
+    // ints that don't fit json's i64/u64 become strings, non finite floats null
+    pub fn to_json(&self) -> serde_json::Value {
+        use serde_json::Value;
+        match self {
+            FieldValue::Bool(v) => Value::Bool(*v),
+            FieldValue::Int(v) => match i64::try_from(*v) {
+                Ok(v) => Value::from(v),
+                Err(_) => Value::String(v.to_string()),
+            },
+            FieldValue::UInt(v) => match u64::try_from(*v) {
+                Ok(v) => Value::from(v),
+                Err(_) => Value::String(v.to_string()),
+            },
+            FieldValue::Float(v) => serde_json::Number::from_f64(*v)
+                .map(Value::Number)
+                .unwrap_or(Value::Null),
+            FieldValue::Str(v) => Value::String(v.to_string()),
+            FieldValue::Bytes(v) => Value::String(hex(v)),
+            FieldValue::Array(values) => Value::Array(values.iter().map(Self::to_json).collect()),
+        }
+    }
+}
+
+//
+// ###### CSV ######
+//
+
+pub(crate) fn write_csv_header<W: Write>(w: &mut W, field_names: &[&str]) -> std::io::Result<()> {
+    let mut line = String::from("row_id");
+    for name in field_names {
+        line.push(',');
+        push_csv_cell(&mut line, name);
+    }
+    line.push('\n');
+    w.write_all(line.as_bytes())
+}
+//
+pub(crate) fn write_csv_row<W: Write>(
+    w: &mut W,
+    row_id: RowId,
+    values: &[FieldValue<'_>],
+) -> std::io::Result<()> {
+    let mut line = row_id.to_string();
+    for value in values {
+        line.push(',');
+        push_csv_cell(&mut line, &value.to_text());
+    }
+    line.push('\n');
+    w.write_all(line.as_bytes())
+}
+//
+// rfc 4180, cells holding a separator, a quote or a line break are quoted
+fn push_csv_cell(line: &mut String, cell: &str) {
+    if !cell.contains([',', '"', '\n', '\r']) {
+        line.push_str(cell);
+        return;
+    }
+    line.push('"');
+    line.push_str(&cell.replace('"', "\"\""));
+    line.push('"');
+}
+
+//
+// ###### JSON LINES ######
+//
+
+pub(crate) fn write_jsonl_row<W: Write>(
+    w: &mut W,
+    field_names: &[&str],
+    row_id: RowId,
+    values: &[FieldValue<'_>],
+) -> std::io::Result<()> {
+    // written field by field, a serde_json::Map would sort the keys
+    let mut line = format!("{{\"row_id\":{}", row_id.get());
+    for (name, value) in field_names.iter().zip(values) {
+        line.push(',');
+        line.push_str(&serde_json::Value::from(*name).to_string());
+        line.push(':');
+        line.push_str(&value.to_json().to_string());
+    }
+    line.push_str("}\n");
+    w.write_all(line.as_bytes())
+}
+
+// #### UTILS
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
@@ -0,0 +1,216 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::ops::Bound;
+use std::path::Path;
+
+//
+const FTS_MAGIC: [u8; 4] = *b"LFTS";
+// 2: row ids are u64
+const FTS_VERSION: u8 = 2;
+//
+// ###### FULL TEXT INDEX ######
+//
+// inverted index of term -> sorted row ids. persisted as a sidecar file next to the ledger,
+// postings are written as delta encoded varints. terms are kept sorted so a query term matches
+// every term it is a prefix of with one range scan.
+//
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FullTextIndex {
+    terms: BTreeMap<String, Vec<u64>>,
+    // one past the highest row id seen. compared against the ledger on open to detect a stale index
+    watermark: u64,
+}
+
+impl FullTextIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    //
+    #[inline(always)]
+    pub fn watermark(&self) -> u64 {
+        self.watermark
+    }
+    //
+    #[inline(always)]
+    pub fn num_terms(&self) -> usize {
+        self.terms.len()
+    }
+    //
+    pub fn insert(&mut self, row_id: u64, text: &str) {
+        for term in tokenize(text) {
+            let postings = self.terms.entry(term).or_default();
+            //
+            match postings.last() {
+                Some(last) if *last == row_id => {}
+                Some(last) if *last < row_id => postings.push(row_id),
+                None => postings.push(row_id),
+                Some(_) => {
+                    if let Err(pos) = postings.binary_search(&row_id) {
+                        postings.insert(pos, row_id);
+                    }
+                }
+            }
+        }
+        //
+        self.watermark = self.watermark.max(row_id + 1);
+    }
+    //
+    pub fn remove(&mut self, row_id: u64, text: &str) {
+        for term in tokenize(text) {
+            let Some(postings) = self.terms.get_mut(&term) else {
+                continue;
+            };
+            if let Ok(pos) = postings.binary_search(&row_id) {
+                postings.remove(pos);
+            }
+            if postings.is_empty() {
+                self.terms.remove(&term);
+            }
+        }
+    }
+    //
+    // every term in the query must start an indexed term, "docu" matches "document". returns
+    // sorted row ids
+    pub fn search(&self, query: &str) -> Vec<u64> {
+        let mut lists: Vec<Cow<[u64]>> = Vec::new();
+        for term in tokenize(query) {
+            let postings = self.prefixed(&term);
+            if postings.is_empty() {
+                return Vec::new();
+            }
+            lists.push(postings);
+        }
+        //
+        lists.sort_by_key(|p| p.len());
+        let Some((first, rest)) = lists.split_first() else {
+            return Vec::new();
+        };
+        //
+        first
+            .iter()
+            .copied()
+            .filter(|row_id| rest.iter().all(|p| p.binary_search(row_id).is_ok()))
+            .collect()
+    }
+    //
+    // the sorted union of the postings of every term starting with `prefix`
+    fn prefixed(&self, prefix: &str) -> Cow<'_, [u64]> {
+        let mut terms = self
+            .terms
+            .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(|(term, _)| term.starts_with(prefix))
+            .map(|(_, postings)| postings);
+        //
+        let Some(first) = terms.next() else {
+            return Cow::Borrowed(&[]);
+        };
+        let mut rest = terms.peekable();
+        if rest.peek().is_none() {
+            return Cow::Borrowed(first);
+        }
+        let mut union: Vec<u64> = first.iter().chain(rest.flatten()).copied().collect();
+        union.sort_unstable();
+        union.dedup();
+        Cow::Owned(union)
+    }
+    //
+    //  ###### PERSISTENCE ######
+    //
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let mut r = BufReader::new(File::open(path)?);
+        //
+        let mut magic = [0_u8; 5];
+        r.read_exact(&mut magic)?;
+        if magic[0..4] != FTS_MAGIC || magic[4] != FTS_VERSION {
+            return Err(invalid_data("bad fulltext index header"));
+        }
+        //
+        let watermark = read_varint(&mut r)?;
+        let num_terms = read_varint(&mut r)?;
+        //
+        let mut terms = BTreeMap::new();
+        for _ in 0..num_terms {
+            let term_len = read_varint(&mut r)? as usize;
+            let mut term = vec![0_u8; term_len];
+            r.read_exact(&mut term)?;
+            let term = String::from_utf8(term).map_err(|_| invalid_data("term is not utf8"))?;
+            //
+            let num_postings = read_varint(&mut r)? as usize;
+            let mut postings = Vec::with_capacity(num_postings);
+            let mut prev = 0_u64;
+            for _ in 0..num_postings {
+                prev += read_varint(&mut r)?;
+                postings.push(prev);
+            }
+            terms.insert(term, postings);
+        }
+        //
+        Ok(Self { terms, watermark })
+    }
+    //
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        // write next to the target and rename so a crash never leaves a half written index
+        let tmp_path = path.with_extension("tmp");
+        let mut w = BufWriter::new(File::create(&tmp_path)?);
+        //
+        w.write_all(&FTS_MAGIC)?;
+        w.write_all(&[FTS_VERSION])?;
+        write_varint(&mut w, self.watermark)?;
+        write_varint(&mut w, self.terms.len() as u64)?;
+        //
+        for (term, postings) in &self.terms {
+            write_varint(&mut w, term.len() as u64)?;
+            w.write_all(term.as_bytes())?;
+            //
+            write_varint(&mut w, postings.len() as u64)?;
+            let mut prev = 0_u64;
+            for row_id in postings {
+                write_varint(&mut w, row_id - prev)?;
+                prev = *row_id;
+            }
+        }
+        //
+        w.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        std::fs::rename(tmp_path, path)
+    }
+}
+
+// #### UTILS
+
+// lowercased alphanumeric runs. "Document #42.txt" -> ["document", "42", "txt"]
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_lowercase())
+}
+//
+fn write_varint<W: Write>(w: &mut W, mut v: u64) -> std::io::Result<()> {
+    while v >= 0x80 {
+        w.write_all(&[(v as u8) | 0x80])?;
+        v >>= 7;
+    }
+    w.write_all(&[v as u8])
+}
+//
+fn read_varint<R: Read>(r: &mut R) -> std::io::Result<u64> {
+    let mut v = 0_u64;
+    let mut shift = 0;
+    loop {
+        let mut byte = [0_u8; 1];
+        r.read_exact(&mut byte)?;
+        if shift >= 64 {
+            return Err(invalid_data("varint overflow"));
+        }
+        v |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(v);
+        }
+        shift += 7;
+    }
+}
+//
+fn invalid_data(msg: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
@@ -90,10 +90,14 @@ impl PageHeader {
         rkyv::access_mut::<Self, rkyv::rancor::Error>(f)
     }
     //
+    /// # Safety
+    /// `f` must hold a valid archived header, the bytes aren't validated.
     #[inline(always)]
     pub unsafe fn access_unchecked(f: &[u8]) -> &Self {
         unsafe { rkyv::access_unchecked::<Self>(f) }
     }
+    /// # Safety
+    /// `f` must hold a valid archived header, the bytes aren't validated.
     #[inline(always)]
     pub unsafe fn access_unchecked_mut<'a>(f: &'a mut [u8]) -> Seal<'a, Self> {
         unsafe { rkyv::access_unchecked_mut::<Self>(f) }
@@ -104,6 +108,8 @@ impl PageHeader {
         self.free_end() - self.free_start()
     }
     //
+    /// # Safety
+    /// `self` must be a valid page header, the offsets aren't validated.
     #[inline(always)]
     pub unsafe fn free_space_unchecked(&self) -> u32 {
         unsafe {
@@ -120,12 +126,18 @@ impl PageHeader {
 pub enum HeaderFlags {
     Private,
     DeleteMe,
+    // rows are hash chained and may only be appended
+    Audit,
+    // pages are sealed with the key identified by the header's key id
+    Encrypted,
 }
 impl FlagMask for HeaderFlags {
     fn mask(&self) -> u8 {
         match self {
             HeaderFlags::Private => 1 << 0,
             HeaderFlags::DeleteMe => 1 << 1,
+            HeaderFlags::Audit => 1 << 2,
+            HeaderFlags::Encrypted => 1 << 3,
         }
     }
 }
@@ -133,6 +145,20 @@ impl FlagMask for HeaderFlags {
 //
 // ###### LEDGER HEADER ######
 //
+// format 1 widened the page counters to u64. a format 0 header is the first 68 bytes of this
+// struct with the version byte zero: its counters live in the u32 fields and the pages start
+// right after it. the accessors below pick the right fields, nothing past byte 68 is touched on a
+// format 0 ledger.
+//
+// format 2 follows the header with a schema page (`schema::LedgerSchema`) describing the row type,
+// the pages start after it.
+//
+// format 3 puts a slot for the latest signed checkpoint (`signing::SignedCheckpoint`) after the
+// schema page, so a ledger file carries its own signature. it's zero until the ledger is signed.
+//
+pub const FORMAT_VERSION: u8 = 3;
+pub const LEGACY_HEADER_SZ: usize = 68;
+pub const CHECKPOINT_SLOT_SZ: usize = 128;
 //
 #[derive(Archive, Deserialize, Serialize, Debug, Clone, PartialEq, Portable, CheckBytes)]
 #[rkyv(compare(PartialEq))]
@@ -142,14 +168,25 @@ pub struct LedgerHeader {
     //
     ledger_name: LedgerName,
     ledger_description: LedgerDescription,
-    //
+    // format 0 only
     num_pages_u32: [u8; 4],
     //
     rows_per_page_u32: [u8; 4],
-    //
+    // format 0 only
     page_cursor_u32: [u8; 4],
+    // encrypted ledgers only, zero otherwise
+    key_id: [u8; 4],
+    salt: [u8; 8],
     //
-    pub _padding: [u8; 14],
+    format_version: u8,
+    pub _padding: [u8; 1],
+    // format 1
+    num_pages_u64: [u8; 8],
+    page_cursor_u64: [u8; 8],
+    // leading pages whose rows were all expired by retention
+    retired_pages_u64: [u8; 8],
+    // format 2, bytes of the schema page
+    schema_sz_u32: [u8; 4],
 }
 
 unsafe impl rkyv::traits::NoUndef for LedgerHeader {}
@@ -180,7 +217,64 @@ impl LedgerHeader {
             //
             page_cursor_u32: 0_u32.to_le_bytes(),
             //
-            _padding: [0; 14],
+            key_id: [0; 4],
+            salt: [0; 8],
+            //
+            format_version: FORMAT_VERSION,
+            _padding: [0; 1],
+            //
+            num_pages_u64: 0_u64.to_le_bytes(),
+            page_cursor_u64: 0_u64.to_le_bytes(),
+            retired_pages_u64: 0_u64.to_le_bytes(),
+            schema_sz_u32: 0_u32.to_le_bytes(),
+        }
+    }
+    //
+    #[inline(always)]
+    pub fn format_version(&self) -> u8 {
+        self.format_version
+    }
+    // bytes before the first page
+    #[inline(always)]
+    pub fn size(&self) -> usize {
+        match self.format_version {
+            0 => LEGACY_HEADER_SZ,
+            1 => size_of::<Self>(),
+            2 => size_of::<Self>() + self.schema_size(),
+            _ => size_of::<Self>() + self.schema_size() + CHECKPOINT_SLOT_SZ,
+        }
+    }
+    // where the latest signed checkpoint is kept, format 3 and later
+    #[inline(always)]
+    pub fn checkpoint_slot(&self) -> Option<std::ops::Range<usize>> {
+        match self.format_version {
+            0..=2 => None,
+            _ => {
+                let start = size_of::<Self>() + self.schema_size();
+                Some(start..start + CHECKPOINT_SLOT_SZ)
+            }
+        }
+    }
+    // bytes of the schema page after the header, format 2 and later
+    #[inline(always)]
+    pub fn schema_size(&self) -> usize {
+        match self.format_version {
+            0 | 1 => 0,
+            _ => u32::from_le_bytes(self.schema_sz_u32) as usize,
+        }
+    }
+    #[inline(always)]
+    pub fn set_schema_size(&mut self, v: u32) {
+        if self.format_version > 1 {
+            self.schema_sz_u32 = v.to_le_bytes();
+        }
+    }
+    // the largest page count the format can record
+    #[inline(always)]
+    pub fn max_pages(&self) -> u64 {
+        match self.format_version {
+            0 => u32::MAX as u64,
+            _ => u64::MAX,
         }
     }
 
@@ -188,6 +282,14 @@ impl LedgerHeader {
     pub fn name(&self) -> String {
         self.ledger_name.to_string()
     }
+    #[inline(always)]
+    pub fn ledger_name(&self) -> &LedgerName {
+        &self.ledger_name
+    }
+    #[inline(always)]
+    pub fn ledger_description(&self) -> &LedgerDescription {
+        &self.ledger_description
+    }
     //
     #[inline(always)]
     pub fn rows_per_page(&self) -> u32 {
@@ -199,43 +301,76 @@ impl LedgerHeader {
     }
     //
     #[inline(always)]
-    pub fn num_pages(&self) -> u32 {
-        u32::from_le_bytes(self.num_pages_u32)
+    pub fn num_pages(&self) -> u64 {
+        match self.format_version {
+            0 => u32::from_le_bytes(self.num_pages_u32) as u64,
+            _ => u64::from_le_bytes(self.num_pages_u64),
+        }
     }
+    // callers check `max_pages` first, a format 0 header keeps the low 32 bits
     #[inline(always)]
-    pub fn set_num_pages(&mut self, v: u32) {
-        self.num_pages_u32 = v.to_le_bytes();
+    pub fn set_num_pages(&mut self, v: u64) {
+        match self.format_version {
+            0 => self.num_pages_u32 = (v as u32).to_le_bytes(),
+            _ => self.num_pages_u64 = v.to_le_bytes(),
+        }
     }
     //
     #[inline(always)]
-    pub fn page_cursor(&self) -> u32 {
-        u32::from_le_bytes(self.page_cursor_u32)
+    pub fn page_cursor(&self) -> u64 {
+        match self.format_version {
+            0 => u32::from_le_bytes(self.page_cursor_u32) as u64,
+            _ => u64::from_le_bytes(self.page_cursor_u64),
+        }
     }
     #[inline(always)]
-    pub fn set_page_cursor(&mut self, v: u32) {
-        self.page_cursor_u32 = v.to_le_bytes();
+    pub fn set_page_cursor(&mut self, v: u64) {
+        match self.format_version {
+            0 => self.page_cursor_u32 = (v as u32).to_le_bytes(),
+            _ => self.page_cursor_u64 = v.to_le_bytes(),
+        }
     }
     //
     #[inline(always)]
     pub fn inc_page_cursor(&mut self) {
-        unsafe {
-            let ptr = self.page_cursor_u32.as_mut_ptr() as *mut u32;
-            let val = ptr.read_unaligned();
-            ptr.write_unaligned(val.wrapping_add(1));
-        }
+        self.set_page_cursor(self.page_cursor().wrapping_add(1));
     }
     #[inline(always)]
     pub fn dec_page_cursor(&mut self) {
-        unsafe {
-            let ptr = self.page_cursor_u32.as_mut_ptr() as *mut u32;
-            let val = ptr.read_unaligned();
-            ptr.write_unaligned(val.wrapping_sub(1));
+        self.set_page_cursor(self.page_cursor().wrapping_sub(1));
+    }
+    // format 0 headers have no room for it, retention walks those ledgers from page 0 every run
+    #[inline(always)]
+    pub fn retired_pages(&self) -> u64 {
+        match self.format_version {
+            0 => 0,
+            _ => u64::from_le_bytes(self.retired_pages_u64),
+        }
+    }
+    #[inline(always)]
+    pub fn set_retired_pages(&mut self, v: u64) {
+        if self.format_version != 0 {
+            self.retired_pages_u64 = v.to_le_bytes();
         }
     }
     //
     #[inline(always)]
-    pub fn num_rows(&self, rows_per_page: u32) -> u32 {
-        ((self.num_pages()) * rows_per_page) + self.page_cursor()
+    pub fn key_id(&self) -> [u8; 4] {
+        self.key_id
+    }
+    #[inline(always)]
+    pub fn salt(&self) -> [u8; 8] {
+        self.salt
+    }
+    #[inline(always)]
+    pub fn set_encryption(&mut self, key_id: [u8; 4], salt: [u8; 8]) {
+        self.key_id = key_id;
+        self.salt = salt;
+    }
+    //
+    #[inline(always)]
+    pub fn num_rows(&self, rows_per_page: u32) -> u64 {
+        ((self.num_pages()) * rows_per_page as u64) + self.page_cursor()
     }
     //
     #[inline(always)]
@@ -254,10 +389,14 @@ impl LedgerHeader {
     pub fn access_mut<'a>(f: &'a mut [u8]) -> Result<Seal<'a, Self>, rkyv::rancor::Error> {
         rkyv::access_mut::<Self, rkyv::rancor::Error>(f)
     }
+    /// # Safety
+    /// `f` must hold a valid archived header, the bytes aren't validated.
     #[inline(always)]
     pub unsafe fn access_unchecked(f: &[u8]) -> &Self {
         unsafe { rkyv::access_unchecked::<Self>(f) }
     }
+    /// # Safety
+    /// `f` must hold a valid archived header, the bytes aren't validated.
     #[inline(always)]
     pub unsafe fn access_unchecked_mut<'a>(f: &'a mut [u8]) -> Seal<'a, Self> {
         unsafe { rkyv::access_unchecked_mut::<Self>(f) }
@@ -311,6 +450,7 @@ impl LedgerName {
         s.parse()
     }
 
+    #[allow(clippy::inherent_to_string_shadow_display)]
     pub fn to_string(&self) -> String {
         URL_SAFE_NO_PAD.encode(self.0)
     }
@@ -413,6 +553,7 @@ impl LedgerDescription {
         desc
     }
 
+    #[allow(clippy::inherent_to_string_shadow_display)]
     pub fn to_string(&self) -> String {
         URL_SAFE_NO_PAD.encode(self.0)
     }
@@ -0,0 +1,351 @@
+use crate::ledger::RowId;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt;
+use std::io::BufRead;
+use std::str::FromStr;
+
+//
+// ###### IMPORT ######
+//
+// the inverse of export. every record is turned into a row by `PageSchema::from_record`, which
+// #[ledger] generates: each field is looked up by name, so the columns can come in any order and
+// extra ones (like the exported `row_id`) are ignored. ints and floats are parsed from their text,
+// `[u8; N]` fields from hex and other arrays from a json array, the way export writes them.
+//
+// a record that doesn't parse is reported with its line and skipped, the good rows are inserted
+// in batches, one transaction per batch.
+//
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportOptions {
+    pub on_overflow: OnOverflow,
+    // rows inserted per transaction
+    pub batch_size: usize,
+    // stop at the first bad record, the rows before it are still inserted
+    pub stop_on_error: bool,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            on_overflow: OnOverflow::Error,
+            batch_size: 1024,
+            stop_on_error: false,
+        }
+    }
+}
+//
+// what to do with a string longer than its field's max_len
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnOverflow {
+    #[default]
+    Error,
+    // cut at the last char boundary that fits
+    Truncate,
+}
+//
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImportReport {
+    // data records read, the csv header row isn't counted
+    pub records: u64,
+    pub imported: u64,
+    // row id of the first and last imported row
+    pub first_row: Option<RowId>,
+    pub last_row: Option<RowId>,
+    pub errors: Vec<ImportError>,
+}
+
+impl ImportReport {
+    #[inline(always)]
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+//
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImportError {
+    // 1 based, the line the record starts on
+    pub line: u64,
+    pub field: Option<String>,
+    pub reason: String,
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.field {
+            Some(field) => write!(f, "line {}, field {field}: {}", self.line, self.reason),
+            None => write!(f, "line {}: {}", self.line, self.reason),
+        }
+    }
+}
+
+//
+// ###### RECORD ######
+//
+// one csv line or json object, read field by field by the generated `from_record`
+//
+pub struct Record<'a> {
+    line: u64,
+    on_overflow: OnOverflow,
+    values: RecordValues<'a>,
+}
+//
+enum RecordValues<'a> {
+    Csv {
+        columns: &'a HashMap<String, usize>,
+        cells: &'a [String],
+    },
+    Json(&'a serde_json::Map<String, Value>),
+}
+
+impl<'a> Record<'a> {
+    pub(crate) fn csv(
+        line: u64,
+        on_overflow: OnOverflow,
+        columns: &'a HashMap<String, usize>,
+        cells: &'a [String],
+    ) -> Self {
+        Self {
+            line,
+            on_overflow,
+            values: RecordValues::Csv { columns, cells },
+        }
+    }
+    //
+    pub(crate) fn json(
+        line: u64,
+        on_overflow: OnOverflow,
+        object: &'a serde_json::Map<String, Value>,
+    ) -> Self {
+        Self {
+            line,
+            on_overflow,
+            values: RecordValues::Json(object),
+        }
+    }
+    //
+    #[inline(always)]
+    pub fn line(&self) -> u64 {
+        self.line
+    }
+    //
+    pub fn error(&self, field: Option<&str>, reason: impl Into<String>) -> ImportError {
+        ImportError {
+            line: self.line,
+            field: field.map(str::to_string),
+            reason: reason.into(),
+        }
+    }
+    //
+    // the field's text. json strings are taken as they are, other json values as their json text.
+    // null reads as NaN, what export writes for a float that isn't finite
+    pub fn text(&self, field: &str) -> Result<Cow<'a, str>, ImportError> {
+        let missing = || self.error(Some(field), "missing");
+        match &self.values {
+            RecordValues::Csv { columns, cells } => columns
+                .get(field)
+                .and_then(|column| cells.get(*column))
+                .map(|cell| Cow::Borrowed(cell.as_str()))
+                .ok_or_else(missing),
+            RecordValues::Json(object) => match object.get(field).ok_or_else(missing)? {
+                Value::String(s) => Ok(Cow::Borrowed(s.as_str())),
+                Value::Null => Ok(Cow::Borrowed("NaN")),
+                value => Ok(Cow::Owned(value.to_string())),
+            },
+        }
+    }
+    //
+    // ints, floats, bools and chars, parsed from their text
+    pub fn value<V>(&self, field: &str) -> Result<V, ImportError>
+    where
+        V: FromStr,
+        V::Err: fmt::Display,
+    {
+        let text = self.text(field)?;
+        text.trim()
+            .parse()
+            .map_err(|e| self.error(Some(field), format!("{text:?}: {e}")))
+    }
+    //
+    // a #[max_len] string, over max_len bytes is an error or cut depending on `on_overflow`
+    pub fn string(&self, field: &str, max_len: usize) -> Result<Cow<'a, str>, ImportError> {
+        let text = self.text(field)?;
+        if text.len() <= max_len {
+            return Ok(text);
+        }
+        match self.on_overflow {
+            OnOverflow::Error => Err(self.error(
+                Some(field),
+                format!("{} bytes, max_len is {max_len}", text.len()),
+            )),
+            OnOverflow::Truncate => {
+                let end = (0..=max_len)
+                    .rev()
+                    .find(|end| text.is_char_boundary(*end))
+                    .unwrap_or(0);
+                Ok(match text {
+                    Cow::Borrowed(s) => Cow::Borrowed(&s[..end]),
+                    Cow::Owned(s) => Cow::Owned(s[..end].to_string()),
+                })
+            }
+        }
+    }
+    //
+    // a `[u8; N]` field, written as hex by export
+    pub fn bytes<const N: usize>(&self, field: &str) -> Result<[u8; N], ImportError> {
+        let text = self.text(field)?;
+        let text = text.trim();
+        if text.len() != N * 2 {
+            return Err(self.error(
+                Some(field),
+                format!("{} hex digits, expected {}", text.len(), N * 2),
+            ));
+        }
+        let mut bytes = [0_u8; N];
+        for (n, byte) in bytes.iter_mut().enumerate() {
+            *byte = text
+                .get(n * 2..n * 2 + 2)
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                .ok_or_else(|| self.error(Some(field), format!("{text:?} isn't hex")))?;
+        }
+        Ok(bytes)
+    }
+    //
+    // any other array, a json array in both formats
+    pub fn array<V, const N: usize>(&self, field: &str) -> Result<[V; N], ImportError>
+    where
+        V: FromStr,
+        V::Err: fmt::Display,
+    {
+        let values: Vec<Value> = match &self.values {
+            RecordValues::Json(object) => match object.get(field) {
+                Some(Value::Array(values)) => values.clone(),
+                Some(_) => return Err(self.error(Some(field), "not a json array")),
+                None => return Err(self.error(Some(field), "missing")),
+            },
+            RecordValues::Csv { .. } => serde_json::from_str(&self.text(field)?)
+                .map_err(|e| self.error(Some(field), format!("not a json array: {e}")))?,
+        };
+        if values.len() != N {
+            return Err(self.error(
+                Some(field),
+                format!("{} elements, expected {N}", values.len()),
+            ));
+        }
+        //
+        let mut parsed = Vec::with_capacity(N);
+        for value in values {
+            let text = match value {
+                Value::String(s) => s,
+                Value::Null => "NaN".to_string(),
+                value => value.to_string(),
+            };
+            parsed.push(
+                text.parse()
+                    .map_err(|e| self.error(Some(field), format!("{text:?}: {e}")))?,
+            );
+        }
+        Ok(parsed.try_into().unwrap_or_else(|_| unreachable!()))
+    }
+    //
+    // for field types import doesn't know how to parse
+    pub fn unsupported(&self, field: &str) -> Result<Infallible, ImportError> {
+        Err(self.error(Some(field), "this field type can't be imported"))
+    }
+}
+
+//
+// ###### CSV ######
+//
+// rfc 4180: cells holding a separator, a quote or a line break are quoted, a quote inside one is
+// doubled. a quoted cell can span lines
+//
+// (line the record starts on, its cells or why they couldn't be read)
+pub(crate) type CsvRecord = (u64, Result<Vec<String>, String>);
+//
+pub(crate) struct CsvReader<R> {
+    reader: R,
+    line: u64,
+}
+
+impl<R: BufRead> CsvReader<R> {
+    pub(crate) fn new(reader: R) -> Self {
+        Self { reader, line: 0 }
+    }
+    //
+    // None at the end of the input
+    pub(crate) fn next_record(&mut self) -> std::io::Result<Option<CsvRecord>> {
+        let mut record = String::new();
+        let mut first_line = self.line + 1;
+        loop {
+            if self.reader.read_line(&mut record)? == 0 {
+                return Ok(match record.is_empty() {
+                    true => None,
+                    false => Some((first_line, Err("unterminated quoted cell".to_string()))),
+                });
+            }
+            self.line += 1;
+            if record.trim_end_matches(['\r', '\n']).is_empty() {
+                // blank lines between records are skipped
+                record.clear();
+                first_line = self.line + 1;
+                continue;
+            }
+            // an odd number of quotes so far means a quoted cell goes on on the next line
+            if record.matches('"').count().is_multiple_of(2) {
+                break;
+            }
+        }
+        let record = record
+            .strip_suffix('\n')
+            .map(|r| r.strip_suffix('\r').unwrap_or(r))
+            .unwrap_or(&record);
+        Ok(Some((first_line, parse_csv_record(record))))
+    }
+}
+//
+fn parse_csv_record(record: &str) -> Result<Vec<String>, String> {
+    let mut cells = Vec::new();
+    let mut chars = record.chars().peekable();
+    loop {
+        let mut cell = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        cell.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => cell.push(c),
+                    None => return Err("unterminated quoted cell".to_string()),
+                }
+            }
+            match chars.next() {
+                Some(',') => cells.push(cell),
+                None => {
+                    cells.push(cell);
+                    return Ok(cells);
+                }
+                Some(c) => return Err(format!("{c:?} after a quoted cell")),
+            }
+        } else {
+            loop {
+                match chars.next() {
+                    Some(',') => break,
+                    Some('"') => return Err("quote inside an unquoted cell".to_string()),
+                    Some(c) => cell.push(c),
+                    None => {
+                        cells.push(cell);
+                        return Ok(cells);
+                    }
+                }
+            }
+            cells.push(cell);
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use crate::bitmap::{BitmapIndex, RowSet};
+use crate::fulltext::FullTextIndex;
+use crate::ledger::RowId;
+use crate::page::PageSchema;
+use crate::utils::DatastoreError;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+//
+// ###### SECONDARY INDEXES ######
+//
+// every index declared on the row schema, kept in memory and persisted as sidecar files
+// (`<ledger>.<field>.<ext>`) next to the ledger file. a `<ledger>.indexes.dirty` marker exists
+// while the in-memory indexes are ahead of the sidecars, so a crash forces a rebuild on open.
+//
+#[derive(Debug, Default)]
+pub struct Indexes {
+    ledger_path: PathBuf,
+    //
+    fulltext: Vec<(&'static str, FullTextIndex)>,
+    bitmap: Vec<(&'static str, BitmapIndex)>,
+    //
+    dirty: AtomicBool,
+    // the dirty marker was left behind by a previous process
+    unclean: bool,
+    // false for read-only ledgers, which rebuild their indexes in memory and never write sidecars
+    persist: bool,
+}
+
+impl Indexes {
+    pub fn load<T: PageSchema>(ledger_path: &Path) -> Self {
+        let fulltext = T::FULLTEXT_FIELDS
+            .iter()
+            .map(|field| {
+                let path = sidecar_path(ledger_path, field, "fts");
+                (*field, load_or_default(&path, FullTextIndex::load))
+            })
+            .collect();
+        //
+        let bitmap = T::BITMAP_FIELDS
+            .iter()
+            .map(|field| {
+                let path = sidecar_path(ledger_path, field, "bmi");
+                (*field, load_or_default(&path, BitmapIndex::load))
+            })
+            .collect();
+        //
+        let unclean = dirty_marker_path(ledger_path).exists();
+        //
+        Self {
+            ledger_path: ledger_path.to_path_buf(),
+            fulltext,
+            bitmap,
+            dirty: AtomicBool::new(false),
+            unclean,
+            persist: true,
+        }
+    }
+    //
+    // empty indexes for every indexed field, never loaded from or saved to disk
+    pub fn in_memory<T: PageSchema>() -> Self {
+        Self {
+            fulltext: T::FULLTEXT_FIELDS
+                .iter()
+                .map(|field| (*field, FullTextIndex::new()))
+                .collect(),
+            bitmap: T::BITMAP_FIELDS
+                .iter()
+                .map(|field| (*field, BitmapIndex::new()))
+                .collect(),
+            ..Self::default()
+        }
+    }
+    //
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.fulltext.is_empty() && self.bitmap.is_empty()
+    }
+    //
+    // true when an index has not seen every row in the ledger (crash before flush, new attribute)
+    pub fn is_stale(&self, num_rows: u64) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        self.unclean
+            || self.fulltext.iter().any(|(_, i)| i.watermark() != num_rows)
+            || self.bitmap.iter().any(|(_, i)| i.watermark() != num_rows)
+    }
+    //
+    pub fn clear(&mut self) {
+        for (_, index) in self.fulltext.iter_mut() {
+            *index = FullTextIndex::new();
+        }
+        for (_, index) in self.bitmap.iter_mut() {
+            *index = BitmapIndex::new();
+        }
+        self.unclean = false;
+        self.mark_dirty();
+    }
+    //
+    // forces a rebuild on the next open, e.g. after rows were restored underneath the indexes
+    pub fn invalidate(&mut self) {
+        self.unclean = true;
+        self.mark_dirty();
+    }
+    //
+    fn mark_dirty(&self) {
+        if self.is_empty() || !self.persist || self.dirty.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        if let Err(e) = std::fs::File::create(dirty_marker_path(&self.ledger_path)) {
+            tracing::error!("couldn't create index dirty marker. error:\n{e}");
+        }
+    }
+    //
+    pub fn insert_row<T: PageSchema>(&mut self, row_id: RowId, row: &T) {
+        self.mark_dirty();
+        for (field, index) in self.fulltext.iter_mut() {
+            index.insert(row_id.get(), row.fulltext_value(field).unwrap_or_default());
+        }
+        for (field, index) in self.bitmap.iter_mut() {
+            if let Some(value) = row.bitmap_value(field) {
+                index.insert(row_id.get(), value);
+            }
+        }
+    }
+    //
+    pub fn remove_row<T: PageSchema>(&mut self, row_id: RowId, row: &T) {
+        self.mark_dirty();
+        for (field, index) in self.fulltext.iter_mut() {
+            index.remove(row_id.get(), row.fulltext_value(field).unwrap_or_default());
+        }
+        for (field, index) in self.bitmap.iter_mut() {
+            if let Some(value) = row.bitmap_value(field) {
+                index.remove(row_id.get(), value);
+            }
+        }
+    }
+    //
+    pub fn fulltext(&self, field: &str) -> Result<&FullTextIndex, DatastoreError> {
+        self.fulltext
+            .iter()
+            .find(|(f, _)| *f == field)
+            .map(|(_, index)| index)
+            .ok_or_else(|| DatastoreError::IndexNotFound(field.to_string()))
+    }
+    //
+    pub fn bitmap(&self, field: &str) -> Result<&BitmapIndex, DatastoreError> {
+        self.bitmap
+            .iter()
+            .find(|(f, _)| *f == field)
+            .map(|(_, index)| index)
+            .ok_or_else(|| DatastoreError::IndexNotFound(field.to_string()))
+    }
+    //
+    pub fn rows_eq(&self, field: &str, value: u64) -> Result<RowSet, DatastoreError> {
+        Ok(self.bitmap(field)?.rows_eq(value))
+    }
+    //
+    // writes the sidecars if anything changed since the last save
+    pub fn save(&self) -> std::io::Result<()> {
+        if !self.persist || !self.dirty.load(Ordering::Acquire) {
+            return Ok(());
+        }
+        //
+        for (field, index) in &self.fulltext {
+            index.save(&sidecar_path(&self.ledger_path, field, "fts"))?;
+        }
+        for (field, index) in &self.bitmap {
+            index.save(&sidecar_path(&self.ledger_path, field, "bmi"))?;
+        }
+        //
+        if self.unclean {
+            // rows were restored underneath the indexes, leave the marker for the next open
+            return Ok(());
+        }
+        //
+        match std::fs::remove_file(dirty_marker_path(&self.ledger_path)) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        self.dirty.store(false, Ordering::Release);
+        Ok(())
+    }
+}
+
+// #### UTILS
+
+pub fn sidecar_path(ledger_path: &Path, field: &str, ext: &str) -> PathBuf {
+    let mut name = ledger_path.as_os_str().to_os_string();
+    name.push(format!(".{field}.{ext}"));
+    PathBuf::from(name)
+}
+//
+fn dirty_marker_path(ledger_path: &Path) -> PathBuf {
+    sidecar_path(ledger_path, "indexes", "dirty")
+}
+//
+// a missing or unreadable sidecar starts empty, the stale check on open then rebuilds it
+fn load_or_default<I: Default>(path: &Path, load: fn(&Path) -> std::io::Result<I>) -> I {
+    match load(path) {
+        Ok(index) => index,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => I::default(),
+        Err(e) => {
+            tracing::error!("couldn't load index {:?}, rebuilding. error:\n{e}", path);
+            I::default()
+        }
+    }
+}
@@ -0,0 +1,446 @@
+use crate::compression::CompressedPages;
+use crate::export::{self, FieldValue};
+use crate::header::{FORMAT_VERSION, HeaderFlags, LedgerHeader, PageHeader};
+use crate::ledger::{PAGE_HEADER_SZ, RowId};
+use crate::page::TOMBSTONE;
+use crate::schema::LedgerSchema;
+use crate::signing::SignedCheckpoint;
+use crate::utils::DatastoreError;
+use crate::verify::{Anomaly, VerifyReport};
+use memmap2::Mmap;
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+//
+const LEDGER_HEADER_SZ: usize = size_of::<LedgerHeader>();
+const RAW_FIELD_NAMES: &[&str] = &["bytes"];
+//
+// ###### RAW LEDGER ######
+//
+// read-only view of a ledger file that doesn't need its row type. the page size is worked out
+// from the file length and the page count in the header, the row size from how far the row heap
+// of a page has grown (rows are packed back to back from the end of the page). both can be
+// given explicitly when the file is too damaged to tell.
+//
+// format 2 ledgers carry the schema of their row type, which gives both sizes and lets rows be
+// decoded field by field.
+//
+// compressed pages are read back from `<ledger>.lz4`. pages of an encrypted ledger are sealed,
+// only the header can be read.
+//
+pub struct RawLedger {
+    path: PathBuf,
+    mmap: Mmap,
+    header_sz: usize,
+    page_size: usize,
+    row_size: Option<usize>,
+    schema: Option<LedgerSchema>,
+    compressed: CompressedPages,
+}
+//
+// slot of a row within its page
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawSlot {
+    Row(u32),
+    Deleted,
+}
+
+impl RawLedger {
+    pub fn open<P>(path: P) -> Result<Self, DatastoreError>
+    where
+        P: AsRef<Path>,
+    {
+        Self::open_with(path, None, None)
+    }
+    //
+    // `page_size` and `row_size` override what would be inferred from the file
+    pub fn open_with<P>(
+        path: P,
+        page_size: Option<usize>,
+        row_size: Option<usize>,
+    ) -> Result<Self, DatastoreError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref().to_path_buf();
+        let file = File::open(&path)?;
+        // read-only mapping of a file nothing else should be writing to while it's inspected
+        let mmap = unsafe { Mmap::map(&file)? };
+        if mmap.len() < LEDGER_HEADER_SZ {
+            return Err(format!("{path:?} is too short to hold a ledger header").into());
+        }
+        //
+        let header = LedgerHeader::access(&mmap[0..LEDGER_HEADER_SZ])?;
+        if header.format_version() > FORMAT_VERSION {
+            return Err(DatastoreError::UnsupportedFormat(header.format_version()));
+        }
+        let header_sz = header.size();
+        let num_pages = header.num_pages() as usize;
+        if mmap.len() < header_sz {
+            return Err(format!("{path:?} is too short to hold its schema page").into());
+        }
+        // a damaged schema page only costs the decoding, the sizes can still be inferred
+        let schema_page = &mmap[LEDGER_HEADER_SZ..LEDGER_HEADER_SZ + header.schema_size()];
+        let (schema, schema_page_size) = match header.schema_size() {
+            0 => (None, None),
+            _ => match LedgerSchema::from_page(schema_page) {
+                Ok((schema, page_size)) => (Some(schema), Some(page_size)),
+                Err(e) => {
+                    tracing::warn!("{path:?}: {e}");
+                    (None, None)
+                }
+            },
+        };
+        let row_size = row_size.or(schema
+            .as_ref()
+            .map(|schema| schema.row_size)
+            .filter(|row_size| *row_size > 0));
+        //
+        let page_size = match page_size.or(schema_page_size) {
+            Some(page_size) => page_size,
+            // the first page's slots, then the file length. the slots still tell when the file
+            // was cut short or has trailing bytes
+            None => match Self::infer_page_size(&mmap[header_sz..]) {
+                Some(page_size) => page_size,
+                None => {
+                    let body = mmap.len() - header_sz;
+                    if num_pages == 0
+                        || !body.is_multiple_of(num_pages)
+                        || body / num_pages <= PAGE_HEADER_SZ as usize
+                    {
+                        return Err(format!(
+                            "{path:?}: {body} bytes of pages don't split into {num_pages} pages, give the page size"
+                        )
+                        .into());
+                    }
+                    body / num_pages
+                }
+            },
+        };
+        //
+        let mut raw = Self {
+            compressed: CompressedPages::open_read_only(&path)?,
+            path,
+            mmap,
+            header_sz,
+            page_size,
+            row_size,
+            schema,
+        };
+        if raw.row_size.is_none() && !raw.is_encrypted()? {
+            raw.row_size = raw.infer_row_size()?;
+        }
+        //
+        Ok(raw)
+    }
+    //
+    // row n of a page sits at `page_size - (n + 1) * row_size` and the heap ends at the last
+    // one, so two slots at different positions give both sizes
+    fn infer_page_size(body: &[u8]) -> Option<usize> {
+        let header = Self::parse_page_header(body.get(0..PAGE_HEADER_SZ as usize)?)?;
+        let slot_count = header.slot_count() as usize;
+        if slot_count == 0
+            || header.free_start() as usize != PAGE_HEADER_SZ as usize + slot_count * 4
+        {
+            return None;
+        }
+        let mut points: Vec<(usize, usize)> = (0..slot_count)
+            .map_while(|n| {
+                let at = PAGE_HEADER_SZ as usize + n * 4;
+                let offset = u32::from_le_bytes(body.get(at..at + 4)?.try_into().unwrap());
+                Some((n, offset as usize))
+            })
+            .filter(|(_, offset)| *offset != TOMBSTONE)
+            .collect();
+        points.push((slot_count - 1, header.free_end() as usize));
+        //
+        let (first, last) = (points.first()?, points.last()?);
+        if last.0 <= first.0 || first.1 <= last.1 {
+            return None;
+        }
+        let (spread, rows) = (first.1 - last.1, last.0 - first.0);
+        if !spread.is_multiple_of(rows) {
+            return None;
+        }
+        let page_size = first.1 + (first.0 + 1) * (spread / rows);
+        (page_size > PAGE_HEADER_SZ as usize).then_some(page_size)
+    }
+    //
+    // the first page holding rows tells the row size: the heap ends `slot_count` rows before
+    // the end of the page
+    fn infer_row_size(&self) -> Result<Option<usize>, DatastoreError> {
+        for page_id in 0..self.num_pages()? {
+            let page = self.page(page_id)?;
+            let Some(header) = Self::parse_page_header(&page) else {
+                continue;
+            };
+            let (slot_count, free_end) = (header.slot_count() as usize, header.free_end() as usize);
+            if slot_count == 0 || free_end > self.page_size {
+                continue;
+            }
+            let heap = self.page_size - free_end;
+            if heap.is_multiple_of(slot_count) {
+                return Ok(Some(heap / slot_count));
+            }
+        }
+        Ok(None)
+    }
+
+    //
+    // ###### HEADER ######
+    //
+
+    #[inline(always)]
+    pub fn header(&self) -> Result<&LedgerHeader, DatastoreError> {
+        Ok(LedgerHeader::access(&self.mmap[0..LEDGER_HEADER_SZ])?)
+    }
+    //
+    #[inline(always)]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    // bytes before the first page
+    #[inline(always)]
+    pub fn header_size(&self) -> usize {
+        self.header_sz
+    }
+    //
+    #[inline(always)]
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+    // None for a ledger without rows, or an encrypted one
+    #[inline(always)]
+    pub fn row_size(&self) -> Option<usize> {
+        self.row_size
+    }
+    // the row type's schema, None before format 2
+    #[inline(always)]
+    pub fn schema(&self) -> Option<&LedgerSchema> {
+        self.schema.as_ref().filter(|schema| !schema.is_empty())
+    }
+    //
+    // the checkpoint kept in the file, format 3 and later
+    pub fn signed_checkpoint(&self) -> Result<Option<SignedCheckpoint>, DatastoreError> {
+        Ok(self
+            .header()?
+            .checkpoint_slot()
+            .and_then(|slot| SignedCheckpoint::from_slot(self.mmap.get(slot)?)))
+    }
+    //
+    #[inline(always)]
+    pub fn file_len(&self) -> usize {
+        self.mmap.len()
+    }
+    //
+    pub fn num_pages(&self) -> Result<usize, DatastoreError> {
+        Ok(self.header()?.num_pages() as usize)
+    }
+    //
+    pub fn is_encrypted(&self) -> Result<bool, DatastoreError> {
+        Ok(self.header()?.flags.is_set(HeaderFlags::Encrypted))
+    }
+    //
+    pub fn max_rows_per_page(&self) -> Option<usize> {
+        self.row_size
+            .map(|row_size| (self.page_size - PAGE_HEADER_SZ as usize) / (row_size + 4))
+    }
+
+    //
+    // structural checks without the row type, the rows themselves aren't validated. the pages of
+    // an encrypted ledger are sealed, only its header and file length are checked
+    pub fn verify(&self) -> Result<VerifyReport, DatastoreError> {
+        let mut report = VerifyReport::new(&self.path);
+        let header = self.header()?;
+        report.encrypted = header.flags.is_set(HeaderFlags::Encrypted);
+        let num_pages = report.check_layout(
+            header,
+            self.header_sz,
+            self.page_size,
+            self.mmap.len() as u64,
+        );
+        if report.encrypted {
+            return Ok(report);
+        }
+        //
+        let page_cursor = header.page_cursor();
+        for page_id in 0..num_pages {
+            match self.page(page_id as usize) {
+                Ok(page) => {
+                    report.check_page(page_id, &page, self.row_size, page_id > page_cursor);
+                }
+                Err(e) => report.push(Anomaly::UnreadablePage {
+                    page_id,
+                    reason: e.to_string(),
+                }),
+            }
+        }
+        Ok(report)
+    }
+
+    //
+    // ###### PAGES ######
+    //
+
+    // the page's bytes, decompressed when the page lives in the compressed segment
+    pub fn page(&self, page_id: usize) -> Result<Cow<'_, [u8]>, DatastoreError> {
+        let start = self.header_sz + page_id * self.page_size;
+        if page_id >= self.num_pages()? || start + self.page_size > self.mmap.len() {
+            return Err(crate::utils::PageError::PageIdOutOfBounds.into());
+        }
+        //
+        if self.compressed.contains(page_id) {
+            let mut page = vec![0_u8; self.page_size];
+            self.compressed.read_into(page_id, &mut page)?;
+            return Ok(Cow::Owned(page));
+        }
+        Ok(Cow::Borrowed(&self.mmap[start..start + self.page_size]))
+    }
+    //
+    #[inline(always)]
+    pub fn is_compressed(&self, page_id: usize) -> bool {
+        self.compressed.contains(page_id)
+    }
+    //
+    // None when the page header is all zeros, a page that was never written or punched out
+    pub fn page_header(&self, page_id: usize) -> Result<Option<PageHeader>, DatastoreError> {
+        Ok(Self::parse_page_header(&self.page(page_id)?).cloned())
+    }
+    //
+    fn parse_page_header(page: &[u8]) -> Option<&PageHeader> {
+        let bytes = &page[0..PAGE_HEADER_SZ as usize];
+        if bytes.iter().all(|b| *b == 0) {
+            return None;
+        }
+        PageHeader::access(bytes).ok()
+    }
+    //
+    // the offsets in the page's slot array, clamped to the slots that fit in the page
+    pub fn slots(&self, page_id: usize) -> Result<Vec<RawSlot>, DatastoreError> {
+        let page = self.page(page_id)?;
+        let Some(header) = Self::parse_page_header(&page) else {
+            return Ok(Vec::new());
+        };
+        let max_slots = (self.page_size - PAGE_HEADER_SZ as usize) / 4;
+        let slot_count = (header.slot_count() as usize).min(max_slots);
+        //
+        Ok((0..slot_count)
+            .map(|n| {
+                let at = PAGE_HEADER_SZ as usize + n * 4;
+                match u32::from_le_bytes(page[at..at + 4].try_into().unwrap()) {
+                    offset if offset as usize == TOMBSTONE => RawSlot::Deleted,
+                    offset => RawSlot::Row(offset),
+                }
+            })
+            .collect())
+    }
+
+    //
+    // ###### ROWS ######
+    //
+
+    // (page id, row within the page), needs the row size
+    pub fn locate(&self, row_id: RowId) -> Result<(usize, usize), DatastoreError> {
+        let max_rows = self
+            .max_rows_per_page()
+            .ok_or("row size is unknown, give it explicitly")? as u64;
+        Ok((
+            (row_id.get() / max_rows) as usize,
+            (row_id.get() % max_rows) as usize,
+        ))
+    }
+    //
+    // the stored bytes of a row, None for a deleted one
+    pub fn row_bytes(&self, row_id: RowId) -> Result<Option<Vec<u8>>, DatastoreError> {
+        let row_size = self
+            .row_size
+            .ok_or("row size is unknown, give it explicitly")?;
+        let (page_id, page_row_n) = self.locate(row_id)?;
+        //
+        let slots = self.slots(page_id)?;
+        match slots.get(page_row_n) {
+            None => Err(crate::utils::PageError::RowNotFound.into()),
+            Some(RawSlot::Deleted) => Ok(None),
+            Some(RawSlot::Row(offset)) => {
+                let (start, page) = (*offset as usize, self.page(page_id)?);
+                if start + row_size > page.len() {
+                    return Err(format!("row {row_id} points past the end of its page").into());
+                }
+                Ok(Some(page[start..start + row_size].to_vec()))
+            }
+        }
+    }
+
+    //
+    // ###### EXPORT ######
+    //
+
+    // rows are decoded with the stored schema, the same columns the row type would export.
+    // without one each row is exported as `row_id` and its stored bytes in hex. returns the
+    // number of rows written
+    pub fn export_csv<W: Write>(&self, writer: W) -> Result<u64, DatastoreError> {
+        let mut writer = BufWriter::new(writer);
+        export::write_csv_header(&mut writer, &self.field_names())?;
+        self.export_rows(&mut writer, |w, row_id, values| {
+            export::write_csv_row(w, row_id, values)
+        })
+    }
+    //
+    pub fn export_jsonl<W: Write>(&self, writer: W) -> Result<u64, DatastoreError> {
+        let mut writer = BufWriter::new(writer);
+        let field_names = self.field_names();
+        self.export_rows(&mut writer, |w, row_id, values| {
+            export::write_jsonl_row(w, &field_names, row_id, values)
+        })
+    }
+    //
+    fn field_names(&self) -> Vec<&str> {
+        match self.schema() {
+            Some(schema) => schema.fields.iter().map(|field| &*field.name).collect(),
+            None => RAW_FIELD_NAMES.to_vec(),
+        }
+    }
+    //
+    fn export_rows<W, F>(
+        &self,
+        writer: &mut BufWriter<W>,
+        mut write_row: F,
+    ) -> Result<u64, DatastoreError>
+    where
+        W: Write,
+        F: FnMut(&mut BufWriter<W>, RowId, &[FieldValue<'_>]) -> std::io::Result<()>,
+    {
+        if self.is_encrypted()? {
+            return Err(
+                "pages of an encrypted ledger are sealed, only the header can be read".into(),
+            );
+        }
+        let row_size = self
+            .row_size
+            .ok_or("row size is unknown, give it explicitly")?;
+        let max_rows = self.max_rows_per_page().unwrap_or(0) as u64;
+        //
+        let mut rows = 0;
+        for page_id in 0..self.num_pages()? {
+            let page = self.page(page_id)?;
+            for (page_row_n, slot) in self.slots(page_id)?.iter().enumerate() {
+                let RawSlot::Row(offset) = slot else {
+                    continue;
+                };
+                let row_id = RowId(page_id as u64 * max_rows + page_row_n as u64);
+                let Some(bytes) = page.get(*offset as usize..*offset as usize + row_size) else {
+                    return Err(format!("row {row_id} points past the end of its page").into());
+                };
+                match self.schema() {
+                    Some(schema) => write_row(writer, row_id, &schema.decode_row(bytes))?,
+                    None => write_row(writer, row_id, &[FieldValue::Bytes(bytes)])?,
+                }
+                rows += 1;
+            }
+        }
+        writer.flush()?;
+        Ok(rows)
+    }
+}
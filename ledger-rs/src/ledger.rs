@@ -1,16 +1,64 @@
-use crate::header::{LedgerDescription, LedgerHeader, LedgerName, PageHeader};
-use crate::page::{PageSchema, SlottedPage, page_sz};
-use crate::utils::{DatastoreError, PageError};
-use memmap2::MmapMut;
+#[cfg(feature = "arrow")]
+use crate::arrow::BatchBuilder;
+use crate::audit::{BrokenLink, ChainHash, GENESIS, chain_hash, stored_link};
+use crate::bitmap::RowSet;
+use crate::checkpoint::{
+    CheckpointKind, CheckpointManifest, checkpoint_dir, checkpoint_path, reflink, validate_name,
+};
+use crate::compression::{
+    CacheRef, CompressedPages, CompressionStats, PAGE_CACHE_PAGES, PageCache, compress_page,
+    punch_hole,
+};
+use crate::encryption::{EncryptionKey, PageCipher, SEAL_SZ, key_id, release_range};
+use crate::export::{self, FieldValue};
+use crate::header::{
+    CHECKPOINT_SLOT_SZ, FORMAT_VERSION, HeaderFlags, LedgerDescription, LedgerHeader, LedgerName,
+    PageHeader,
+};
+use crate::import::{CsvReader, ImportError, ImportOptions, ImportReport, Record};
+use crate::index::Indexes;
+use crate::merkle::{self, Hash, MerkleCache, Proof};
+use crate::page::{PageSchema, SlottedPage, TOMBSTONE, page_sz};
+#[cfg(feature = "parquet")]
+use crate::parquet::ParquetOptions;
+use crate::repair::{
+    JournalAction, RepairOptions, RepairReport, RepairedPage, deleted_page, header_from_slots,
+    set_page_header,
+};
+use crate::retention::{RetentionPolicy, RetentionStats, unix_now};
+use crate::schema::LedgerSchema;
+use crate::signing::{
+    BadCheckpoint, BadCheckpointReason, SignedCheckpoint, SigningKey, VerifyingKey,
+};
+use crate::snapshot::{Snapshot, SnapshotPages};
+use crate::transaction::{Journal, JournalImage, Transaction};
+use crate::utils::{DatastoreError, FileAt, PageError};
+use crate::vector::Neighbor;
+use crate::verify::{Anomaly, VerifyReport};
+#[cfg(feature = "arrow")]
+use arrow_ipc::writer::StreamWriter;
+use memmap2::{Mmap, MmapMut, MmapOptions};
+use parking_lot::{Mutex, RwLock};
+#[cfg(feature = "parquet")]
+use parquet::arrow::ArrowWriter;
 use rkyv::traits::NoUndef;
+use rkyv::util::AlignedVec;
 use rkyv::{Archive, Portable, access_unchecked, access_unchecked_mut};
 use rkyv::{access, access_mut, seal::Seal};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeSet, BinaryHeap, HashMap};
+use std::fmt;
 use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
 use std::marker::PhantomData;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
 
 //
 pub const PAGE_HEADER_SZ: u32 = size_of::<PageHeader>() as u32;
+// pages compressed and committed to the segment together
+const COMPRESS_BATCH_PAGES: usize = 256;
 //
 // DATA LEDGER
 //
@@ -18,10 +66,53 @@ pub struct DataLedgerStore<T, const PAGESZ: usize, const ROWS_PER_PAGE: usize> {
     //
     file: File,
     mmap: MmapMut,
+    path: PathBuf,
+    // bytes before the first page, depends on the format version of the file
+    header_sz: usize,
+    //
+    indexes: Indexes,
+    // undo journal of the running transaction
+    journal: Option<Journal>,
+    // hashes of pages that haven't changed since they were hashed
+    merkle: Mutex<MerkleCache>,
+    // key for signed checkpoints and how many filled pages apart they are taken (0 = manual only)
+    signer: Option<(SigningKey, u32)>,
+    // undo log of the newest checkpoint that isn't a reflink
+    checkpoint_log: Option<Journal>,
+    // opened from a checkpoint. the mapping is private and every checked mutation fails
+    read_only: bool,
+    // pre-image stores of the live snapshots, pruned as snapshots are dropped
+    snapshots: Mutex<Vec<Weak<SnapshotPages>>>,
+    // pages moved into the compressed segment, shared with snapshots
+    compressed: Arc<RwLock<CompressedPages>>,
+    // decompressed pages handed out by `access_page`. entries are only dropped through &mut self
+    page_cache: Mutex<PageCache>,
+    // encrypted ledgers. `mmap` is then a plaintext copy in anonymous memory, not the file
+    cipher: Option<PageCipher>,
+    // expiry applied by `run_retention`, the row type's #[ledger(ttl)] unless replaced
+    retention: Option<RetentionPolicy<T>>,
     //
     phantom: PhantomData<T>,
 }
 //
+impl<T, const PAGESZ: usize, const ROWS_PER_PAGE: usize> Drop
+    for DataLedgerStore<T, PAGESZ, ROWS_PER_PAGE>
+{
+    fn drop(&mut self) {
+        // the plaintext copy of an encrypted ledger is never written back by the OS
+        if let Some(cipher) = self.cipher.as_ref()
+            && let Err(e) = cipher.flush(&self.file, &self.mmap)
+        {
+            tracing::error!("couldn't seal pages of {:?}. error:\n{e}", self.path);
+        }
+        if let Err(e) = self.indexes.save() {
+            tracing::error!("couldn't save indexes for {:?}. error:\n{e}", self.path);
+        }
+        if let Err(e) = self.merkle.get_mut().save() {
+            tracing::error!("couldn't save page hashes for {:?}. error:\n{e}", self.path);
+        }
+    }
+}
 
 //
 impl<T, const PAGESZ: usize, const ROWS_PER_PAGE: usize> DataLedgerStore<T, PAGESZ, ROWS_PER_PAGE>
@@ -40,7 +131,7 @@ where
         >,
     SlottedPage<T, PAGESZ, ROWS_PER_PAGE>: NoUndef + std::marker::Unpin,
 {
-    const PAGE_SIZE: u32 = page_sz::<T>();
+    pub(crate) const PAGE_SIZE: u32 = page_sz::<T>();
     pub const LEDGER_HEADER_SZ: u32 = size_of::<LedgerHeader>() as u32;
     //
     pub fn open<P>(
@@ -52,38 +143,117 @@ where
         P: AsRef<Path>,
     {
         let ledger_path = folder_path.as_ref().join(ledger_name.to_string());
+        Self::open_with(ledger_path, ledger_name, ledger_description, None)
+    }
+    //
+    // opens or creates the ledger at an explicit file path instead of `<folder>/<encoded name>`
+    pub fn open_file<P>(
+        ledger_path: P,
+        ledger_name: LedgerName,
+        ledger_description: LedgerDescription,
+    ) -> Result<Self, DatastoreError>
+    where
+        P: AsRef<Path>,
+    {
+        Self::open_with(
+            ledger_path.as_ref().to_path_buf(),
+            ledger_name,
+            ledger_description,
+            None,
+        )
+    }
+    //
+    // opens or creates a ledger whose pages are encrypted at rest with `key`. an existing ledger
+    // must have been created with the same key
+    pub fn open_encrypted<P>(
+        folder_path: P,
+        ledger_name: LedgerName,
+        ledger_description: LedgerDescription,
+        key: &EncryptionKey,
+    ) -> Result<Self, DatastoreError>
+    where
+        P: AsRef<Path>,
+    {
+        let ledger_path = folder_path.as_ref().join(ledger_name.to_string());
+        Self::open_with(ledger_path, ledger_name, ledger_description, Some(key))
+    }
+    //
+    fn open_with(
+        ledger_path: PathBuf,
+        ledger_name: LedgerName,
+        ledger_description: LedgerDescription,
+        key: Option<&EncryptionKey>,
+    ) -> Result<Self, DatastoreError> {
         //
         let file = OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
+            .truncate(false)
             .open(&ledger_path)?;
         //
         let new_file = file.metadata()?.len() == 0;
+        // the row type's schema goes between the header and the first page
+        let mut schema_page = Vec::new();
         if new_file {
             tracing::debug!("creating new ledger: {}", ledger_name);
-            // new empty file
-            file.set_len((Self::PAGE_SIZE + Self::LEDGER_HEADER_SZ) as u64)?;
+            schema_page = T::SCHEMA.to_page(Self::PAGE_SIZE as usize)?;
+            // new empty file, the checkpoint slot after the schema page starts out zeroed
+            file.set_len(
+                (Self::PAGE_SIZE + Self::LEDGER_HEADER_SZ) as u64
+                    + (schema_page.len() + CHECKPOINT_SLOT_SZ) as u64,
+            )?;
         }
         //
-        let mut mmap = unsafe { MmapMut::map_mut(&file)? }; // maps the file to virtual memory and makes an ASSUMPTION that the OS or any other process will not mutate this file while it mapped!
+        if key.is_some() && !new_file {
+            PageCipher::recover(&ledger_path, &file)?;
+        }
+        let mut mmap = match key {
+            // pages are decrypted into anonymous memory as they're written, the header and schema
+            // page are plaintext
+            Some(_) => {
+                let mut mmap = MmapMut::map_anon(file.metadata()?.len() as usize)?;
+                file.read_exact_at(&mut mmap[0..Self::LEDGER_HEADER_SZ as usize], 0)?;
+                if !new_file {
+                    let header_sz = Self::header_size(&mmap, mmap.len() as u64)?;
+                    file.read_exact_at(&mut mmap[0..header_sz], 0)?;
+                }
+                mmap
+            }
+            None => unsafe { MmapMut::map_mut(&file)? }, // maps the file to virtual memory and makes an ASSUMPTION that the OS or any other process will not mutate this file while it mapped!
+        };
         //
         let mut ledger_header: LedgerHeader;
         if new_file {
-            ledger_header = LedgerHeader::new(ledger_name, ledger_description, None);
+            let mut flags = Vec::new();
+            if T::CHAIN_OFFSET.is_some() {
+                flags.push(HeaderFlags::Audit);
+            }
+            if key.is_some() {
+                flags.push(HeaderFlags::Encrypted);
+            }
+            ledger_header = LedgerHeader::new(ledger_name, ledger_description, Some(flags));
             ledger_header.set_num_pages(1);
+            ledger_header.set_schema_size(schema_page.len() as u32);
+            if let Some(key) = key {
+                ledger_header.set_encryption(key_id(key), rand::random());
+            }
             //
             let ledger_header_bytes = ledger_header.serialize()?;
             //
             mmap[0..Self::LEDGER_HEADER_SZ as usize].copy_from_slice(&ledger_header_bytes);
+            let header_sz = ledger_header.size();
+            let schema_end = Self::LEDGER_HEADER_SZ as usize + schema_page.len();
+            mmap[Self::LEDGER_HEADER_SZ as usize..schema_end].copy_from_slice(&schema_page);
             //
             let page = SlottedPage::<T, PAGESZ, ROWS_PER_PAGE>::new()?;
-            mmap[Self::LEDGER_HEADER_SZ as usize
-                ..(Self::LEDGER_HEADER_SZ + Self::PAGE_SIZE) as usize]
+            mmap[header_sz..header_sz + Self::PAGE_SIZE as usize]
                 .copy_from_slice(page.data.as_slice());
             //
             //
         } else {
+            // checks the format version and schema size first
+            Self::header_size(&mmap, mmap.len() as u64)?;
             let ledger_header = LedgerHeader::access(&mmap[0..Self::LEDGER_HEADER_SZ as usize])?;
             tracing::debug!(
                 "opening existing ledger: {}. pages: {} rows: {}",
@@ -91,36 +261,166 @@ where
                 ledger_header.num_pages(),
                 ledger_header.num_rows(T::ROWS_PER_PAGE as u32)
             );
+            if ledger_header.flags.is_set(HeaderFlags::Audit) != T::CHAIN_OFFSET.is_some() {
+                return Err(format!(
+                    "{:?} was created with a different audit mode than its row type",
+                    ledger_path
+                )
+                .into());
+            }
+            if ledger_header.flags.is_set(HeaderFlags::Encrypted) != key.is_some() {
+                return Err(match key {
+                    Some(_) => format!("{:?} is not encrypted", ledger_path),
+                    None => format!("{:?} is encrypted, open it with a key", ledger_path),
+                }
+                .into());
+            }
+            if key.is_some_and(|key| key_id(key) != ledger_header.key_id()) {
+                return Err(DatastoreError::WrongKey);
+            }
+            // a crash while pages were being added, every page access past the end would fail
+            let pages_end = (ledger_header.num_pages() as u128) * Self::PAGE_SIZE as u128
+                + ledger_header.size() as u128;
+            if pages_end > mmap.len() as u128 {
+                return Err(format!(
+                    "{:?} is {} bytes, too short for the {} pages its header records. \
+                     `DataLedgerStore::repair` can bring it back to the pages it holds",
+                    ledger_path,
+                    mmap.len(),
+                    ledger_header.num_pages()
+                )
+                .into());
+            }
+            // rows are still read through T, a stale schema only misleads tools reading the file
+            if let Ok(Some(stored)) = Self::read_schema(&mmap)
+                && stored != T::SCHEMA
+            {
+                tracing::warn!(
+                    "{:?} was created with a different schema than its row type",
+                    ledger_path
+                );
+            }
         }
+        // read back after a new header is written, format 0 ledgers have a shorter one
+        let header_sz = Self::header_size(&mmap, mmap.len() as u64)?;
+        //
+        let cipher = match key {
+            Some(key) => {
+                let salt = LedgerHeader::access(&mmap[0..Self::LEDGER_HEADER_SZ as usize])?.salt();
+                let mut cipher =
+                    PageCipher::open(&ledger_path, key, salt, header_sz, Self::PAGE_SIZE as usize)?;
+                if new_file {
+                    cipher.set_resident(0);
+                    cipher.mark_dirty(0);
+                }
+                Some(cipher)
+            }
+            None => None,
+        };
+        // sidecar indexes would hold row values in the clear, encrypted ledgers rebuild them
+        let indexes = match key {
+            Some(_) => Indexes::in_memory::<T>(),
+            None => Indexes::load::<T>(&ledger_path),
+        };
+        let merkle = MerkleCache::load(&ledger_path);
+        let compressed = CompressedPages::open(&ledger_path)?;
         //
-        Ok(Self {
+        let mut store = Self {
             //
             file,
             mmap,
+            path: ledger_path,
+            header_sz,
+            indexes,
+            merkle: Mutex::new(merkle),
+            signer: None,
+            journal: None,
+            checkpoint_log: None,
+            read_only: false,
+            snapshots: Mutex::new(Vec::new()),
+            compressed: Arc::new(RwLock::new(compressed)),
+            page_cache: Mutex::new(PageCache::default()),
+            cipher,
+            retention: RetentionPolicy::from_schema(),
             phantom: PhantomData,
-        })
+        };
+        //
+        store.recover_journal()?;
+        store.resume_checkpoint_log()?;
+        //
+        if store.indexes.is_stale(store.num_rows()?) {
+            store.rebuild_indexes()?;
+        }
+        if new_file && store.cipher.is_some() {
+            store.flush_pages()?;
+        }
+        //
+        Ok(store)
     }
     //
     //  ###### HEADER MANAGEMENT ######
     //
+    // bytes before the first page, format 0 ledgers have a shorter header
+    // `bytes` starts with the header, `file_len` is the length of the whole file
+    fn header_size(bytes: &[u8], file_len: u64) -> Result<usize, DatastoreError> {
+        if bytes.len() < Self::LEDGER_HEADER_SZ as usize {
+            return Err("ledger file is too short for its header".into());
+        }
+        let header = LedgerHeader::access(&bytes[0..Self::LEDGER_HEADER_SZ as usize])?;
+        if header.format_version() > FORMAT_VERSION {
+            return Err(DatastoreError::UnsupportedFormat(header.format_version()));
+        }
+        // a damaged schema size would put the first page past the end of the file
+        if header.size() as u64 > file_len {
+            return Err("ledger file is too short for its schema page".into());
+        }
+        Ok(header.size())
+    }
+    //
+    // the schema written when the ledger was created, None before format 2
+    pub fn stored_schema(&self) -> Result<Option<LedgerSchema>, DatastoreError> {
+        Self::read_schema(&self.mmap)
+    }
+    //
+    fn read_schema(bytes: &[u8]) -> Result<Option<LedgerSchema>, DatastoreError> {
+        // checks the format version first
+        Self::header_size(bytes, bytes.len() as u64)?;
+        let header = LedgerHeader::access(&bytes[0..Self::LEDGER_HEADER_SZ as usize])?;
+        if header.schema_size() == 0 {
+            return Ok(None);
+        }
+        let schema_end = Self::LEDGER_HEADER_SZ as usize + header.schema_size();
+        let page = bytes
+            .get(Self::LEDGER_HEADER_SZ as usize..schema_end)
+            .ok_or("ledger file is too short for its schema page")?;
+        Ok(Some(LedgerSchema::from_page(page)?.0))
+    }
+    //
     #[inline(always)]
-    pub fn access_header<'a>(&'a self) -> Result<&'a LedgerHeader, DatastoreError> {
+    pub fn access_header(&self) -> Result<&LedgerHeader, DatastoreError> {
         Ok(LedgerHeader::access(
             &self.mmap[0..Self::LEDGER_HEADER_SZ as usize],
         )?)
     }
     #[inline(always)]
     pub fn access_header_mut<'a>(&'a mut self) -> Result<Seal<'a, LedgerHeader>, DatastoreError> {
+        if self.read_only {
+            return Err(DatastoreError::ReadOnly);
+        }
         Ok(LedgerHeader::access_mut(
             &mut self.mmap[0..Self::LEDGER_HEADER_SZ as usize],
         )?)
     }
+    /// # Safety
+    /// the mapping must start with a valid ledger header, it isn't validated.
     #[inline(always)]
-    pub unsafe fn access_header_unchecked<'a>(&'a self) -> &'a LedgerHeader {
+    pub unsafe fn access_header_unchecked(&self) -> &LedgerHeader {
         unsafe { LedgerHeader::access_unchecked(&self.mmap[0..Self::LEDGER_HEADER_SZ as usize]) }
     }
+    /// # Safety
+    /// the mapping must start with a valid ledger header, it isn't validated.
     #[inline(always)]
-    pub unsafe fn access_header_unchecked_mut<'a>(&'a mut self) -> &'a mut LedgerHeader {
+    pub unsafe fn access_header_unchecked_mut(&mut self) -> &mut LedgerHeader {
         unsafe {
             LedgerHeader::access_unchecked_mut(&mut self.mmap[0..Self::LEDGER_HEADER_SZ as usize])
                 .unseal()
@@ -137,13 +437,16 @@ where
             return Err(PageError::PageIdOutOfBounds.into());
         }
 
-        let start = Self::_get_page_data_start(page_id);
+        let start = self.page_start(page_id);
         let end = start + Self::PAGE_SIZE as usize;
 
-        let file_bytes = &self.mmap[start..end];
-
         let mut page = SlottedPage::new()?;
-        page.data.copy_from_slice(file_bytes);
+        match self.load_page(page_id)? {
+            Some(buf) => page
+                .data
+                .copy_from_slice(&buf[self.page_misalign(page_id)..]),
+            None => page.data.copy_from_slice(&self.mmap[start..end]),
+        }
 
         Ok(page)
     }
@@ -151,42 +454,73 @@ where
     pub fn access_page<'a>(
         &'a self,
         page_id: usize,
-    ) -> Result<&'a SlottedPage<T, PAGESZ, ROWS_PER_PAGE>, DatastoreError> {
+    ) -> Result<CacheRef<'a, SlottedPage<T, PAGESZ, ROWS_PER_PAGE>>, DatastoreError> {
         if page_id >= self.total_pages()? {
-            println!("total pages: {}", self.total_pages()?);
+            tracing::debug!(
+                "page #{page_id} past the last page, total pages: {}",
+                self.total_pages()?
+            );
             return Err(PageError::PageIdOutOfBounds.into());
         }
         //
-        let start = Self::_get_page_data_start(page_id);
-        let end = start + Self::PAGE_SIZE as usize;
-        //
-        let page_bytes = &self.mmap[start..end];
+        if let Some(buf) = self.cached_page(page_id)? {
+            let misalign = self.page_misalign(page_id);
+            return Ok(CacheRef::cached(buf).try_map(|bytes| {
+                access::<SlottedPage<T, PAGESZ, ROWS_PER_PAGE>, rkyv::rancor::Error>(
+                    &bytes[misalign..],
+                )
+            })?);
+        }
         //
+        let start = self.page_start(page_id);
         let page: &'a SlottedPage<T, PAGESZ, ROWS_PER_PAGE> =
-            access::<SlottedPage<T, PAGESZ, ROWS_PER_PAGE>, rkyv::rancor::Error>(page_bytes)?;
+            access::<SlottedPage<T, PAGESZ, ROWS_PER_PAGE>, rkyv::rancor::Error>(
+                &self.mmap[start..start + Self::PAGE_SIZE as usize],
+            )?;
         //
-        Ok(page)
+        Ok(CacheRef::borrowed(page))
     }
     //
+    /// # Safety
+    /// `page_id` must be a page of the ledger, its bytes aren't validated.
     #[inline(always)]
     pub unsafe fn access_page_unchecked<'a>(
         &'a self,
         page_id: usize,
-    ) -> &'a SlottedPage<T, PAGESZ, ROWS_PER_PAGE>
+    ) -> CacheRef<'a, SlottedPage<T, PAGESZ, ROWS_PER_PAGE>>
     where
         SlottedPage<T, PAGESZ, ROWS_PER_PAGE>: NoUndef + std::marker::Unpin,
     {
-        let start = Self::_get_page_data_start(page_id);
+        let misalign = self.page_misalign(page_id);
+        let cached = match self.cached_page(page_id) {
+            Ok(cached) => cached,
+            // the page reads as empty rather than as the hole or ciphertext in the mapping
+            Err(e) => {
+                tracing::error!("couldn't read page #{}. error:\n{e}", page_id);
+                let mut buf = AlignedVec::<16>::with_capacity(misalign + Self::PAGE_SIZE as usize);
+                buf.resize(misalign + Self::PAGE_SIZE as usize, 0);
+                Some(Arc::new(buf))
+            }
+        };
+        if let Some(buf) = cached {
+            return CacheRef::cached(buf).map(|bytes| unsafe {
+                access_unchecked::<SlottedPage<T, PAGESZ, ROWS_PER_PAGE>>(&bytes[misalign..])
+            });
+        }
+        //
+        let start = self.page_start(page_id);
         let end = start + Self::PAGE_SIZE as usize;
         //
-        unsafe { access_unchecked::<SlottedPage<T, PAGESZ, ROWS_PER_PAGE>>(&self.mmap[start..end]) }
+        CacheRef::borrowed(unsafe {
+            access_unchecked::<SlottedPage<T, PAGESZ, ROWS_PER_PAGE>>(&self.mmap[start..end])
+        })
     }
     //
     //
-    pub fn access_page_mut<'a>(
-        &'a mut self,
+    pub fn access_page_mut(
+        &mut self,
         page_id: usize,
-    ) -> Result<&'a mut SlottedPage<T, PAGESZ, ROWS_PER_PAGE>, DatastoreError>
+    ) -> Result<&mut SlottedPage<T, PAGESZ, ROWS_PER_PAGE>, DatastoreError>
     where
         SlottedPage<T, PAGESZ, ROWS_PER_PAGE>: NoUndef + std::marker::Unpin,
     {
@@ -194,7 +528,9 @@ where
             return Err(PageError::PageIdOutOfBounds.into());
         }
         //
-        let start = Self::_get_page_data_start(page_id);
+        self.before_page_write(page_id)?;
+        //
+        let start = self.page_start(page_id);
         let end = start + Self::PAGE_SIZE as usize;
         //
         let page_bytes = &mut self.mmap[start..end];
@@ -205,15 +541,24 @@ where
         Ok(page.unseal())
     }
     //
+    /// # Safety
+    /// `page_id` must be a page of the ledger, its bytes aren't validated.
+    /// panics on a read only ledger.
     #[inline(always)]
-    pub unsafe fn access_page_unchecked_mut<'a>(
-        &'a mut self,
+    pub unsafe fn access_page_unchecked_mut(
+        &mut self,
         page_id: usize,
-    ) -> &'a mut SlottedPage<T, PAGESZ, ROWS_PER_PAGE>
+    ) -> &mut SlottedPage<T, PAGESZ, ROWS_PER_PAGE>
     where
         SlottedPage<T, PAGESZ, ROWS_PER_PAGE>: NoUndef + std::marker::Unpin,
     {
-        let start = Self::_get_page_data_start(page_id);
+        // a checkpoint's private mapping, nothing written would reach the file
+        assert!(!self.read_only, "unchecked write to a read only ledger");
+        if let Err(e) = self.preserve_page(page_id) {
+            tracing::error!("couldn't preserve page #{}. error:\n{e}", page_id);
+        }
+        //
+        let start = self.page_start(page_id);
         let end = start + Self::PAGE_SIZE as usize;
         //
         unsafe {
@@ -229,42 +574,136 @@ where
         page_id: usize,
         page: &SlottedPage<T, PAGESZ, ROWS_PER_PAGE>,
     ) -> Result<(), DatastoreError> {
+        self.forbid_in_audit("write_page")?;
         if page_id >= self.total_pages()? {
             return Err(PageError::PageIdOutOfBounds.into());
         }
         //
-        let start = Self::_get_page_data_start(page_id);
+        self.before_page_write(page_id)?;
+        //
+        let start = self.page_start(page_id);
         self.mmap[start..start + Self::PAGE_SIZE as usize].copy_from_slice(&page.data);
-        self.mmap.flush_range(start, Self::PAGE_SIZE as usize)?;
+        match self.cipher {
+            Some(_) => self.flush_pages()?,
+            None => self.mmap.flush_range(start, Self::PAGE_SIZE as usize)?,
+        }
         //
         Ok(())
     }
     //
     //
     pub fn allocate_new_page(&mut self) -> Result<usize, DatastoreError> {
-        let mut header_mut = self.access_header_mut()?;
-        let num_pages: usize = header_mut.num_pages() as usize;
-        header_mut.set_num_pages(num_pages as u32 + 1);
+        let header = self.access_header()?;
+        let num_pages: usize = header.num_pages() as usize;
+        if num_pages as u64 >= header.max_pages() {
+            return Err(DatastoreError::RowIdOverflow(
+                "the ledger format can't record more pages",
+            ));
+        }
+        // every row of the new page must have an id
+        let max_rows = SlottedPage::<T, PAGESZ, ROWS_PER_PAGE>::max_rows_per_page() as u32;
+        RowQuery::<T, PAGESZ, ROWS_PER_PAGE>::to_row_id(num_pages, max_rows - 1)?;
+        //
+        self.access_header_mut()?
+            .unseal()
+            .set_num_pages(num_pages as u64 + 1);
         //
-        let new_file_len =
-            Self::LEDGER_HEADER_SZ as usize + (num_pages + 1) * Self::PAGE_SIZE as usize;
+        let new_file_len = self.page_start(num_pages + 1);
         self.file.set_len(new_file_len as u64)?;
         //
         let new_page = SlottedPage::<T, PAGESZ, ROWS_PER_PAGE>::new()?;
         //
-        let start = Self::LEDGER_HEADER_SZ as usize + (num_pages) * Self::PAGE_SIZE as usize;
+        let start = self.page_start(num_pages);
         //
-        self.mmap = unsafe { MmapMut::map_mut(&self.file)? };
+        self.remap(new_file_len)?;
         self.mmap[start..new_file_len].copy_from_slice(&new_page.data);
+        if let Some(cipher) = self.cipher.as_mut() {
+            cipher.set_resident(num_pages);
+            cipher.mark_dirty(num_pages);
+        }
         //
         tracing::info!("allocated new page ({})", num_pages);
         //
         Ok(num_pages)
     }
 
+    // follows the file to a new length. the plaintext copy of an encrypted ledger grows in place
+    fn remap(&mut self, len: usize) -> Result<(), DatastoreError> {
+        if self.cipher.is_none() {
+            self.mmap = unsafe { MmapMut::map_mut(&self.file)? };
+            return Ok(());
+        }
+        #[cfg(target_os = "linux")]
+        unsafe {
+            self.mmap
+                .remap(len, memmap2::RemapOptions::new().may_move(true))?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let mut mmap = MmapMut::map_anon(len)?;
+            let keep = len.min(self.mmap.len());
+            mmap[..keep].copy_from_slice(&self.mmap[..keep]);
+            self.mmap = mmap;
+        }
+        Ok(())
+    }
+
+    // every checked page mutation passes through here first
+    fn before_page_write(&mut self, page_id: usize) -> Result<(), DatastoreError> {
+        if self.read_only {
+            return Err(DatastoreError::ReadOnly);
+        }
+        self.preserve_page(page_id)
+    }
+    //
+    // keeps the current image of the page for the undo log, the journal and live snapshots before
+    // it's modified, the unchecked mutators pass through here too
+    fn preserve_page(&mut self, page_id: usize) -> Result<(), DatastoreError> {
+        // the journal, undo log and snapshots below all copy the page out of the mapping
+        self.restore_page(page_id)?;
+        self.merkle.get_mut().invalidate(page_id);
+        if let Some(cipher) = self.cipher.as_ref() {
+            cipher.mark_dirty(page_id);
+        }
+        //
+        let start = self.page_start(page_id);
+        if let Some(log) = self.checkpoint_log.as_mut() {
+            // pages allocated after the checkpoint are past its end, nothing to preserve
+            if !log.contains(page_id) && ((start as u64) < log.original_len()) {
+                log.save_page(page_id, &self.mmap[start..start + Self::PAGE_SIZE as usize])?;
+            }
+        }
+        //
+        if let Some(journal) = self.journal.as_mut() {
+            // pages allocated inside the transaction are simply truncated on rollback
+            if !journal.contains(page_id) && ((start as u64) < journal.original_len()) {
+                let page = &self.mmap[start..start + Self::PAGE_SIZE as usize];
+                journal.save_page(
+                    page_id,
+                    &Self::journal_image(self.cipher.as_ref(), page_id, page),
+                )?;
+            }
+        }
+        //
+        let mut snapshots = self.snapshots.lock();
+        if !snapshots.is_empty() {
+            let page = &self.mmap[start..start + Self::PAGE_SIZE as usize];
+            snapshots.retain(|weak| match weak.upgrade() {
+                Some(pages) => {
+                    pages.preserve(page_id, page);
+                    true
+                }
+                None => false,
+            });
+        }
+        Ok(())
+    }
+    //
     #[inline(always)]
     pub fn sync_all(&self) -> std::io::Result<()> {
-        self.mmap.flush()
+        self.flush_pages()?;
+        self.merkle.lock().save()?;
+        self.indexes.save()
     }
 
     #[inline(always)]
@@ -273,27 +712,27 @@ where
     }
 
     #[inline]
-    pub fn num_rows(&self) -> Result<u32, DatastoreError> {
-        let num_pages: u32 = self.access_header()?.num_pages();
-        let last_page: &SlottedPage<T, PAGESZ, ROWS_PER_PAGE> =
-            self.access_page(num_pages as usize - 1)?;
-        let num_rows: u32 = (num_pages - 1)
-            * SlottedPage::<T, PAGESZ, ROWS_PER_PAGE>::max_rows_per_page() as u32
-            + last_page.access_header()?.slot_count();
+    pub fn num_rows(&self) -> Result<u64, DatastoreError> {
+        let num_pages: usize = self.total_pages()?;
+        let last_page = self.access_page(num_pages - 1)?;
+        let num_rows = RowQuery::<T, PAGESZ, ROWS_PER_PAGE>::to_row_id(
+            num_pages - 1,
+            last_page.access_header()?.slot_count(),
+        )?;
         //
-        Ok(num_rows)
+        Ok(num_rows.get())
     }
 
     #[inline(always)]
-    const fn _get_page_data_start(page_id: usize) -> usize {
-        Self::LEDGER_HEADER_SZ as usize + page_id * Self::PAGE_SIZE as usize
+    pub(crate) fn page_start(&self, page_id: usize) -> usize {
+        self.header_sz + page_id * Self::PAGE_SIZE as usize
     }
 
     //
     // ###### ROW OPERATIONS ######
     //
 
-    pub fn insert(&mut self, value: &T) -> Result<u32, DatastoreError> {
+    pub fn insert(&mut self, value: &T) -> Result<RowId, DatastoreError> {
         let header = match self.access_header() {
             Ok(header) => header,
             Err(e) => {
@@ -314,12 +753,18 @@ where
         let next_page_id = match page.insert_row(value) {
             //
             Ok(page_row_n) => {
-                return Ok(RowQuery::<T, PAGESZ, ROWS_PER_PAGE>::to_row_id(
-                    page_id, page_row_n,
-                ));
+                let row_id =
+                    RowQuery::<T, PAGESZ, ROWS_PER_PAGE>::to_row_id(page_id as usize, page_row_n)?;
+                self.link_row(row_id)?;
+                self.indexes.insert_row(row_id, value);
+                return Ok(row_id);
             }
             //
             Err(PageError::NoSpace) => {
+                // the page is full and won't change again, remember its hash
+                let hash = self.page_hash(page_id as usize)?;
+                self.merkle.get_mut().set(page_id as usize, hash);
+                self.sign_on_page_fill(page_id as usize)?;
                 //
                 match self.allocate_new_page() {
                     Ok(p) => p,
@@ -370,7 +815,9 @@ where
                 return Err(super::utils::DatastoreError::PageError(e));
             }
         };
-        let row_id = RowQuery::<T, PAGESZ, ROWS_PER_PAGE>::to_row_id(next_page_id, row_n);
+        let row_id = RowQuery::<T, PAGESZ, ROWS_PER_PAGE>::to_row_id(next_page_id as usize, row_n)?;
+        self.link_row(row_id)?;
+        self.indexes.insert_row(row_id, value);
         //
         Ok(row_id)
     }
@@ -404,20 +851,19 @@ where
     // }
 
     //
-    pub fn access_row(&self, row_id: u32) -> Result<Option<&T>, DatastoreError> {
+    pub fn access_row(&self, row_id: RowId) -> Result<Option<CacheRef<'_, T>>, DatastoreError> {
         let query: RowQuery<T, PAGESZ, ROWS_PER_PAGE> =
             RowQuery::<T, PAGESZ, ROWS_PER_PAGE>::from_row_id(row_id);
         //
-        let page: &SlottedPage<T, PAGESZ, ROWS_PER_PAGE> =
-            match self.access_page(query.page_id as usize) {
-                Ok(p) => p,
-                Err(e) => {
-                    tracing::error!("couldn't access page #{}. error:\n{}", query.page_id, e);
-                    return Err(e);
-                }
-            };
+        let page = match self.access_page(query.page_id as usize) {
+            Ok(p) => p,
+            Err(e) => {
+                tracing::error!("couldn't access page #{}. error:\n{}", query.page_id, e);
+                return Err(e);
+            }
+        };
         //
-        let row = match page.access_row(query.page_row_n as usize) {
+        let row = match page.try_filter_map(|page| page.access_row(query.page_row_n as usize)) {
             Ok(r) => r,
             Err(e) => {
                 tracing::error!("couldn't access page #{}. error:\n{}", query.page_id, e);
@@ -428,17 +874,18 @@ where
         Ok(row)
     }
     //
-    pub unsafe fn access_row_unchecked(&self, row_id: u32) -> &T {
+    /// # Safety
+    /// `row_id` must be a live row, neither the row nor its page is validated.
+    pub unsafe fn access_row_unchecked(&self, row_id: RowId) -> CacheRef<'_, T> {
         let query: RowQuery<T, PAGESZ, ROWS_PER_PAGE> =
             RowQuery::<T, PAGESZ, ROWS_PER_PAGE>::from_row_id(row_id);
         //
-        unsafe {
-            self.access_page_unchecked(query.page_id as usize)
-                .access_row_unchecked(query.page_row_n as usize)
-        }
+        unsafe { self.access_page_unchecked(query.page_id as usize) }
+            .map(|page| unsafe { page.access_row_unchecked(query.page_row_n as usize) })
     }
     //
-    pub fn access_row_mut(&mut self, row_id: u32) -> Result<Option<Seal<'_, T>>, DatastoreError> {
+    pub fn access_row_mut(&mut self, row_id: RowId) -> Result<Option<Seal<'_, T>>, DatastoreError> {
+        self.forbid_in_audit("access_row_mut")?;
         //
         let query: RowQuery<T, PAGESZ, ROWS_PER_PAGE> =
             RowQuery::<T, PAGESZ, ROWS_PER_PAGE>::from_row_id(row_id);
@@ -449,9 +896,82 @@ where
         Ok(page.access_row_mut(query.page_row_n as usize)?)
     }
     //
+    // applies the closure to a copy of the row, re-validates it and writes it back. indexes follow the new value
+    pub fn update<F, R>(&mut self, row_id: RowId, f: F) -> Result<R, DatastoreError>
+    where
+        T: Clone,
+        F: FnOnce(&mut T) -> R,
+    {
+        let Some(old_row) = self.access_row(row_id)?.map(|row| T::clone(&row)) else {
+            return Err(PageError::RowNotFound.into());
+        };
+        //
+        let mut new_row = old_row.clone();
+        let result = f(&mut new_row);
+        //
+        self.write_row(row_id, &old_row, &new_row)?;
+        //
+        Ok(result)
+    }
+    //
+    pub fn replace(&mut self, row_id: RowId, value: &T) -> Result<(), DatastoreError>
+    where
+        T: Clone,
+    {
+        let Some(old_row) = self.access_row(row_id)?.map(|row| T::clone(&row)) else {
+            return Err(PageError::RowNotFound.into());
+        };
+        //
+        self.write_row(row_id, &old_row, value)
+    }
+    //
+    fn write_row(&mut self, row_id: RowId, old_row: &T, new_row: &T) -> Result<(), DatastoreError> {
+        self.forbid_in_audit("update")?;
+        new_row.validate()?;
+        //
+        let bytes = new_row.to_bytes()?;
+        access::<T, rkyv::rancor::Error>(&bytes)?;
+        //
+        let query: RowQuery<T, PAGESZ, ROWS_PER_PAGE> =
+            RowQuery::<T, PAGESZ, ROWS_PER_PAGE>::from_row_id(row_id);
+        //
+        let page: &mut SlottedPage<T, PAGESZ, ROWS_PER_PAGE> =
+            self.access_page_mut(query.page_id as usize)?;
+        page.write_row(query.page_row_n as usize, &bytes)?;
+        //
+        self.indexes.remove_row(row_id, old_row);
+        self.indexes.insert_row(row_id, new_row);
+        //
+        Ok(())
+    }
+    //
+    // tombstones the row. its id is never reused and reads return None
+    pub fn delete(&mut self, row_id: RowId) -> Result<(), DatastoreError>
+    where
+        T: Clone,
+    {
+        self.forbid_in_audit("delete")?;
+        let Some(old_row) = self.access_row(row_id)?.map(|row| T::clone(&row)) else {
+            return Err(PageError::RowNotFound.into());
+        };
+        //
+        let query: RowQuery<T, PAGESZ, ROWS_PER_PAGE> =
+            RowQuery::<T, PAGESZ, ROWS_PER_PAGE>::from_row_id(row_id);
+        //
+        self.access_page_mut(query.page_id as usize)?
+            .delete_row(query.page_row_n as usize)?;
+        //
+        self.indexes.remove_row(row_id, &old_row);
+        //
+        Ok(())
+    }
+    //
     //
     //
-    pub unsafe fn access_row_unchecked_mut<'a>(&'a mut self, row_id: u32) -> &'a mut T
+    /// # Safety
+    /// `row_id` must be a live row, neither the row nor its page is validated.
+    /// panics on a read only ledger.
+    pub unsafe fn access_row_unchecked_mut(&mut self, row_id: RowId) -> &mut T
     where
         T: rkyv::traits::NoUndef + std::marker::Unpin,
     {
@@ -467,43 +987,1924 @@ where
                 .unseal()
         }
     }
-}
-
-// ### ROW QUERY
-
-#[derive(Debug)]
-pub struct RowQuery<T, const PAGESZ: usize, const ROWS_PER_PAGE: usize> {
-    pub page_id: u32,
-    pub page_row_n: u32,
     //
-    phantom: PhantomData<T>,
-}
-
-impl<T, const PAGESZ: usize, const ROWS_PER_PAGE: usize> RowQuery<T, PAGESZ, ROWS_PER_PAGE>
-where
-    T: Archive
-        + Portable
-        + PageSchema
-        + for<'b> bytecheck::CheckBytes<
-            bytecheck::rancor::Strategy<
-                rkyv::validation::Validator<
-                    rkyv::validation::archive::ArchiveValidator<'b>,
-                    rkyv::validation::shared::SharedValidator,
-                >,
-                bytecheck::rancor::Error,
-            >,
-        >,
-{
-    #[inline(always)]
-    pub const fn to_row_id(page_id: u32, row_n: u32) -> u32 {
-        (page_id * SlottedPage::<T, PAGESZ, ROWS_PER_PAGE>::max_rows_per_page() as u32) + row_n
+    // visits every row in row id order
+    pub fn for_each_row<F>(&self, mut f: F) -> Result<(), DatastoreError>
+    where
+        F: FnMut(RowId, &T),
+    {
+        for page_id in 0..self.total_pages()? {
+            self.with_page(page_id, |page| {
+                let slot_count = page.access_header()?.slot_count();
+                //
+                for page_row_n in 0..slot_count {
+                    if let Some(row) = page.access_row(page_row_n as usize)? {
+                        f(
+                            RowQuery::<T, PAGESZ, ROWS_PER_PAGE>::to_row_id(page_id, page_row_n)?,
+                            row,
+                        );
+                    }
+                }
+                Ok(())
+            })?;
+        }
+        //
+        Ok(())
+    }
+    //
+    // the k rows with the smallest distance, closest first. a full scan, used by #[vector] fields
+    pub fn nearest_by<F>(&self, k: usize, mut distance: F) -> Result<Vec<Neighbor>, DatastoreError>
+    where
+        F: FnMut(&T) -> f32,
+    {
+        if k == 0 {
+            return Ok(Vec::new());
+        }
+        //
+        let mut heap: BinaryHeap<Neighbor> = BinaryHeap::with_capacity(k + 1);
+        self.for_each_row(|row_id, row| {
+            let candidate = Neighbor {
+                row_id,
+                distance: distance(row),
+            };
+            if heap.len() < k {
+                heap.push(candidate);
+            } else if let Some(worst) = heap.peek()
+                && candidate < *worst
+            {
+                heap.pop();
+                heap.push(candidate);
+            }
+        })?;
+        //
+        Ok(heap.into_sorted_vec())
+    }
+    //
+    //  ###### TRANSACTIONS ######
+    //
+    // runs the closure as one atomic unit. if it returns Err (or panics) every write it made is
+    // undone, otherwise all of them are flushed to disk before this returns
+    pub fn transaction<F, R>(&mut self, f: F) -> Result<R, DatastoreError>
+    where
+        T: Clone,
+        F: FnOnce(&mut Transaction<'_, T, PAGESZ, ROWS_PER_PAGE>) -> Result<R, DatastoreError>,
+    {
+        let mut tx = Transaction::begin(self)?;
+        //
+        match f(&mut tx) {
+            Ok(result) => {
+                tx.commit()?;
+                Ok(result)
+            }
+            Err(e) => {
+                tx.rollback()?;
+                Err(e)
+            }
+        }
     }
     //
+    //  ###### AUDIT ######
+    //
     #[inline(always)]
-    pub const fn from_row_id(row_id: u32) -> RowQuery<T, PAGESZ, ROWS_PER_PAGE> {
-        let max_rows = SlottedPage::<T, PAGESZ, ROWS_PER_PAGE>::max_rows_per_page() as u32;
-        let page_id = row_id / max_rows;
-        let page_row_n = row_id % max_rows;
+    fn forbid_in_audit(&self, op: &'static str) -> Result<(), DatastoreError> {
+        if T::CHAIN_OFFSET.is_some() {
+            return Err(DatastoreError::AuditViolation(op));
+        }
+        Ok(())
+    }
+    //
+    // writes the hash chain link of a freshly inserted row
+    fn link_row(&mut self, row_id: RowId) -> Result<(), DatastoreError> {
+        let Some(chain_offset) = T::CHAIN_OFFSET else {
+            return Ok(());
+        };
+        //
+        let prev = match row_id.get() {
+            0 => GENESIS,
+            n => self.stored_link(RowId(n - 1))?,
+        };
+        //
+        let query: RowQuery<T, PAGESZ, ROWS_PER_PAGE> =
+            RowQuery::<T, PAGESZ, ROWS_PER_PAGE>::from_row_id(row_id);
+        let Some(row) = self
+            .access_page_mut(query.page_id as usize)?
+            .row_bytes_mut(query.page_row_n as usize)?
+        else {
+            return Err(PageError::RowNotFound.into());
+        };
+        //
+        let link = chain_hash(&prev, row, chain_offset);
+        row[chain_offset..chain_offset + 32].copy_from_slice(&link);
+        Ok(())
+    }
+    //
+    fn stored_link(&self, row_id: RowId) -> Result<ChainHash, DatastoreError> {
+        let chain_offset = T::CHAIN_OFFSET.ok_or("not an audit ledger")?;
+        let query: RowQuery<T, PAGESZ, ROWS_PER_PAGE> =
+            RowQuery::<T, PAGESZ, ROWS_PER_PAGE>::from_row_id(row_id);
+        //
+        match self
+            .access_page(query.page_id as usize)?
+            .row_bytes(query.page_row_n as usize)?
+        {
+            Some(row) => Ok(stored_link(row, chain_offset)),
+            None => Err(DatastoreError::AuditViolation(
+                "row in the chain was deleted",
+            )),
+        }
+    }
+    //
+    // the link of the newest row. record it outside the ledger to detect rows removed from the end
+    pub fn chain_head(&self) -> Result<ChainHash, DatastoreError> {
+        match self.num_rows()? {
+            0 => Ok(GENESIS),
+            num_rows => self.stored_link(RowId(num_rows - 1)),
+        }
+    }
+    //
+    // recomputes the chain from the first row. Ok(None) when every link matches
+    pub fn verify_chain(&self) -> Result<Option<BrokenLink>, DatastoreError> {
+        let chain_offset = T::CHAIN_OFFSET.ok_or("not an audit ledger")?;
+        //
+        let mut prev = GENESIS;
+        for page_id in 0..self.total_pages()? {
+            let broken = self.with_page(page_id, |page| {
+                let slot_count = page.access_header()?.slot_count();
+                //
+                for page_row_n in 0..slot_count {
+                    let row_id =
+                        RowQuery::<T, PAGESZ, ROWS_PER_PAGE>::to_row_id(page_id, page_row_n)?;
+                    let Some(row) = page.row_bytes(page_row_n as usize)? else {
+                        return Ok(Some(BrokenLink {
+                            row_id,
+                            expected: prev,
+                            found: None,
+                        }));
+                    };
+                    //
+                    let expected = chain_hash(&prev, row, chain_offset);
+                    let found = stored_link(row, chain_offset);
+                    if found != expected {
+                        return Ok(Some(BrokenLink {
+                            row_id,
+                            expected,
+                            found: Some(found),
+                        }));
+                    }
+                    prev = found;
+                }
+                Ok(None)
+            })?;
+            if broken.is_some() {
+                return Ok(broken);
+            }
+        }
+        //
+        Ok(None)
+    }
+    //
+    //  ###### MERKLE TREE ######
+    //
+    // the stored bytes of a row, what `merkle::verify_proof` takes
+    pub fn row_bytes<'a>(
+        &'a self,
+        row_id: RowId,
+    ) -> Result<Option<CacheRef<'a, [u8]>>, DatastoreError> {
+        let query: RowQuery<T, PAGESZ, ROWS_PER_PAGE> =
+            RowQuery::<T, PAGESZ, ROWS_PER_PAGE>::from_row_id(row_id);
+        Ok(self
+            .access_page(query.page_id as usize)?
+            .try_filter_map(|page| page.row_bytes(query.page_row_n as usize))?)
+    }
+    //
+    fn page_leaves(&self, page_id: usize) -> Result<Vec<Hash>, DatastoreError> {
+        self.with_page(page_id, |page| {
+            let slot_count = page.access_header()?.slot_count();
+            //
+            (0..slot_count as usize)
+                .map(|page_row_n| {
+                    Ok(match page.row_bytes(page_row_n)? {
+                        Some(row) => merkle::leaf_hash(row),
+                        None => merkle::deleted_leaf_hash(),
+                    })
+                })
+                .collect()
+        })
+    }
+    //
+    fn page_hash(&self, page_id: usize) -> Result<Hash, DatastoreError> {
+        Ok(merkle::root(&self.page_leaves(page_id)?))
+    }
+    //
+    // every page hash, filling in the ones that changed since they were last hashed
+    fn page_hashes(&self) -> Result<Vec<Hash>, DatastoreError> {
+        let num_pages = self.total_pages()?;
+        let mut cache = self.merkle.lock();
+        cache.truncate(num_pages);
+        //
+        (0..num_pages)
+            .map(|page_id| match cache.get(page_id) {
+                Some(hash) => Ok(hash),
+                None => {
+                    let hash = self.page_hash(page_id)?;
+                    cache.set(page_id, hash);
+                    Ok(hash)
+                }
+            })
+            .collect()
+    }
+    //
+    // the top level leaves covering the first `num_rows` rows: every full page, then the rows
+    // of the last page
+    fn root_leaves(&self, pages: &[Hash], num_rows: u64) -> Result<Vec<Hash>, DatastoreError> {
+        if num_rows > self.num_rows()? {
+            return Err(PageError::RowIdOutOfBounds.into());
+        }
+        let max_rows = SlottedPage::<T, PAGESZ, ROWS_PER_PAGE>::max_rows_per_page() as u64;
+        let (full_pages, rest) = (num_rows / max_rows, num_rows % max_rows);
+        //
+        let mut leaves = pages[..full_pages as usize].to_vec();
+        if rest > 0 {
+            let page_leaves = self.page_leaves(full_pages as usize)?;
+            leaves.push(merkle::root(&page_leaves[..rest as usize]));
+        }
+        Ok(leaves)
+    }
+    //
+    // digest of every row in the ledger
+    pub fn root_hash(&self) -> Result<Hash, DatastoreError> {
+        self.root_hash_at(self.num_rows()?)
+    }
+    //
+    // digest of the first `num_rows` rows, what the root was when the ledger had that many rows
+    pub fn root_hash_at(&self, num_rows: u64) -> Result<Hash, DatastoreError> {
+        Ok(merkle::root(
+            &self.root_leaves(&self.page_hashes()?, num_rows)?,
+        ))
+    }
+    //
+    // proves the row is part of the ledger with the current `root_hash()`
+    pub fn prove_row(&self, row_id: RowId) -> Result<Proof, DatastoreError> {
+        let query: RowQuery<T, PAGESZ, ROWS_PER_PAGE> =
+            RowQuery::<T, PAGESZ, ROWS_PER_PAGE>::from_row_id(row_id);
+        if self.row_bytes(row_id)?.is_none() {
+            return Err(PageError::RowNotFound.into());
+        }
+        //
+        let leaves = self.page_leaves(query.page_id as usize)?;
+        let pages = self.root_leaves(&self.page_hashes()?, self.num_rows()?)?;
+        //
+        Ok(Proof {
+            row_id,
+            max_rows_per_page: SlottedPage::<T, PAGESZ, ROWS_PER_PAGE>::max_rows_per_page() as u32,
+            page_row_n: query.page_row_n,
+            rows_in_page: leaves.len() as u32,
+            row_path: merkle::audit_path(query.page_row_n as usize, &leaves),
+            page_id: query.page_id,
+            num_pages: pages.len() as u64,
+            page_path: merkle::audit_path(query.page_id as usize, &pages),
+        })
+    }
+    //
+    //  ###### SIGNED CHECKPOINTS ######
+    //
+    // signs the ledger state every `every_pages` filled pages from now on. 0 only signs on
+    // `sign_checkpoint()`
+    pub fn enable_signing(&mut self, key: SigningKey, every_pages: u32) {
+        self.signer = Some((key, every_pages));
+    }
+    //
+    // records a signed (num_rows, root_hash, timestamp) in `<ledger>.sigs` and, from format 3 on,
+    // in the ledger's own checkpoint slot
+    pub fn sign_checkpoint(&mut self) -> Result<SignedCheckpoint, DatastoreError> {
+        let Some((key, _)) = self.signer.as_ref() else {
+            return Err("no signing key configured, see enable_signing".into());
+        };
+        if self.read_only {
+            return Err(DatastoreError::ReadOnly);
+        }
+        //
+        let num_rows = self.num_rows()?;
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_err(|e| e.to_string())?
+            .as_secs();
+        let checkpoint = SignedCheckpoint::sign(
+            key,
+            self.access_header()?.name().as_bytes(),
+            num_rows,
+            self.root_hash_at(num_rows)?,
+            timestamp,
+        );
+        //
+        crate::signing::append(&self.path, &checkpoint)?;
+        if let Some(slot) = self.access_header()?.checkpoint_slot() {
+            self.mmap[slot.clone()].copy_from_slice(&checkpoint.to_slot());
+            match self.cipher {
+                Some(_) => self.flush_pages()?,
+                None => self.mmap.flush_range(slot.start, slot.len())?,
+            }
+        }
+        Ok(checkpoint)
+    }
+    //
+    fn sign_on_page_fill(&mut self, page_id: usize) -> Result<(), DatastoreError> {
+        match self.signer {
+            // rows written inside a transaction may still be rolled back
+            Some((_, every_pages))
+                if every_pages > 0
+                    && self.journal.is_none()
+                    && (page_id + 1).is_multiple_of(every_pages as usize) =>
+            {
+                self.sign_checkpoint()?;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+    //
+    // every checkpoint in `<ledger>.sigs`, followed by the one in the checkpoint slot unless it's
+    // already the last. a file shipped without its `.sigs` still has the latest
+    pub fn signed_checkpoints(&self) -> Result<Vec<SignedCheckpoint>, DatastoreError> {
+        let mut checkpoints = crate::signing::load(&self.path)?;
+        if let Some(latest) = self.latest_signed_checkpoint()?
+            && checkpoints.last() != Some(&latest)
+        {
+            checkpoints.push(latest);
+        }
+        Ok(checkpoints)
+    }
+    //
+    // the checkpoint kept in the ledger file itself. None before format 3 or before the first
+    // signature
+    pub fn latest_signed_checkpoint(&self) -> Result<Option<SignedCheckpoint>, DatastoreError> {
+        Ok(self
+            .access_header()?
+            .checkpoint_slot()
+            .and_then(|slot| SignedCheckpoint::from_slot(self.mmap.get(slot)?)))
+    }
+    //
+    // checks every signed checkpoint against the key and the ledger's rows. Ok(None) when all hold
+    pub fn verify_checkpoints(
+        &self,
+        key: &VerifyingKey,
+    ) -> Result<Option<BadCheckpoint>, DatastoreError> {
+        let name = self.access_header()?.name();
+        let num_rows = self.num_rows()?;
+        // rehash every page, the cached hashes are not evidence
+        let pages = (0..self.total_pages()?)
+            .map(|page_id| self.page_hash(page_id))
+            .collect::<Result<Vec<Hash>, DatastoreError>>()?;
+        //
+        for (index, checkpoint) in self.signed_checkpoints()?.into_iter().enumerate() {
+            let reason = if !checkpoint.verify_signature(key, name.as_bytes()) {
+                Some(BadCheckpointReason::Signature)
+            } else if checkpoint.num_rows > num_rows {
+                Some(BadCheckpointReason::RowsMissing)
+            } else if merkle::root(&self.root_leaves(&pages, checkpoint.num_rows)?)
+                != checkpoint.root
+            {
+                Some(BadCheckpointReason::RootMismatch)
+            } else {
+                None
+            };
+            //
+            if let Some(reason) = reason {
+                return Ok(Some(BadCheckpoint {
+                    index,
+                    num_rows: checkpoint.num_rows,
+                    reason,
+                }));
+            }
+        }
+        //
+        Ok(None)
+    }
+    //
+    //  ###### ENCRYPTION ######
+    //
+    #[inline(always)]
+    pub fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
+    //
+    #[inline(always)]
+    fn forbid_encrypted(&self, op: &'static str) -> Result<(), DatastoreError> {
+        if self.cipher.is_some() {
+            return Err(DatastoreError::EncryptionUnsupported(op));
+        }
+        Ok(())
+    }
+    //
+    // makes every write durable. an encrypted ledger seals its changed pages into the file
+    fn flush_pages(&self) -> std::io::Result<()> {
+        match self.cipher.as_ref() {
+            Some(cipher) => cipher.flush(&self.file, &self.mmap),
+            None => self.mmap.flush(),
+        }
+    }
+    //
+    // page images in the journal of an encrypted ledger are sealed too
+    fn journal_image<'a>(
+        cipher: Option<&PageCipher>,
+        page_id: usize,
+        page: &'a [u8],
+    ) -> Cow<'a, [u8]> {
+        match cipher {
+            Some(cipher) => Cow::Owned(cipher.seal_image(page_id, page)),
+            None => Cow::Borrowed(page),
+        }
+    }
+    //
+    #[inline(always)]
+    fn journal_page_size(&self) -> usize {
+        match self.cipher {
+            Some(_) => Self::PAGE_SIZE as usize + SEAL_SZ,
+            None => Self::PAGE_SIZE as usize,
+        }
+    }
+    //
+    //  ###### COMPRESSION ######
+    //
+    // moves every full page that isn't compressed yet into `<ledger>.lz4` and punches it out of
+    // the ledger file. the tail page stays raw for inserts, pages that don't shrink are skipped
+    pub fn compress_cold_pages(&mut self) -> Result<CompressionStats, DatastoreError> {
+        if self.read_only {
+            return Err(DatastoreError::ReadOnly);
+        }
+        self.forbid_encrypted("compression")?;
+        let page_cursor = self.access_header()?.page_cursor() as usize;
+        let cold: Vec<usize> = {
+            let compressed = self.compressed.read();
+            (0..page_cursor)
+                .filter(|page_id| !compressed.contains(*page_id))
+                .collect()
+        };
+        //
+        let mut stats = CompressionStats::default();
+        // a batch at a time, so memory use doesn't scale with the ledger
+        for chunk in cold.chunks(COMPRESS_BATCH_PAGES) {
+            let batch: Vec<(usize, Vec<u8>)> = chunk
+                .iter()
+                .filter_map(|page_id| {
+                    let start = self.page_start(*page_id);
+                    let page = &self.mmap[start..start + Self::PAGE_SIZE as usize];
+                    compress_page(page).map(|compressed| (*page_id, compressed))
+                })
+                .collect();
+            if batch.is_empty() {
+                continue;
+            }
+            self.compressed.write().append(&batch)?;
+            //
+            // live snapshots copy out of their mapping under these locks, the pages can't turn
+            // into holes under a reader
+            let snapshots: Vec<Arc<SnapshotPages>> = self
+                .snapshots
+                .lock()
+                .iter()
+                .filter_map(Weak::upgrade)
+                .collect();
+            let _reads: Vec<_> = snapshots.iter().map(|pages| pages.lock_reads()).collect();
+            for (page_id, compressed) in &batch {
+                let start = self.page_start(*page_id);
+                punch_hole(&self.file, start as u64, Self::PAGE_SIZE as u64)?;
+                //
+                stats.pages += 1;
+                stats.raw_bytes += Self::PAGE_SIZE as u64;
+                stats.compressed_bytes += compressed.len() as u64;
+            }
+        }
+        //
+        if stats.pages > 0 {
+            tracing::info!(
+                "compressed {} pages of {:?} ({} -> {} bytes)",
+                stats.pages,
+                self.path,
+                stats.raw_bytes,
+                stats.compressed_bytes
+            );
+        }
+        Ok(stats)
+    }
+    //
+    // number of pages currently held in the compressed segment
+    pub fn compressed_pages(&self) -> usize {
+        self.compressed.read().len()
+    }
+    //
+    // pages read out of the mapping and kept in the page cache, at most PAGE_CACHE_PAGES
+    pub fn cached_pages(&self) -> usize {
+        self.page_cache.lock().len()
+    }
+    //
+    //  ###### RETENTION ######
+    //
+    #[inline(always)]
+    pub fn retention_policy(&self) -> Option<&RetentionPolicy<T>> {
+        self.retention.as_ref()
+    }
+    //
+    // None turns expiry off
+    pub fn set_retention_policy(&mut self, policy: Option<RetentionPolicy<T>>) {
+        self.retention = policy;
+    }
+    //
+    pub fn run_retention(&mut self) -> Result<RetentionStats, DatastoreError>
+    where
+        T: Clone,
+    {
+        self.run_retention_at(unix_now())
+    }
+    //
+    // deletes every row stamped more than `max_age` before `now` (unix seconds) and reclaims the
+    // full pages left without rows. leading dead pages are retired in the header, later runs start
+    // after them
+    pub fn run_retention_at(&mut self, now: u64) -> Result<RetentionStats, DatastoreError>
+    where
+        T: Clone,
+    {
+        self.forbid_in_audit("retention")?;
+        if self.read_only {
+            return Err(DatastoreError::ReadOnly);
+        }
+        let Some(policy) = self.retention else {
+            return Err("no retention policy is set".into());
+        };
+        let cutoff = policy.cutoff(now);
+        let max_rows = SlottedPage::<T, PAGESZ, ROWS_PER_PAGE>::max_rows_per_page() as u32;
+        //
+        let mut stats = RetentionStats::default();
+        let first_page = self.access_header()?.retired_pages() as usize;
+        let mut retired = first_page;
+        for page_id in first_page..self.total_pages()? {
+            let (expired, full, live) = self.with_page(page_id, |page| {
+                let slot_count = page.access_header()?.slot_count();
+                let mut expired = Vec::new();
+                let mut live = 0;
+                //
+                for page_row_n in 0..slot_count {
+                    match page.access_row(page_row_n as usize)? {
+                        Some(row) if policy.is_expired(row, cutoff) => {
+                            expired.push(RowQuery::<T, PAGESZ, ROWS_PER_PAGE>::to_row_id(
+                                page_id, page_row_n,
+                            )?)
+                        }
+                        Some(_) => live += 1,
+                        None => {}
+                    }
+                }
+                Ok((expired, slot_count == max_rows, live))
+            })?;
+            //
+            for row_id in &expired {
+                self.delete(*row_id)?;
+            }
+            stats.expired_rows += expired.len() as u64;
+            //
+            // a page that isn't full still takes inserts
+            if !full || live > 0 {
+                continue;
+            }
+            if page_id == retired || !expired.is_empty() {
+                let reclaimed = self.reclaim_page(page_id)?;
+                if reclaimed > 0 {
+                    stats.reclaimed_pages += 1;
+                    stats.reclaimed_bytes += reclaimed;
+                }
+            }
+            if page_id == retired {
+                retired += 1;
+            }
+        }
+        //
+        if retired > first_page {
+            self.access_header_mut()?
+                .unseal()
+                .set_retired_pages(retired as u64);
+        }
+        if stats.expired_rows > 0 {
+            tracing::info!(
+                "expired {} rows of {:?}, reclaimed {} pages ({} bytes)",
+                stats.expired_rows,
+                self.path,
+                stats.reclaimed_pages,
+                stats.reclaimed_bytes
+            );
+        }
+        Ok(stats)
+    }
+    //
+    //  ###### VERIFY ######
+    //
+    // walks every page and collects what's structurally wrong with the file, then checks each
+    // row against the row type and, for audit ledgers, the hash chain
+    pub fn verify(&self) -> Result<VerifyReport, DatastoreError> {
+        let mut report = VerifyReport::new(&self.path);
+        report.encrypted = self.cipher.is_some();
+        let header = match LedgerHeader::access(&self.mmap[0..Self::LEDGER_HEADER_SZ as usize]) {
+            Ok(header) => header,
+            Err(e) => {
+                report.push(Anomaly::Header {
+                    reason: e.to_string(),
+                });
+                return Ok(report);
+            }
+        };
+        let num_pages = report.check_layout(
+            header,
+            self.header_sz,
+            Self::PAGE_SIZE as usize,
+            self.file.metadata()?.len(),
+        );
+        let page_cursor = header.page_cursor();
+        let max_rows = SlottedPage::<T, PAGESZ, ROWS_PER_PAGE>::max_rows_per_page() as u64;
+        //
+        for page_id in 0..num_pages {
+            let checked = self.with_page(page_id as usize, |page| {
+                let rows = report.check_page(
+                    page_id,
+                    &page.data,
+                    Some(size_of::<T>()),
+                    page_id > page_cursor,
+                );
+                for (page_row_n, offset) in rows {
+                    // copied out so the row is aligned whatever the slot says
+                    let mut row_bytes = AlignedVec::<16>::with_capacity(size_of::<T>());
+                    row_bytes.extend_from_slice(&page.data[offset..offset + size_of::<T>()]);
+                    let valid = access::<T, rkyv::rancor::Error>(&row_bytes)
+                        .map_err(DatastoreError::from)
+                        .and_then(|row| Ok(row.validate()?));
+                    if let Err(e) = valid {
+                        report.push(Anomaly::InvalidRow {
+                            row_id: RowId(page_id * max_rows + page_row_n as u64),
+                            reason: e.to_string(),
+                        });
+                    }
+                }
+                Ok(())
+            });
+            if let Err(e) = checked {
+                report.push(Anomaly::UnreadablePage {
+                    page_id,
+                    reason: e.to_string(),
+                });
+            }
+        }
+        //
+        if T::CHAIN_OFFSET.is_some() {
+            match self.verify_chain() {
+                Ok(None) => {}
+                Ok(Some(broken)) => report.push(Anomaly::BrokenChain {
+                    row_id: Some(broken.row_id),
+                    reason: broken.to_string(),
+                }),
+                Err(e) => report.push(Anomaly::BrokenChain {
+                    row_id: None,
+                    reason: e.to_string(),
+                }),
+            }
+        }
+        Ok(report)
+    }
+    //
+    //  ###### REPAIR ######
+    //
+    // brings a ledger left inconsistent by a crash back into a shape `open` accepts, see
+    // `repair.rs`. the ledger must not be open anywhere while it's repaired
+    pub fn repair<P>(ledger_path: P, options: RepairOptions) -> Result<RepairReport, DatastoreError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = ledger_path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(!options.dry_run)
+            .open(&path)?;
+        let file_len = file.metadata()?.len();
+        //
+        // the header names the ledger, without it there's nothing to repair
+        let mut header_bytes = AlignedVec::<16>::with_capacity(Self::LEDGER_HEADER_SZ as usize);
+        header_bytes.resize((Self::LEDGER_HEADER_SZ as u64).min(file_len) as usize, 0);
+        file.read_exact_at(&mut header_bytes, 0)?;
+        let header_sz = Self::header_size(&header_bytes, file_len)?;
+        let mut header = LedgerHeader::deserialize(&header_bytes)?;
+        if header.flags.is_set(HeaderFlags::Encrypted) {
+            return Err(DatastoreError::EncryptionUnsupported("repair"));
+        }
+        if header.flags.is_set(HeaderFlags::Audit) != T::CHAIN_OFFSET.is_some() {
+            return Err(format!(
+                "{:?} was created with a different audit mode than its row type",
+                path
+            )
+            .into());
+        }
+        //
+        let page_size = Self::PAGE_SIZE as usize;
+        // an unfinished transaction is rolled back first. its pages and header are read from the
+        // journal and written in place before anything else
+        let journal_path = Self::journal_path_of(&path);
+        let rollback = Journal::recover(&journal_path, page_size)?;
+        let journal = match &rollback {
+            Some(image) => Some(JournalAction::RolledBack {
+                pages: image.pages.len(),
+            }),
+            None if journal_path.exists() => Some(JournalAction::Discarded),
+            None => None,
+        };
+        // the length the ledger had before the transaction
+        let ledger_len = match &rollback {
+            Some(image) => {
+                if image.header.len() != header_sz {
+                    return Err("journal header does not match this ledger".into());
+                }
+                header_bytes.clear();
+                header_bytes.extend_from_slice(&image.header[0..Self::LEDGER_HEADER_SZ as usize]);
+                header = LedgerHeader::deserialize(&header_bytes)?;
+                image.original_len
+            }
+            None => file_len,
+        };
+        //
+        let row_size = size_of::<T>();
+        let max_rows = SlottedPage::<T, PAGESZ, ROWS_PER_PAGE>::max_rows_per_page();
+        let whole_pages = ledger_len.saturating_sub(header_sz as u64) / page_size as u64;
+        let mut compressed = match options.dry_run {
+            true => CompressedPages::open_read_only(&path)?,
+            false => CompressedPages::open(&path)?,
+        };
+        let quarantine_path = options
+            .quarantine_path
+            .clone()
+            .unwrap_or_else(|| crate::repair::quarantine_path(&path));
+        //
+        let mut report = RepairReport {
+            path: path.clone(),
+            dry_run: options.dry_run,
+            file_len_before: file_len,
+            num_pages_before: header.num_pages(),
+            page_cursor_before: header.page_cursor(),
+            journal,
+            ..RepairReport::default()
+        };
+        //
+        let mut pages = Vec::with_capacity(whole_pages as usize);
+        for page_id in 0..whole_pages {
+            let mut page = vec![0_u8; page_size];
+            if let Some(original) = rollback
+                .as_ref()
+                .and_then(|image| image.pages.get(&(page_id as usize)))
+                .filter(|original| original.len() == page_size)
+            {
+                page.copy_from_slice(original);
+                pages.push(Self::repair_page(page_id, page, &mut report));
+                continue;
+            }
+            let read = match compressed.contains(page_id as usize) {
+                true => compressed
+                    .read_into(page_id as usize, &mut page)
+                    .map(|_| ()),
+                false => {
+                    file.read_exact_at(&mut page, (header_sz + page_id as usize * page_size) as u64)
+                }
+            };
+            pages.push(match read {
+                Ok(()) => Self::repair_page(page_id, page, &mut report),
+                Err(e) => {
+                    tracing::warn!("couldn't read page {page_id} of {:?}. error:\n{e}", path);
+                    RepairedPage::Unreadable(None)
+                }
+            });
+        }
+        //
+        // the pages after the last readable one are garbage, a ledger keeps at least one page
+        let last_readable = pages
+            .iter()
+            .rposition(|page| matches!(page, RepairedPage::Keep | RepairedPage::Rewrite(..)));
+        let num_pages = last_readable.map_or(1, |last| last + 1);
+        let mut quarantined = Vec::new();
+        let mut writes = Vec::new();
+        for (page_id, page) in pages.into_iter().enumerate() {
+            let in_ledger = page_id < num_pages;
+            match page {
+                RepairedPage::Keep => {}
+                RepairedPage::Rewrite(page, original) => {
+                    quarantined.extend(original.map(|original| (page_id as u64, original)));
+                    writes.push((page_id, page));
+                }
+                RepairedPage::Unreadable(original) => {
+                    quarantined.extend(original.map(|original| (page_id as u64, original)));
+                    if in_ledger {
+                        report.replaced_pages.push(page_id as u64);
+                        writes.push((page_id, deleted_page(page_size, row_size, max_rows)));
+                    }
+                }
+                RepairedPage::Zeroed if in_ledger => {
+                    report.replaced_pages.push(page_id as u64);
+                    writes.push((page_id, deleted_page(page_size, row_size, max_rows)));
+                }
+                RepairedPage::Zeroed => {}
+            }
+        }
+        // nothing readable was left, start over with an empty first page
+        if last_readable.is_none() {
+            report.replaced_pages = vec![0];
+            writes.retain(|(page_id, _)| *page_id != 0);
+            writes.push((
+                0,
+                SlottedPage::<T, PAGESZ, ROWS_PER_PAGE>::new()?
+                    .data
+                    .to_vec(),
+            ));
+        }
+        report.quarantined_pages = quarantined.iter().map(|(page_id, _)| *page_id).collect();
+        if !quarantined.is_empty() {
+            report.quarantine_path = Some(quarantine_path.clone());
+        }
+        report.num_pages = num_pages as u64;
+        report.page_cursor = num_pages as u64 - 1;
+        report.file_len = (header_sz + num_pages * page_size) as u64;
+        //
+        if options.dry_run || report.is_clean() {
+            return Ok(report);
+        }
+        //
+        // the bytes that are about to be overwritten or cut off are kept first
+        crate::repair::quarantine(&quarantine_path, &quarantined)?;
+        if let Some(image) = &rollback {
+            for (page_id, page) in &image.pages {
+                if *page_id < whole_pages as usize && page.len() == page_size {
+                    file.write_all_at(page, (header_sz + page_id * page_size) as u64)?;
+                }
+            }
+            file.write_all_at(&image.header, 0)?;
+        }
+        for (page_id, page) in &writes {
+            file.write_all_at(page, (header_sz + page_id * page_size) as u64)?;
+        }
+        header.set_num_pages(num_pages as u64);
+        header.set_page_cursor(num_pages as u64 - 1);
+        // the schema page after the header is left as it is
+        file.write_all_at(
+            &header.serialize()?[0..header.size().min(Self::LEDGER_HEADER_SZ as usize)],
+            0,
+        )?;
+        file.set_len(report.file_len)?;
+        file.sync_all()?;
+        // the raw pages in the file are what counts now, and truncated pages are gone
+        for (page_id, _) in &writes {
+            compressed.mark_raw(*page_id)?;
+        }
+        for page_id in num_pages..whole_pages.max(report.num_pages_before) as usize {
+            compressed.mark_raw(page_id)?;
+        }
+        if let Some(image) = &rollback {
+            for page_id in image.pages.keys() {
+                compressed.mark_raw(*page_id)?;
+            }
+        }
+        // the pages it held are in place now, the next open mustn't roll them back again
+        Journal::discard(&journal_path)?;
+        drop(file);
+        //
+        // the sidecars describe rows that may be gone
+        let mut store = Self::open_file(
+            &path,
+            header.ledger_name().clone(),
+            header.ledger_description().clone(),
+        )?;
+        {
+            let merkle = store.merkle.get_mut();
+            merkle.truncate(num_pages);
+            for (page_id, _) in &writes {
+                merkle.invalidate(*page_id);
+            }
+        }
+        store.rebuild_indexes()?;
+        store.sync_all()?;
+        //
+        tracing::info!(
+            "repaired {:?}: {} pages, {} rebuilt, {} replaced, {} rows dropped, {} quarantined",
+            path,
+            num_pages,
+            report.rebuilt_pages.len(),
+            report.replaced_pages.len(),
+            report.dropped_rows.len(),
+            report.quarantined_pages.len()
+        );
+        Ok(report)
+    }
+    //
+    // checks one page read from the file, rebuilding its header from the slot array if the header
+    // is off and deleting the rows that don't validate
+    fn repair_page(page_id: u64, mut page: Vec<u8>, report: &mut RepairReport) -> RepairedPage {
+        if page.iter().all(|b| *b == 0) {
+            return RepairedPage::Zeroed;
+        }
+        let row_size = size_of::<T>();
+        let max_rows = SlottedPage::<T, PAGESZ, ROWS_PER_PAGE>::max_rows_per_page();
+        let original = page.clone();
+        //
+        let mut check = VerifyReport::default();
+        let mut rows = check.check_page(page_id, &page, Some(row_size), false);
+        let rebuilt = !check.is_ok();
+        if rebuilt {
+            let Some(header) = header_from_slots(&page, row_size, max_rows) else {
+                return RepairedPage::Unreadable(Some(original));
+            };
+            set_page_header(&mut page, &header);
+            let mut check = VerifyReport::default();
+            rows = check.check_page(page_id, &page, Some(row_size), false);
+            if !check.is_ok() {
+                return RepairedPage::Unreadable(Some(original));
+            }
+            report.rebuilt_pages.push(page_id);
+        }
+        //
+        let mut dropped = false;
+        for (page_row_n, offset) in rows {
+            let mut row_bytes = AlignedVec::<16>::with_capacity(row_size);
+            row_bytes.extend_from_slice(&page[offset..offset + row_size]);
+            let valid = access::<T, rkyv::rancor::Error>(&row_bytes)
+                .map_err(DatastoreError::from)
+                .and_then(|row| Ok(row.validate()?));
+            if valid.is_err() {
+                let at = PAGE_HEADER_SZ as usize + page_row_n as usize * 4;
+                page[at..at + 4].copy_from_slice(&(TOMBSTONE as u32).to_le_bytes());
+                report
+                    .dropped_rows
+                    .push(RowId(page_id * max_rows as u64 + page_row_n as u64));
+                dropped = true;
+            }
+        }
+        //
+        match (rebuilt, dropped) {
+            (false, false) => RepairedPage::Keep,
+            // the rows are intact, only the header changed
+            (true, false) => RepairedPage::Rewrite(page, None),
+            (_, true) => RepairedPage::Rewrite(page, Some(original)),
+        }
+    }
+    //
+    // punches out everything after the slot array of a page whose rows are all deleted. the
+    // tombstones stay, so the page still reads as deleted rows. returns the bytes freed
+    fn reclaim_page(&mut self, page_id: usize) -> Result<u64, DatastoreError> {
+        self.before_page_write(page_id)?;
+        //
+        let slots_end = self.access_page(page_id)?.access_header()?.free_start() as usize;
+        let start = self.page_start(page_id) + slots_end;
+        let len = Self::PAGE_SIZE as usize - slots_end;
+        // a hole in a sealed page wouldn't open again. the expired rows are zeroed before the
+        // page is sealed instead
+        if self.cipher.is_some() {
+            self.mmap[start..start + len].fill(0);
+            return Ok(0);
+        }
+        punch_hole(&self.file, start as u64, len as u64)?;
+        Ok(len as u64)
+    }
+    //
+    // brings a page into the mapping before anything modifies it. a compressed page is copied
+    // back into the ledger file, an encrypted one is decrypted into the plaintext copy
+    fn restore_page(&mut self, page_id: usize) -> Result<(), DatastoreError> {
+        let start = self.page_start(page_id);
+        if let Some(cipher) = self.cipher.as_mut() {
+            if cipher.is_resident(page_id) {
+                return Ok(());
+            }
+            let page = &mut self.mmap[start..start + Self::PAGE_SIZE as usize];
+            cipher.read_page(&self.file, page_id, page)?;
+            cipher.set_resident(page_id);
+            self.page_cache.get_mut().remove(page_id);
+            if cipher.num_resident() > PAGE_CACHE_PAGES {
+                self.evict_plaintext(page_id)?;
+            }
+            return Ok(());
+        }
+        if !self.compressed.read().contains(page_id) {
+            return Ok(());
+        }
+        //
+        let start = self.page_start(page_id);
+        let page = &mut self.mmap[start..start + Self::PAGE_SIZE as usize];
+        self.compressed.read().read_into(page_id, page)?;
+        //
+        if self.read_only {
+            // the private mapping of a checkpoint now holds the page
+            self.compressed.write().forget(page_id);
+        } else {
+            // the raw record only goes out once the page is back on disk
+            self.mmap.flush_range(start, Self::PAGE_SIZE as usize)?;
+            self.compressed.write().mark_raw(page_id)?;
+        }
+        self.page_cache.get_mut().remove(page_id);
+        Ok(())
+    }
+    //
+    // seals the plaintext copy and hands its clean pages back to the OS, all but `keep` and the
+    // last page. a page that's written again is decrypted back in
+    fn evict_plaintext(&mut self, keep: usize) -> Result<(), DatastoreError> {
+        self.flush_pages()?;
+        let last_page = self.total_pages()?.saturating_sub(1);
+        let Some(cipher) = self.cipher.as_mut() else {
+            return Ok(());
+        };
+        for page_id in cipher.evictable(&[keep, last_page]) {
+            cipher.set_evicted(page_id);
+            let start = self.header_sz + page_id * Self::PAGE_SIZE as usize;
+            release_range(&self.mmap, start, Self::PAGE_SIZE as usize)?;
+        }
+        Ok(())
+    }
+    //
+    // a copy of a page that isn't in the mapping, compressed or still encrypted in the file, at
+    // the alignment it has in the ledger file. None for pages in the mapping
+    fn load_page(&self, page_id: usize) -> Result<Option<AlignedVec<16>>, DatastoreError> {
+        if self.in_mapping(page_id) || page_id >= self.total_pages()? {
+            return Ok(None);
+        }
+        //
+        let misalign = self.page_misalign(page_id);
+        let mut buf = AlignedVec::<16>::with_capacity(misalign + Self::PAGE_SIZE as usize);
+        buf.resize(misalign + Self::PAGE_SIZE as usize, 0);
+        match self.cipher.as_ref() {
+            Some(cipher) => cipher.read_page(&self.file, page_id, &mut buf[misalign..])?,
+            None => {
+                self.compressed
+                    .read()
+                    .read_into(page_id, &mut buf[misalign..])?;
+            }
+        }
+        Ok(Some(buf))
+    }
+    //
+    // false for pages read through `load_page`
+    #[inline(always)]
+    fn in_mapping(&self, page_id: usize) -> bool {
+        match self.cipher.as_ref() {
+            Some(cipher) => cipher.is_resident(page_id),
+            None => !self.compressed.read().contains(page_id),
+        }
+    }
+    //
+    // a page outside the mapping through the page cache. None for pages in the mapping
+    fn cached_page(&self, page_id: usize) -> Result<Option<Arc<AlignedVec<16>>>, DatastoreError> {
+        if self.in_mapping(page_id) {
+            return Ok(None);
+        }
+        //
+        let mut cache = self.page_cache.lock();
+        if let Some(buf) = cache.get(page_id) {
+            return Ok(Some(buf));
+        }
+        Ok(self
+            .load_page(page_id)?
+            .map(|buf| cache.insert(page_id, buf)))
+    }
+    //
+    // runs `f` on the page. a compressed page is decompressed into a scratch buffer rather than
+    // the page cache, so a scan doesn't leave every page it passed decompressed
+    fn with_page<R, F>(&self, page_id: usize, f: F) -> Result<R, DatastoreError>
+    where
+        F: FnOnce(&SlottedPage<T, PAGESZ, ROWS_PER_PAGE>) -> Result<R, DatastoreError>,
+    {
+        match self.load_page(page_id)? {
+            Some(buf) => f(access::<
+                SlottedPage<T, PAGESZ, ROWS_PER_PAGE>,
+                rkyv::rancor::Error,
+            >(&buf[self.page_misalign(page_id)..])?),
+            None => f(&*self.access_page(page_id)?),
+        }
+    }
+    //
+    // rows are validated at their address, copies of a page keep its offset from 16 bytes
+    #[inline(always)]
+    fn page_misalign(&self, page_id: usize) -> usize {
+        self.page_start(page_id) % 16
+    }
+    //
+    //  ###### EXPORT ######
+    //
+    // every live row as csv, a header row with `row_id` and the field names first. returns the
+    // number of rows written
+    pub fn export_csv<W: Write>(&self, writer: W) -> Result<u64, DatastoreError> {
+        let mut writer = BufWriter::new(writer);
+        export::write_csv_header(&mut writer, T::FIELD_NAMES)?;
+        self.export_rows(&mut writer, |w, row_id, values| {
+            export::write_csv_row(w, row_id, values)
+        })
+    }
+    //
+    // every live row as one json object per line, keyed by field name after `row_id`
+    pub fn export_jsonl<W: Write>(&self, writer: W) -> Result<u64, DatastoreError> {
+        let mut writer = BufWriter::new(writer);
+        self.export_rows(&mut writer, |w, row_id, values| {
+            export::write_jsonl_row(w, T::FIELD_NAMES, row_id, values)
+        })
+    }
+    //
+    fn export_rows<W, F>(
+        &self,
+        writer: &mut BufWriter<W>,
+        mut write_row: F,
+    ) -> Result<u64, DatastoreError>
+    where
+        W: Write,
+        F: FnMut(&mut BufWriter<W>, RowId, &[FieldValue<'_>]) -> std::io::Result<()>,
+    {
+        let mut rows = 0;
+        // for_each_row can't be stopped, the first write error is kept and the rest skipped
+        let mut error = None;
+        self.for_each_row(|row_id, row| {
+            if error.is_none() {
+                match write_row(writer, row_id, &row.field_values()) {
+                    Ok(()) => rows += 1,
+                    Err(e) => error = Some(e),
+                }
+            }
+        })?;
+        if let Some(e) = error {
+            return Err(e.into());
+        }
+        writer.flush()?;
+        Ok(rows)
+    }
+    //
+    // every live row as an arrow ipc stream, schema from `arrow::arrow_schema`. pages are read one
+    // at a time and a record batch is written every `batch_rows` rows. returns the number of rows
+    #[cfg(feature = "arrow")]
+    pub fn to_arrow_ipc<W: Write>(
+        &self,
+        writer: W,
+        batch_rows: usize,
+    ) -> Result<u64, DatastoreError> {
+        let batch_rows = batch_rows.max(1);
+        let mut batch = BatchBuilder::new::<T>(batch_rows);
+        let mut ipc = StreamWriter::try_new(BufWriter::new(writer), batch.schema())?;
+        let mut rows = 0;
+        //
+        for page_id in 0..self.total_pages()? {
+            self.with_page(page_id, |page| {
+                let slot_count = page.access_header()?.slot_count();
+                for page_row_n in 0..slot_count {
+                    if let Some(row) = page.access_row(page_row_n as usize)? {
+                        let row_id =
+                            RowQuery::<T, PAGESZ, ROWS_PER_PAGE>::to_row_id(page_id, page_row_n)?;
+                        batch.push(row_id, &row.field_values())?;
+                        rows += 1;
+                    }
+                    if batch.len() == batch_rows {
+                        ipc.write(&batch.finish()?)?;
+                    }
+                }
+                Ok(())
+            })?;
+        }
+        if batch.len() > 0 {
+            ipc.write(&batch.finish()?)?;
+        }
+        ipc.finish()?;
+        ipc.into_inner()?.flush()?;
+        Ok(rows)
+    }
+    //
+    // every live row as a parquet file, one row group per `pages_per_row_group` pages. the schema
+    // is the one of the arrow export. returns the number of rows
+    #[cfg(feature = "parquet")]
+    pub fn to_parquet<W>(&self, writer: W, options: ParquetOptions) -> Result<u64, DatastoreError>
+    where
+        W: Write + Send,
+    {
+        let pages_per_row_group = options.pages_per_row_group.max(1);
+        let mut batch = BatchBuilder::new::<T>(ROWS_PER_PAGE);
+        let mut parquet = ArrowWriter::try_new(
+            writer,
+            Arc::new(batch.schema().clone()),
+            Some(options.writer_properties()?),
+        )?;
+        let mut rows = 0;
+        //
+        for page_id in 0..self.total_pages()? {
+            self.with_page(page_id, |page| {
+                let slot_count = page.access_header()?.slot_count();
+                for page_row_n in 0..slot_count {
+                    if let Some(row) = page.access_row(page_row_n as usize)? {
+                        let row_id =
+                            RowQuery::<T, PAGESZ, ROWS_PER_PAGE>::to_row_id(page_id, page_row_n)?;
+                        batch.push(row_id, &row.field_values())?;
+                        rows += 1;
+                    }
+                }
+                Ok(())
+            })?;
+            if batch.len() > 0 {
+                parquet.write(&batch.finish()?)?;
+            }
+            // ends the row group, a no-op when it holds no rows
+            if (page_id + 1).is_multiple_of(pages_per_row_group) {
+                parquet.flush()?;
+            }
+        }
+        parquet.close()?;
+        Ok(rows)
+    }
+    //
+    //  ###### IMPORT ######
+    //
+    // rows from csv with a header row naming the fields, e.g. the output of export_csv. records
+    // that don't parse are skipped and reported by line, the others are inserted `batch_size` at a
+    // time, each batch in its own transaction
+    pub fn import_csv<R: Read>(
+        &mut self,
+        reader: R,
+        options: ImportOptions,
+    ) -> Result<ImportReport, DatastoreError>
+    where
+        T: Clone,
+    {
+        let mut csv = CsvReader::new(BufReader::new(reader));
+        let columns: HashMap<String, usize> = match csv.next_record()? {
+            None => return Ok(ImportReport::default()),
+            Some((line, Err(e))) => return Err(format!("csv header on line {line}: {e}").into()),
+            Some((_, Ok(names))) => names
+                .into_iter()
+                .enumerate()
+                .map(|(column, name)| (name.trim().to_string(), column))
+                .collect(),
+        };
+        //
+        self.import_records(&options, || {
+            let Some((line, cells)) = csv.next_record()? else {
+                return Ok(None);
+            };
+            let row = cells
+                .map_err(|reason| ImportError {
+                    line,
+                    field: None,
+                    reason,
+                })
+                .and_then(|cells| match cells.len() == columns.len() {
+                    true => {
+                        T::from_record(&Record::csv(line, options.on_overflow, &columns, &cells))
+                    }
+                    false => Err(ImportError {
+                        line,
+                        field: None,
+                        reason: format!("{} cells, the header has {}", cells.len(), columns.len()),
+                    }),
+                });
+            Ok(Some(row))
+        })
+    }
+    //
+    // rows from one json object per line, keyed by field name, e.g. the output of export_jsonl
+    pub fn import_jsonl<R: Read>(
+        &mut self,
+        reader: R,
+        options: ImportOptions,
+    ) -> Result<ImportReport, DatastoreError>
+    where
+        T: Clone,
+    {
+        let mut lines = BufReader::new(reader).lines();
+        let mut line = 0;
+        //
+        self.import_records(&options, || {
+            loop {
+                let Some(text) = lines.next().transpose()? else {
+                    return Ok(None);
+                };
+                line += 1;
+                if text.trim().is_empty() {
+                    continue;
+                }
+                let row = match serde_json::from_str::<serde_json::Value>(&text) {
+                    Ok(serde_json::Value::Object(object)) => {
+                        T::from_record(&Record::json(line, options.on_overflow, &object))
+                    }
+                    Ok(_) => Err(ImportError {
+                        line,
+                        field: None,
+                        reason: "not a json object".to_string(),
+                    }),
+                    Err(e) => Err(ImportError {
+                        line,
+                        field: None,
+                        reason: e.to_string(),
+                    }),
+                };
+                return Ok(Some(row));
+            }
+        })
+    }
+    //
+    fn import_records<F>(
+        &mut self,
+        options: &ImportOptions,
+        mut next_row: F,
+    ) -> Result<ImportReport, DatastoreError>
+    where
+        T: Clone,
+        F: FnMut() -> Result<Option<Result<T, ImportError>>, DatastoreError>,
+    {
+        let mut report = ImportReport::default();
+        let mut batch = Vec::with_capacity(options.batch_size.max(1));
+        while let Some(row) = next_row()? {
+            report.records += 1;
+            match row {
+                Ok(row) => batch.push(row),
+                Err(e) => {
+                    report.errors.push(e);
+                    if options.stop_on_error {
+                        break;
+                    }
+                }
+            }
+            if batch.len() >= options.batch_size.max(1) {
+                self.insert_batch(&mut batch, &mut report)?;
+            }
+        }
+        self.insert_batch(&mut batch, &mut report)?;
+        Ok(report)
+    }
+    //
+    fn insert_batch(
+        &mut self,
+        batch: &mut Vec<T>,
+        report: &mut ImportReport,
+    ) -> Result<(), DatastoreError>
+    where
+        T: Clone,
+    {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let (first, last) = self.transaction(|tx| {
+            let first = tx.insert(&batch[0])?;
+            let mut last = first;
+            for row in &batch[1..] {
+                last = tx.insert(row)?;
+            }
+            Ok((first, last))
+        })?;
+        report.first_row.get_or_insert(first);
+        report.last_row = Some(last);
+        report.imported += batch.len() as u64;
+        batch.clear();
+        Ok(())
+    }
+    //
+    //  ###### SNAPSHOTS ######
+    //
+    // a consistent read view of every row inserted so far. later inserts, updates and deletes are
+    // not visible to it, and the snapshot can be sent to another thread while writes continue
+    pub fn snapshot(&self) -> Result<Snapshot<T, PAGESZ, ROWS_PER_PAGE>, DatastoreError>
+    where
+        T: Clone,
+    {
+        if self.read_only {
+            // the file behind a checkpoint's private mapping is not the checkpoint
+            return Err(DatastoreError::ReadOnly);
+        }
+        // the mapping would only see ciphertext
+        self.forbid_encrypted("snapshots")?;
+        //
+        let num_rows = self.num_rows()?;
+        let pages = Arc::new(SnapshotPages::new(self.total_pages()?));
+        //
+        // only the pages that existed now are read through this mapping, later growth is ignored
+        let mmap = unsafe { Mmap::map(&self.file)? };
+        //
+        let mut snapshots = self.snapshots.lock();
+        snapshots.retain(|weak| weak.strong_count() > 0);
+        snapshots.push(Arc::downgrade(&pages));
+        //
+        Ok(Snapshot::new(
+            mmap,
+            self.header_sz,
+            pages,
+            self.compressed.clone(),
+            num_rows,
+        ))
+    }
+    //
+    //  ###### CHECKPOINTS ######
+    //
+    // flushes the ledger and records it as a named restore point. returns how it was stored:
+    // a reflink shares blocks with the ledger, an undo log costs one page copy per page modified later
+    pub fn checkpoint(&mut self, name: &str) -> Result<CheckpointKind, DatastoreError> {
+        if self.read_only {
+            return Err(DatastoreError::ReadOnly);
+        }
+        self.forbid_encrypted("checkpoints")?;
+        validate_name(name)?;
+        //
+        let dir = checkpoint_dir(&self.path);
+        std::fs::create_dir_all(&dir)?;
+        let mut manifest = CheckpointManifest::load(&dir)?;
+        if manifest.position(name).is_some() {
+            return Err(format!("checkpoint {name:?} already exists").into());
+        }
+        //
+        self.mmap.flush()?;
+        self.file.sync_all()?;
+        //
+        // a clone would share the holes of compressed pages, whose segment keeps changing
+        let reflink_path = checkpoint_path(&dir, name, CheckpointKind::Reflink);
+        if self.compressed.read().is_empty() && reflink(&self.file, &reflink_path)? {
+            manifest.push(CheckpointKind::Reflink, name);
+            if let Err(e) = manifest.save(&dir) {
+                std::fs::remove_file(&reflink_path)?;
+                return Err(e);
+            }
+            tracing::info!("checkpoint {name:?} of {:?} (reflink)", self.path);
+            return Ok(CheckpointKind::Reflink);
+        }
+        //
+        let log_path = checkpoint_path(&dir, name, CheckpointKind::UndoLog);
+        let log = Journal::begin_log(
+            log_path.clone(),
+            &self.mmap[0..self.header_sz],
+            self.file.metadata()?.len(),
+        )?;
+        manifest.push(CheckpointKind::UndoLog, name);
+        if let Err(e) = manifest.save(&dir) {
+            drop(log);
+            std::fs::remove_file(&log_path)?;
+            return Err(e);
+        }
+        // the previous log is complete now, later writes are preserved in the new one
+        self.checkpoint_log = Some(log);
+        //
+        tracing::info!("checkpoint {name:?} of {:?} (undo log)", self.path);
+        Ok(CheckpointKind::UndoLog)
+    }
+    //
+    // checkpoint names, oldest first
+    pub fn checkpoints(&self) -> Result<Vec<String>, DatastoreError> {
+        let manifest = CheckpointManifest::load(&checkpoint_dir(&self.path))?;
+        Ok(manifest.entries.into_iter().map(|(_, name)| name).collect())
+    }
+    //
+    // an undo log is folded into the previous log before it is deleted, older checkpoints need its pages
+    pub fn remove_checkpoint(&mut self, name: &str) -> Result<(), DatastoreError> {
+        if self.read_only {
+            return Err(DatastoreError::ReadOnly);
+        }
+        let dir = checkpoint_dir(&self.path);
+        let mut manifest = CheckpointManifest::load(&dir)?;
+        let Some(pos) = manifest.position(name) else {
+            return Err(DatastoreError::CheckpointNotFound(name.to_string()));
+        };
+        let (kind, _) = manifest.entries.remove(pos);
+        let path = checkpoint_path(&dir, name, kind);
+        //
+        if kind == CheckpointKind::UndoLog {
+            let active = manifest.entries[pos..]
+                .iter()
+                .all(|(kind, _)| *kind != CheckpointKind::UndoLog);
+            let previous = manifest.entries[..pos]
+                .iter()
+                .rev()
+                .find(|(kind, _)| *kind == CheckpointKind::UndoLog);
+            //
+            let mut previous_log = match previous {
+                Some((kind, previous)) => Journal::resume_log(
+                    checkpoint_path(&dir, previous, *kind),
+                    Self::PAGE_SIZE as usize,
+                )?,
+                None => None,
+            };
+            //
+            if let Some(previous_log) = previous_log.as_mut() {
+                let image = Journal::recover(&path, Self::PAGE_SIZE as usize)?.unwrap_or_default();
+                for (page_id, page) in &image.pages {
+                    let start = self.page_start(*page_id) as u64;
+                    if start < previous_log.original_len() {
+                        previous_log.save_page(*page_id, page)?;
+                    }
+                }
+            }
+            //
+            manifest.save(&dir)?;
+            if active {
+                self.checkpoint_log = previous_log;
+            }
+        } else {
+            manifest.save(&dir)?;
+        }
+        //
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+    //
+    fn resume_checkpoint_log(&mut self) -> Result<(), DatastoreError> {
+        let dir = checkpoint_dir(&self.path);
+        let manifest = CheckpointManifest::load(&dir)?;
+        let Some(name) = manifest.newest_log() else {
+            return Ok(());
+        };
+        //
+        let path = checkpoint_path(&dir, name, CheckpointKind::UndoLog);
+        self.checkpoint_log = Journal::resume_log(path, Self::PAGE_SIZE as usize)?;
+        if self.checkpoint_log.is_none() {
+            return Err(format!("checkpoint {name:?} is unreadable").into());
+        }
+        Ok(())
+    }
+    //
+    // opens a checkpoint of the ledger. reads work as usual, indexes are rebuilt in memory and
+    // every mutation returns DatastoreError::ReadOnly
+    pub fn open_checkpoint<P>(
+        folder_path: P,
+        ledger_name: LedgerName,
+        checkpoint_name: &str,
+    ) -> Result<Self, DatastoreError>
+    where
+        P: AsRef<Path>,
+    {
+        let ledger_path = folder_path.as_ref().join(ledger_name.to_string());
+        let dir = checkpoint_dir(&ledger_path);
+        let manifest = CheckpointManifest::load(&dir)?;
+        let Some(pos) = manifest.position(checkpoint_name) else {
+            return Err(DatastoreError::CheckpointNotFound(
+                checkpoint_name.to_string(),
+            ));
+        };
+        //
+        let (kind, _) = manifest.entries[pos];
+        let path = checkpoint_path(&dir, checkpoint_name, kind);
+        let file = match kind {
+            CheckpointKind::Reflink => File::open(&path)?,
+            CheckpointKind::UndoLog => File::open(&ledger_path)?,
+        };
+        // private mapping, nothing written through it ever reaches the file
+        let mut mmap = unsafe { MmapOptions::new().map_copy(&file)? };
+        let header_sz = Self::header_size(&mmap, mmap.len() as u64)?;
+        //
+        // pages compressed since the checkpoint are holes in the ledger file, read them from the
+        // segment unless an undo log restores an older image
+        let mut compressed = match kind {
+            CheckpointKind::Reflink => CompressedPages::default(),
+            CheckpointKind::UndoLog => CompressedPages::open_read_only(&ledger_path)?,
+        };
+        if kind == CheckpointKind::UndoLog {
+            // newest log first so the checkpoint's own images are applied last
+            for (kind, name) in manifest.entries[pos..].iter().rev() {
+                if *kind != CheckpointKind::UndoLog {
+                    continue;
+                }
+                let log_path = checkpoint_path(&dir, name, *kind);
+                let Some(image) = Journal::recover(&log_path, Self::PAGE_SIZE as usize)? else {
+                    return Err(format!("checkpoint {name:?} is unreadable").into());
+                };
+                Self::apply_image_to(&mut mmap, header_sz, &image)?;
+                for page_id in image.pages.keys() {
+                    compressed.forget(*page_id);
+                }
+            }
+        }
+        //
+        let mut store = Self {
+            file,
+            mmap,
+            path,
+            header_sz,
+            indexes: Indexes::in_memory::<T>(),
+            merkle: Mutex::new(MerkleCache::in_memory()),
+            signer: None,
+            journal: None,
+            checkpoint_log: None,
+            read_only: true,
+            snapshots: Mutex::new(Vec::new()),
+            compressed: Arc::new(RwLock::new(compressed)),
+            page_cache: Mutex::new(PageCache::default()),
+            cipher: None,
+            retention: RetentionPolicy::from_schema(),
+            phantom: PhantomData,
+        };
+        //
+        if store.indexes.is_stale(store.num_rows()?) {
+            store.rebuild_indexes()?;
+        }
+        //
+        Ok(store)
+    }
+    //
+    #[inline(always)]
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+    //
+    #[inline(always)]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    //
+    fn journal_path(&self) -> PathBuf {
+        Self::journal_path_of(&self.path)
+    }
+    //
+    fn journal_path_of(ledger_path: &Path) -> PathBuf {
+        let mut name = ledger_path.as_os_str().to_os_string();
+        name.push(".journal");
+        PathBuf::from(name)
+    }
+    //
+    pub(crate) fn begin_journal(&mut self) -> Result<(), DatastoreError> {
+        if self.read_only {
+            return Err(DatastoreError::ReadOnly);
+        }
+        if self.journal.is_some() {
+            return Err("a transaction is already running".into());
+        }
+        //
+        let header = &self.mmap[0..self.header_sz];
+        let original_len = self.file.metadata()?.len();
+        //
+        self.journal = Some(Journal::begin(self.journal_path(), header, original_len)?);
+        Ok(())
+    }
+    //
+    pub(crate) fn commit_journal(&mut self) -> Result<(), DatastoreError> {
+        let flushed = self.flush_pages().and_then(|_| self.file.sync_all());
+        if let Err(e) = flushed {
+            tracing::error!("couldn't flush transaction, rolling back. error:\n{e}");
+            self.abort_journal()?;
+            return Err(e.into());
+        }
+        //
+        if let Some(journal) = self.journal.take() {
+            journal.finish()?;
+        }
+        Ok(())
+    }
+    //
+    pub(crate) fn rollback_journal(
+        &mut self,
+        touched: &BTreeSet<RowId>,
+    ) -> Result<(), DatastoreError>
+    where
+        T: Clone,
+    {
+        let Some(journal) = self.journal.take() else {
+            return Ok(());
+        };
+        //
+        let current = self.touched_rows(touched);
+        for (row_id, row) in &current {
+            self.indexes.remove_row(*row_id, row);
+        }
+        //
+        let (path, image) = journal.into_image();
+        self.apply_journal_image(&image)?;
+        Journal::discard(&path)?;
+        //
+        let restored = self.touched_rows(touched);
+        for (row_id, row) in &restored {
+            self.indexes.insert_row(*row_id, row);
+        }
+        //
+        Ok(())
+    }
+    //
+    // rollback without knowing which rows were staged. the indexes are rebuilt from the restored
+    // rows so searches don't return rolled back ones. if that fails they're left for the next open
+    pub(crate) fn abort_journal(&mut self) -> Result<(), DatastoreError> {
+        let Some(journal) = self.journal.take() else {
+            return Ok(());
+        };
+        //
+        let (path, image) = journal.into_image();
+        self.indexes.invalidate();
+        self.apply_journal_image(&image)?;
+        Journal::discard(&path)?;
+        //
+        if let Err(e) = self.rebuild_indexes() {
+            self.indexes.invalidate();
+            return Err(e);
+        }
+        Ok(())
+    }
+    //
+    fn recover_journal(&mut self) -> Result<(), DatastoreError> {
+        let path = self.journal_path();
+        let Some(image) = Journal::recover(&path, self.journal_page_size())? else {
+            Journal::discard(&path)?;
+            return Ok(());
+        };
+        //
+        tracing::warn!(
+            "rolling back unfinished transaction on {:?} ({} pages)",
+            self.path,
+            image.pages.len()
+        );
+        self.apply_journal_image(&image)?;
+        Journal::discard(&path)?;
+        self.indexes.invalidate();
+        //
+        Ok(())
+    }
+    //
+    fn apply_journal_image(&mut self, image: &JournalImage) -> Result<(), DatastoreError> {
+        let merkle = self.merkle.get_mut();
+        for page_id in image.pages.keys() {
+            merkle.invalidate(*page_id);
+        }
+        let original_pages =
+            (image.original_len as usize).saturating_sub(self.header_sz) / Self::PAGE_SIZE as usize;
+        merkle.truncate(original_pages);
+        //
+        let opened;
+        let image = match self.cipher.as_mut() {
+            Some(cipher) => {
+                cipher.truncate(original_pages);
+                let mut pages = HashMap::new();
+                for (page_id, page) in &image.pages {
+                    pages.insert(*page_id, cipher.open_image(*page_id, page)?);
+                    cipher.set_resident(*page_id);
+                    cipher.mark_dirty(*page_id);
+                }
+                opened = JournalImage {
+                    original_len: image.original_len,
+                    header: image.header.clone(),
+                    pages,
+                };
+                &opened
+            }
+            None => image,
+        };
+        //
+        Self::apply_image_to(&mut self.mmap, self.header_sz, image)?;
+        self.page_cache.get_mut().clear();
+        self.flush_pages()?;
+        //
+        if self.file.metadata()?.len() != image.original_len {
+            self.file.set_len(image.original_len)?;
+            self.remap(image.original_len as usize)?;
+        }
+        self.file.sync_all()?;
+        //
+        Ok(())
+    }
+    //
+    fn apply_image_to(
+        mmap: &mut MmapMut,
+        header_sz: usize,
+        image: &JournalImage,
+    ) -> Result<(), DatastoreError> {
+        if image.header.len() != header_sz {
+            return Err("journal header does not match this ledger".into());
+        }
+        //
+        for (page_id, page) in &image.pages {
+            let start = header_sz + page_id * Self::PAGE_SIZE as usize;
+            if page.len() != Self::PAGE_SIZE as usize || start + page.len() > mmap.len() {
+                return Err(format!("journal page {page_id} does not fit this ledger").into());
+            }
+            mmap[start..start + page.len()].copy_from_slice(page);
+        }
+        mmap[0..header_sz].copy_from_slice(&image.header);
+        //
+        Ok(())
+    }
+    //
+    fn touched_rows(&self, touched: &BTreeSet<RowId>) -> Vec<(RowId, T)>
+    where
+        T: Clone,
+    {
+        touched
+            .iter()
+            .filter_map(|row_id| match self.access_row(*row_id) {
+                Ok(Some(row)) => Some((*row_id, row.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+    //
+    //  ###### INDEXES ######
+    //
+    // row ids whose #[fulltext] field has a term starting with every term of the query
+    pub fn search(&self, field: &str, query: &str) -> Result<Vec<RowId>, DatastoreError> {
+        Ok(self
+            .indexes
+            .fulltext(field)?
+            .search(query)
+            .into_iter()
+            .map(RowId)
+            .collect())
+    }
+    //
+    // row ids whose #[bitmap_index] field equals the value. signed values are passed as `v as i64 as u64`
+    pub fn rows_where_eq(&self, field: &str, value: u64) -> Result<RowSet, DatastoreError> {
+        self.indexes.rows_eq(field, value)
+    }
+    //
+    pub fn rebuild_indexes(&mut self) -> Result<(), DatastoreError> {
+        if self.indexes.is_empty() {
+            return Ok(());
+        }
+        tracing::info!("rebuilding indexes for {:?}", self.path);
+        //
+        let mut indexes = std::mem::take(&mut self.indexes);
+        indexes.clear();
+        let scanned = self.for_each_row(|row_id, row| indexes.insert_row(row_id, row));
+        self.indexes = indexes;
+        scanned?;
+        //
+        self.indexes.save()?;
+        Ok(())
+    }
+}
+
+// ### ROW ID
+
+// a row's position in the ledger, `page_id * max_rows_per_page + page_row_n`. ids are never reused
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct RowId(pub u64);
+
+impl RowId {
+    #[inline(always)]
+    pub const fn new(v: u64) -> Self {
+        Self(v)
+    }
+    //
+    #[inline(always)]
+    pub const fn get(self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for RowId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<u64> for RowId {
+    fn from(v: u64) -> Self {
+        Self(v)
+    }
+}
+
+impl From<u32> for RowId {
+    fn from(v: u32) -> Self {
+        Self(v as u64)
+    }
+}
+
+impl From<RowId> for u64 {
+    fn from(row_id: RowId) -> u64 {
+        row_id.0
+    }
+}
+
+// ### ROW QUERY
+
+#[derive(Debug)]
+pub struct RowQuery<T, const PAGESZ: usize, const ROWS_PER_PAGE: usize> {
+    pub page_id: u64,
+    pub page_row_n: u32,
+    //
+    phantom: PhantomData<T>,
+}
+
+impl<T, const PAGESZ: usize, const ROWS_PER_PAGE: usize> RowQuery<T, PAGESZ, ROWS_PER_PAGE>
+where
+    T: Archive
+        + Portable
+        + PageSchema
+        + for<'b> bytecheck::CheckBytes<
+            bytecheck::rancor::Strategy<
+                rkyv::validation::Validator<
+                    rkyv::validation::archive::ArchiveValidator<'b>,
+                    rkyv::validation::shared::SharedValidator,
+                >,
+                bytecheck::rancor::Error,
+            >,
+        >,
+{
+    // Err instead of wrapping around when the page is past the last addressable row
+    #[inline(always)]
+    pub fn to_row_id(page_id: usize, row_n: u32) -> Result<RowId, DatastoreError> {
+        let max_rows = SlottedPage::<T, PAGESZ, ROWS_PER_PAGE>::max_rows_per_page() as u64;
+        (page_id as u64)
+            .checked_mul(max_rows)
+            .and_then(|first_row| first_row.checked_add(row_n as u64))
+            .map(RowId)
+            .ok_or(DatastoreError::RowIdOverflow(
+                "page is past the last row id",
+            ))
+    }
+    //
+    #[inline(always)]
+    pub const fn from_row_id(row_id: RowId) -> RowQuery<T, PAGESZ, ROWS_PER_PAGE> {
+        let max_rows = SlottedPage::<T, PAGESZ, ROWS_PER_PAGE>::max_rows_per_page() as u64;
+        let page_id = row_id.0 / max_rows;
+        let page_row_n = (row_id.0 % max_rows) as u32;
         //
         RowQuery::<T, PAGESZ, ROWS_PER_PAGE> {
             page_id,
@@ -2,3 +2,31 @@ pub mod header;
 pub mod page;
 pub mod utils;
 pub mod ledger;
+pub mod index;
+pub mod fulltext;
+pub mod vector;
+pub mod bitmap;
+pub mod transaction;
+pub mod snapshot;
+pub mod compression;
+pub mod retention;
+pub mod inspect;
+pub mod verify;
+pub mod repair;
+pub mod export;
+pub mod import;
+pub mod schema;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+pub mod encryption;
+pub mod segmented;
+pub mod checkpoint;
+pub mod audit;
+pub mod merkle;
+pub mod signing;
+
+// the serde impls #[ledger] generates go through this, users don't need their own dependency
+#[doc(hidden)]
+pub use serde;
@@ -0,0 +1,305 @@
+use crate::ledger::RowId;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+//
+const MERKLE_MAGIC: [u8; 4] = *b"LMRK";
+const MERKLE_VERSION: u8 = 1;
+//
+pub type Hash = [u8; 32];
+//
+// ###### MERKLE TREE ######
+//
+// two levels of RFC 6962 style trees. each page hashes to the root of a tree over its rows
+// (leaf = H(0x00 || row bytes), node = H(0x01 || left || right)), and the ledger root is the
+// root of a tree over the page hashes. a deleted row keeps its position as H(0x02).
+//
+pub fn leaf_hash(row: &[u8]) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([0x00]);
+    hasher.update(row);
+    hasher.finalize().into()
+}
+//
+pub fn deleted_leaf_hash() -> Hash {
+    Sha256::digest([0x02]).into()
+}
+//
+pub fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([0x01]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+//
+// root of the tree over `leaves`. the empty tree hashes to H("")
+pub fn root(leaves: &[Hash]) -> Hash {
+    match leaves.len() {
+        0 => Sha256::digest([]).into(),
+        1 => leaves[0],
+        n => {
+            let k = split(n);
+            node_hash(&root(&leaves[..k]), &root(&leaves[k..]))
+        }
+    }
+}
+//
+// sibling hashes from the leaf up to the root
+pub fn audit_path(index: usize, leaves: &[Hash]) -> Vec<Hash> {
+    let (mut index, mut leaves) = (index, leaves);
+    let mut path = Vec::new();
+    while leaves.len() > 1 {
+        let k = split(leaves.len());
+        if index < k {
+            path.push(root(&leaves[k..]));
+            leaves = &leaves[..k];
+        } else {
+            path.push(root(&leaves[..k]));
+            leaves = &leaves[k..];
+            index -= k;
+        }
+    }
+    // collected from the root down
+    path.reverse();
+    path
+}
+//
+// recomputes the root from a leaf and its audit path (RFC 9162 2.1.3.2)
+pub fn root_from_path(index: u64, size: u64, leaf: Hash, path: &[Hash]) -> Option<Hash> {
+    if index >= size {
+        return None;
+    }
+    let (mut f, mut s) = (index, size - 1);
+    let mut r = leaf;
+    for p in path {
+        if s == 0 {
+            return None;
+        }
+        if f & 1 == 1 || f == s {
+            r = node_hash(p, &r);
+            while f & 1 == 0 && f != 0 {
+                f >>= 1;
+                s >>= 1;
+            }
+        } else {
+            r = node_hash(&r, p);
+        }
+        f >>= 1;
+        s >>= 1;
+    }
+    (s == 0).then_some(r)
+}
+//
+// largest power of two smaller than n
+#[inline(always)]
+fn split(n: usize) -> usize {
+    1 << (usize::BITS - 1 - (n - 1).leading_zeros())
+}
+//
+// ###### INCLUSION PROOF ######
+//
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Proof {
+    pub row_id: RowId,
+    // rows a full page holds, ties the row id to its page and position
+    pub max_rows_per_page: u32,
+    // position of the row in its page and the path to the page hash
+    pub page_row_n: u32,
+    pub rows_in_page: u32,
+    pub row_path: Vec<Hash>,
+    // position of the page in the ledger and the path to the root
+    pub page_id: u64,
+    pub num_pages: u64,
+    pub page_path: Vec<Hash>,
+}
+//
+// true when `row_bytes` is the row the proof was made for, in the ledger with this root. the
+// hashes don't cover the row id, it has to follow from the page and the position in it. a
+// verifier that knows the row type should also compare `max_rows_per_page` with its own
+pub fn verify_proof(root: &Hash, row_bytes: &[u8], proof: &Proof) -> bool {
+    let max_rows = proof.max_rows_per_page as u64;
+    if proof.rows_in_page as u64 > max_rows || proof.page_id >= proof.num_pages {
+        return false;
+    }
+    // only the last page can be partly filled
+    if proof.page_id + 1 < proof.num_pages && proof.rows_in_page as u64 != max_rows {
+        return false;
+    }
+    let row_id = proof
+        .page_id
+        .checked_mul(max_rows)
+        .and_then(|first_row| first_row.checked_add(proof.page_row_n as u64));
+    if row_id != Some(proof.row_id.get()) {
+        return false;
+    }
+    //
+    let Some(page_hash) = root_from_path(
+        proof.page_row_n as u64,
+        proof.rows_in_page as u64,
+        leaf_hash(row_bytes),
+        &proof.row_path,
+    ) else {
+        return false;
+    };
+    root_from_path(proof.page_id, proof.num_pages, page_hash, &proof.page_path)
+        .is_some_and(|r| r == *root)
+}
+//
+// ###### PAGE HASH CACHE ######
+//
+// page hashes persisted in `<ledger>.merkle`. a page's hash is recorded when the page fills and
+// dropped before the page is written again. `<ledger>.merkle.dirty` exists while the sidecar is
+// behind memory, a crash then discards the sidecar and the hashes are recomputed on demand.
+//
+#[derive(Debug, Default)]
+pub struct MerkleCache {
+    path: PathBuf,
+    pages: Vec<Option<Hash>>,
+    dirty: bool,
+    // false for read-only ledgers
+    persist: bool,
+}
+
+impl MerkleCache {
+    pub fn load(ledger_path: &Path) -> Self {
+        let path = merkle_path(ledger_path);
+        let mut cache = Self {
+            path,
+            pages: Vec::new(),
+            dirty: false,
+            persist: true,
+        };
+        //
+        if dirty_marker_path(&cache.path).exists() {
+            tracing::warn!("discarding stale page hashes {:?}", cache.path);
+            cache.dirty = true;
+            return cache;
+        }
+        //
+        match read_pages(&cache.path) {
+            Ok(pages) => cache.pages = pages,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => tracing::error!("couldn't load page hashes {:?}. error:\n{e}", cache.path),
+        }
+        cache
+    }
+    //
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+    //
+    #[inline(always)]
+    pub fn get(&self, page_id: usize) -> Option<Hash> {
+        self.pages.get(page_id).copied().flatten()
+    }
+    //
+    pub fn set(&mut self, page_id: usize, hash: Hash) {
+        if self.pages.len() <= page_id {
+            self.pages.resize(page_id + 1, None);
+        }
+        self.pages[page_id] = Some(hash);
+        self.mark_dirty();
+    }
+    //
+    // called before the page is modified
+    pub fn invalidate(&mut self, page_id: usize) {
+        if let Some(hash) = self.pages.get_mut(page_id)
+            && hash.take().is_some()
+        {
+            self.mark_dirty();
+        }
+    }
+    //
+    // pages past the end were truncated away
+    pub fn truncate(&mut self, num_pages: usize) {
+        if self.pages.len() > num_pages {
+            self.pages.truncate(num_pages);
+            self.mark_dirty();
+        }
+    }
+    //
+    fn mark_dirty(&mut self) {
+        if self.dirty || !self.persist {
+            return;
+        }
+        self.dirty = true;
+        if let Err(e) = File::create(dirty_marker_path(&self.path)) {
+            tracing::error!("couldn't create page hash dirty marker. error:\n{e}");
+        }
+    }
+    //
+    // [magic][version][num pages u64] then per page [present u8][hash]
+    pub fn save(&mut self) -> std::io::Result<()> {
+        if !self.dirty || !self.persist {
+            return Ok(());
+        }
+        //
+        let tmp_path = self.path.with_extension("tmp");
+        let mut w = BufWriter::new(File::create(&tmp_path)?);
+        w.write_all(&MERKLE_MAGIC)?;
+        w.write_all(&[MERKLE_VERSION])?;
+        w.write_all(&(self.pages.len() as u64).to_le_bytes())?;
+        for hash in &self.pages {
+            match hash {
+                Some(hash) => {
+                    w.write_all(&[1])?;
+                    w.write_all(hash)?;
+                }
+                None => w.write_all(&[0; 33])?,
+            }
+        }
+        w.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        std::fs::rename(tmp_path, &self.path)?;
+        //
+        match std::fs::remove_file(dirty_marker_path(&self.path)) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+// #### UTILS
+
+fn merkle_path(ledger_path: &Path) -> PathBuf {
+    let mut name = ledger_path.as_os_str().to_os_string();
+    name.push(".merkle");
+    PathBuf::from(name)
+}
+//
+fn dirty_marker_path(merkle_path: &Path) -> PathBuf {
+    let mut name = merkle_path.as_os_str().to_os_string();
+    name.push(".dirty");
+    PathBuf::from(name)
+}
+//
+fn read_pages(path: &Path) -> std::io::Result<Vec<Option<Hash>>> {
+    let mut r = BufReader::new(File::open(path)?);
+    //
+    let mut magic = [0_u8; 5];
+    r.read_exact(&mut magic)?;
+    if magic[0..4] != MERKLE_MAGIC || magic[4] != MERKLE_VERSION {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "bad page hash file header",
+        ));
+    }
+    //
+    let mut buf = [0_u8; 8];
+    r.read_exact(&mut buf)?;
+    let num_pages = u64::from_le_bytes(buf);
+    //
+    let mut pages = Vec::new();
+    let mut record = [0_u8; 33];
+    for _ in 0..num_pages {
+        r.read_exact(&mut record)?;
+        pages.push((record[0] == 1).then(|| record[1..].try_into().unwrap()));
+    }
+    Ok(pages)
+}
@@ -1,6 +1,9 @@
 // // #### UTILS
+use crate::export::{ColumnType, FieldValue};
 use crate::header::PageHeader;
+use crate::import::{ImportError, Record};
 use crate::ledger::PAGE_HEADER_SZ;
+use crate::schema::LedgerSchema;
 use crate::utils::PageError;
 use bytecheck::CheckBytes;
 use rkyv::api::high::HighValidator;
@@ -29,6 +32,20 @@ where
 pub trait PageSchema {
     const ROWS_PER_PAGE: usize;
     const PAGE_SZ: usize;
+    // fields tagged #[fulltext]
+    const FULLTEXT_FIELDS: &'static [&'static str] = &[];
+    // fields tagged #[bitmap_index]
+    const BITMAP_FIELDS: &'static [&'static str] = &[];
+    // byte offset of the hidden hash chain field of #[ledger(audit)] rows
+    const CHAIN_OFFSET: Option<usize> = None;
+    // maximum row age in seconds, from #[ledger(ttl_field = "...", ttl = "...")]
+    const TTL: Option<u64> = None;
+    // the row's fields in declaration order, the columns of an export
+    const FIELD_NAMES: &'static [&'static str] = &[];
+    // the logical type of each field, in FIELD_NAMES order
+    const COLUMN_TYPES: &'static [ColumnType] = &[];
+    // where each field lives in the stored row, embedded in the ledger file
+    const SCHEMA: LedgerSchema = LedgerSchema::EMPTY;
     //
     fn to_bytes(&self) -> Result<rkyv::util::AlignedVec, rkyv::rancor::Error>;
     fn from_bytes(f: &rkyv::util::AlignedVec) -> Result<Self, rkyv::rancor::Error>
@@ -36,6 +53,37 @@ pub trait PageSchema {
         Self: Sized;
 
     fn deleted_row(page_row_n: usize) -> &'static [u8];
+
+    // schema level checks bytecheck can't express (string lengths, utf8)
+    fn validate(&self) -> Result<(), PageError> {
+        Ok(())
+    }
+
+    fn fulltext_value(&self, _field: &str) -> Option<&str> {
+        None
+    }
+
+    fn bitmap_value(&self, _field: &str) -> Option<u64> {
+        None
+    }
+
+    // unix seconds of the `ttl_field`
+    fn ttl_timestamp(&self) -> Option<u64> {
+        None
+    }
+
+    // the logical value of every field, in FIELD_NAMES order
+    fn field_values(&self) -> Vec<FieldValue<'_>> {
+        Vec::new()
+    }
+
+    // builds a row from an imported record, the inverse of field_values
+    fn from_record(record: &Record<'_>) -> Result<Self, ImportError>
+    where
+        Self: Sized,
+    {
+        Err(record.error(None, "this row type has no from_record"))
+    }
 }
 //
 unsafe impl<T, const PAGE_SZ: usize, const ROWS_PER_PAGE: usize> NoUndef
@@ -79,11 +127,13 @@ where
     // #### HEADER
 
     #[inline(always)]
-    pub fn access_header<'a>(&'a self) -> Result<&'a PageHeader, PageError> {
+    pub fn access_header(&self) -> Result<&PageHeader, PageError> {
         Ok(PageHeader::access(&self.data[0..PAGE_HEADER_SZ as usize])?)
     }
+    /// # Safety
+    /// the page must start with a valid page header, it isn't validated.
     #[inline(always)]
-    pub unsafe fn access_header_unchecked<'a>(&'a self) -> &'a PageHeader {
+    pub unsafe fn access_header_unchecked(&self) -> &PageHeader {
         unsafe { PageHeader::access_unchecked(&self.data[0..PAGE_HEADER_SZ as usize]) }
     }
     #[inline(always)]
@@ -92,6 +142,8 @@ where
             &mut self.data[0..PAGE_HEADER_SZ as usize],
         )?)
     }
+    /// # Safety
+    /// the page must start with a valid page header, it isn't validated.
     #[inline(always)]
     pub unsafe fn access_header_unchecked_mut<'a>(&'a mut self) -> Seal<'a, PageHeader> {
         unsafe { PageHeader::access_unchecked_mut(&mut self.data[0..PAGE_HEADER_SZ as usize]) }
@@ -126,6 +178,8 @@ where
         Ok(slot_count)
     }
     //
+    /// # Safety
+    /// the page header isn't validated. panics when the row doesn't fit.
     pub unsafe fn insert_row_unchecked(&mut self, object: &T) -> u32 {
         //
         let mut header = unsafe { self.access_header_unchecked_mut() };
@@ -154,6 +208,8 @@ where
         slot_count
     }
     //
+    /// # Safety
+    /// the page header isn't validated.
     pub unsafe fn set_row_deleted(&mut self, page_row_n: usize) -> Result<(), PageError> {
         //
         let header = unsafe { self.access_header_unchecked() };
@@ -163,13 +219,13 @@ where
         };
         //
         self.data[data_offset..data_offset + Self::ROW_SZ as usize]
-            .copy_from_slice(&T::deleted_row(page_row_n));
+            .copy_from_slice(T::deleted_row(page_row_n));
         //
         Ok(())
     }
     //
     //
-    pub fn access_row<'a>(&'a self, page_row_n: usize) -> Result<Option<&'a T>, PageError>
+    pub fn access_row(&self, page_row_n: usize) -> Result<Option<&T>, PageError>
     where
         T: Archive + Portable + PageSchema,
     {
@@ -180,6 +236,9 @@ where
         else {
             return Err(PageError::RowNotFound);
         };
+        if data_offset == TOMBSTONE {
+            return Ok(None);
+        }
         //
         let sz = size_of::<T>();
         Ok(Some(access::<T, rkyv::rancor::Error>(
@@ -187,8 +246,10 @@ where
         )?))
     }
     //
+    /// # Safety
+    /// `page_row_n` must be a live row of the page, its slot and bytes aren't validated.
     #[inline(always)]
-    pub unsafe fn access_row_unchecked<'a>(&'a self, page_row_n: usize) -> &'a T {
+    pub unsafe fn access_row_unchecked(&self, page_row_n: usize) -> &T {
         //
         let data_offset = unsafe { _offset_unchecked(page_row_n, &self.data) };
         //
@@ -208,6 +269,9 @@ where
         else {
             return Err(PageError::RowNotFound);
         };
+        if data_offset == TOMBSTONE {
+            return Ok(None);
+        }
         //
         let sz = size_of::<T>();
         Ok(Some(access_mut::<T, rkyv::rancor::Error>(
@@ -215,6 +279,88 @@ where
         )?))
     }
     //
+    // overwrites an existing row with already serialized bytes
+    pub fn write_row(&mut self, page_row_n: usize, bytes: &[u8]) -> Result<(), PageError> {
+        //
+        if bytes.len() != Self::ROW_SZ as usize {
+            return Err(PageError::Error(format!(
+                "row is {} bytes, wants {}",
+                bytes.len(),
+                Self::ROW_SZ
+            )));
+        }
+        //
+        let header = self.access_header()?;
+        let Some(data_offset) = _offset(page_row_n, header.slot_count() as usize, &self.data)
+        else {
+            return Err(PageError::RowNotFound);
+        };
+        if data_offset == TOMBSTONE {
+            return Err(PageError::RowNotFound);
+        }
+        //
+        self.data[data_offset..data_offset + bytes.len()].copy_from_slice(bytes);
+        //
+        Ok(())
+    }
+    //
+    // points the slot at the tombstone offset. the row id stays reserved and reads return None
+    pub fn delete_row(&mut self, page_row_n: usize) -> Result<(), PageError> {
+        //
+        let header = self.access_header()?;
+        if page_row_n >= header.slot_count() as usize {
+            return Err(PageError::RowNotFound);
+        }
+        //
+        let slot_id = _get_slot_id(page_row_n);
+        self.data[slot_id..slot_id + 4].copy_from_slice(&(TOMBSTONE as u32).to_le_bytes());
+        //
+        Ok(())
+    }
+    //
+    // the stored bytes of a row, None for a deleted row
+    pub fn row_bytes(&self, page_row_n: usize) -> Result<Option<&[u8]>, PageError> {
+        let header = self.access_header()?;
+        let Some(data_offset) = _offset(page_row_n, header.slot_count() as usize, &self.data)
+        else {
+            return Err(PageError::RowNotFound);
+        };
+        if data_offset == TOMBSTONE {
+            return Ok(None);
+        }
+        //
+        Ok(Some(
+            &self.data[data_offset..data_offset + Self::ROW_SZ as usize],
+        ))
+    }
+    //
+    pub fn row_bytes_mut(&mut self, page_row_n: usize) -> Result<Option<&mut [u8]>, PageError> {
+        let header = self.access_header()?;
+        let Some(data_offset) = _offset(page_row_n, header.slot_count() as usize, &self.data)
+        else {
+            return Err(PageError::RowNotFound);
+        };
+        if data_offset == TOMBSTONE {
+            return Ok(None);
+        }
+        //
+        Ok(Some(
+            &mut self.data[data_offset..data_offset + Self::ROW_SZ as usize],
+        ))
+    }
+    //
+    #[inline(always)]
+    pub fn is_row_deleted(&self, page_row_n: usize) -> Result<bool, PageError> {
+        let header = self.access_header()?;
+        let Some(data_offset) = _offset(page_row_n, header.slot_count() as usize, &self.data)
+        else {
+            return Err(PageError::RowNotFound);
+        };
+        Ok(data_offset == TOMBSTONE)
+    }
+    //
+    /// # Safety
+    /// `page_row_n` must be a live row of the page, its slot and bytes aren't validated.
     #[inline(always)]
     pub unsafe fn access_row_unchecked_mut<'a>(&'a mut self, page_row_n: usize) -> Seal<'a, T> {
         //
@@ -230,6 +376,8 @@ where
         Ok(self.access_header()?.free_space())
     }
 
+    /// # Safety
+    /// the page must start with a valid page header, it isn't validated.
     #[inline(always)]
     pub unsafe fn free_space_unchecked(&self) -> u32 {
         unsafe { self.access_header_unchecked().free_space_unchecked() }
@@ -247,6 +395,9 @@ where
 
 // #### UTILS
 
+// slot offset of a deleted row. offset 0 is the page header so it can never hold a row
+pub const TOMBSTONE: usize = 0;
+
 #[inline(always)]
 unsafe fn _offset_unchecked(page_row_n: usize, data: &[u8]) -> usize {
     unsafe {
@@ -257,7 +408,7 @@ unsafe fn _offset_unchecked(page_row_n: usize, data: &[u8]) -> usize {
 }
 //
 #[inline(always)]
-fn _offset(page_row_n: usize, num_rows: usize, data: &[u8]) -> Option<usize> {
+pub(crate) fn _offset(page_row_n: usize, num_rows: usize, data: &[u8]) -> Option<usize> {
     if page_row_n >= num_rows {
         tracing::error!("row out of bounds");
         return None;
@@ -279,5 +430,5 @@ fn _offset(page_row_n: usize, num_rows: usize, data: &[u8]) -> Option<usize> {
 //
 #[inline(always)]
 const fn _get_slot_id(row_n: usize) -> usize {
-    PAGE_HEADER_SZ as usize + (row_n as usize * 4)
+    PAGE_HEADER_SZ as usize + (row_n * 4)
 }
@@ -0,0 +1,55 @@
+use parquet::basic::{Compression, GzipLevel, ZstdLevel};
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+
+//
+// ###### PARQUET ######
+//
+// `DataLedgerStore::to_parquet` writes the rows with the same schema as the arrow export
+// (`arrow::arrow_schema`). each row group holds the rows of `pages_per_row_group` pages, so a
+// reader can skip a range of row ids a row group at a time.
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParquetOptions {
+    pub compression: ParquetCompression,
+    pub pages_per_row_group: usize,
+}
+
+impl Default for ParquetOptions {
+    fn default() -> Self {
+        Self {
+            compression: ParquetCompression::Snappy,
+            pages_per_row_group: 32,
+        }
+    }
+}
+//
+// the codecs built in, levels are checked when the export starts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParquetCompression {
+    None,
+    #[default]
+    Snappy,
+    // 0..=10
+    Gzip(u32),
+    Lz4,
+    // 1..=22
+    Zstd(i32),
+}
+
+impl ParquetOptions {
+    pub(crate) fn writer_properties(&self) -> Result<WriterProperties, ParquetError> {
+        let compression = match self.compression {
+            ParquetCompression::None => Compression::UNCOMPRESSED,
+            ParquetCompression::Snappy => Compression::SNAPPY,
+            ParquetCompression::Gzip(level) => Compression::GZIP(GzipLevel::try_new(level)?),
+            ParquetCompression::Lz4 => Compression::LZ4_RAW,
+            ParquetCompression::Zstd(level) => Compression::ZSTD(ZstdLevel::try_new(level)?),
+        };
+        // row groups are only cut by `to_parquet`, every `pages_per_row_group` pages
+        Ok(WriterProperties::builder()
+            .set_compression(compression)
+            .set_max_row_group_size(usize::MAX)
+            .build())
+    }
+}
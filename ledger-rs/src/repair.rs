@@ -0,0 +1,213 @@
+use crate::header::PageHeader;
+use crate::ledger::{PAGE_HEADER_SZ, RowId};
+use crate::page::TOMBSTONE;
+use crate::transaction::sync_parent_dir;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+//
+const QUARANTINE_MAGIC: [u8; 4] = *b"LDGQ";
+const QUARANTINE_VERSION: u8 = 1;
+//
+// ###### REPAIR ######
+//
+// `DataLedgerStore::repair` puts a ledger that a crash or a bad disk left inconsistent back into
+// a shape `open` accepts:
+//   - a partial page and zeroed or unreadable pages at the end of the file are cut off
+//   - num_pages and page_cursor are recomputed from the pages that are left
+//   - a page header that disagrees with its slot array is rebuilt from the slots
+//   - rows that don't validate as the row type are tombstoned
+//   - an unreadable page in the middle is replaced by a full page of deleted rows, so the row
+//     ids after it don't move
+// a journal left by an unfinished transaction is rolled back first, as `open` would, or discarded
+// when it was cut short. otherwise the next open would replay it over the repaired pages.
+// every page that loses rows is first appended to the quarantine file, `<ledger>.quarantine`.
+//
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairOptions {
+    // only report what would be done, nothing is written
+    pub dry_run: bool,
+    // defaults to `<ledger>.quarantine`
+    pub quarantine_path: Option<PathBuf>,
+}
+//
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepairReport {
+    pub path: PathBuf,
+    pub dry_run: bool,
+    pub file_len_before: u64,
+    pub file_len: u64,
+    pub num_pages_before: u64,
+    pub num_pages: u64,
+    pub page_cursor_before: u64,
+    pub page_cursor: u64,
+    // page headers rebuilt from their slot arrays
+    pub rebuilt_pages: Vec<u64>,
+    // unreadable pages replaced by a page of deleted rows
+    pub replaced_pages: Vec<u64>,
+    // rows that didn't validate, now deleted
+    pub dropped_rows: Vec<RowId>,
+    // pages whose original bytes were appended to the quarantine file
+    pub quarantined_pages: Vec<u64>,
+    pub quarantine_path: Option<PathBuf>,
+    // what happened to `<ledger>.journal`, None when there was none
+    pub journal: Option<JournalAction>,
+}
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JournalAction {
+    // the transaction's original pages were put back before the repair
+    RolledBack { pages: usize },
+    // the journal was cut short, nothing in the ledger had been changed through it yet
+    Discarded,
+}
+
+impl RepairReport {
+    // nothing needed repairing
+    pub fn is_clean(&self) -> bool {
+        self.file_len == self.file_len_before
+            && self.num_pages == self.num_pages_before
+            && self.page_cursor == self.page_cursor_before
+            && self.rebuilt_pages.is_empty()
+            && self.replaced_pages.is_empty()
+            && self.dropped_rows.is_empty()
+            && self.journal.is_none()
+    }
+}
+//
+// what a page read from the file turned into
+pub(crate) enum RepairedPage {
+    Keep,
+    // (repaired page, original bytes when rows were dropped)
+    Rewrite(Vec<u8>, Option<Vec<u8>>),
+    // the original bytes, None when not even they could be read
+    Unreadable(Option<Vec<u8>>),
+    Zeroed,
+}
+//
+pub fn quarantine_path(ledger_path: &Path) -> PathBuf {
+    let mut name = ledger_path.as_os_str().to_os_string();
+    name.push(".quarantine");
+    PathBuf::from(name)
+}
+
+//
+// ###### PAGES ######
+//
+
+// the header a page would have if its slot array is right. rows are packed back to back from
+// the end of the page, so a live slot can only hold one offset. deleted slots past the last live
+// one still count while their row bytes are there. None when a slot holds anything else
+pub(crate) fn header_from_slots(
+    page: &[u8],
+    row_size: usize,
+    max_rows: usize,
+) -> Option<PageHeader> {
+    let page_size = page.len();
+    let slot = |n: usize| {
+        let at = PAGE_HEADER_SZ as usize + n * 4;
+        u32::from_le_bytes(page[at..at + 4].try_into().unwrap()) as usize
+    };
+    let row_start = |n: usize| page_size - (n + 1) * row_size;
+    //
+    let mut last_live = None;
+    for n in 0..max_rows {
+        match slot(n) {
+            TOMBSTONE => {}
+            offset if offset == row_start(n) => last_live = Some(n),
+            _ => return None,
+        }
+    }
+    let mut slot_count = last_live.map_or(0, |n| n + 1);
+    while slot_count < max_rows
+        && page[row_start(slot_count)..row_start(slot_count) + row_size]
+            .iter()
+            .any(|b| *b != 0)
+    {
+        slot_count += 1;
+    }
+    //
+    let mut header = PageHeader::new(None, page_size as u32);
+    header.set_slot_count(slot_count as u32);
+    header.set_free_start(PAGE_HEADER_SZ + slot_count as u32 * 4);
+    header.set_free_end((page_size - slot_count * row_size) as u32);
+    Some(header)
+}
+//
+// a full page whose rows are all deleted, it takes the place of a page that couldn't be read
+pub(crate) fn deleted_page(page_size: usize, row_size: usize, max_rows: usize) -> Vec<u8> {
+    let mut header = PageHeader::new(None, page_size as u32);
+    header.set_slot_count(max_rows as u32);
+    header.set_free_start(PAGE_HEADER_SZ + max_rows as u32 * 4);
+    header.set_free_end((page_size - max_rows * row_size) as u32);
+    //
+    let mut page = vec![0_u8; page_size];
+    set_page_header(&mut page, &header);
+    page
+}
+//
+pub(crate) fn set_page_header(page: &mut [u8], header: &PageHeader) {
+    let bytes = header.serialize().expect("page header serializes");
+    page[0..PAGE_HEADER_SZ as usize].copy_from_slice(&bytes);
+}
+
+//
+// ###### QUARANTINE ######
+//
+// [magic][version] then per page [page id u64][len u32][page bytes]. pages are only appended,
+// a page quarantined by two repairs is there twice
+//
+pub(crate) fn quarantine(path: &Path, pages: &[(u64, Vec<u8>)]) -> std::io::Result<()> {
+    if pages.is_empty() {
+        return Ok(());
+    }
+    let mut file = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(path)?;
+    let mut batch = Vec::new();
+    if file.metadata()?.len() == 0 {
+        batch.extend_from_slice(&QUARANTINE_MAGIC);
+        batch.push(QUARANTINE_VERSION);
+    }
+    for (page_id, page) in pages {
+        batch.extend_from_slice(&page_id.to_le_bytes());
+        batch.extend_from_slice(&(page.len() as u32).to_le_bytes());
+        batch.extend_from_slice(page);
+    }
+    file.write_all(&batch)?;
+    file.sync_all()?;
+    sync_parent_dir(path)
+}
+//
+// (page id, page bytes) of every quarantined page, oldest first
+pub fn read_quarantine(path: &Path) -> std::io::Result<Vec<(u64, Vec<u8>)>> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+    let invalid = |msg| std::io::Error::new(std::io::ErrorKind::InvalidData, msg);
+    if bytes.len() < 5 || bytes[0..4] != QUARANTINE_MAGIC {
+        return Err(invalid("not a quarantine file"));
+    }
+    if bytes[4] != QUARANTINE_VERSION {
+        return Err(invalid("unsupported quarantine file version"));
+    }
+    //
+    let mut pages = Vec::new();
+    let mut at = 5;
+    while at < bytes.len() {
+        let Some(record) = bytes.get(at..at + 12) else {
+            return Err(invalid("quarantine file ends inside a record"));
+        };
+        let page_id = u64::from_le_bytes(record[0..8].try_into().unwrap());
+        let len = u32::from_le_bytes(record[8..12].try_into().unwrap()) as usize;
+        let Some(page) = bytes.get(at + 12..at + 12 + len) else {
+            return Err(invalid("quarantine file ends inside a record"));
+        };
+        pages.push((page_id, page.to_vec()));
+        at += 12 + len;
+    }
+    Ok(pages)
+}
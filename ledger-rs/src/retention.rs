@@ -0,0 +1,96 @@
+use crate::page::PageSchema;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+//
+// ###### RETENTION ######
+//
+// rows older than `max_age` are expired by `run_retention`: they're tombstoned through the normal
+// delete path, and a full page whose rows are all gone has its row bytes punched out of the file.
+// the slot array stays behind, so the page still reads as deleted rows and row ids don't move.
+// segmented ledgers also drop whole segments at the front once nothing in them is left.
+//
+// timestamps are unix seconds. #[ledger(ttl_field = "created_at", ttl = "30d")] sets a default
+// policy on the row type, `set_retention_policy` replaces it at runtime.
+//
+pub struct RetentionPolicy<T> {
+    max_age: Duration,
+    timestamp: fn(&T) -> u64,
+}
+//
+impl<T> Clone for RetentionPolicy<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<T> Copy for RetentionPolicy<T> {}
+//
+impl<T> fmt::Debug for RetentionPolicy<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetentionPolicy")
+            .field("max_age", &self.max_age)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> RetentionPolicy<T> {
+    // `timestamp` returns the row's age reference in unix seconds
+    pub fn new(max_age: Duration, timestamp: fn(&T) -> u64) -> Self {
+        Self { max_age, timestamp }
+    }
+    //
+    // the policy declared by #[ledger(ttl_field, ttl)], if any
+    pub fn from_schema() -> Option<Self>
+    where
+        T: PageSchema,
+    {
+        T::TTL.map(|secs| {
+            Self::new(Duration::from_secs(secs), |row: &T| {
+                row.ttl_timestamp().unwrap_or(u64::MAX)
+            })
+        })
+    }
+    //
+    #[inline(always)]
+    pub fn max_age(&self) -> Duration {
+        self.max_age
+    }
+    //
+    // rows stamped before the cutoff are expired
+    #[inline(always)]
+    pub fn cutoff(&self, now: u64) -> u64 {
+        now.saturating_sub(self.max_age.as_secs())
+    }
+    //
+    #[inline(always)]
+    pub fn is_expired(&self, row: &T, cutoff: u64) -> bool {
+        (self.timestamp)(row) < cutoff
+    }
+}
+//
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetentionStats {
+    // rows tombstoned by this run
+    pub expired_rows: u64,
+    // pages whose row bytes were punched out of the file
+    pub reclaimed_pages: u64,
+    pub reclaimed_bytes: u64,
+    // segment files removed, segmented ledgers only
+    pub dropped_segments: u32,
+}
+
+impl std::ops::AddAssign for RetentionStats {
+    fn add_assign(&mut self, other: Self) {
+        self.expired_rows += other.expired_rows;
+        self.reclaimed_pages += other.reclaimed_pages;
+        self.reclaimed_bytes += other.reclaimed_bytes;
+        self.dropped_segments += other.dropped_segments;
+    }
+}
+//
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
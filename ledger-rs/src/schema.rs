@@ -0,0 +1,159 @@
+use crate::export::{ColumnType, FieldValue};
+use crate::utils::DatastoreError;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+//
+// ###### SCHEMA ######
+//
+// what #[ledger] knows about a row type, written into the ledger file so tools without the type
+// compiled in (`RawLedger`, ledgerctl) can still decode its rows. offsets and sizes are those of
+// the stored row: numbers are little endian, a #[max_len] string is `max_len` bytes at `offset`
+// with its length in the byte at `len_offset`.
+//
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LedgerSchema {
+    // the row type
+    pub name: Cow<'static, str>,
+    pub row_size: usize,
+    pub fields: Cow<'static, [FieldSchema]>,
+}
+//
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldSchema {
+    pub name: Cow<'static, str>,
+    #[serde(rename = "type")]
+    pub column_type: ColumnType,
+    pub offset: usize,
+    pub size: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_len: Option<usize>,
+    // strings only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub len_offset: Option<usize>,
+}
+
+impl LedgerSchema {
+    // row types that don't come from #[ledger]
+    pub const EMPTY: Self = Self {
+        name: Cow::Borrowed(""),
+        row_size: 0,
+        fields: Cow::Borrowed(&[]),
+    };
+    //
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+    //
+    pub fn field(&self, name: &str) -> Option<&FieldSchema> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+    //
+    // the logical value of every field of a stored row, in field order. a field that doesn't fit
+    // in `row` comes out as empty bytes
+    pub fn decode_row<'a>(&self, row: &'a [u8]) -> Vec<FieldValue<'a>> {
+        self.fields
+            .iter()
+            .map(|field| field.decode(row).unwrap_or(FieldValue::Bytes(&[])))
+            .collect()
+    }
+}
+
+impl FieldSchema {
+    pub fn decode<'a>(&self, row: &'a [u8]) -> Option<FieldValue<'a>> {
+        let bytes = row.get(self.offset..self.offset.checked_add(self.size)?)?;
+        match self.column_type {
+            ColumnType::Str { max_len } => {
+                let len = *row.get(self.len_offset?)? as usize;
+                Some(FieldValue::Str(String::from_utf8_lossy(
+                    &bytes[..len.min(max_len).min(bytes.len())],
+                )))
+            }
+            column_type => decode_value(&column_type, bytes),
+        }
+    }
+}
+//
+fn decode_value<'a>(column_type: &ColumnType, bytes: &'a [u8]) -> Option<FieldValue<'a>> {
+    let le = |bits: u8| {
+        let n = bits as usize / 8;
+        let mut buf = [0_u8; 16];
+        buf.get_mut(..n)?.copy_from_slice(bytes.get(..n)?);
+        Some((u128::from_le_bytes(buf), 128 - bits as u32))
+    };
+    Some(match column_type {
+        ColumnType::Bool => FieldValue::Bool(*bytes.first()? != 0),
+        ColumnType::Char => {
+            let (v, _) = le(32)?;
+            let c = char::from_u32(v as u32).unwrap_or(char::REPLACEMENT_CHARACTER);
+            FieldValue::Str(Cow::Owned(c.to_string()))
+        }
+        ColumnType::UInt(bits) => FieldValue::UInt(le(*bits)?.0),
+        // sign extended from the top bit of the field
+        ColumnType::Int(bits) => {
+            let (v, shift) = le(*bits)?;
+            FieldValue::Int(((v << shift) as i128) >> shift)
+        }
+        ColumnType::Float(32) => {
+            FieldValue::Float(f32::from_le_bytes(bytes.get(..4)?.try_into().ok()?) as f64)
+        }
+        ColumnType::Float(64) => {
+            FieldValue::Float(f64::from_le_bytes(bytes.get(..8)?.try_into().ok()?))
+        }
+        ColumnType::Array { elem, len } if *len > 0 => FieldValue::Array(
+            bytes
+                .chunks_exact(bytes.len() / len)
+                .take(*len)
+                .map(|elem_bytes| decode_value(elem, elem_bytes))
+                .collect::<Option<_>>()?,
+        ),
+        ColumnType::Array { .. } => FieldValue::Array(Vec::new()),
+        // [u8; N], and anything the schema can't describe as its raw bytes
+        _ => FieldValue::Bytes(bytes),
+    })
+}
+
+//
+// ###### SCHEMA PAGE ######
+//
+// written between the ledger header and the first page of a format 2 ledger:
+//
+//   magic "LSCH" | version u8 | 3 bytes padding | json length u32 | page size u32 | json
+//
+// zero padded to a multiple of 16 so the pages after it stay aligned. the page size lets a raw
+// reader skip inferring it.
+//
+const SCHEMA_MAGIC: &[u8; 4] = b"LSCH";
+const SCHEMA_PAGE_VERSION: u8 = 1;
+const SCHEMA_PREFIX_SZ: usize = 16;
+
+impl LedgerSchema {
+    pub(crate) fn to_page(&self, page_size: usize) -> Result<Vec<u8>, DatastoreError> {
+        let json = serde_json::to_vec(self).map_err(|e| e.to_string())?;
+        let mut page = Vec::with_capacity(SCHEMA_PREFIX_SZ + json.len() + 16);
+        page.extend_from_slice(SCHEMA_MAGIC);
+        page.extend_from_slice(&[SCHEMA_PAGE_VERSION, 0, 0, 0]);
+        page.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        page.extend_from_slice(&(page_size as u32).to_le_bytes());
+        page.extend_from_slice(&json);
+        page.resize(page.len().next_multiple_of(16), 0);
+        Ok(page)
+    }
+    //
+    // the schema and the page size it was written with
+    pub fn from_page(page: &[u8]) -> Result<(Self, usize), DatastoreError> {
+        let u32_at = |at: usize| u32::from_le_bytes(page[at..at + 4].try_into().unwrap()) as usize;
+        if page.len() < SCHEMA_PREFIX_SZ || &page[0..4] != SCHEMA_MAGIC {
+            return Err("no schema page after the ledger header".into());
+        }
+        if page[4] != SCHEMA_PAGE_VERSION {
+            return Err(format!("unsupported schema page version {}", page[4]).into());
+        }
+        let json = page
+            .get(SCHEMA_PREFIX_SZ..SCHEMA_PREFIX_SZ + u32_at(8))
+            .ok_or("schema page is cut short")?;
+        let schema = serde_json::from_slice(json).map_err(|e| format!("schema page: {e}"))?;
+        Ok((schema, u32_at(12)))
+    }
+}
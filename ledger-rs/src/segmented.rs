@@ -0,0 +1,498 @@
+use crate::compression::CacheRef;
+use crate::header::{LedgerDescription, LedgerName};
+use crate::ledger::{DataLedgerStore, RowId};
+use crate::page::{PageSchema, SlottedPage};
+use crate::retention::{RetentionPolicy, RetentionStats, unix_now};
+use crate::transaction::sync_parent_dir;
+use crate::utils::{DatastoreError, PageError};
+use rkyv::traits::NoUndef;
+use rkyv::{Archive, Portable};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+//
+const MANIFEST_MAGIC: [u8; 4] = *b"LSGM";
+const MANIFEST_VERSION: u8 = 1;
+//
+// ###### SEGMENTED LEDGER ######
+//
+// one logical ledger split over fixed size segment files `<name>.0000`, `<name>.0001`, ... each
+// segment is a normal ledger with its own sidecars. inserts go to the newest segment, a new one is
+// started once it holds `pages_per_segment` full pages. global row ids are
+// `segment * rows_per_segment + local row id`, so they stay stable when older segments are
+// dropped or archived. `pages_per_segment` is recorded in `<name>.segments` on creation.
+//
+pub struct SegmentedLedger<T, const PAGESZ: usize, const ROWS_PER_PAGE: usize> {
+    folder: PathBuf,
+    ledger_name: LedgerName,
+    ledger_description: LedgerDescription,
+    pages_per_segment: u32,
+    // segment index -> store. the last one takes inserts, dropped segments are missing
+    segments: BTreeMap<u32, DataLedgerStore<T, PAGESZ, ROWS_PER_PAGE>>,
+    // handed to every segment
+    retention: Option<RetentionPolicy<T>>,
+}
+//
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentInfo {
+    pub index: u32,
+    pub path: PathBuf,
+    // global id of the segment's first row
+    pub first_row_id: RowId,
+    pub num_rows: u64,
+}
+
+//
+impl<T, const PAGESZ: usize, const ROWS_PER_PAGE: usize> SegmentedLedger<T, PAGESZ, ROWS_PER_PAGE>
+where
+    T: Archive
+        + Portable
+        + PageSchema
+        + for<'b> bytecheck::CheckBytes<
+            bytecheck::rancor::Strategy<
+                rkyv::validation::Validator<
+                    rkyv::validation::archive::ArchiveValidator<'b>,
+                    rkyv::validation::shared::SharedValidator,
+                >,
+                bytecheck::rancor::Error,
+            >,
+        >,
+    SlottedPage<T, PAGESZ, ROWS_PER_PAGE>: NoUndef + std::marker::Unpin,
+{
+    // opens every segment in the folder, or creates segment 0. fails when the ledger was created
+    // with a different `pages_per_segment`
+    pub fn open<P>(
+        folder_path: P,
+        ledger_name: LedgerName,
+        ledger_description: LedgerDescription,
+        pages_per_segment: u32,
+    ) -> Result<Self, DatastoreError>
+    where
+        P: AsRef<Path>,
+    {
+        if pages_per_segment == 0 {
+            return Err("pages_per_segment must be at least 1".into());
+        }
+        let folder = folder_path.as_ref().to_path_buf();
+        let base_name = ledger_name.to_string();
+        //
+        let manifest_path = folder.join(format!("{base_name}.segments"));
+        match read_manifest(&manifest_path) {
+            Ok(stored) if stored != pages_per_segment => {
+                return Err(format!(
+                    "ledger {ledger_name} was created with {stored} pages per segment, not {pages_per_segment}"
+                )
+                .into());
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                write_manifest(&manifest_path, pages_per_segment)?;
+            }
+            Err(e) => return Err(e.into()),
+        }
+        //
+        let mut ledger = Self {
+            folder,
+            ledger_name,
+            ledger_description,
+            pages_per_segment,
+            segments: BTreeMap::new(),
+            retention: RetentionPolicy::from_schema(),
+        };
+        //
+        for entry in std::fs::read_dir(&ledger.folder)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let Some(index) = file_name
+                .to_str()
+                .and_then(|s| s.strip_prefix(base_name.as_str()))
+                .and_then(|s| s.strip_prefix('.'))
+                .and_then(parse_segment_index)
+            else {
+                continue;
+            };
+            let segment = ledger.open_segment(index)?;
+            ledger.segments.insert(index, segment);
+        }
+        if ledger.segments.is_empty() {
+            let segment = ledger.open_segment(0)?;
+            ledger.segments.insert(0, segment);
+        }
+        //
+        Ok(ledger)
+    }
+    //
+    fn open_segment(
+        &self,
+        index: u32,
+    ) -> Result<DataLedgerStore<T, PAGESZ, ROWS_PER_PAGE>, DatastoreError> {
+        let mut segment = DataLedgerStore::open_file(
+            self.segment_path(index),
+            self.ledger_name.clone(),
+            self.ledger_description.clone(),
+        )?;
+        segment.set_retention_policy(self.retention);
+        Ok(segment)
+    }
+    //
+    #[inline(always)]
+    fn segment_path(&self, index: u32) -> PathBuf {
+        self.folder.join(format!("{}.{index:04}", self.ledger_name))
+    }
+    //
+    #[inline(always)]
+    pub fn rows_per_segment(&self) -> u64 {
+        self.pages_per_segment as u64
+            * SlottedPage::<T, PAGESZ, ROWS_PER_PAGE>::max_rows_per_page() as u64
+    }
+    //
+    #[inline(always)]
+    pub fn pages_per_segment(&self) -> u32 {
+        self.pages_per_segment
+    }
+    //
+    // (segment, local row id)
+    #[inline(always)]
+    pub fn split_row_id(&self, row_id: RowId) -> Result<(u32, RowId), DatastoreError> {
+        let rows_per_segment = self.rows_per_segment();
+        let segment = u32::try_from(row_id.get() / rows_per_segment)
+            .map_err(|_| PageError::RowIdOutOfBounds)?;
+        Ok((segment, RowId(row_id.get() % rows_per_segment)))
+    }
+    //
+    #[inline(always)]
+    pub fn to_row_id(&self, segment: u32, local_row_id: RowId) -> Result<RowId, DatastoreError> {
+        (segment as u64)
+            .checked_mul(self.rows_per_segment())
+            .and_then(|first_row| first_row.checked_add(local_row_id.get()))
+            .map(RowId)
+            .ok_or(DatastoreError::RowIdOverflow(
+                "segment is past the last row id",
+            ))
+    }
+    //
+    fn active(&self) -> (u32, &DataLedgerStore<T, PAGESZ, ROWS_PER_PAGE>) {
+        let (index, segment) = self.segments.last_key_value().expect("active segment");
+        (*index, segment)
+    }
+    //
+    // the store holding the row, None when its segment was dropped
+    fn locate(
+        &self,
+        row_id: RowId,
+    ) -> Result<Option<(&DataLedgerStore<T, PAGESZ, ROWS_PER_PAGE>, RowId)>, DatastoreError> {
+        let (index, local_row_id) = self.split_row_id(row_id)?;
+        if index > self.active().0 {
+            return Err(PageError::RowIdOutOfBounds.into());
+        }
+        Ok(self
+            .segments
+            .get(&index)
+            .map(|segment| (segment, local_row_id)))
+    }
+    //
+    fn locate_mut(
+        &mut self,
+        row_id: RowId,
+    ) -> Result<(&mut DataLedgerStore<T, PAGESZ, ROWS_PER_PAGE>, RowId), DatastoreError> {
+        let (index, local_row_id) = self.split_row_id(row_id)?;
+        match self.segments.get_mut(&index) {
+            Some(segment) => Ok((segment, local_row_id)),
+            None => Err(PageError::RowNotFound.into()),
+        }
+    }
+
+    //
+    // ###### ROW OPERATIONS ######
+    //
+
+    // rows ever inserted, dropped segments included
+    pub fn num_rows(&self) -> Result<u64, DatastoreError> {
+        let (index, segment) = self.active();
+        Ok(self.to_row_id(index, RowId(segment.num_rows()?))?.get())
+    }
+    //
+    pub fn insert(&mut self, value: &T) -> Result<RowId, DatastoreError> {
+        let (mut index, segment) = self.active();
+        if segment.num_rows()? >= self.rows_per_segment() {
+            // the active segment is full, roll over. it's synced so it's complete on disk
+            segment.sync_all()?;
+            index = index
+                .checked_add(1)
+                .ok_or(DatastoreError::RowIdOverflow("no segment index left"))?;
+            tracing::debug!("starting segment {:?}", self.segment_path(index));
+            let segment = self.open_segment(index)?;
+            self.segments.insert(index, segment);
+        }
+        //
+        let segment = self.segments.get_mut(&index).expect("active segment");
+        let local_row_id = segment.insert(value)?;
+        self.to_row_id(index, local_row_id)
+    }
+    //
+    // Ok(None) for deleted rows and rows of dropped segments
+    pub fn access_row(&self, row_id: RowId) -> Result<Option<CacheRef<'_, T>>, DatastoreError> {
+        match self.locate(row_id)? {
+            Some((segment, local_row_id)) => segment.access_row(local_row_id),
+            None => Ok(None),
+        }
+    }
+    //
+    pub fn update<F, R>(&mut self, row_id: RowId, f: F) -> Result<R, DatastoreError>
+    where
+        T: Clone,
+        F: FnOnce(&mut T) -> R,
+    {
+        let (segment, local_row_id) = self.locate_mut(row_id)?;
+        segment.update(local_row_id, f)
+    }
+    //
+    pub fn replace(&mut self, row_id: RowId, value: &T) -> Result<(), DatastoreError>
+    where
+        T: Clone,
+    {
+        let (segment, local_row_id) = self.locate_mut(row_id)?;
+        segment.replace(local_row_id, value)
+    }
+    //
+    pub fn delete(&mut self, row_id: RowId) -> Result<(), DatastoreError>
+    where
+        T: Clone,
+    {
+        let (segment, local_row_id) = self.locate_mut(row_id)?;
+        segment.delete(local_row_id)
+    }
+    //
+    // visits every row of the remaining segments in row id order
+    pub fn for_each_row<F>(&self, mut f: F) -> Result<(), DatastoreError>
+    where
+        F: FnMut(RowId, &T),
+    {
+        for (index, segment) in &self.segments {
+            let first_row_id = self.to_row_id(*index, RowId(0))?.get();
+            segment.for_each_row(|local_row_id, row| {
+                f(RowId(first_row_id + local_row_id.get()), row)
+            })?;
+        }
+        Ok(())
+    }
+    //
+    // full-text search over every remaining segment
+    pub fn search(&self, field: &str, query: &str) -> Result<Vec<RowId>, DatastoreError> {
+        let mut row_ids = Vec::new();
+        for (index, segment) in &self.segments {
+            let first_row_id = self.to_row_id(*index, RowId(0))?.get();
+            row_ids.extend(
+                segment
+                    .search(field, query)?
+                    .into_iter()
+                    .map(|local_row_id| RowId(first_row_id + local_row_id.get())),
+            );
+        }
+        Ok(row_ids)
+    }
+    //
+    pub fn sync_all(&self) -> std::io::Result<()> {
+        for segment in self.segments.values() {
+            segment.sync_all()?;
+        }
+        Ok(())
+    }
+
+    //
+    // ###### RETENTION ######
+    //
+
+    #[inline(always)]
+    pub fn retention_policy(&self) -> Option<&RetentionPolicy<T>> {
+        self.retention.as_ref()
+    }
+    //
+    pub fn set_retention_policy(&mut self, policy: Option<RetentionPolicy<T>>) {
+        self.retention = policy;
+        for segment in self.segments.values_mut() {
+            segment.set_retention_policy(policy);
+        }
+    }
+    //
+    pub fn run_retention(&mut self) -> Result<RetentionStats, DatastoreError>
+    where
+        T: Clone,
+    {
+        self.run_retention_at(unix_now())
+    }
+    //
+    // expires rows in every segment, then drops the leading segments that have no rows left.
+    // the active segment always stays
+    pub fn run_retention_at(&mut self, now: u64) -> Result<RetentionStats, DatastoreError>
+    where
+        T: Clone,
+    {
+        let mut stats = RetentionStats::default();
+        for segment in self.segments.values_mut() {
+            stats += segment.run_retention_at(now)?;
+        }
+        //
+        let active = self.active().0;
+        let mut dead = Vec::new();
+        for (index, segment) in &self.segments {
+            let header = segment.access_header()?;
+            if *index == active || header.retired_pages() < header.num_pages() {
+                break;
+            }
+            dead.push(*index);
+        }
+        for index in dead {
+            self.drop_segment(index)?;
+            stats.dropped_segments += 1;
+        }
+        Ok(stats)
+    }
+
+    //
+    // ###### SEGMENTS ######
+    //
+
+    // remaining segments, oldest first
+    pub fn segments(&self) -> Result<Vec<SegmentInfo>, DatastoreError> {
+        let mut infos = Vec::with_capacity(self.segments.len());
+        for (index, segment) in &self.segments {
+            infos.push(SegmentInfo {
+                index: *index,
+                path: segment.path().to_path_buf(),
+                first_row_id: self.to_row_id(*index, RowId(0))?,
+                num_rows: segment.num_rows()?,
+            });
+        }
+        Ok(infos)
+    }
+    //
+    pub fn segment(&self, index: u32) -> Option<&DataLedgerStore<T, PAGESZ, ROWS_PER_PAGE>> {
+        self.segments.get(&index)
+    }
+    //
+    pub fn segment_mut(
+        &mut self,
+        index: u32,
+    ) -> Option<&mut DataLedgerStore<T, PAGESZ, ROWS_PER_PAGE>> {
+        self.segments.get_mut(&index)
+    }
+    //
+    // deletes a segment file and its sidecars. its rows read back as None afterwards.
+    // the active segment can't be dropped
+    pub fn drop_segment(&mut self, index: u32) -> Result<(), DatastoreError> {
+        for path in self.detach_segment(index)? {
+            if path.is_dir() {
+                std::fs::remove_dir_all(&path)?;
+            } else {
+                std::fs::remove_file(&path)?;
+            }
+        }
+        sync_parent_dir(&self.segment_path(index))?;
+        Ok(())
+    }
+    //
+    // moves a segment file and its sidecars into `dest_dir`, where it can be opened as a plain
+    // ledger with `DataLedgerStore::open_file`. its rows read back as None afterwards
+    pub fn archive_segment<P>(&mut self, index: u32, dest_dir: P) -> Result<PathBuf, DatastoreError>
+    where
+        P: AsRef<Path>,
+    {
+        let dest_dir = dest_dir.as_ref();
+        std::fs::create_dir_all(dest_dir)?;
+        let archived = dest_dir.join(self.segment_path(index).file_name().unwrap());
+        //
+        for path in self.detach_segment(index)? {
+            move_path(&path, &dest_dir.join(path.file_name().unwrap()))?;
+        }
+        sync_parent_dir(&archived)?;
+        sync_parent_dir(&self.segment_path(index))?;
+        Ok(archived)
+    }
+    //
+    // closes the segment and lists the files that belong to it
+    fn detach_segment(&mut self, index: u32) -> Result<Vec<PathBuf>, DatastoreError> {
+        if index == self.active().0 {
+            return Err(format!("segment {index} takes inserts and can't be removed").into());
+        }
+        let Some(segment) = self.segments.remove(&index) else {
+            return Err(format!("segment {index} not found").into());
+        };
+        // flushed on drop
+        drop(segment);
+        //
+        let file_name = self.segment_path(index);
+        let file_name = file_name.file_name().unwrap().to_str().unwrap();
+        let sidecar_prefix = format!("{file_name}.");
+        //
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(&self.folder)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            if name == file_name || name.starts_with(&sidecar_prefix) {
+                paths.push(entry.path());
+            }
+        }
+        Ok(paths)
+    }
+}
+
+// #### UTILS
+
+// `0000`, `0001`, ... never a sidecar like `0000.merkle`
+fn parse_segment_index(suffix: &str) -> Option<u32> {
+    if suffix.len() < 4 || !suffix.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    suffix.parse().ok()
+}
+//
+// [magic][version][pages_per_segment u32]
+fn read_manifest(path: &Path) -> std::io::Result<u32> {
+    let mut bytes = [0_u8; 9];
+    File::open(path)?.read_exact(&mut bytes)?;
+    if bytes[0..4] != MANIFEST_MAGIC || bytes[4] != MANIFEST_VERSION {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "bad segment manifest header",
+        ));
+    }
+    Ok(u32::from_le_bytes(bytes[5..9].try_into().unwrap()))
+}
+//
+fn write_manifest(path: &Path, pages_per_segment: u32) -> std::io::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    let mut f = File::create(&tmp_path)?;
+    f.write_all(&MANIFEST_MAGIC)?;
+    f.write_all(&[MANIFEST_VERSION])?;
+    f.write_all(&pages_per_segment.to_le_bytes())?;
+    f.sync_all()?;
+    std::fs::rename(tmp_path, path)?;
+    sync_parent_dir(path)
+}
+//
+// rename, or copy and delete when the destination is on another filesystem
+fn move_path(from: &Path, to: &Path) -> std::io::Result<()> {
+    match std::fs::rename(from, to) {
+        Ok(()) => return Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {}
+        Err(e) => return Err(e),
+    }
+    if from.is_dir() {
+        std::fs::create_dir_all(to)?;
+        for entry in std::fs::read_dir(from)? {
+            let entry = entry?;
+            move_path(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        std::fs::remove_dir(from)
+    } else {
+        std::fs::copy(from, to)?;
+        File::open(to)?.sync_all()?;
+        std::fs::remove_file(from)
+    }
+}
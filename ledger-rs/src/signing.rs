@@ -0,0 +1,255 @@
+use crate::header::CHECKPOINT_SLOT_SZ;
+use crate::merkle::Hash;
+use ed25519_dalek::{Signature, Signer};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+
+//
+const SIGS_MAGIC: [u8; 4] = *b"LSIG";
+const SIGS_VERSION: u8 = 2;
+const RECORD_SZ: usize = 1 + 8 + 32 + 8 + 64;
+// version 1 files, written before row counts were widened to u64
+const LEGACY_SIGS_VERSION: u8 = 1;
+const LEGACY_RECORD_SZ: usize = 4 + 32 + 8 + 64;
+// domain separation, a ledger signature can't be replayed as anything else
+const SIGNING_CONTEXT: &[u8] = b"ledger-rs signed checkpoint v2";
+const LEGACY_SIGNING_CONTEXT: &[u8] = b"ledger-rs signed checkpoint v1";
+//
+// ###### SIGNED CHECKPOINTS ######
+//
+// an ed25519 signature over (ledger name, num_rows, merkle root of the first num_rows rows,
+// timestamp). appended to `<ledger>.sigs`, the history of every signed state. the latest one is
+// also kept in the checkpoint slot of a format 3 ledger, so the file alone carries a signature.
+// a consumer with the public key can check every recorded state is a prefix of the ledger it
+// received.
+//
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedCheckpoint {
+    pub num_rows: u64,
+    pub root: Hash,
+    // unix seconds
+    pub timestamp: u64,
+    pub signature: [u8; 64],
+    // layout of the signed message. 1 for checkpoints signed with a u32 row count
+    pub version: u8,
+}
+
+impl SignedCheckpoint {
+    pub fn sign(
+        key: &SigningKey,
+        ledger_name: &[u8],
+        num_rows: u64,
+        root: Hash,
+        timestamp: u64,
+    ) -> Self {
+        let mut checkpoint = Self {
+            num_rows,
+            root,
+            timestamp,
+            signature: [0; 64],
+            version: SIGS_VERSION,
+        };
+        checkpoint.signature = key.sign(&checkpoint.message(ledger_name)).to_bytes();
+        checkpoint
+    }
+    //
+    pub fn verify_signature(&self, key: &VerifyingKey, ledger_name: &[u8]) -> bool {
+        key.verify_strict(
+            &self.message(ledger_name),
+            &Signature::from_bytes(&self.signature),
+        )
+        .is_ok()
+    }
+    //
+    fn message(&self, ledger_name: &[u8]) -> Vec<u8> {
+        let mut message = Vec::with_capacity(SIGNING_CONTEXT.len() + ledger_name.len() + 48);
+        match self.version {
+            LEGACY_SIGS_VERSION => {
+                message.extend_from_slice(LEGACY_SIGNING_CONTEXT);
+                message.extend_from_slice(ledger_name);
+                message.extend_from_slice(&(self.num_rows as u32).to_le_bytes());
+            }
+            _ => {
+                message.extend_from_slice(SIGNING_CONTEXT);
+                message.extend_from_slice(ledger_name);
+                message.extend_from_slice(&self.num_rows.to_le_bytes());
+            }
+        }
+        message.extend_from_slice(&self.root);
+        message.extend_from_slice(&self.timestamp.to_le_bytes());
+        message
+    }
+    //
+    fn to_record(&self) -> [u8; RECORD_SZ] {
+        let mut record = [0_u8; RECORD_SZ];
+        record[0] = self.version;
+        record[1..9].copy_from_slice(&self.num_rows.to_le_bytes());
+        record[9..41].copy_from_slice(&self.root);
+        record[41..49].copy_from_slice(&self.timestamp.to_le_bytes());
+        record[49..].copy_from_slice(&self.signature);
+        record
+    }
+    //
+    fn from_record(record: &[u8]) -> Self {
+        Self {
+            version: record[0],
+            num_rows: u64::from_le_bytes(record[1..9].try_into().unwrap()),
+            root: record[9..41].try_into().unwrap(),
+            timestamp: u64::from_le_bytes(record[41..49].try_into().unwrap()),
+            signature: record[49..RECORD_SZ].try_into().unwrap(),
+        }
+    }
+    //
+    // [magic][record], zero padded
+    pub(crate) fn to_slot(&self) -> [u8; CHECKPOINT_SLOT_SZ] {
+        let mut slot = [0_u8; CHECKPOINT_SLOT_SZ];
+        slot[0..4].copy_from_slice(&SIGS_MAGIC);
+        slot[4..4 + RECORD_SZ].copy_from_slice(&self.to_record());
+        slot
+    }
+    //
+    // None for a slot that was never written
+    pub(crate) fn from_slot(slot: &[u8]) -> Option<Self> {
+        if slot.len() < 4 + RECORD_SZ || slot[0..4] != SIGS_MAGIC {
+            return None;
+        }
+        Some(Self::from_record(&slot[4..4 + RECORD_SZ]))
+    }
+    //
+    fn from_legacy_record(record: &[u8]) -> Self {
+        Self {
+            version: LEGACY_SIGS_VERSION,
+            num_rows: u32::from_le_bytes(record[0..4].try_into().unwrap()) as u64,
+            root: record[4..36].try_into().unwrap(),
+            timestamp: u64::from_le_bytes(record[36..44].try_into().unwrap()),
+            signature: record[44..LEGACY_RECORD_SZ].try_into().unwrap(),
+        }
+    }
+}
+//
+// the first checkpoint that doesn't hold for this ledger
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BadCheckpoint {
+    pub index: usize,
+    pub num_rows: u64,
+    pub reason: BadCheckpointReason,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BadCheckpointReason {
+    // not signed by the given key, or the record was altered
+    Signature,
+    // the ledger has fewer rows than were signed
+    RowsMissing,
+    // the signed rows were changed
+    RootMismatch,
+}
+
+impl std::fmt::Display for BadCheckpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self.reason {
+            BadCheckpointReason::Signature => "has an invalid signature",
+            BadCheckpointReason::RowsMissing => "covers rows the ledger doesn't have",
+            BadCheckpointReason::RootMismatch => "doesn't match the ledger's rows",
+        };
+        write!(
+            f,
+            "signed checkpoint #{} ({} rows) {}",
+            self.index, self.num_rows, reason
+        )
+    }
+}
+//
+// ###### PERSISTENCE ######
+//
+// [magic][version] then fixed size records of
+// [message version u8][num_rows u64][root][timestamp u64][signature]. version 1 files hold
+// records of [num_rows u32][root][timestamp u64][signature] and are rewritten on the next append
+//
+pub fn load(ledger_path: &Path) -> std::io::Result<Vec<SignedCheckpoint>> {
+    read(&sigs_path(ledger_path))
+}
+//
+fn read(path: &Path) -> std::io::Result<Vec<SignedCheckpoint>> {
+    let mut bytes = Vec::new();
+    match File::open(path) {
+        Ok(mut f) => f.read_to_end(&mut bytes)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    //
+    if bytes.len() < 5 || bytes[0..4] != SIGS_MAGIC {
+        return Err(bad_header());
+    }
+    // a torn trailing record is ignored
+    match bytes[4] {
+        SIGS_VERSION => Ok(bytes[5..]
+            .chunks_exact(RECORD_SZ)
+            .map(SignedCheckpoint::from_record)
+            .collect()),
+        LEGACY_SIGS_VERSION => Ok(bytes[5..]
+            .chunks_exact(LEGACY_RECORD_SZ)
+            .map(SignedCheckpoint::from_legacy_record)
+            .collect()),
+        _ => Err(bad_header()),
+    }
+}
+//
+pub fn append(ledger_path: &Path, checkpoint: &SignedCheckpoint) -> std::io::Result<()> {
+    let path = sigs_path(ledger_path);
+    upgrade_legacy(&path)?;
+    let mut f = OpenOptions::new().create(true).append(true).open(&path)?;
+    //
+    let len = f.metadata()?.len();
+    if len < 5 {
+        f.set_len(0)?;
+        f.write_all(&SIGS_MAGIC)?;
+        f.write_all(&[SIGS_VERSION])?;
+    } else if (len - 5) % RECORD_SZ as u64 != 0 {
+        // drop a torn record so the new one stays aligned
+        f.set_len(5 + (len - 5) / RECORD_SZ as u64 * RECORD_SZ as u64)?;
+    }
+    //
+    f.write_all(&checkpoint.to_record())?;
+    f.sync_data()
+}
+
+//
+// rewrites a version 1 file in the current layout, its records keep their message version
+fn upgrade_legacy(path: &Path) -> std::io::Result<()> {
+    let mut header = [0_u8; 5];
+    match File::open(path).and_then(|mut f| f.read_exact(&mut header)) {
+        Ok(()) if header[0..4] == SIGS_MAGIC && header[4] == LEGACY_SIGS_VERSION => {}
+        _ => return Ok(()),
+    }
+    //
+    let checkpoints = read(path)?;
+    let mut tmp_path = path.as_os_str().to_os_string();
+    tmp_path.push(".tmp");
+    let mut f = File::create(&tmp_path)?;
+    f.write_all(&SIGS_MAGIC)?;
+    f.write_all(&[SIGS_VERSION])?;
+    for checkpoint in &checkpoints {
+        f.write_all(&checkpoint.to_record())?;
+    }
+    f.sync_all()?;
+    std::fs::rename(tmp_path, path)
+}
+
+// #### UTILS
+
+fn sigs_path(ledger_path: &Path) -> PathBuf {
+    let mut name = ledger_path.as_os_str().to_os_string();
+    name.push(".sigs");
+    PathBuf::from(name)
+}
+//
+fn bad_header() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "bad signature file header",
+    )
+}
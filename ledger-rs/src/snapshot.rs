@@ -0,0 +1,210 @@
+use crate::compression::CompressedPages;
+use crate::ledger::{DataLedgerStore, RowId, RowQuery};
+use crate::page::{PageSchema, SlottedPage};
+use crate::utils::{DatastoreError, PageError};
+use memmap2::Mmap;
+use parking_lot::{Mutex, MutexGuard, RwLock};
+use rkyv::traits::NoUndef;
+use rkyv::util::AlignedVec;
+use rkyv::{Archive, Portable, access};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+//
+// ###### SNAPSHOT ######
+//
+// a point-in-time read view of a ledger. it owns a read-only mapping of the file and the row
+// count at creation, so it can outlive borrows of the store and move to another thread while
+// inserts continue. before the store modifies a page that a live snapshot can see, the page's
+// pre-image is copied into the snapshot (copy-on-write), so reads never observe later writes.
+// pages compressed after the snapshot was taken are read back from the compressed segment.
+// the unchecked mutation methods preserve pages the same way.
+//
+pub struct Snapshot<T, const PAGESZ: usize, const ROWS_PER_PAGE: usize> {
+    mmap: Mmap,
+    // bytes before the first page of the ledger file
+    header_sz: usize,
+    pages: Arc<SnapshotPages>,
+    compressed: Arc<RwLock<CompressedPages>>,
+    num_rows: u64,
+    //
+    phantom: PhantomData<T>,
+}
+//
+// pre-images preserved for one snapshot. the lock is held while a reader copies out of the
+// mapping, which keeps the writer from modifying the page underneath it
+#[derive(Debug)]
+pub struct SnapshotPages {
+    num_pages: usize,
+    preserved: Mutex<HashMap<usize, Box<[u8]>>>,
+}
+
+impl SnapshotPages {
+    pub(crate) fn new(num_pages: usize) -> Self {
+        Self {
+            num_pages,
+            preserved: Mutex::new(HashMap::new()),
+        }
+    }
+    //
+    // called by the store before it writes to the page
+    pub(crate) fn preserve(&self, page_id: usize, page: &[u8]) {
+        if page_id >= self.num_pages {
+            return;
+        }
+        self.preserved
+            .lock()
+            .entry(page_id)
+            .or_insert_with(|| page.into());
+    }
+    //
+    // held by the store while it punches compressed pages out of the file
+    pub(crate) fn lock_reads(&self) -> MutexGuard<'_, HashMap<usize, Box<[u8]>>> {
+        self.preserved.lock()
+    }
+}
+
+impl<T, const PAGESZ: usize, const ROWS_PER_PAGE: usize> Snapshot<T, PAGESZ, ROWS_PER_PAGE>
+where
+    T: Archive
+        + Portable
+        + PageSchema
+        + Clone
+        + for<'b> bytecheck::CheckBytes<
+            bytecheck::rancor::Strategy<
+                rkyv::validation::Validator<
+                    rkyv::validation::archive::ArchiveValidator<'b>,
+                    rkyv::validation::shared::SharedValidator,
+                >,
+                bytecheck::rancor::Error,
+            >,
+        >,
+    SlottedPage<T, PAGESZ, ROWS_PER_PAGE>: NoUndef + std::marker::Unpin,
+{
+    const PAGE_SIZE: usize = DataLedgerStore::<T, PAGESZ, ROWS_PER_PAGE>::PAGE_SIZE as usize;
+    //
+    pub(crate) fn new(
+        mmap: Mmap,
+        header_sz: usize,
+        pages: Arc<SnapshotPages>,
+        compressed: Arc<RwLock<CompressedPages>>,
+        num_rows: u64,
+    ) -> Self {
+        Self {
+            mmap,
+            header_sz,
+            pages,
+            compressed,
+            num_rows,
+            phantom: PhantomData,
+        }
+    }
+    //
+    #[inline(always)]
+    pub fn num_rows(&self) -> u64 {
+        self.num_rows
+    }
+    //
+    pub fn access_row(&self, row_id: RowId) -> Result<Option<T>, DatastoreError> {
+        if row_id.get() >= self.num_rows {
+            return Err(PageError::RowIdOutOfBounds.into());
+        }
+        //
+        let query: RowQuery<T, PAGESZ, ROWS_PER_PAGE> =
+            RowQuery::<T, PAGESZ, ROWS_PER_PAGE>::from_row_id(row_id);
+        //
+        let max_rows = SlottedPage::<T, PAGESZ, ROWS_PER_PAGE>::max_rows_per_page() as u64;
+        let visible = (self.num_rows - query.page_id * max_rows).min(max_rows);
+        //
+        let row_bytes = {
+            let preserved = self.pages.preserved.lock();
+            let page = self.page_bytes(&preserved, query.page_id as usize)?;
+            //
+            let Some(data_offset) =
+                crate::page::_offset(query.page_row_n as usize, visible as usize, &page)
+            else {
+                return Err(PageError::RowNotFound.into());
+            };
+            if data_offset == crate::page::TOMBSTONE {
+                return Ok(None);
+            }
+            //
+            // copied out so the row is aligned and outlives the lock
+            let mut row_bytes = AlignedVec::<16>::with_capacity(size_of::<T>());
+            row_bytes.extend_from_slice(&page[data_offset..data_offset + size_of::<T>()]);
+            row_bytes
+        };
+        //
+        Ok(Some(access::<T, rkyv::rancor::Error>(&row_bytes)?.clone()))
+    }
+    //
+    // visits every row visible to the snapshot in row id order. pages are copied out one at a time
+    pub fn for_each_row<F>(&self, mut f: F) -> Result<(), DatastoreError>
+    where
+        F: FnMut(RowId, &T),
+    {
+        let max_rows = SlottedPage::<T, PAGESZ, ROWS_PER_PAGE>::max_rows_per_page() as u64;
+        //
+        for page_id in 0..self.pages.num_pages {
+            let first_row = page_id as u64 * max_rows;
+            if first_row >= self.num_rows {
+                break;
+            }
+            //
+            // keep the copy at the same alignment the rows had in the mapping
+            let misalign = self.page_start(page_id) % 16;
+            let mut buf = AlignedVec::<16>::with_capacity(misalign + Self::PAGE_SIZE);
+            buf.resize(misalign, 0);
+            {
+                let preserved = self.pages.preserved.lock();
+                buf.extend_from_slice(&self.page_bytes(&preserved, page_id)?);
+            }
+            //
+            let page = access::<SlottedPage<T, PAGESZ, ROWS_PER_PAGE>, rkyv::rancor::Error>(
+                &buf[misalign..],
+            )?;
+            //
+            let visible = (self.num_rows - first_row).min(max_rows);
+            let slot_count = page.access_header()?.slot_count().min(visible as u32);
+            for page_row_n in 0..slot_count {
+                if let Some(row) = page.access_row(page_row_n as usize)? {
+                    f(RowId(first_row + page_row_n as u64), row);
+                }
+            }
+        }
+        //
+        Ok(())
+    }
+    //
+    fn page_bytes<'a>(
+        &'a self,
+        preserved: &'a HashMap<usize, Box<[u8]>>,
+        page_id: usize,
+    ) -> Result<Cow<'a, [u8]>, DatastoreError> {
+        if page_id >= self.pages.num_pages {
+            return Err(PageError::PageIdOutOfBounds.into());
+        }
+        //
+        if let Some(page) = preserved.get(&page_id) {
+            return Ok(Cow::Borrowed(page));
+        }
+        //
+        // not written since the snapshot, so a compressed image is still the snapshot's page
+        let compressed = self.compressed.read();
+        if compressed.contains(page_id) {
+            let mut page = vec![0_u8; Self::PAGE_SIZE];
+            compressed.read_into(page_id, &mut page)?;
+            return Ok(Cow::Owned(page));
+        }
+        //
+        let start = self.page_start(page_id);
+        Ok(Cow::Borrowed(&self.mmap[start..start + Self::PAGE_SIZE]))
+    }
+    //
+    #[inline(always)]
+    fn page_start(&self, page_id: usize) -> usize {
+        self.header_sz + page_id * Self::PAGE_SIZE
+    }
+}
@@ -0,0 +1,364 @@
+use crate::compression::CacheRef;
+use crate::ledger::{DataLedgerStore, RowId};
+use crate::page::{PageSchema, SlottedPage};
+use crate::utils::DatastoreError;
+use rkyv::traits::NoUndef;
+use rkyv::{Archive, Portable};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+//
+const JOURNAL_MAGIC: [u8; 4] = *b"LJNL";
+const JOURNAL_VERSION: u8 = 1;
+//
+// ###### TRANSACTION ######
+//
+// staged writes against a ledger. every write goes straight to the mapped pages, but the first
+// time a page is touched its original image is appended to an undo journal and fsynced. commit
+// flushes the ledger and deletes the journal, rollback (or recovery on the next open) copies the
+// original pages back and truncates any pages allocated by the transaction.
+//
+pub struct Transaction<'a, T, const PAGESZ: usize, const ROWS_PER_PAGE: usize>
+where
+    T: Archive
+        + Portable
+        + PageSchema
+        + for<'b> bytecheck::CheckBytes<
+            bytecheck::rancor::Strategy<
+                rkyv::validation::Validator<
+                    rkyv::validation::archive::ArchiveValidator<'b>,
+                    rkyv::validation::shared::SharedValidator,
+                >,
+                bytecheck::rancor::Error,
+            >,
+        >,
+    SlottedPage<T, PAGESZ, ROWS_PER_PAGE>: NoUndef + std::marker::Unpin,
+{
+    store: &'a mut DataLedgerStore<T, PAGESZ, ROWS_PER_PAGE>,
+    // rows written by this transaction, re-indexed from the restored pages on rollback
+    touched: BTreeSet<RowId>,
+    finished: bool,
+}
+
+impl<'a, T, const PAGESZ: usize, const ROWS_PER_PAGE: usize>
+    Transaction<'a, T, PAGESZ, ROWS_PER_PAGE>
+where
+    T: Archive
+        + Portable
+        + PageSchema
+        + Clone
+        + for<'b> bytecheck::CheckBytes<
+            bytecheck::rancor::Strategy<
+                rkyv::validation::Validator<
+                    rkyv::validation::archive::ArchiveValidator<'b>,
+                    rkyv::validation::shared::SharedValidator,
+                >,
+                bytecheck::rancor::Error,
+            >,
+        >,
+    SlottedPage<T, PAGESZ, ROWS_PER_PAGE>: NoUndef + std::marker::Unpin,
+{
+    pub(crate) fn begin(
+        store: &'a mut DataLedgerStore<T, PAGESZ, ROWS_PER_PAGE>,
+    ) -> Result<Self, DatastoreError> {
+        store.begin_journal()?;
+        //
+        Ok(Self {
+            store,
+            touched: BTreeSet::new(),
+            finished: false,
+        })
+    }
+    //
+    pub fn insert(&mut self, value: &T) -> Result<RowId, DatastoreError> {
+        let row_id = self.store.insert(value)?;
+        self.touched.insert(row_id);
+        Ok(row_id)
+    }
+    //
+    pub fn update<F, R>(&mut self, row_id: RowId, f: F) -> Result<R, DatastoreError>
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        self.touched.insert(row_id);
+        self.store.update(row_id, f)
+    }
+    //
+    pub fn replace(&mut self, row_id: RowId, value: &T) -> Result<(), DatastoreError> {
+        self.touched.insert(row_id);
+        self.store.replace(row_id, value)
+    }
+    //
+    pub fn delete(&mut self, row_id: RowId) -> Result<(), DatastoreError> {
+        self.touched.insert(row_id);
+        self.store.delete(row_id)
+    }
+    //
+    // reads see the transaction's own writes
+    pub fn access_row(&self, row_id: RowId) -> Result<Option<CacheRef<'_, T>>, DatastoreError> {
+        self.store.access_row(row_id)
+    }
+    //
+    pub(crate) fn commit(mut self) -> Result<(), DatastoreError> {
+        self.finished = true;
+        self.store.commit_journal()
+    }
+    //
+    pub(crate) fn rollback(mut self) -> Result<(), DatastoreError> {
+        self.finished = true;
+        let touched = std::mem::take(&mut self.touched);
+        self.store.rollback_journal(&touched)
+    }
+}
+
+impl<'a, T, const PAGESZ: usize, const ROWS_PER_PAGE: usize> Drop
+    for Transaction<'a, T, PAGESZ, ROWS_PER_PAGE>
+where
+    T: Archive
+        + Portable
+        + PageSchema
+        + for<'b> bytecheck::CheckBytes<
+            bytecheck::rancor::Strategy<
+                rkyv::validation::Validator<
+                    rkyv::validation::archive::ArchiveValidator<'b>,
+                    rkyv::validation::shared::SharedValidator,
+                >,
+                bytecheck::rancor::Error,
+            >,
+        >,
+    SlottedPage<T, PAGESZ, ROWS_PER_PAGE>: NoUndef + std::marker::Unpin,
+{
+    // the closure panicked. roll back and rebuild the indexes from the restored rows
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        if let Err(e) = self.store.abort_journal() {
+            tracing::error!("couldn't roll back transaction. error:\n{e}");
+        }
+    }
+}
+//
+// ###### JOURNAL ######
+//
+// [magic][version][original file len u64][header len u32][header bytes][checksum u64]
+// followed by page records of [page id u64][page bytes][checksum u64]
+//
+#[derive(Debug)]
+pub struct Journal {
+    file: File,
+    path: PathBuf,
+    //
+    // pages already saved. only the first write to a page needs its original image
+    saved: HashSet<usize>,
+    // images needed to undo the transaction, also kept in memory for in-process rollback.
+    // checkpoint logs are never rolled back in process, their page images stay on disk only
+    image: JournalImage,
+    retain_images: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct JournalImage {
+    pub original_len: u64,
+    pub header: Vec<u8>,
+    pub pages: HashMap<usize, Vec<u8>>,
+}
+
+impl Journal {
+    pub fn begin(path: PathBuf, header: &[u8], original_len: u64) -> std::io::Result<Self> {
+        Self::create(path, header, original_len, true)
+    }
+    //
+    // an undo log that only keeps page images on disk (used by checkpoints)
+    pub fn begin_log(path: PathBuf, header: &[u8], original_len: u64) -> std::io::Result<Self> {
+        Self::create(path, header, original_len, false)
+    }
+    //
+    fn create(
+        path: PathBuf,
+        header: &[u8],
+        original_len: u64,
+        retain_images: bool,
+    ) -> std::io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+        //
+        let mut record = Vec::with_capacity(header.len() + 17);
+        record.extend_from_slice(&JOURNAL_MAGIC);
+        record.push(JOURNAL_VERSION);
+        record.extend_from_slice(&original_len.to_le_bytes());
+        record.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        record.extend_from_slice(header);
+        record.extend_from_slice(&checksum(&record).to_le_bytes());
+        //
+        file.write_all(&record)?;
+        file.sync_all()?;
+        sync_parent_dir(&path)?;
+        //
+        Ok(Self {
+            file,
+            path,
+            saved: HashSet::new(),
+            image: JournalImage {
+                original_len,
+                header: header.to_vec(),
+                pages: HashMap::new(),
+            },
+            retain_images,
+        })
+    }
+    //
+    // reopens an undo log for appending. a torn trailing record is cut off first
+    pub fn resume_log(path: PathBuf, page_size: usize) -> std::io::Result<Option<Self>> {
+        let Some((image, valid_len)) = Self::read(&path, page_size)? else {
+            return Ok(None);
+        };
+        //
+        let mut file = OpenOptions::new().read(true).write(true).open(&path)?;
+        file.set_len(valid_len)?;
+        file.seek(SeekFrom::End(0))?;
+        //
+        Ok(Some(Self {
+            file,
+            path,
+            saved: image.pages.keys().copied().collect(),
+            image: JournalImage {
+                original_len: image.original_len,
+                header: image.header,
+                pages: HashMap::new(),
+            },
+            retain_images: false,
+        }))
+    }
+    //
+    #[inline(always)]
+    pub fn original_len(&self) -> u64 {
+        self.image.original_len
+    }
+    //
+    #[inline(always)]
+    pub fn contains(&self, page_id: usize) -> bool {
+        self.saved.contains(&page_id)
+    }
+    //
+    // must be called, and return, before the page is modified
+    pub fn save_page(&mut self, page_id: usize, page: &[u8]) -> std::io::Result<()> {
+        if self.contains(page_id) {
+            return Ok(());
+        }
+        //
+        let mut record = Vec::with_capacity(page.len() + 16);
+        record.extend_from_slice(&(page_id as u64).to_le_bytes());
+        record.extend_from_slice(page);
+        record.extend_from_slice(&checksum(&record).to_le_bytes());
+        //
+        self.file.write_all(&record)?;
+        self.file.sync_data()?;
+        //
+        self.saved.insert(page_id);
+        if self.retain_images {
+            self.image.pages.insert(page_id, page.to_vec());
+        }
+        Ok(())
+    }
+    //
+    // the transaction is durable once the journal is gone
+    pub fn finish(self) -> std::io::Result<()> {
+        drop(self.file);
+        Self::discard(&self.path)
+    }
+    //
+    pub fn into_image(self) -> (PathBuf, JournalImage) {
+        (self.path, self.image)
+    }
+    //
+    // reads a journal left behind by a crashed transaction. trailing records that fail their
+    // checksum were never fsynced, so the page they describe was never modified
+    pub fn recover(path: &Path, page_size: usize) -> std::io::Result<Option<JournalImage>> {
+        Ok(Self::read(path, page_size)?.map(|(image, _)| image))
+    }
+    //
+    // the image and the length of the readable prefix of the file
+    fn read(path: &Path, page_size: usize) -> std::io::Result<Option<(JournalImage, u64)>> {
+        let mut bytes = Vec::new();
+        match File::open(path) {
+            Ok(mut f) => f.read_to_end(&mut bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        //
+        if bytes.len() < 17 || bytes[0..4] != JOURNAL_MAGIC || bytes[4] != JOURNAL_VERSION {
+            tracing::warn!("discarding unreadable journal {:?}", path);
+            return Ok(None);
+        }
+        let original_len = u64::from_le_bytes(bytes[5..13].try_into().unwrap());
+        let header_len = u32::from_le_bytes(bytes[13..17].try_into().unwrap()) as usize;
+        //
+        let header_end = 17 + header_len;
+        if bytes.len() < header_end + 8 || !checksum_ok(&bytes[0..header_end + 8]) {
+            // the journal header itself never made it to disk, nothing was modified
+            tracing::warn!("discarding incomplete journal {:?}", path);
+            return Ok(None);
+        }
+        //
+        let mut image = JournalImage {
+            original_len,
+            header: bytes[17..header_end].to_vec(),
+            pages: HashMap::new(),
+        };
+        //
+        let record_len = 8 + page_size + 8;
+        let mut pos = header_end + 8;
+        while pos + record_len <= bytes.len() {
+            let record = &bytes[pos..pos + record_len];
+            if !checksum_ok(record) {
+                break;
+            }
+            let page_id = u64::from_le_bytes(record[0..8].try_into().unwrap()) as usize;
+            image
+                .pages
+                .insert(page_id, record[8..8 + page_size].to_vec());
+            pos += record_len;
+        }
+        //
+        Ok(Some((image, pos as u64)))
+    }
+    //
+    pub fn discard(path: &Path) -> std::io::Result<()> {
+        match std::fs::remove_file(path) {
+            Ok(()) => sync_parent_dir(path),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+// #### UTILS
+
+// FNV-1a, only guards against torn journal records
+pub(crate) fn checksum(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+//
+fn checksum_ok(record: &[u8]) -> bool {
+    let (body, sum) = record.split_at(record.len() - 8);
+    checksum(body) == u64::from_le_bytes(sum.try_into().unwrap())
+}
+//
+pub(crate) fn sync_parent_dir(path: &Path) -> std::io::Result<()> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => File::open(dir)?.sync_all(),
+        _ => Ok(()),
+    }
+}
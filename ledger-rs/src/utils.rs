@@ -48,6 +48,12 @@ impl<T: FlagMask> BitMask<T> {
     }
 }
 //
+impl<T: FlagMask> Default for ArchivedBitMask<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+//
 impl<T: FlagMask> ArchivedBitMask<T> {
     pub fn new() -> Self {
         Self(0, std::marker::PhantomData)
@@ -91,6 +97,45 @@ pub enum DatastoreError {
     //
     #[error("LedgerNameError: {0}")]
     LedgerNameError(#[from] LedgerNameError),
+    //
+    #[error("IndexNotFound: {0}")]
+    IndexNotFound(String),
+    //
+    #[error("ledger is read-only")]
+    ReadOnly,
+    //
+    #[error("CheckpointNotFound: {0}")]
+    CheckpointNotFound(String),
+    //
+    #[error("not allowed on an audit ledger: {0}")]
+    AuditViolation(&'static str),
+    //
+    #[error("not supported on encrypted ledgers: {0}")]
+    EncryptionUnsupported(&'static str),
+    //
+    #[error("wrong encryption key")]
+    WrongKey,
+    //
+    #[error("row id overflow: {0}")]
+    RowIdOverflow(&'static str),
+    //
+    #[error("unsupported ledger format version {0}")]
+    UnsupportedFormat(u8),
+    //
+    #[error("{field} is {len} bytes, max_len is {max_len}")]
+    MaxLen {
+        field: &'static str,
+        len: usize,
+        max_len: usize,
+    },
+    //
+    #[cfg(feature = "arrow")]
+    #[error("arrow err: {0}")]
+    ArrowError(#[from] arrow_schema::ArrowError),
+    //
+    #[cfg(feature = "parquet")]
+    #[error("parquet err: {0}")]
+    ParquetError(#[from] parquet::errors::ParquetError),
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -136,3 +181,59 @@ impl From<&str> for DatastoreError {
         Self::Error(value.to_string())
     }
 }
+
+//
+// ####### POSITIONED IO
+//
+// reads and writes at an offset without moving a shared cursor. unix has them in `FileExt`,
+// windows only has `seek_read`/`seek_write`, which can stop short, so those are looped
+pub(crate) trait FileAt {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()>;
+    fn write_all_at(&self, buf: &[u8], offset: u64) -> std::io::Result<()>;
+}
+//
+#[cfg(unix)]
+impl FileAt for std::fs::File {
+    #[inline(always)]
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+        std::os::unix::fs::FileExt::read_exact_at(self, buf, offset)
+    }
+    #[inline(always)]
+    fn write_all_at(&self, buf: &[u8], offset: u64) -> std::io::Result<()> {
+        std::os::unix::fs::FileExt::write_all_at(self, buf, offset)
+    }
+}
+//
+#[cfg(windows)]
+impl FileAt for std::fs::File {
+    fn read_exact_at(&self, mut buf: &mut [u8], mut offset: u64) -> std::io::Result<()> {
+        use std::os::windows::fs::FileExt;
+        while !buf.is_empty() {
+            match self.seek_read(buf, offset) {
+                Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => {
+                    buf = &mut buf[n..];
+                    offset += n as u64;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+    fn write_all_at(&self, mut buf: &[u8], mut offset: u64) -> std::io::Result<()> {
+        use std::os::windows::fs::FileExt;
+        while !buf.is_empty() {
+            match self.seek_write(buf, offset) {
+                Ok(0) => return Err(std::io::ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    buf = &buf[n..];
+                    offset += n as u64;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+use crate::ledger::RowId;
+use std::cmp::Ordering;
+
+//
+// ###### VECTOR SIMILARITY ######
+//
+// brute force distance kernels for #[vector] fields. the loops run over fixed width lanes with
+// independent accumulators so the compiler can vectorize them.
+//
+const LANES: usize = 8;
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    // 1 - cos(a, b)
+    Cosine,
+    // squared euclidean distance
+    L2,
+    // negated dot product, so smaller is still closer
+    Dot,
+}
+
+impl Metric {
+    #[inline]
+    pub fn distance(&self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            Metric::Cosine => {
+                let denom = (dot(a, a) * dot(b, b)).sqrt();
+                if denom == 0.0 {
+                    return 1.0;
+                }
+                1.0 - dot(a, b) / denom
+            }
+            Metric::L2 => l2_squared(a, b),
+            Metric::Dot => -dot(a, b),
+        }
+    }
+}
+//
+#[inline]
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    let mut acc = [0_f32; LANES];
+    //
+    let a_chunks = a.chunks_exact(LANES);
+    let b_chunks = b.chunks_exact(LANES);
+    let tail: f32 = a_chunks
+        .remainder()
+        .iter()
+        .zip(b_chunks.remainder())
+        .map(|(x, y)| x * y)
+        .sum();
+    //
+    for (ca, cb) in a_chunks.zip(b_chunks) {
+        for i in 0..LANES {
+            acc[i] += ca[i] * cb[i];
+        }
+    }
+    //
+    acc.iter().sum::<f32>() + tail
+}
+//
+#[inline]
+pub fn l2_squared(a: &[f32], b: &[f32]) -> f32 {
+    let mut acc = [0_f32; LANES];
+    //
+    let a_chunks = a.chunks_exact(LANES);
+    let b_chunks = b.chunks_exact(LANES);
+    let tail: f32 = a_chunks
+        .remainder()
+        .iter()
+        .zip(b_chunks.remainder())
+        .map(|(x, y)| (x - y) * (x - y))
+        .sum();
+    //
+    for (ca, cb) in a_chunks.zip(b_chunks) {
+        for i in 0..LANES {
+            let d = ca[i] - cb[i];
+            acc[i] += d * d;
+        }
+    }
+    //
+    acc.iter().sum::<f32>() + tail
+}
+//
+// ### NEIGHBOR
+//
+// heap entry for top-k selection. ordered by distance so a max-heap keeps the worst candidate on top
+#[derive(Debug, Clone, Copy)]
+pub struct Neighbor {
+    pub row_id: RowId,
+    pub distance: f32,
+}
+
+impl PartialEq for Neighbor {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for Neighbor {}
+
+impl PartialOrd for Neighbor {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Neighbor {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then(self.row_id.cmp(&other.row_id))
+    }
+}
@@ -0,0 +1,330 @@
+use crate::header::{LedgerHeader, PageHeader};
+use crate::ledger::{PAGE_HEADER_SZ, RowId};
+use crate::page::TOMBSTONE;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+//
+// ###### VERIFY ######
+//
+// offline structural checks of a ledger file. every anomaly found is collected instead of
+// stopping at the first one, so a damaged file can be judged as a whole. the page checks only
+// need the page and row size, they're shared by `DataLedgerStore::verify` and
+// `RawLedger::verify` (`ledgerctl check`). the store also validates every row against its type.
+//
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifyReport {
+    pub path: PathBuf,
+    pub num_pages: u64,
+    pub pages_checked: u64,
+    pub rows: u64,
+    pub deleted_rows: u64,
+    // the pages are sealed and were only checked if the ledger was opened with its key
+    pub encrypted: bool,
+    pub anomalies: Vec<Anomaly>,
+}
+//
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Anomaly {
+    // the ledger header doesn't pass rkyv validation or holds impossible values
+    Header {
+        reason: String,
+    },
+    // the file isn't `header + num_pages * page_size` bytes long
+    FileLength {
+        expected: u64,
+        found: u64,
+    },
+    PageCursor {
+        page_cursor: u64,
+        num_pages: u64,
+    },
+    // couldn't be read at all, e.g. a corrupt compressed image or past the end of the file
+    UnreadablePage {
+        page_id: u64,
+        reason: String,
+    },
+    // an all-zero page header, a page that was never written
+    ZeroedPage {
+        page_id: u64,
+    },
+    FreePointers {
+        page_id: u64,
+        free_start: u32,
+        free_end: u32,
+    },
+    // slot_count doesn't match where the free pointers are
+    SlotCount {
+        page_id: u64,
+        slot_count: u32,
+        free_start: u32,
+        free_end: u32,
+    },
+    // a page after the page cursor holds rows
+    RowsPastCursor {
+        page_id: u64,
+        slot_count: u32,
+    },
+    SlotOutOfHeap {
+        page_id: u64,
+        slot: u32,
+        offset: u32,
+    },
+    SlotOverlap {
+        page_id: u64,
+        slot: u32,
+        other_slot: u32,
+        offset: u32,
+    },
+    // the row's bytes don't validate as the row type
+    InvalidRow {
+        row_id: RowId,
+        reason: String,
+    },
+    // audit ledgers only
+    BrokenChain {
+        row_id: Option<RowId>,
+        reason: String,
+    },
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Anomaly::Header { reason } => write!(f, "ledger header: {reason}"),
+            Anomaly::FileLength { expected, found } => {
+                write!(f, "file is {found} bytes, header and pages need {expected}")
+            }
+            Anomaly::PageCursor {
+                page_cursor,
+                num_pages,
+            } => write!(
+                f,
+                "page cursor {page_cursor} is past the last of {num_pages} pages"
+            ),
+            Anomaly::UnreadablePage { page_id, reason } => {
+                write!(f, "page {page_id} is unreadable: {reason}")
+            }
+            Anomaly::ZeroedPage { page_id } => write!(f, "page {page_id} has a zeroed header"),
+            Anomaly::FreePointers {
+                page_id,
+                free_start,
+                free_end,
+            } => write!(
+                f,
+                "page {page_id} has bad free pointers: free_start {free_start}, free_end {free_end}"
+            ),
+            Anomaly::SlotCount {
+                page_id,
+                slot_count,
+                free_start,
+                free_end,
+            } => write!(
+                f,
+                "page {page_id} slot_count {slot_count} disagrees with free_start {free_start}, free_end {free_end}"
+            ),
+            Anomaly::RowsPastCursor {
+                page_id,
+                slot_count,
+            } => write!(
+                f,
+                "page {page_id} is past the page cursor but holds {slot_count} rows"
+            ),
+            Anomaly::SlotOutOfHeap {
+                page_id,
+                slot,
+                offset,
+            } => write!(
+                f,
+                "page {page_id} slot {slot} points outside the row heap at {offset}"
+            ),
+            Anomaly::SlotOverlap {
+                page_id,
+                slot,
+                other_slot,
+                offset,
+            } => write!(
+                f,
+                "page {page_id} slot {slot} at {offset} overlaps the row of slot {other_slot}"
+            ),
+            Anomaly::InvalidRow { row_id, reason } => {
+                write!(f, "row {row_id} is invalid: {reason}")
+            }
+            Anomaly::BrokenChain { row_id, reason } => match row_id {
+                Some(row_id) => write!(f, "hash chain broken at row {row_id}: {reason}"),
+                None => write!(f, "hash chain couldn't be checked: {reason}"),
+            },
+        }
+    }
+}
+
+impl VerifyReport {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            ..Self::default()
+        }
+    }
+    //
+    #[inline(always)]
+    pub fn is_ok(&self) -> bool {
+        self.anomalies.is_empty()
+    }
+    //
+    #[inline(always)]
+    pub(crate) fn push(&mut self, anomaly: Anomaly) {
+        self.anomalies.push(anomaly);
+    }
+    //
+    // file length and page cursor against the header. the number of pages actually present is
+    // returned, pages past the end of the file can't be checked
+    pub(crate) fn check_layout(
+        &mut self,
+        header: &LedgerHeader,
+        header_sz: usize,
+        page_size: usize,
+        file_len: u64,
+    ) -> u64 {
+        let num_pages = header.num_pages();
+        self.num_pages = num_pages;
+        //
+        if num_pages == 0 {
+            self.push(Anomaly::Header {
+                reason: "num_pages is 0".to_string(),
+            });
+        }
+        if header.page_cursor() >= num_pages.max(1) {
+            self.push(Anomaly::PageCursor {
+                page_cursor: header.page_cursor(),
+                num_pages,
+            });
+        }
+        //
+        let expected = (num_pages as u128 * page_size as u128 + header_sz as u128)
+            .min(u64::MAX as u128) as u64;
+        if expected != file_len {
+            self.push(Anomaly::FileLength {
+                expected,
+                found: file_len,
+            });
+        }
+        num_pages.min(file_len.saturating_sub(header_sz as u64) / page_size as u64)
+    }
+    //
+    // structural checks of one page. returns the slots holding rows whose offsets are sound, with
+    // their offsets, so the caller can look at the rows themselves
+    pub(crate) fn check_page(
+        &mut self,
+        page_id: u64,
+        page: &[u8],
+        row_size: Option<usize>,
+        past_cursor: bool,
+    ) -> Vec<(u32, usize)> {
+        self.pages_checked += 1;
+        let page_size = page.len();
+        let header_bytes = &page[0..PAGE_HEADER_SZ as usize];
+        if header_bytes.iter().all(|b| *b == 0) {
+            self.push(Anomaly::ZeroedPage { page_id });
+            return Vec::new();
+        }
+        let header = match PageHeader::access(header_bytes) {
+            Ok(header) => header,
+            Err(e) => {
+                self.push(Anomaly::UnreadablePage {
+                    page_id,
+                    reason: e.to_string(),
+                });
+                return Vec::new();
+            }
+        };
+        let (slot_count, free_start, free_end) =
+            (header.slot_count(), header.free_start(), header.free_end());
+        //
+        if free_start > free_end || free_start < PAGE_HEADER_SZ || free_end as usize > page_size {
+            self.push(Anomaly::FreePointers {
+                page_id,
+                free_start,
+                free_end,
+            });
+        }
+        let slots_end = PAGE_HEADER_SZ as u64 + slot_count as u64 * 4;
+        // where the row heap starts with that many rows, None if they don't fit
+        let heap_start = |slots: u64| {
+            row_size.and_then(|row_size| (page_size as u64).checked_sub(slots * row_size as u64))
+        };
+        if slots_end != free_start as u64
+            || (row_size.is_some() && heap_start(slot_count as u64) != Some(free_end as u64))
+        {
+            self.push(Anomaly::SlotCount {
+                page_id,
+                slot_count,
+                free_start,
+                free_end,
+            });
+        }
+        if past_cursor && slot_count > 0 {
+            self.push(Anomaly::RowsPastCursor {
+                page_id,
+                slot_count,
+            });
+        }
+        //
+        let row_len = row_size.unwrap_or(1);
+        // when slot_count is the odd one out and both free pointers agree, they tell how many
+        // slots there are. only the slots that fit in the page can be read
+        let slots = match free_start.checked_sub(PAGE_HEADER_SZ) {
+            Some(slot_bytes)
+                if slots_end != free_start as u64
+                    && slot_bytes.is_multiple_of(4)
+                    && row_size.is_some()
+                    && heap_start((slot_bytes / 4) as u64) == Some(free_end as u64) =>
+            {
+                (slot_bytes / 4) as usize
+            }
+            _ => slot_count as usize,
+        };
+        let slots = slots.min((page_size - PAGE_HEADER_SZ as usize) / (row_size.unwrap_or(0) + 4));
+        // the heap can't start before the slot array ends
+        let heap_floor = (free_end as usize).max(PAGE_HEADER_SZ as usize + slots * 4);
+        //
+        let mut rows = Vec::with_capacity(slots);
+        for slot in 0..slots {
+            let at = PAGE_HEADER_SZ as usize + slot * 4;
+            let offset = u32::from_le_bytes(page[at..at + 4].try_into().unwrap());
+            if offset as usize == TOMBSTONE {
+                self.deleted_rows += 1;
+                continue;
+            }
+            self.rows += 1;
+            if (offset as usize) < heap_floor || offset as usize + row_len > page_size {
+                self.push(Anomaly::SlotOutOfHeap {
+                    page_id,
+                    slot: slot as u32,
+                    offset,
+                });
+                continue;
+            }
+            rows.push((slot as u32, offset as usize));
+        }
+        //
+        let mut by_offset = rows.clone();
+        by_offset.sort_by_key(|(_, offset)| *offset);
+        let mut overlapping = Vec::new();
+        for pair in by_offset.windows(2) {
+            let ((other_slot, other_offset), (slot, offset)) = (pair[0], pair[1]);
+            if offset < other_offset + row_len {
+                self.push(Anomaly::SlotOverlap {
+                    page_id,
+                    slot,
+                    other_slot,
+                    offset: offset as u32,
+                });
+                overlapping.push(slot);
+            }
+        }
+        rows.retain(|(slot, _)| !overlapping.contains(slot));
+        rows
+    }
+}
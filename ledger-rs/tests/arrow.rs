@@ -0,0 +1,146 @@
+#![cfg(feature = "arrow")]
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Decimal128Type, Float32Type, Int16Type, UInt64Type};
+use arrow_ipc::reader::StreamReader;
+use arrow_schema::{DataType, Field, Schema};
+use ledger_rs::arrow::arrow_schema;
+use ledger_rs::ledger::RowId;
+use ledger_rs::page::PageSchema as _;
+use ledger_rs_macros::ledger;
+use std::sync::Arc;
+
+mod common;
+use common::temp_dir;
+
+#[ledger(page_size = 16)]
+pub struct Metric {
+    pub flags: u8,
+    pub level: i16,
+    pub count: u32,
+    pub total: i64,
+    pub ratio: f32,
+    pub mean: f64,
+    pub active: bool,
+    pub big: i128,
+    pub huge: u128,
+    #[max_len(12)]
+    pub name: String,
+    pub digest: [u8; 4],
+    pub samples: [f32; 3],
+}
+
+fn metric(n: u64) -> Metric {
+    Metric::new(
+        n as u8,
+        -(n as i16),
+        n as u32 * 10,
+        n as i64 - 5,
+        n as f32 / 2.0,
+        n as f64 / 4.0,
+        n.is_multiple_of(2),
+        -(n as i128) << 100,
+        (n as u128) << 120 | 1,
+        &format!("metric {n}"),
+        [n as u8, 1, 2, 3],
+        [n as f32, 0.5, -1.0],
+    )
+}
+
+#[test]
+fn maps_every_column_type() {
+    let item = |data_type| Arc::new(Field::new("item", data_type, false));
+    let expected = Schema::new(
+        [
+            ("row_id", DataType::UInt64),
+            ("flags", DataType::UInt8),
+            ("level", DataType::Int16),
+            ("count", DataType::UInt32),
+            ("total", DataType::Int64),
+            ("ratio", DataType::Float32),
+            ("mean", DataType::Float64),
+            ("active", DataType::Boolean),
+            // no 128 bit ints in arrow
+            ("big", DataType::Decimal128(38, 0)),
+            ("huge", DataType::FixedSizeBinary(16)),
+            ("name", DataType::Utf8),
+            ("digest", DataType::FixedSizeBinary(4)),
+            (
+                "samples",
+                DataType::FixedSizeList(item(DataType::Float32), 3),
+            ),
+        ]
+        .into_iter()
+        .map(|(name, data_type)| Field::new(name, data_type, false))
+        .collect::<Vec<_>>(),
+    );
+    assert_eq!(arrow_schema::<Metric>(), expected);
+}
+
+#[test]
+fn writes_an_ipc_stream_in_batches() {
+    let dir = temp_dir("arrow-ipc");
+    let mut ledger = Metric::create_ledger(&dir, "metrics", "arrow").unwrap();
+    for n in 0..8 {
+        ledger.insert(&metric(n)).unwrap();
+    }
+    ledger.delete(RowId(4)).unwrap();
+    //
+    let mut ipc = Vec::new();
+    assert_eq!(ledger.to_arrow_ipc(&mut ipc, 3).unwrap(), 7);
+    let reader = StreamReader::try_new(&ipc[..], None).unwrap();
+    assert_eq!(*reader.schema(), arrow_schema::<Metric>());
+    let batches: Vec<_> = reader.map(Result::unwrap).collect();
+    assert_eq!(
+        batches.iter().map(|b| b.num_rows()).collect::<Vec<_>>(),
+        [3, 3, 1]
+    );
+    //
+    let batch = &batches[1];
+    let row_ids = batch.column(0).as_primitive::<UInt64Type>();
+    assert_eq!(row_ids.values(), &[3, 5, 6]);
+    // row 5
+    assert_eq!(batch.column(2).as_primitive::<Int16Type>().value(1), -5);
+    assert_eq!(
+        batch.column(8).as_primitive::<Decimal128Type>().value(1),
+        -5 << 100
+    );
+    let huge = batch.column(9).as_fixed_size_binary().value(1);
+    assert_eq!(huge, ((5_u128 << 120) | 1).to_le_bytes());
+    assert_eq!(batch.column(10).as_string::<i32>().value(1), "metric 5");
+    assert_eq!(
+        batch.column(11).as_fixed_size_binary().value(1),
+        [5, 1, 2, 3]
+    );
+    let samples = batch.column(12).as_fixed_size_list().value(1);
+    assert_eq!(
+        samples.as_primitive::<Float32Type>().values(),
+        &[5.0, 0.5, -1.0]
+    );
+    assert!(!batch.column(7).as_boolean().value(1));
+    assert!(batch.column(7).as_boolean().value(2));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn keeps_i128_past_38_digits() {
+    let dir = temp_dir("arrow-i128");
+    let mut ledger = Metric::create_ledger(&dir, "metrics", "arrow").unwrap();
+    for big in [i128::MIN, i128::MAX] {
+        let mut row = metric(1);
+        row.set_big(big);
+        ledger.insert(&row).unwrap();
+    }
+    let mut ipc = Vec::new();
+    ledger.to_arrow_ipc(&mut ipc, 8).unwrap();
+    let batch = StreamReader::try_new(&ipc[..], None)
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap();
+    // the bits are kept, though they don't fit the declared precision
+    let big = batch.column(8).as_primitive::<Decimal128Type>();
+    assert_eq!(big.values(), &[i128::MIN, i128::MAX]);
+    assert!(big.validate_decimal_precision(38).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,205 @@
+use ledger_rs::checkpoint::{CheckpointKind, checkpoint_dir, checkpoint_path};
+use ledger_rs::ledger::RowId;
+use ledger_rs::page::PageSchema as _;
+use ledger_rs::utils::DatastoreError;
+use ledger_rs_macros::ledger;
+use std::path::Path;
+
+mod common;
+use common::temp_dir;
+
+#[ledger(page_size = 16)]
+pub struct Account {
+    pub id: u64,
+    pub balance: i64,
+    #[max_len(16)]
+    pub owner: String,
+}
+
+const NUM_ROWS: u64 = 100;
+
+fn create(dir: &Path) -> AccountLedger {
+    let mut ledger = Account::create_ledger(dir, "accounts", "checkpoints").unwrap();
+    for n in 0..NUM_ROWS {
+        ledger
+            .insert(&Account::new(n, n as i64 * 10, &format!("owner #{n}")))
+            .unwrap();
+    }
+    ledger
+}
+
+fn balances(ledger: &AccountLedger) -> Vec<Option<i64>> {
+    (0..ledger.num_rows().unwrap())
+        .map(|n| {
+            ledger
+                .access_row(RowId(n))
+                .unwrap()
+                .map(|row| row.balance())
+        })
+        .collect()
+}
+
+fn original() -> Vec<Option<i64>> {
+    (0..NUM_ROWS).map(|n| Some(n as i64 * 10)).collect()
+}
+
+#[test]
+fn checkpoint_survives_later_writes() {
+    let dir = temp_dir("checkpoint-writes");
+    let mut ledger = create(&dir);
+    ledger.checkpoint("before").unwrap();
+    //
+    for n in (0..NUM_ROWS).step_by(3) {
+        ledger.update(RowId(n), |row| row.set_balance(-1)).unwrap();
+    }
+    ledger.delete(RowId(1)).unwrap();
+    ledger.delete(RowId(NUM_ROWS - 1)).unwrap();
+    ledger.insert(&Account::new(NUM_ROWS, 0, "new")).unwrap();
+    //
+    let checkpoint = Account::open_checkpoint(&dir, "accounts", "before").unwrap();
+    assert_eq!(checkpoint.num_rows().unwrap(), NUM_ROWS);
+    assert_eq!(balances(&checkpoint), original());
+    drop(checkpoint);
+    // and after the ledger is closed and reopened
+    drop(ledger);
+    let ledger = Account::create_ledger(&dir, "accounts", "checkpoints").unwrap();
+    assert_eq!(ledger.access_row(RowId(1)).unwrap().map(|_| ()), None);
+    let checkpoint = Account::open_checkpoint(&dir, "accounts", "before").unwrap();
+    assert_eq!(balances(&checkpoint), original());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn unchecked_writes_are_preserved() {
+    let dir = temp_dir("checkpoint-unchecked");
+    let mut ledger = create(&dir);
+    ledger.checkpoint("before").unwrap();
+    //
+    unsafe { ledger.access_row_unchecked_mut(RowId(5)) }.set_balance(12345);
+    assert_eq!(
+        ledger.access_row(RowId(5)).unwrap().unwrap().balance(),
+        12345
+    );
+    //
+    let checkpoint = Account::open_checkpoint(&dir, "accounts", "before").unwrap();
+    assert_eq!(balances(&checkpoint), original());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reflink_or_undo_log() {
+    let dir = temp_dir("checkpoint-kind");
+    let mut ledger = create(&dir);
+    let cp_dir = checkpoint_dir(ledger.path());
+    // a reflink when the filesystem shares blocks, an undo log otherwise
+    let kind = ledger.checkpoint("first").unwrap();
+    assert!(checkpoint_path(&cp_dir, "first", kind).exists());
+    //
+    // compressed pages are holes in the ledger file, a clone would share them
+    assert!(ledger.compress_cold_pages().unwrap().pages > 0);
+    assert_eq!(
+        ledger.checkpoint("second").unwrap(),
+        CheckpointKind::UndoLog
+    );
+    assert!(checkpoint_path(&cp_dir, "second", CheckpointKind::UndoLog).exists());
+    //
+    ledger.update(RowId(0), |row| row.set_balance(-1)).unwrap();
+    for name in ["first", "second"] {
+        let checkpoint = Account::open_checkpoint(&dir, "accounts", name).unwrap();
+        assert_eq!(balances(&checkpoint), original());
+    }
+    assert_eq!(ledger.checkpoints().unwrap(), ["first", "second"]);
+    assert!(matches!(
+        ledger.checkpoint("second"),
+        Err(DatastoreError::Error(_))
+    ));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn open_checkpoint_is_read_only() {
+    let dir = temp_dir("checkpoint-read-only");
+    let mut ledger = create(&dir);
+    ledger.checkpoint("before").unwrap();
+    drop(ledger);
+    //
+    let mut checkpoint = Account::open_checkpoint(&dir, "accounts", "before").unwrap();
+    assert!(checkpoint.is_read_only());
+    assert!(matches!(
+        checkpoint.insert(&Account::new(0, 0, "x")),
+        Err(DatastoreError::ReadOnly)
+    ));
+    assert!(matches!(
+        checkpoint.update(RowId(0), |row| row.set_balance(1)),
+        Err(DatastoreError::ReadOnly)
+    ));
+    assert!(matches!(
+        checkpoint.delete(RowId(0)),
+        Err(DatastoreError::ReadOnly)
+    ));
+    assert!(matches!(
+        checkpoint.checkpoint("nested"),
+        Err(DatastoreError::ReadOnly)
+    ));
+    assert!(matches!(
+        checkpoint.compress_cold_pages(),
+        Err(DatastoreError::ReadOnly)
+    ));
+    assert!(matches!(
+        checkpoint.snapshot(),
+        Err(DatastoreError::ReadOnly)
+    ));
+    drop(checkpoint);
+    //
+    let ledger = Account::create_ledger(&dir, "accounts", "checkpoints").unwrap();
+    assert_eq!(balances(&ledger), original());
+    assert!(matches!(
+        Account::open_checkpoint(&dir, "accounts", "missing"),
+        Err(DatastoreError::CheckpointNotFound(_))
+    ));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn remove_checkpoint_folds_its_log() {
+    let dir = temp_dir("checkpoint-remove");
+    let mut ledger = create(&dir);
+    // undo logs on every filesystem
+    ledger.compress_cold_pages().unwrap();
+    assert_eq!(ledger.checkpoint("a").unwrap(), CheckpointKind::UndoLog);
+    //
+    ledger.update(RowId(0), |row| row.set_balance(-1)).unwrap();
+    let after_a = balances(&ledger);
+    assert_eq!(ledger.checkpoint("b").unwrap(), CheckpointKind::UndoLog);
+    // b's log gets row 20's original page, which only folding b hands to a
+    ledger.update(RowId(0), |row| row.set_balance(-2)).unwrap();
+    ledger.update(RowId(20), |row| row.set_balance(-2)).unwrap();
+    //
+    ledger.remove_checkpoint("b").unwrap();
+    assert_eq!(ledger.checkpoints().unwrap(), ["a"]);
+    let cp_dir = checkpoint_dir(ledger.path());
+    assert!(!checkpoint_path(&cp_dir, "b", CheckpointKind::UndoLog).exists());
+    assert!(matches!(
+        Account::open_checkpoint(&dir, "accounts", "b"),
+        Err(DatastoreError::CheckpointNotFound(_))
+    ));
+    let checkpoint = Account::open_checkpoint(&dir, "accounts", "a").unwrap();
+    assert_eq!(balances(&checkpoint), original());
+    drop(checkpoint);
+    //
+    // a is the active log again, later writes still reach it
+    ledger.update(RowId(40), |row| row.set_balance(-3)).unwrap();
+    let checkpoint = Account::open_checkpoint(&dir, "accounts", "a").unwrap();
+    assert_eq!(balances(&checkpoint), original());
+    drop(checkpoint);
+    assert_ne!(balances(&ledger), after_a);
+    //
+    ledger.remove_checkpoint("a").unwrap();
+    assert!(ledger.checkpoints().unwrap().is_empty());
+    assert!(matches!(
+        ledger.remove_checkpoint("a"),
+        Err(DatastoreError::CheckpointNotFound(_))
+    ));
+    ledger.update(RowId(60), |row| row.set_balance(-4)).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,8 @@
+use std::path::PathBuf;
+
+// a fresh directory under the system temp dir, tests run in parallel
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ledger-rs-{name}-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -0,0 +1,114 @@
+use ledger_rs::compression::PAGE_CACHE_PAGES;
+use ledger_rs::ledger::RowId;
+use ledger_rs::page::{PageSchema as _, SlottedPage};
+use ledger_rs_macros::ledger;
+use std::path::Path;
+
+mod common;
+use common::temp_dir;
+
+#[ledger(page_size = 16)]
+pub struct Reading {
+    pub sensor: u32,
+    pub value: i64,
+    #[max_len(24)]
+    pub label: String,
+}
+
+fn max_rows() -> u64 {
+    SlottedPage::<Reading, { Reading::PAGE_SZ }, { Reading::ROWS_PER_PAGE }>::max_rows_per_page()
+        as u64
+}
+
+fn create(dir: &Path, num_rows: u64) -> ReadingLedger {
+    let mut ledger = Reading::create_ledger(dir, "readings", "compression").unwrap();
+    for n in 0..num_rows {
+        ledger
+            .insert(&Reading::new(7, n as i64, &format!("sensor 7 reading {n}")))
+            .unwrap();
+    }
+    ledger
+}
+
+fn values(ledger: &ReadingLedger) -> Vec<Option<(i64, String)>> {
+    (0..ledger.num_rows().unwrap())
+        .map(|n| {
+            ledger
+                .access_row(RowId(n))
+                .unwrap()
+                .map(|row| (row.value(), row.label().unwrap().to_string()))
+        })
+        .collect()
+}
+
+#[test]
+fn round_trips_cold_pages() {
+    let dir = temp_dir("compression-round-trip");
+    let num_rows = max_rows() * 10 + 3;
+    let mut ledger = create(&dir, num_rows);
+    let before = values(&ledger);
+    let root = ledger.root_hash().unwrap();
+    //
+    // every full page, the page cursor's stays raw for inserts
+    let stats = ledger.compress_cold_pages().unwrap();
+    assert_eq!(stats.pages, 10);
+    assert!(stats.compressed_bytes < stats.raw_bytes);
+    assert_eq!(ledger.compressed_pages(), 10);
+    assert_eq!(ledger.compress_cold_pages().unwrap().pages, 0);
+    assert_eq!(values(&ledger), before);
+    assert_eq!(ledger.root_hash().unwrap(), root);
+    //
+    // a write restores its page into the ledger file first
+    ledger.update(RowId(5), |row| row.set_value(-5)).unwrap();
+    ledger.delete(RowId(max_rows() * 3)).unwrap();
+    ledger.insert(&Reading::new(8, 0, "after")).unwrap();
+    let after = values(&ledger);
+    assert_eq!(after[5].as_ref().unwrap().0, -5);
+    assert_eq!(after[max_rows() as usize * 3], None);
+    assert_eq!(ledger.compressed_pages(), 8);
+    drop(ledger);
+    //
+    let mut ledger = Reading::create_ledger(&dir, "readings", "compression").unwrap();
+    assert_eq!(ledger.compressed_pages(), 8);
+    assert_eq!(values(&ledger), after);
+    // the restored pages compress again
+    assert_eq!(ledger.compress_cold_pages().unwrap().pages, 2);
+    assert_eq!(values(&ledger), after);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn page_cache_is_capped() {
+    let dir = temp_dir("compression-cache");
+    let num_pages = PAGE_CACHE_PAGES as u64 * 2 + 10;
+    let mut ledger = create(&dir, max_rows() * num_pages + 1);
+    ledger.compress_cold_pages().unwrap();
+    assert_eq!(ledger.cached_pages(), 0);
+    // a scan decompresses into a scratch buffer and leaves the cache alone
+    let mut scanned = 0;
+    ledger.for_each_row(|_, _| scanned += 1).unwrap();
+    assert_eq!(scanned, max_rows() * num_pages + 1);
+    assert_eq!(ledger.cached_pages(), 0);
+    //
+    // a reader holding a row of the first page while every other page passes through the cache
+    let held = ledger.access_row(RowId(1)).unwrap().unwrap();
+    for page_id in 0..num_pages {
+        let row = ledger
+            .access_row(RowId(page_id * max_rows()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(row.value(), (page_id * max_rows()) as i64);
+        assert!(ledger.cached_pages() <= PAGE_CACHE_PAGES);
+    }
+    assert_eq!(ledger.cached_pages(), PAGE_CACHE_PAGES);
+    // its page was evicted long ago, the buffer lives on in the reference
+    assert_eq!(held.value(), 1);
+    assert_eq!(held.label().unwrap(), "sensor 7 reading 1");
+    drop(held);
+    //
+    // a write drops the page from the cache, it's read from the file again
+    ledger.update(RowId(1), |row| row.set_value(-1)).unwrap();
+    assert_eq!(ledger.access_row(RowId(1)).unwrap().unwrap().value(), -1);
+    assert!(ledger.cached_pages() <= PAGE_CACHE_PAGES);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
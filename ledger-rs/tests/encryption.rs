@@ -0,0 +1,265 @@
+use ledger_rs::encryption::{EncryptionKey, SEAL_SZ};
+use ledger_rs::header::LedgerHeader;
+use ledger_rs::index::sidecar_path;
+use ledger_rs::ledger::RowId;
+use ledger_rs::page::{PageSchema as _, SlottedPage, page_sz};
+use ledger_rs::utils::DatastoreError;
+use ledger_rs_macros::ledger;
+use std::path::{Path, PathBuf};
+
+mod common;
+use common::temp_dir;
+
+#[ledger(page_size = 16)]
+pub struct Note {
+    pub id: u64,
+    pub amount: i64,
+    #[fulltext]
+    #[max_len(24)]
+    pub location: String,
+}
+
+const KEY: EncryptionKey = [7; 32];
+const NUM_ROWS: u64 = 60;
+// the magic and version in front of the seals
+const SEALS_HEADER_SZ: usize = 5;
+
+fn max_rows() -> u64 {
+    SlottedPage::<Note, { Note::PAGE_SZ }, { Note::ROWS_PER_PAGE }>::max_rows_per_page() as u64
+}
+
+fn create(dir: &Path) -> PathBuf {
+    let mut ledger = Note::create_encrypted_ledger(dir, "notes", "encrypted", &KEY).unwrap();
+    for n in 0..NUM_ROWS {
+        ledger
+            .insert(&Note::new(n, n as i64, &format!("street {n}")))
+            .unwrap();
+    }
+    ledger.path().to_path_buf()
+}
+
+fn open(dir: &Path) -> Result<NoteLedger, DatastoreError> {
+    Note::create_encrypted_ledger(dir, "notes", "encrypted", &KEY)
+}
+
+fn amounts(ledger: &NoteLedger) -> Vec<i64> {
+    (0..ledger.num_rows().unwrap())
+        .map(|n| ledger.access_row(RowId(n)).unwrap().unwrap().amount())
+        .collect()
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+// (header size, page size) of the ledger file
+fn layout(bytes: &[u8]) -> (usize, usize) {
+    let header = LedgerHeader::access(&bytes[0..size_of::<LedgerHeader>()]).unwrap();
+    (header.size(), page_sz::<Note>() as usize)
+}
+
+#[test]
+fn rows_and_tokens_stay_off_disk() {
+    let dir = temp_dir("enc-plaintext");
+    let path = create(&dir);
+    let bytes = std::fs::read(&path).unwrap();
+    assert!(!bytes.windows(6).any(|w| w == b"street"));
+    assert!(!sidecar_path(&path, "location", "fts").exists());
+    //
+    let ledger = open(&dir).unwrap();
+    assert!(ledger.is_encrypted());
+    assert_eq!(amounts(&ledger), (0..NUM_ROWS as i64).collect::<Vec<_>>());
+    // rebuilt in memory on open
+    assert_eq!(
+        ledger.search_location("street").unwrap().len(),
+        NUM_ROWS as usize
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn wrong_key_fails_cleanly() {
+    let dir = temp_dir("enc-wrong-key");
+    create(&dir);
+    //
+    assert!(matches!(
+        Note::create_encrypted_ledger(&dir, "notes", "encrypted", &[8; 32]),
+        Err(DatastoreError::WrongKey)
+    ));
+    assert!(matches!(
+        Note::create_ledger(&dir, "notes", "encrypted"),
+        Err(DatastoreError::Error(_))
+    ));
+    // nothing was touched by the failed opens
+    let ledger = open(&dir).unwrap();
+    assert_eq!(ledger.num_rows().unwrap(), NUM_ROWS);
+    assert_eq!(amounts(&ledger), (0..NUM_ROWS as i64).collect::<Vec<_>>());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn swapped_pages_fail_authentication() {
+    let dir = temp_dir("enc-swap");
+    let path = create(&dir);
+    let original = std::fs::read(&path).unwrap();
+    let seals_path = with_suffix(&path, ".seal");
+    let original_seals = std::fs::read(&seals_path).unwrap();
+    let (header_sz, page_size) = layout(&original);
+    //
+    let mut bytes = original.clone();
+    let mut seals = original_seals.clone();
+    // pages 0 and 1 trade places, each with its own nonce and tag
+    let (first, second) = bytes[header_sz..].split_at_mut(page_size);
+    first.swap_with_slice(&mut second[..page_size]);
+    let (first, second) = seals[SEALS_HEADER_SZ..].split_at_mut(SEAL_SZ);
+    first.swap_with_slice(&mut second[..SEAL_SZ]);
+    std::fs::write(&path, &bytes).unwrap();
+    std::fs::write(&seals_path, &seals).unwrap();
+    //
+    // opening rebuilds the fulltext index from every page
+    assert!(matches!(open(&dir), Err(DatastoreError::Error(e)) if e.contains("page 0 failed")));
+    //
+    // a single flipped bit in page 1 is caught too
+    let mut bytes = original.clone();
+    bytes[header_sz + page_size + 100] ^= 1;
+    std::fs::write(&path, &bytes).unwrap();
+    std::fs::write(&seals_path, &original_seals).unwrap();
+    assert!(matches!(open(&dir), Err(DatastoreError::Error(e)) if e.contains("page 1 failed")));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// FNV-1a, as the seal log checksums itself
+fn checksum(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+// the seal log of a flush writing the header, page 1 and its seal
+fn seal_log(bytes: &[u8], seals: &[u8]) -> Vec<u8> {
+    let (header_sz, page_size) = layout(bytes);
+    let page_start = header_sz + page_size;
+    let seal_start = SEALS_HEADER_SZ + SEAL_SZ;
+    let writes: [(u8, usize, &[u8]); 3] = [
+        (0, 0, &bytes[..header_sz]),
+        (0, page_start, &bytes[page_start..page_start + page_size]),
+        (1, seal_start, &seals[seal_start..seal_start + SEAL_SZ]),
+    ];
+    //
+    let mut log = b"LSLG".to_vec();
+    log.push(1);
+    log.extend_from_slice(&(writes.len() as u32).to_le_bytes());
+    for (target, offset, data) in writes {
+        log.push(target);
+        log.extend_from_slice(&(offset as u64).to_le_bytes());
+        log.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        log.extend_from_slice(data);
+    }
+    log.extend_from_slice(&checksum(&log).to_le_bytes());
+    log
+}
+
+// the ledger file and its seals
+type Files = (Vec<u8>, Vec<u8>);
+
+// the files before and after a flush that rewrote page 1
+fn two_flushes(dir: &Path) -> (PathBuf, Files, Files) {
+    let path = create(dir);
+    let seals_path = with_suffix(&path, ".seal");
+    let before = (
+        std::fs::read(&path).unwrap(),
+        std::fs::read(&seals_path).unwrap(),
+    );
+    let mut ledger = open(dir).unwrap();
+    ledger
+        .update(RowId(max_rows()), |row| row.set_amount(-1))
+        .unwrap();
+    drop(ledger);
+    let after = (
+        std::fs::read(&path).unwrap(),
+        std::fs::read(&seals_path).unwrap(),
+    );
+    (path, before, after)
+}
+
+#[test]
+fn replays_a_complete_seal_log() {
+    let dir = temp_dir("enc-sealog-replay");
+    let (path, before, after) = two_flushes(&dir);
+    let (header_sz, page_size) = layout(&before.0);
+    //
+    // the crash hit halfway through writing page 1 in place, its seal is still the old one
+    let mut torn = before.0.clone();
+    let half = header_sz + page_size + page_size / 2;
+    torn[header_sz + page_size..half].copy_from_slice(&after.0[header_sz + page_size..half]);
+    std::fs::write(&path, &torn).unwrap();
+    std::fs::write(with_suffix(&path, ".seal"), &before.1).unwrap();
+    assert!(matches!(open(&dir), Err(DatastoreError::Error(e)) if e.contains("page 1 failed")));
+    //
+    std::fs::write(&path, &torn).unwrap();
+    std::fs::write(with_suffix(&path, ".seal"), &before.1).unwrap();
+    std::fs::write(with_suffix(&path, ".sealog"), seal_log(&after.0, &after.1)).unwrap();
+    let ledger = open(&dir).unwrap();
+    assert_eq!(
+        std::fs::metadata(with_suffix(&path, ".sealog"))
+            .unwrap()
+            .len(),
+        0
+    );
+    let mut expected: Vec<i64> = (0..NUM_ROWS as i64).collect();
+    expected[max_rows() as usize] = -1;
+    assert_eq!(amounts(&ledger), expected);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn discards_an_incomplete_seal_log() {
+    let dir = temp_dir("enc-sealog-discard");
+    let (path, before, after) = two_flushes(&dir);
+    //
+    // the crash hit while the log was written, nothing reached the ledger yet
+    let log = seal_log(&after.0, &after.1);
+    std::fs::write(&path, &before.0).unwrap();
+    std::fs::write(with_suffix(&path, ".seal"), &before.1).unwrap();
+    std::fs::write(with_suffix(&path, ".sealog"), &log[..log.len() - 10]).unwrap();
+    //
+    let ledger = open(&dir).unwrap();
+    assert_eq!(
+        std::fs::metadata(with_suffix(&path, ".sealog"))
+            .unwrap()
+            .len(),
+        0
+    );
+    assert_eq!(amounts(&ledger), (0..NUM_ROWS as i64).collect::<Vec<_>>());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn writes_past_the_plaintext_cache() {
+    let dir = temp_dir("enc-evict");
+    let mut ledger = Note::create_encrypted_ledger(&dir, "notes", "encrypted", &KEY).unwrap();
+    // far more pages than the 64 decrypted pages kept in memory
+    let num_rows = max_rows() * 200;
+    for n in 0..num_rows {
+        ledger.insert(&Note::new(n, n as i64, "somewhere")).unwrap();
+    }
+    for n in (0..num_rows).step_by(3) {
+        ledger
+            .update(RowId(n), |row| row.set_amount(-(n as i64)))
+            .unwrap();
+    }
+    let expected: Vec<i64> = (0..num_rows as i64)
+        .map(|n| if n % 3 == 0 { -n } else { n })
+        .collect();
+    assert_eq!(amounts(&ledger), expected);
+    drop(ledger);
+    //
+    let ledger = open(&dir).unwrap();
+    assert_eq!(amounts(&ledger), expected);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,109 @@
+use ledger_rs::ledger::RowId;
+use ledger_rs::page::PageSchema as _;
+use ledger_rs_macros::ledger;
+use std::path::Path;
+
+mod common;
+use common::temp_dir;
+
+#[ledger(page_size = 16)]
+pub struct Shipment {
+    pub id: u64,
+    pub delta: i128,
+    pub weight: f64,
+    #[max_len(24)]
+    pub label: String,
+    pub tag: [u8; 4],
+    pub dims: [f32; 3],
+    pub fragile: bool,
+}
+
+fn create(dir: &Path) -> ShipmentLedger {
+    let mut ledger = Shipment::create_ledger(dir, "shipments", "export").unwrap();
+    let rows = [
+        Shipment::new(
+            1,
+            -5,
+            1.5,
+            "plain",
+            [0xde, 0xad, 0xbe, 0xef],
+            [1.0, 2.5, -3.0],
+            true,
+        ),
+        Shipment::new(2, 0, 0.0, "deleted", [0; 4], [0.0; 3], false),
+        Shipment::new(
+            3,
+            i128::MAX,
+            f64::NAN,
+            "a, \"quoted\" label",
+            [0, 1, 2, 255],
+            [0.5; 3],
+            false,
+        ),
+    ];
+    for row in &rows {
+        ledger.insert(row).unwrap();
+    }
+    ledger.delete(RowId(1)).unwrap();
+    ledger
+}
+
+#[test]
+fn writes_csv() {
+    let dir = temp_dir("export-csv");
+    let ledger = create(&dir);
+    let mut csv = Vec::new();
+    assert_eq!(ledger.export_csv(&mut csv).unwrap(), 2);
+    // deleted rows are skipped, strings are cut to their length and quoted when they need it
+    let expected = [
+        "row_id,id,delta,weight,label,tag,dims,fragile",
+        "0,1,-5,1.5,plain,deadbeef,\"[1.0,2.5,-3.0]\",true",
+        "2,3,170141183460469231731687303715884105727,NaN,\"a, \"\"quoted\"\" label\",000102ff,\"[0.5,0.5,0.5]\",false",
+    ];
+    assert_eq!(String::from_utf8(csv).unwrap(), expected.join("\n") + "\n");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn writes_json_lines() {
+    let dir = temp_dir("export-jsonl");
+    let ledger = create(&dir);
+    let mut jsonl = Vec::new();
+    assert_eq!(ledger.export_jsonl(&mut jsonl).unwrap(), 2);
+    let jsonl = String::from_utf8(jsonl).unwrap();
+    let lines: Vec<&str> = jsonl.lines().collect();
+    // keys in declaration order after row_id
+    assert_eq!(
+        lines[0],
+        r#"{"row_id":0,"id":1,"delta":-5,"weight":1.5,"label":"plain","tag":"deadbeef","dims":[1.0,2.5,-3.0],"fragile":true}"#
+    );
+    // past 64 bits an int is a string, NaN is null
+    let row: serde_json::Value = serde_json::from_str(lines[1]).unwrap();
+    assert_eq!(
+        row,
+        serde_json::json!({
+            "row_id": 2,
+            "id": 3,
+            "delta": i128::MAX.to_string(),
+            "weight": null,
+            "label": "a, \"quoted\" label",
+            "tag": "000102ff",
+            "dims": [0.5, 0.5, 0.5],
+            "fragile": false,
+        })
+    );
+    assert_eq!(lines.len(), 2);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn an_empty_ledger_writes_the_header_only() {
+    let dir = temp_dir("export-empty");
+    let ledger = Shipment::create_ledger(&dir, "shipments", "export").unwrap();
+    let (mut csv, mut jsonl) = (Vec::new(), Vec::new());
+    assert_eq!(ledger.export_csv(&mut csv).unwrap(), 0);
+    assert_eq!(ledger.export_jsonl(&mut jsonl).unwrap(), 0);
+    assert_eq!(csv, b"row_id,id,delta,weight,label,tag,dims,fragile\n");
+    assert!(jsonl.is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,217 @@
+use ledger_rs::header::{CHECKPOINT_SLOT_SZ, FORMAT_VERSION, LEGACY_HEADER_SZ, LedgerHeader};
+use ledger_rs::inspect::RawLedger;
+use ledger_rs::ledger::{RowId, RowQuery};
+use ledger_rs::page::{PageSchema as _, SlottedPage, page_sz};
+use ledger_rs::repair::RepairOptions;
+use ledger_rs::utils::DatastoreError;
+use ledger_rs_macros::ledger;
+use std::path::{Path, PathBuf};
+
+mod common;
+use common::temp_dir;
+
+// format 0 pages start at byte 68, only rows aligned to 4 bytes were ever written with it. a few
+// rows per page so appends walk the page counters
+#[ledger(page_size = 64)]
+pub struct Reading {
+    pub id: u32,
+    pub value: i32,
+    #[max_len(16)]
+    pub label: String,
+}
+
+const NUM_ROWS: u64 = 300;
+// offsets of the u32 counters in the 68 byte header of format 0
+const NUM_PAGES_U32_AT: usize = 42;
+const PAGE_CURSOR_U32_AT: usize = 50;
+// offsets in the 96 byte header, format 1 and later
+const FORMAT_VERSION_AT: usize = 66;
+const SCHEMA_SZ_AT: usize = 92;
+const LEDGER_HEADER_SZ: usize = size_of::<LedgerHeader>();
+
+// a format 3 ledger, returns the file
+fn create(dir: &Path) -> PathBuf {
+    let mut ledger = Reading::create_ledger(dir, "readings", "formats").unwrap();
+    for n in 0..NUM_ROWS {
+        ledger
+            .insert(&Reading::new(
+                n as u32,
+                -(n as i32),
+                &format!("reading #{n}"),
+            ))
+            .unwrap();
+    }
+    ledger.path().to_path_buf()
+}
+
+// the same ledger as an older format wrote it
+fn downgrade(bytes: &[u8], format_version: u8) -> Vec<u8> {
+    let header = LedgerHeader::access(&bytes[0..LEDGER_HEADER_SZ]).unwrap();
+    assert_eq!(header.format_version(), FORMAT_VERSION);
+    let schema_sz = header.schema_size();
+    let pages = &bytes[header.size()..];
+    //
+    let mut old = bytes[0..LEDGER_HEADER_SZ].to_vec();
+    old[FORMAT_VERSION_AT] = format_version;
+    match format_version {
+        0 => {
+            let num_pages = header.num_pages() as u32;
+            let page_cursor = header.page_cursor() as u32;
+            old.truncate(LEGACY_HEADER_SZ);
+            old[NUM_PAGES_U32_AT..NUM_PAGES_U32_AT + 4].copy_from_slice(&num_pages.to_le_bytes());
+            old[PAGE_CURSOR_U32_AT..PAGE_CURSOR_U32_AT + 4]
+                .copy_from_slice(&page_cursor.to_le_bytes());
+        }
+        1 => old[SCHEMA_SZ_AT..SCHEMA_SZ_AT + 4].copy_from_slice(&[0; 4]),
+        2 => old.extend_from_slice(&bytes[LEDGER_HEADER_SZ..LEDGER_HEADER_SZ + schema_sz]),
+        _ => unreachable!(),
+    }
+    old.extend_from_slice(pages);
+    old
+}
+
+fn check(path: &Path, format_version: u8) {
+    let dir = path.parent().unwrap();
+    let mut ledger = Reading::create_ledger(dir, "readings", "formats").unwrap();
+    assert_eq!(
+        ledger.access_header().unwrap().format_version(),
+        format_version
+    );
+    assert_eq!(
+        ledger.stored_schema().unwrap().is_some(),
+        format_version > 1
+    );
+    assert_eq!(ledger.num_rows().unwrap(), NUM_ROWS);
+    for n in 0..NUM_ROWS {
+        let row = ledger.access_row(RowId(n)).unwrap().unwrap();
+        assert_eq!(row.id(), n as u32);
+        assert_eq!(row.value(), -(n as i32));
+        assert_eq!(row.label().unwrap(), format!("reading #{n}"));
+    }
+    // new rows land after the header of the format the file was written with
+    ledger
+        .insert(&Reading::new(NUM_ROWS as u32, 0, "appended"))
+        .unwrap();
+    drop(ledger);
+    //
+    let ledger = Reading::create_ledger(dir, "readings", "formats").unwrap();
+    assert_eq!(
+        ledger.access_header().unwrap().format_version(),
+        format_version
+    );
+    assert_eq!(ledger.num_rows().unwrap(), NUM_ROWS + 1);
+    let row = ledger.access_row(RowId(NUM_ROWS)).unwrap().unwrap();
+    assert_eq!(row.label().unwrap(), "appended");
+    drop(ledger);
+    //
+    let raw = RawLedger::open(path).unwrap();
+    assert_eq!(raw.schema().is_some(), format_version > 1);
+    assert!(raw.verify().unwrap().is_ok());
+}
+
+#[test]
+fn opens_every_format() {
+    let dir = temp_dir("formats");
+    std::fs::create_dir_all(dir.join("3")).unwrap();
+    let path = create(&dir.join("3"));
+    let bytes = std::fs::read(&path).unwrap();
+    let header = LedgerHeader::access(&bytes[0..LEDGER_HEADER_SZ]).unwrap();
+    assert_eq!(
+        header.size(),
+        LEDGER_HEADER_SZ + header.schema_size() + CHECKPOINT_SLOT_SZ
+    );
+    check(&path, FORMAT_VERSION);
+    //
+    for format_version in [0, 1, 2] {
+        let old_dir = dir.join(format_version.to_string());
+        std::fs::create_dir_all(&old_dir).unwrap();
+        let old_path = old_dir.join(path.file_name().unwrap());
+        std::fs::write(&old_path, downgrade(&bytes, format_version)).unwrap();
+        check(&old_path, format_version);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rejects_schema_page_past_the_end() {
+    let dir = temp_dir("schema-size");
+    let path = create(&dir);
+    let mut bytes = std::fs::read(&path).unwrap();
+    bytes[SCHEMA_SZ_AT..SCHEMA_SZ_AT + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+    std::fs::write(&path, &bytes).unwrap();
+    //
+    assert!(matches!(
+        Reading::create_ledger(&dir, "readings", "formats"),
+        Err(DatastoreError::Error(_))
+    ));
+    assert!(RawLedger::open(&path).is_err());
+    // the pages are intact, repair must not cut them away
+    assert!(ReadingLedger::repair(&path, RepairOptions::default()).is_err());
+    assert_eq!(std::fs::read(&path).unwrap(), bytes);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[ledger(page_size = 2)]
+pub struct Tick {
+    pub n: u32,
+}
+
+#[test]
+fn format_0_runs_out_of_pages() {
+    let dir = temp_dir("format-0-limit");
+    let mut ledger = Tick::create_ledger(&dir, "ticks", "formats").unwrap();
+    ledger.insert(&Tick::new(0)).unwrap();
+    let path = ledger.path().to_path_buf();
+    drop(ledger);
+    let bytes = std::fs::read(&path).unwrap();
+    let header = LedgerHeader::access(&bytes[0..LEDGER_HEADER_SZ]).unwrap();
+    let page = &bytes[header.size()..header.size() + page_sz::<Tick>() as usize];
+    //
+    // page 0 is full and the header records every page a u32 can count. the file is sparse
+    let mut old = downgrade(&bytes, 0)[0..LEGACY_HEADER_SZ].to_vec();
+    old[NUM_PAGES_U32_AT..NUM_PAGES_U32_AT + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+    old.extend_from_slice(page);
+    std::fs::write(&path, &old).unwrap();
+    let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+    file.set_len(LEGACY_HEADER_SZ as u64 + u32::MAX as u64 * page_sz::<Tick>() as u64)
+        .unwrap();
+    drop(file);
+    //
+    let mut ledger = Tick::create_ledger(&dir, "ticks", "formats").unwrap();
+    assert_eq!(ledger.access_header().unwrap().format_version(), 0);
+    assert_eq!(ledger.access_row(RowId(0)).unwrap().unwrap().n(), 0);
+    assert!(matches!(
+        ledger.insert(&Tick::new(1)),
+        Err(DatastoreError::RowIdOverflow(_))
+    ));
+    assert_eq!(ledger.access_header().unwrap().num_pages(), u32::MAX as u64);
+    drop(ledger);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn row_id_past_u64_is_an_error() {
+    type Query = RowQuery<Reading, { Reading::PAGE_SZ }, { Reading::ROWS_PER_PAGE }>;
+    let max_rows =
+        SlottedPage::<Reading, { Reading::PAGE_SZ }, { Reading::ROWS_PER_PAGE }>::max_rows_per_page(
+        );
+    //
+    let last_page = (u64::MAX / max_rows as u64) as usize;
+    let last_row = (u64::MAX % max_rows as u64) as u32;
+    assert_eq!(
+        Query::to_row_id(last_page, last_row).unwrap(),
+        RowId(u64::MAX)
+    );
+    assert!(matches!(
+        Query::to_row_id(last_page, last_row + 1),
+        Err(DatastoreError::RowIdOverflow(_))
+    ));
+    assert!(matches!(
+        Query::to_row_id(last_page + 1, 0),
+        Err(DatastoreError::RowIdOverflow(_))
+    ));
+    assert!(matches!(
+        Query::to_row_id(usize::MAX, 0),
+        Err(DatastoreError::RowIdOverflow(_))
+    ));
+}
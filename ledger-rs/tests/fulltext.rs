@@ -0,0 +1,130 @@
+use ledger_rs::fulltext::{FullTextIndex, tokenize};
+use ledger_rs::index::sidecar_path;
+use ledger_rs::ledger::RowId;
+use ledger_rs::page::PageSchema as _;
+use ledger_rs_macros::ledger;
+use std::path::Path;
+
+mod common;
+use common::temp_dir;
+
+#[ledger(page_size = 16)]
+pub struct Document {
+    pub id: u64,
+    #[fulltext]
+    #[max_len(32)]
+    pub title: String,
+}
+
+fn create(dir: &Path) -> DocumentLedger {
+    Document::create_ledger(dir, "documents", "fulltext").unwrap()
+}
+
+#[test]
+fn tokenizes_lowercased_alphanumeric_runs() {
+    let tokens: Vec<String> = tokenize("Document #42.txt").collect();
+    assert_eq!(tokens, ["document", "42", "txt"]);
+    let tokens: Vec<String> = tokenize("  Größe--ÄPFEL__x ").collect();
+    assert_eq!(tokens, ["größe", "äpfel", "x"]);
+    assert_eq!(tokenize(" .,;- ").count(), 0);
+}
+
+#[test]
+fn matches_query_terms_as_prefixes() {
+    let mut index = FullTextIndex::new();
+    index.insert(0, "Quarterly report");
+    index.insert(1, "Report draft");
+    index.insert(2, "reporter notes");
+    index.insert(3, "Quarter end");
+    //
+    assert_eq!(index.search("report"), [0, 1, 2]);
+    assert_eq!(index.search("REP"), [0, 1, 2]);
+    assert_eq!(index.search("quarter"), [0, 3]);
+    // every term has to match
+    assert_eq!(index.search("quart rep"), [0]);
+    assert_eq!(index.search("report missing"), Vec::<u64>::new());
+    // no term in the query, no rows
+    assert_eq!(index.search(""), Vec::<u64>::new());
+    // a term is not matched by its own prefix
+    assert_eq!(index.search("reporters"), Vec::<u64>::new());
+    //
+    index.remove(2, "reporter notes");
+    assert_eq!(index.search("rep"), [0, 1]);
+    assert_eq!(index.search("notes"), Vec::<u64>::new());
+}
+
+#[test]
+fn postings_survive_save_and_load() {
+    let dir = temp_dir("fts-persist");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("index.fts");
+    //
+    let mut index = FullTextIndex::new();
+    // gaps that need one to ten varint bytes, inserted out of order
+    let row_ids = [u64::MAX - 1, 0, 1, 127, 128, 16_384, 1 << 35, 1 << 56];
+    for row_id in row_ids {
+        index.insert(row_id, "shared");
+        index.insert(row_id, &format!("only{row_id}"));
+    }
+    index.save(&path).unwrap();
+    //
+    let loaded = FullTextIndex::load(&path).unwrap();
+    assert_eq!(loaded, index);
+    assert_eq!(loaded.watermark(), u64::MAX);
+    assert_eq!(loaded.num_terms(), row_ids.len() + 1);
+    let mut sorted = row_ids;
+    sorted.sort();
+    assert_eq!(loaded.search("shared"), sorted);
+    assert_eq!(loaded.search("only128"), [128]);
+    //
+    // cut off in the middle of the postings
+    let bytes = std::fs::read(&path).unwrap();
+    std::fs::write(&path, &bytes[..bytes.len() - 3]).unwrap();
+    assert!(FullTextIndex::load(&path).is_err());
+    // an unknown version
+    let mut bad = bytes.clone();
+    bad[4] = 1;
+    std::fs::write(&path, &bad).unwrap();
+    assert!(FullTextIndex::load(&path).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rebuilds_a_stale_sidecar_on_open() {
+    let dir = temp_dir("fts-stale");
+    let mut ledger = create(&dir);
+    let path = ledger.path().to_path_buf();
+    let fts_path = sidecar_path(&path, "title", "fts");
+    for n in 0..50 {
+        ledger
+            .insert(&Document::new(n, &format!("early note {n}")))
+            .unwrap();
+    }
+    drop(ledger);
+    let early = std::fs::read(&fts_path).unwrap();
+    assert_eq!(FullTextIndex::load(&fts_path).unwrap().watermark(), 50);
+    //
+    let mut ledger = create(&dir);
+    for n in 50..60 {
+        ledger
+            .insert(&Document::new(n, &format!("late note {n}")))
+            .unwrap();
+    }
+    drop(ledger);
+    //
+    // an index that stopped at row 50, as a crash before the sidecar was written leaves it
+    std::fs::write(&fts_path, &early).unwrap();
+    let ledger = create(&dir);
+    let late: Vec<RowId> = (50..60).map(RowId).collect();
+    assert_eq!(ledger.search_title("late").unwrap(), late);
+    assert_eq!(ledger.search_title("note").unwrap().len(), 60);
+    drop(ledger);
+    assert_eq!(FullTextIndex::load(&fts_path).unwrap().watermark(), 60);
+    //
+    // an unreadable sidecar is rebuilt the same way
+    std::fs::write(&fts_path, b"garbage").unwrap();
+    let ledger = create(&dir);
+    assert_eq!(ledger.search_title("late").unwrap(), late);
+    assert_eq!(ledger.search_title("early 7").unwrap(), [RowId(7)]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
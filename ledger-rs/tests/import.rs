@@ -0,0 +1,249 @@
+use ledger_rs::import::{ImportError, ImportOptions, OnOverflow};
+use ledger_rs::ledger::RowId;
+use ledger_rs::page::PageSchema as _;
+use ledger_rs_macros::ledger;
+use std::path::Path;
+
+mod common;
+use common::temp_dir;
+
+#[ledger(page_size = 16)]
+pub struct Shipment {
+    pub id: u64,
+    pub delta: i128,
+    pub weight: f64,
+    #[max_len(24)]
+    pub label: String,
+    pub tag: [u8; 4],
+    pub dims: [f32; 3],
+    pub fragile: bool,
+}
+
+fn create(dir: &Path) -> ShipmentLedger {
+    std::fs::create_dir_all(dir).unwrap();
+    Shipment::create_ledger(dir, "shipments", "import").unwrap()
+}
+
+// every live row, formatted so NaN compares equal to itself
+fn rows(ledger: &ShipmentLedger) -> Vec<String> {
+    let mut rows = Vec::new();
+    ledger
+        .for_each_row(|_, row| {
+            rows.push(format!(
+                "{} {} {:?} {:?} {:?} {:?} {}",
+                row.id(),
+                row.delta(),
+                row.weight(),
+                row.label().unwrap(),
+                row.tag,
+                row.dims(),
+                row.fragile
+            ))
+        })
+        .unwrap();
+    rows
+}
+
+fn error(line: u64, field: Option<&str>, reason: &str) -> ImportError {
+    ImportError {
+        line,
+        field: field.map(str::to_string),
+        reason: reason.to_string(),
+    }
+}
+
+const HEADER: &str = "row_id,id,delta,weight,label,tag,dims,fragile\n";
+
+#[test]
+fn round_trips_an_export() {
+    let dir = temp_dir("import-round-trip");
+    let mut source = create(&dir.join("source"));
+    for n in 0..40 {
+        source
+            .insert(&Shipment::new(
+                n,
+                i128::MIN + n as i128,
+                if n == 7 { f64::NAN } else { n as f64 / 3.0 },
+                &format!("crate {n}, \"a\"\nb"),
+                [n as u8, 0, 0xff, 1],
+                [n as f32, -0.5, f32::MAX],
+                n % 2 == 0,
+            ))
+            .unwrap();
+    }
+    source.delete(RowId(3)).unwrap();
+    let expected = rows(&source);
+    let (mut csv, mut jsonl) = (Vec::new(), Vec::new());
+    source.export_csv(&mut csv).unwrap();
+    source.export_jsonl(&mut jsonl).unwrap();
+    //
+    // small batches, the row ids run on across them
+    let options = ImportOptions {
+        batch_size: 16,
+        ..ImportOptions::default()
+    };
+    let mut from_csv = create(&dir.join("csv"));
+    let report = from_csv.import_csv(&csv[..], options.clone()).unwrap();
+    assert!(report.is_ok(), "{:?}", report.errors);
+    assert_eq!((report.records, report.imported), (39, 39));
+    assert_eq!(report.first_row, Some(RowId(0)));
+    assert_eq!(report.last_row, Some(RowId(38)));
+    assert_eq!(rows(&from_csv), expected);
+    //
+    let mut from_jsonl = create(&dir.join("jsonl"));
+    let report = from_jsonl.import_jsonl(&jsonl[..], options).unwrap();
+    assert!(report.is_ok(), "{:?}", report.errors);
+    assert_eq!(report.imported, 39);
+    assert_eq!(rows(&from_jsonl), expected);
+    //
+    // and back out again, only the row ids after the deleted row differ
+    let mut again = Vec::new();
+    from_csv.export_csv(&mut again).unwrap();
+    let strip_row_id = |csv: &[u8]| -> Vec<String> {
+        String::from_utf8(csv.to_vec())
+            .unwrap()
+            .lines()
+            .map(|line| line.split_once(',').unwrap().1.to_string())
+            .collect()
+    };
+    assert_eq!(strip_row_id(&again), strip_row_id(&csv));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn overflowing_strings_error_or_truncate() {
+    let dir = temp_dir("import-overflow");
+    // 26 bytes, and 23 ascii bytes followed by a two byte char
+    let long = "abcdefghijklmnopqrstuvwxyz";
+    let split = format!("{}é", &long[..23]);
+    let csv = format!(
+        "{HEADER}0,1,0,0,{long},00000000,\"[0,0,0]\",true\n1,2,0,0,{split},00000000,\"[0,0,0]\",true\n2,3,0,0,short,00000000,\"[0,0,0]\",true\n"
+    );
+    //
+    let mut ledger = create(&dir.join("error"));
+    let report = ledger
+        .import_csv(csv.as_bytes(), ImportOptions::default())
+        .unwrap();
+    assert_eq!((report.records, report.imported), (3, 1));
+    assert_eq!(
+        report.errors,
+        [
+            error(2, Some("label"), "26 bytes, max_len is 24"),
+            error(3, Some("label"), "25 bytes, max_len is 24"),
+        ]
+    );
+    assert_eq!(
+        report.errors[0].to_string(),
+        "line 2, field label: 26 bytes, max_len is 24"
+    );
+    assert_eq!(
+        ledger
+            .access_row(RowId(0))
+            .unwrap()
+            .unwrap()
+            .label()
+            .unwrap(),
+        "short"
+    );
+    //
+    // cut at the last char boundary that fits
+    let mut ledger = create(&dir.join("truncate"));
+    let options = ImportOptions {
+        on_overflow: OnOverflow::Truncate,
+        ..ImportOptions::default()
+    };
+    let report = ledger.import_csv(csv.as_bytes(), options).unwrap();
+    assert!(report.is_ok());
+    let labels: Vec<String> = (0..3)
+        .map(|n| {
+            let row = ledger.access_row(RowId(n)).unwrap().unwrap();
+            row.label().unwrap().to_string()
+        })
+        .collect();
+    assert_eq!(labels, [&long[..24], &long[..23], "short"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reports_errors_by_line() {
+    let dir = temp_dir("import-csv-errors");
+    // a quoted label spanning two lines, so records and lines don't line up
+    let csv = [
+        "id,delta,weight,label,tag,dims,fragile",
+        "1,0,0,\"two",
+        "lines\",00000000,\"[0,0,0]\",true",
+        "",
+        "2,zero,0,bad int,00000000,\"[0,0,0]\",true",
+        "3,0,0,bad hex,0000zz00,\"[0,0,0]\",true",
+        "4,0,0,short array,00000000,\"[0,0]\",true",
+        "5,0,0,too few cells",
+        "6,0,0,last,00000000,\"[1,2,3]\",false",
+        // runs to the end of the input looking for its closing quote
+        "7,0,0,\"unterminated,00000000",
+    ]
+    .join("\n");
+    let mut ledger = create(&dir);
+    let report = ledger
+        .import_csv(csv.as_bytes(), ImportOptions::default())
+        .unwrap();
+    assert_eq!((report.records, report.imported), (7, 2));
+    let reasons: Vec<(u64, Option<&str>)> = report
+        .errors
+        .iter()
+        .map(|e| (e.line, e.field.as_deref()))
+        .collect();
+    assert_eq!(
+        reasons,
+        [
+            (5, Some("delta")),
+            (6, Some("tag")),
+            (7, Some("dims")),
+            (8, None),
+            (10, None),
+        ]
+    );
+    assert_eq!(report.errors[2].reason, "2 elements, expected 3");
+    assert_eq!(report.errors[3].reason, "4 cells, the header has 7");
+    assert_eq!(report.errors[4].reason, "unterminated quoted cell");
+    assert_eq!(
+        ledger
+            .access_row(RowId(0))
+            .unwrap()
+            .unwrap()
+            .label()
+            .unwrap(),
+        "two\nlines"
+    );
+    //
+    // stopping at the first bad record keeps the rows before it
+    let mut ledger = create(&dir.join("stop"));
+    let options = ImportOptions {
+        stop_on_error: true,
+        ..ImportOptions::default()
+    };
+    let report = ledger.import_csv(csv.as_bytes(), options).unwrap();
+    assert_eq!((report.records, report.imported), (2, 1));
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(ledger.num_rows().unwrap(), 1);
+    //
+    let jsonl = [
+        r#"{"id":1,"delta":"-170141183460469231731687303715884105728","weight":null,"label":"ok","tag":"00000000","dims":[0,0,0],"fragile":true}"#,
+        "",
+        "[1, 2]",
+        "{not json",
+        r#"{"id":2,"delta":0,"weight":0,"label":"no tag","dims":[0,0,0],"fragile":true}"#,
+    ]
+    .join("\n");
+    let mut ledger = create(&dir.join("jsonl"));
+    let report = ledger
+        .import_jsonl(jsonl.as_bytes(), ImportOptions::default())
+        .unwrap();
+    assert_eq!((report.records, report.imported), (4, 1));
+    assert_eq!(report.errors[0], error(3, None, "not a json object"));
+    assert_eq!(report.errors[1].line, 4);
+    assert_eq!(report.errors[2], error(5, Some("tag"), "missing"));
+    let row = ledger.access_row(RowId(0)).unwrap().unwrap();
+    assert_eq!(row.delta(), i128::MIN);
+    assert!(row.weight().is_nan());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,125 @@
+use ledger_rs::ledger::RowId;
+use ledger_rs::merkle::{Proof, verify_proof};
+use ledger_rs::page::{PageSchema as _, SlottedPage};
+use ledger_rs::utils::DatastoreError;
+use ledger_rs_macros::ledger;
+
+mod common;
+use common::temp_dir;
+
+#[ledger(page_size = 16)]
+pub struct Entry {
+    pub id: u64,
+    pub amount: i64,
+}
+
+fn max_rows() -> u64 {
+    SlottedPage::<Entry, { Entry::PAGE_SZ }, { Entry::ROWS_PER_PAGE }>::max_rows_per_page() as u64
+}
+
+fn create(dir: &std::path::Path, num_rows: u64) -> EntryLedger {
+    let mut ledger = Entry::create_ledger(dir, "entries", "merkle").unwrap();
+    for n in 0..num_rows {
+        ledger.insert(&Entry::new(n, n as i64 * 3)).unwrap();
+    }
+    ledger
+}
+
+fn verifies(ledger: &EntryLedger, row_id: RowId, proof: &Proof) -> bool {
+    let root = ledger.root_hash().unwrap();
+    let row = ledger.row_bytes(row_id).unwrap().unwrap();
+    verify_proof(&root, &row, proof)
+}
+
+#[test]
+fn proves_every_row_up_to_a_partial_last_page() {
+    let dir = temp_dir("merkle-partial");
+    // three full pages and a few rows on the last one
+    let num_rows = max_rows() * 3 + 5;
+    let ledger = create(&dir, num_rows);
+    //
+    for n in 0..num_rows {
+        let proof = ledger.prove_row(RowId(n)).unwrap();
+        assert_eq!(proof.row_id, RowId(n));
+        assert_eq!(proof.max_rows_per_page as u64, max_rows());
+        assert_eq!(proof.num_pages, 4);
+        assert!(verifies(&ledger, RowId(n), &proof), "row {n}");
+    }
+    let last = ledger.prove_row(RowId(num_rows - 1)).unwrap();
+    assert_eq!(last.rows_in_page, 5);
+    //
+    // a proof holds for the root it was made against, not a later one
+    let proof = ledger.prove_row(RowId(0)).unwrap();
+    let root = ledger.root_hash().unwrap();
+    let row = ledger.row_bytes(RowId(0)).unwrap().unwrap().to_vec();
+    let mut ledger = ledger;
+    ledger.insert(&Entry::new(num_rows, 0)).unwrap();
+    assert!(verify_proof(&root, &row, &proof));
+    assert!(!verify_proof(&ledger.root_hash().unwrap(), &row, &proof));
+    assert!(matches!(
+        ledger.prove_row(RowId(num_rows + 1)),
+        Err(DatastoreError::PageError(_))
+    ));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn deleted_rows_keep_their_position() {
+    let dir = temp_dir("merkle-deleted");
+    let mut ledger = create(&dir, max_rows() * 2);
+    let before = ledger.root_hash().unwrap();
+    ledger.delete(RowId(3)).unwrap();
+    assert_ne!(ledger.root_hash().unwrap(), before);
+    //
+    assert!(matches!(
+        ledger.prove_row(RowId(3)),
+        Err(DatastoreError::PageError(_))
+    ));
+    // the rest of the page still proves against the new root
+    for n in [0, 2, 4, max_rows() - 1, max_rows()] {
+        let proof = ledger.prove_row(RowId(n)).unwrap();
+        assert!(verifies(&ledger, RowId(n), &proof), "row {n}");
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rejects_tampered_proofs() {
+    let dir = temp_dir("merkle-tampered");
+    let ledger = create(&dir, max_rows() * 3 + 5);
+    let row_id = RowId(max_rows() + 2);
+    let proof = ledger.prove_row(row_id).unwrap();
+    assert!(verifies(&ledger, row_id, &proof));
+    //
+    let mut sibling = proof.clone();
+    sibling.row_path[0][0] ^= 1;
+    assert!(!verifies(&ledger, row_id, &sibling));
+    let mut page_sibling = proof.clone();
+    page_sibling.page_path[1][31] ^= 1;
+    assert!(!verifies(&ledger, row_id, &page_sibling));
+    // a different row under the same proof
+    let root = ledger.root_hash().unwrap();
+    let other = ledger.row_bytes(RowId(0)).unwrap().unwrap();
+    assert!(!verify_proof(&root, &other, &proof));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rejects_a_relabeled_row_id() {
+    let dir = temp_dir("merkle-relabeled");
+    let ledger = create(&dir, max_rows() * 3 + 5);
+    let row_id = RowId(max_rows() + 2);
+    let proof = ledger.prove_row(row_id).unwrap();
+    //
+    for other in [0, row_id.get() - 1, row_id.get() + 1, max_rows() * 2 + 2] {
+        let mut relabeled = proof.clone();
+        relabeled.row_id = RowId(other);
+        assert!(!verifies(&ledger, row_id, &relabeled), "row {other}");
+    }
+    // a page geometry that fits another row id, but full pages hold max_rows_per_page rows
+    let mut regrouped = proof.clone();
+    regrouped.row_id = RowId(row_id.get() + 1);
+    regrouped.max_rows_per_page += 1;
+    assert!(!verifies(&ledger, row_id, &regrouped));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,126 @@
+use ledger_rs::ledger::RowId;
+use ledger_rs::page::PageSchema as _;
+use ledger_rs::utils::DatastoreError;
+use ledger_rs_macros::ledger;
+
+mod common;
+use common::temp_dir;
+
+#[ledger(page_size = 16)]
+pub struct Contact {
+    pub id: u64,
+    pub score: f64,
+    pub verified: bool,
+    #[max_len(12)]
+    pub name: String,
+    #[max_len(32)]
+    pub email: String,
+    pub key: [u8; 4],
+    pub history: [i32; 3],
+}
+
+fn contact() -> Contact {
+    Contact::new(
+        42,
+        9.5,
+        true,
+        "Zoë",
+        "zoe@example.com",
+        [1, 2, 3, 4],
+        [-1, 0, 1],
+    )
+}
+
+#[test]
+fn owned_holds_native_values() {
+    let owned = ContactOwned::from(&contact());
+    // strings cut to their length, numbers and arrays in native order
+    assert_eq!(
+        owned,
+        ContactOwned {
+            id: 42,
+            score: 9.5,
+            verified: true,
+            name: "Zoë".to_string(),
+            email: "zoe@example.com".to_string(),
+            key: [1, 2, 3, 4],
+            history: [-1, 0, 1],
+        }
+    );
+    assert_eq!(ContactOwned::from(contact()), owned);
+    //
+    // and back to the same row
+    assert_eq!(Contact::try_from(&owned).unwrap(), contact());
+    assert_eq!(Contact::try_from(owned).unwrap(), contact());
+}
+
+#[test]
+fn edits_through_owned() {
+    let dir = temp_dir("owned-ledger");
+    let mut ledger = Contact::create_ledger(&dir, "contacts", "owned").unwrap();
+    ledger.insert(&contact()).unwrap();
+    let owned = ContactOwned::from(&*ledger.access_row(RowId(0)).unwrap().unwrap());
+    assert_eq!(owned, ContactOwned::from(contact()));
+    //
+    let edited = Contact::try_from(ContactOwned {
+        name: "Zoë Smith".into(),
+        history: [1, 2, 3],
+        ..owned
+    })
+    .unwrap();
+    ledger.insert(&edited).unwrap();
+    let row = ledger.access_row(RowId(1)).unwrap().unwrap();
+    assert_eq!(row.name().unwrap(), "Zoë Smith");
+    assert_eq!(row.email().unwrap(), "zoe@example.com");
+    assert_eq!(row.history(), [1, 2, 3]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rejects_strings_over_max_len() {
+    let owned = ContactOwned::from(contact());
+    // exactly max_len fits
+    let row = Contact::try_from(ContactOwned {
+        name: "x".repeat(12),
+        ..owned.clone()
+    })
+    .unwrap();
+    assert_eq!(row.name().unwrap(), "x".repeat(12));
+    //
+    let e = Contact::try_from(ContactOwned {
+        name: "x".repeat(13),
+        ..owned.clone()
+    })
+    .unwrap_err();
+    assert!(matches!(
+        e,
+        DatastoreError::MaxLen {
+            field: "name",
+            len: 13,
+            max_len: 12
+        }
+    ));
+    assert_eq!(e.to_string(), "name is 13 bytes, max_len is 12");
+    //
+    // lengths are in bytes, eleven chars but fourteen bytes
+    let e = Contact::try_from(&ContactOwned {
+        name: "ëëë".to_string() + "abcdefgh",
+        ..owned.clone()
+    })
+    .unwrap_err();
+    assert_eq!(e.to_string(), "name is 14 bytes, max_len is 12");
+    //
+    let e = Contact::try_from(ContactOwned {
+        email: format!("{}@example.com", "a".repeat(24)),
+        ..owned
+    })
+    .unwrap_err();
+    assert!(matches!(
+        e,
+        DatastoreError::MaxLen {
+            field: "email",
+            len: 36,
+            max_len: 32
+        }
+    ));
+}
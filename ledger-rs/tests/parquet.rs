@@ -0,0 +1,151 @@
+#![cfg(feature = "parquet")]
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Decimal128Type, Float64Type, UInt64Type};
+use bytes::Bytes;
+use ledger_rs::arrow::arrow_schema;
+use ledger_rs::ledger::RowId;
+use ledger_rs::page::{PageSchema as _, SlottedPage};
+use ledger_rs::parquet::{ParquetCompression, ParquetOptions};
+use ledger_rs_macros::ledger;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::basic::Compression;
+use std::path::Path;
+
+mod common;
+use common::temp_dir;
+
+#[ledger(page_size = 16)]
+pub struct Position {
+    pub id: u64,
+    pub price: f64,
+    pub notional: i128,
+    pub account: u128,
+    #[max_len(8)]
+    pub symbol: String,
+}
+
+fn max_rows() -> u64 {
+    SlottedPage::<Position, { Position::PAGE_SZ }, { Position::ROWS_PER_PAGE }>::max_rows_per_page()
+        as u64
+}
+
+fn create(dir: &Path, num_rows: u64) -> PositionLedger {
+    let mut ledger = Position::create_ledger(dir, "positions", "parquet").unwrap();
+    for n in 0..num_rows {
+        ledger
+            .insert(&Position::new(
+                n,
+                n as f64 * 1.25,
+                i128::MIN + n as i128,
+                u128::MAX - n as u128,
+                &format!("SYM{n}"),
+            ))
+            .unwrap();
+    }
+    ledger
+}
+
+fn write(ledger: &PositionLedger, options: ParquetOptions) -> Bytes {
+    let mut parquet = Vec::new();
+    ledger.to_parquet(&mut parquet, options).unwrap();
+    Bytes::from(parquet)
+}
+
+#[test]
+fn a_row_group_per_pages_per_row_group() {
+    let dir = temp_dir("parquet-row-groups");
+    // four full pages and two rows on a fifth
+    let m = max_rows();
+    let mut ledger = create(&dir, m * 4 + 2);
+    ledger.delete(RowId(1)).unwrap();
+    let options = ParquetOptions {
+        compression: ParquetCompression::Zstd(3),
+        pages_per_row_group: 2,
+    };
+    let builder = ParquetRecordBatchReaderBuilder::try_new(write(&ledger, options)).unwrap();
+    //
+    let metadata = builder.metadata().clone();
+    let row_groups: Vec<i64> = metadata.row_groups().iter().map(|g| g.num_rows()).collect();
+    assert_eq!(row_groups, [(m * 2 - 1) as i64, (m * 2) as i64, 2]);
+    for group in metadata.row_groups() {
+        for column in group.columns() {
+            assert!(matches!(column.compression(), Compression::ZSTD(_)));
+        }
+    }
+    //
+    // the same schema as the arrow export
+    assert_eq!(
+        builder.schema().fields(),
+        arrow_schema::<Position>().fields()
+    );
+    let batches: Vec<_> = builder.build().unwrap().map(Result::unwrap).collect();
+    let row_ids: Vec<u64> = batches
+        .iter()
+        .flat_map(|b| b.column(0).as_primitive::<UInt64Type>().values().to_vec())
+        .collect();
+    assert_eq!(row_ids.len() as u64, m * 4 + 1);
+    assert_eq!(row_ids[..3], [0, 2, 3]);
+    assert_eq!(*row_ids.last().unwrap(), m * 4 + 1);
+    //
+    let batch = &batches[0];
+    assert_eq!(batch.column(2).as_primitive::<Float64Type>().value(1), 2.5);
+    assert_eq!(
+        batch.column(3).as_primitive::<Decimal128Type>().value(1),
+        i128::MIN + 2
+    );
+    assert_eq!(
+        batch.column(4).as_fixed_size_binary().value(1),
+        (u128::MAX - 2).to_le_bytes()
+    );
+    assert_eq!(batch.column(5).as_string::<i32>().value(1), "SYM2");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn every_codec_reads_back() {
+    let dir = temp_dir("parquet-codecs");
+    let ledger = create(&dir, max_rows() + 3);
+    for (compression, expected) in [
+        (ParquetCompression::None, Compression::UNCOMPRESSED),
+        (ParquetCompression::Lz4, Compression::LZ4_RAW),
+    ] {
+        let options = ParquetOptions {
+            compression,
+            ..ParquetOptions::default()
+        };
+        let builder = ParquetRecordBatchReaderBuilder::try_new(write(&ledger, options)).unwrap();
+        // a single row group under the default of 32 pages
+        assert_eq!(builder.metadata().num_row_groups(), 1);
+        assert_eq!(
+            builder.metadata().row_group(0).column(0).compression(),
+            expected
+        );
+        let rows: usize = builder
+            .build()
+            .unwrap()
+            .map(|b| b.unwrap().num_rows())
+            .sum();
+        assert_eq!(rows as u64, max_rows() + 3);
+    }
+    for compression in [ParquetCompression::Snappy, ParquetCompression::Gzip(6)] {
+        let options = ParquetOptions {
+            compression,
+            ..ParquetOptions::default()
+        };
+        let builder = ParquetRecordBatchReaderBuilder::try_new(write(&ledger, options)).unwrap();
+        assert_eq!(
+            builder.metadata().file_metadata().num_rows() as u64,
+            max_rows() + 3
+        );
+    }
+    // levels are checked before anything is written
+    let options = ParquetOptions {
+        compression: ParquetCompression::Gzip(11),
+        ..ParquetOptions::default()
+    };
+    let mut parquet = Vec::new();
+    assert!(ledger.to_parquet(&mut parquet, options).is_err());
+    assert!(parquet.is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
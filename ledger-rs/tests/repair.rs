@@ -0,0 +1,258 @@
+use ledger_rs::header::LedgerHeader;
+use ledger_rs::ledger::{PAGE_HEADER_SZ, RowId};
+use ledger_rs::page::{PageSchema as _, SlottedPage, page_sz};
+use ledger_rs::repair::{
+    JournalAction, RepairOptions, RepairReport, quarantine_path, read_quarantine,
+};
+use ledger_rs_macros::ledger;
+use std::path::{Path, PathBuf};
+
+mod common;
+use common::temp_dir;
+
+#[ledger(page_size = 16)]
+pub struct Item {
+    pub id: u64,
+    pub amount: i64,
+    #[fulltext]
+    #[max_len(16)]
+    pub name: String,
+}
+
+const LEDGER_HEADER_SZ: usize = size_of::<LedgerHeader>();
+
+fn max_rows() -> u64 {
+    SlottedPage::<Item, { Item::PAGE_SZ }, { Item::ROWS_PER_PAGE }>::max_rows_per_page() as u64
+}
+
+fn page_size() -> usize {
+    page_sz::<Item>() as usize
+}
+
+fn open(dir: &Path) -> ItemLedger {
+    Item::create_ledger(dir, "items", "repair").unwrap()
+}
+
+// three full pages and a few rows on the last one, returns the ledger file
+fn create(dir: &Path) -> PathBuf {
+    let mut ledger = open(dir);
+    for n in 0..max_rows() * 3 + 4 {
+        ledger
+            .insert(&Item::new(n, n as i64, &format!("item {n}")))
+            .unwrap();
+    }
+    ledger.path().to_path_buf()
+}
+
+fn header_sz(bytes: &[u8]) -> usize {
+    LedgerHeader::access(&bytes[0..LEDGER_HEADER_SZ])
+        .unwrap()
+        .size()
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn amounts(ledger: &ItemLedger) -> Vec<Option<i64>> {
+    (0..ledger.num_rows().unwrap())
+        .map(|n| ledger.access_row(RowId(n)).unwrap().map(|row| row.amount()))
+        .collect()
+}
+
+#[test]
+fn cuts_off_a_truncated_tail() {
+    let dir = temp_dir("repair-tail");
+    let path = create(&dir);
+    // half of the last page made it to disk
+    let bytes = std::fs::read(&path).unwrap();
+    let header_sz = header_sz(&bytes);
+    let torn_len = (header_sz + page_size() * 3 + page_size() / 2) as u64;
+    std::fs::OpenOptions::new()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_len(torn_len)
+        .unwrap();
+    //
+    let dry_run = RepairOptions {
+        dry_run: true,
+        ..RepairOptions::default()
+    };
+    let report = ItemLedger::repair(&path, dry_run).unwrap();
+    assert!(report.dry_run);
+    assert_eq!(report.file_len_before, torn_len);
+    assert_eq!(report.file_len, (header_sz + page_size() * 3) as u64);
+    assert_eq!((report.num_pages_before, report.num_pages), (4, 3));
+    assert_eq!((report.page_cursor_before, report.page_cursor), (3, 2));
+    assert!(report.quarantined_pages.is_empty());
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), torn_len);
+    //
+    let repaired = ItemLedger::repair(&path, RepairOptions::default()).unwrap();
+    assert_eq!(
+        repaired,
+        RepairReport {
+            dry_run: false,
+            ..report
+        }
+    );
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), repaired.file_len);
+    assert!(!quarantine_path(&path).exists());
+    //
+    // the rows of the cut page are gone from the indexes too, their row ids are taken again
+    let mut ledger = open(&dir);
+    let num_rows = max_rows() * 3;
+    assert_eq!(ledger.num_rows().unwrap(), num_rows);
+    assert_eq!(
+        amounts(&ledger),
+        (0..num_rows as i64).map(Some).collect::<Vec<_>>()
+    );
+    assert!(ledger.verify().unwrap().is_ok());
+    assert!(
+        ledger
+            .search_name(&(num_rows + 1).to_string())
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(
+        ledger.insert(&Item::new(0, 0, "again")).unwrap(),
+        RowId(num_rows)
+    );
+    drop(ledger);
+    assert!(
+        ItemLedger::repair(&path, RepairOptions::default())
+            .unwrap()
+            .is_clean()
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn quarantines_the_pages_it_changes() {
+    let dir = temp_dir("repair-quarantine");
+    let path = create(&dir);
+    let mut bytes = std::fs::read(&path).unwrap();
+    let header_sz = header_sz(&bytes);
+    let page = |bytes: &[u8], page_id: usize| {
+        let start = header_sz + page_id * page_size();
+        bytes[start..start + page_size()].to_vec()
+    };
+    let slot = |page_id: usize, slot: usize| {
+        header_sz + page_id * page_size() + PAGE_HEADER_SZ as usize + slot * 4
+    };
+    //
+    // page 1: a name that isn't utf8 in slot 2
+    let schema = Item::SCHEMA;
+    let name = schema
+        .fields
+        .iter()
+        .find(|f| f.name == "name")
+        .unwrap()
+        .offset;
+    let at = slot(1, 2);
+    let row = u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap()) as usize;
+    bytes[header_sz + page_size() + row + name] = 0xff;
+    // page 2: a slot pointing into the slot array, the header can't be rebuilt from that
+    let at = slot(2, 0);
+    bytes[at..at + 4].copy_from_slice(&(PAGE_HEADER_SZ + 4).to_le_bytes());
+    let originals = [page(&bytes, 1), page(&bytes, 2)];
+    std::fs::write(&path, &bytes).unwrap();
+    //
+    let report = ItemLedger::repair(&path, RepairOptions::default()).unwrap();
+    assert_eq!(report.dropped_rows, [RowId(max_rows() + 2)]);
+    assert_eq!(report.replaced_pages, [2]);
+    assert_eq!(report.quarantined_pages, [1, 2]);
+    assert_eq!(report.quarantine_path, Some(quarantine_path(&path)));
+    assert_eq!((report.num_pages, report.journal), (4, None));
+    // the original bytes of both pages
+    let quarantined = read_quarantine(&quarantine_path(&path)).unwrap();
+    assert_eq!(
+        quarantined,
+        [(1, originals[0].clone()), (2, originals[1].clone())]
+    );
+    //
+    // the row ids after the replaced page don't move
+    let ledger = open(&dir);
+    let amounts = amounts(&ledger);
+    let m = max_rows() as usize;
+    assert_eq!(amounts.len(), m * 3 + 4);
+    for (n, amount) in amounts.iter().enumerate() {
+        let dropped = n == m + 2 || (m * 2..m * 3).contains(&n);
+        assert_eq!(*amount, (!dropped).then_some(n as i64), "row {n}");
+    }
+    assert!(ledger.verify().unwrap().is_ok());
+    assert_eq!(ledger.search_name(&(m + 2).to_string()).unwrap(), []);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// the ledger file and its journal as a crash in the middle of a transaction would leave them,
+// and the amounts before it
+fn crash_image(dir: &Path) -> (PathBuf, Vec<Option<i64>>) {
+    let path = create(dir);
+    let mut ledger = open(dir);
+    let before = amounts(&ledger);
+    let crash_dir = dir.join("crash");
+    std::fs::create_dir_all(&crash_dir).unwrap();
+    let crash_path = crash_dir.join(path.file_name().unwrap());
+    //
+    let _: Result<(), _> = ledger.transaction(|tx| {
+        for n in 0..max_rows() * 2 {
+            tx.insert(&Item::new(n, -1, "staged")).unwrap();
+        }
+        tx.update(RowId(1), |row| row.set_amount(-1)).unwrap();
+        tx.delete(RowId(max_rows() + 1)).unwrap();
+        std::fs::copy(&path, &crash_path).unwrap();
+        std::fs::copy(
+            with_suffix(&path, ".journal"),
+            with_suffix(&crash_path, ".journal"),
+        )
+        .unwrap();
+        Err("crashed".into())
+    });
+    (crash_path, before)
+}
+
+#[test]
+fn rolls_back_a_pending_journal() {
+    let dir = temp_dir("repair-journal");
+    let (crash_path, before) = crash_image(&dir);
+    let journal = with_suffix(&crash_path, ".journal");
+    let crash_len = std::fs::metadata(&crash_path).unwrap().len();
+    //
+    let report = ItemLedger::repair(&crash_path, RepairOptions::default()).unwrap();
+    assert!(matches!(
+        report.journal,
+        Some(JournalAction::RolledBack { pages }) if pages > 0
+    ));
+    assert_eq!(report.file_len_before, crash_len);
+    assert!(report.file_len < crash_len);
+    assert!(report.dropped_rows.is_empty());
+    assert!(!journal.exists());
+    assert!(!quarantine_path(&crash_path).exists());
+    //
+    let ledger = open(crash_path.parent().unwrap());
+    assert_eq!(amounts(&ledger), before);
+    assert!(ledger.search_name("staged").unwrap().is_empty());
+    assert!(ledger.verify().unwrap().is_ok());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn discards_a_journal_cut_short() {
+    let dir = temp_dir("repair-torn-journal");
+    let (crash_path, before) = crash_image(&dir);
+    // the journal header never made it to disk, so neither did anything the transaction wrote
+    let path = dir.join(crash_path.file_name().unwrap());
+    let journal = with_suffix(&path, ".journal");
+    let bytes = std::fs::read(with_suffix(&crash_path, ".journal")).unwrap();
+    std::fs::write(&journal, &bytes[..20]).unwrap();
+    //
+    let report = ItemLedger::repair(&path, RepairOptions::default()).unwrap();
+    assert_eq!(report.journal, Some(JournalAction::Discarded));
+    assert_eq!(report.file_len, report.file_len_before);
+    assert!(!journal.exists());
+    assert_eq!(amounts(&open(&dir)), before);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
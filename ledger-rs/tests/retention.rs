@@ -0,0 +1,172 @@
+use ledger_rs::ledger::RowId;
+use ledger_rs::page::{PageSchema as _, SlottedPage};
+use ledger_rs::retention::RetentionPolicy;
+use ledger_rs::utils::DatastoreError;
+use ledger_rs_macros::ledger;
+use std::path::Path;
+use std::time::Duration;
+
+mod common;
+use common::temp_dir;
+
+#[ledger(page_size = 16, ttl_field = "created_at", ttl = "1h")]
+pub struct Event {
+    pub id: u64,
+    pub created_at: u64,
+}
+
+const HOUR: u64 = 3600;
+
+fn max_rows() -> u64 {
+    SlottedPage::<Event, { Event::PAGE_SZ }, { Event::ROWS_PER_PAGE }>::max_rows_per_page() as u64
+}
+
+// row n is stamped n seconds after the epoch
+fn create(dir: &Path, num_rows: u64) -> EventLedger {
+    let mut ledger = Event::create_ledger(dir, "events", "retention").unwrap();
+    for n in 0..num_rows {
+        ledger.insert(&Event::new(n, n)).unwrap();
+    }
+    ledger
+}
+
+fn live_rows(ledger: &EventLedger) -> Vec<u64> {
+    let mut ids = Vec::new();
+    ledger.for_each_row(|_, row| ids.push(row.id())).unwrap();
+    ids
+}
+
+fn retired_pages(ledger: &EventLedger) -> u64 {
+    ledger.access_header().unwrap().retired_pages()
+}
+
+#[test]
+fn expires_rows_and_retires_leading_pages() {
+    let dir = temp_dir("retention-expiry");
+    let m = max_rows();
+    // three full pages and two rows on the last one
+    let num_rows = m * 3 + 2;
+    let mut ledger = create(&dir, num_rows);
+    assert_eq!(
+        ledger.retention_policy().unwrap().max_age(),
+        Duration::from_secs(HOUR)
+    );
+    //
+    // the first page and half of the second are older than an hour
+    let expired = m + m / 2;
+    let stats = ledger.run_retention_at(HOUR + expired).unwrap();
+    assert_eq!(stats.expired_rows, expired);
+    assert_eq!(stats.reclaimed_pages, 1);
+    assert!(stats.reclaimed_bytes > 0);
+    assert_eq!(stats.dropped_segments, 0);
+    assert_eq!(retired_pages(&ledger), 1);
+    assert_eq!(live_rows(&ledger), (expired..num_rows).collect::<Vec<_>>());
+    // the row ids don't move, the expired ones read as deleted
+    assert_eq!(ledger.num_rows().unwrap(), num_rows);
+    assert!(ledger.access_row(RowId(0)).unwrap().is_none());
+    assert!(ledger.access_row(RowId(m - 1)).unwrap().is_none());
+    assert_eq!(
+        ledger.access_row(RowId(expired)).unwrap().unwrap().id(),
+        expired
+    );
+    //
+    // nothing more to do at the same time
+    let stats = ledger.run_retention_at(HOUR + expired).unwrap();
+    assert_eq!((stats.expired_rows, stats.reclaimed_pages), (0, 0));
+    assert_eq!(retired_pages(&ledger), 1);
+    //
+    // everything expires, the last page isn't full and stays for inserts
+    let stats = ledger.run_retention_at(HOUR + num_rows).unwrap();
+    assert_eq!(stats.expired_rows, num_rows - expired);
+    assert_eq!(stats.reclaimed_pages, 2);
+    assert_eq!(retired_pages(&ledger), 3);
+    assert!(live_rows(&ledger).is_empty());
+    drop(ledger);
+    //
+    let mut ledger = Event::create_ledger(&dir, "events", "retention").unwrap();
+    assert_eq!(retired_pages(&ledger), 3);
+    assert!(ledger.access_row(RowId(m)).unwrap().is_none());
+    let now = HOUR + num_rows;
+    assert_eq!(
+        ledger.insert(&Event::new(num_rows, now)).unwrap(),
+        RowId(num_rows)
+    );
+    assert_eq!(live_rows(&ledger), [num_rows]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reclaims_a_dead_page_behind_live_ones() {
+    let dir = temp_dir("retention-middle");
+    let m = max_rows();
+    let mut ledger = create(&dir, m * 3);
+    //
+    // a runtime policy on the id instead, only the second page is old
+    let policy: RetentionPolicy<Event> = RetentionPolicy::new(Duration::from_secs(HOUR), |row| {
+        if (max_rows()..max_rows() * 2).contains(&row.id()) {
+            0
+        } else {
+            u64::MAX
+        }
+    });
+    ledger.set_retention_policy(Some(policy));
+    let stats = ledger.run_retention_at(HOUR + 1).unwrap();
+    assert_eq!(stats.expired_rows, m);
+    assert_eq!(stats.reclaimed_pages, 1);
+    // the first page is still live, so nothing is retired
+    assert_eq!(retired_pages(&ledger), 0);
+    assert_eq!(
+        live_rows(&ledger),
+        (0..m).chain(m * 2..m * 3).collect::<Vec<_>>()
+    );
+    //
+    ledger.set_retention_policy(None);
+    assert!(matches!(
+        ledger.run_retention_at(HOUR + 1),
+        Err(DatastoreError::Error(_))
+    ));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn drops_expired_segments() {
+    let dir = temp_dir("retention-segments");
+    let mut ledger = Event::create_segmented_ledger(&dir, "events", "retention", 2).unwrap();
+    let rows_per_segment = ledger.rows_per_segment();
+    let num_rows = rows_per_segment * 2 + 1;
+    for n in 0..num_rows {
+        ledger.insert(&Event::new(n, n)).unwrap();
+    }
+    //
+    // the first segment and a page of the second
+    let stats = ledger
+        .run_retention_at(HOUR + rows_per_segment + max_rows())
+        .unwrap();
+    assert_eq!(stats.expired_rows, rows_per_segment + max_rows());
+    assert_eq!(stats.reclaimed_pages, 3);
+    assert_eq!(stats.dropped_segments, 1);
+    assert_eq!(
+        ledger
+            .segments()
+            .unwrap()
+            .iter()
+            .map(|s| s.index)
+            .collect::<Vec<_>>(),
+        [1, 2]
+    );
+    //
+    // the active segment stays even once it's empty
+    let stats = ledger.run_retention_at(HOUR + num_rows).unwrap();
+    assert_eq!(stats.dropped_segments, 1);
+    assert_eq!(
+        ledger
+            .segments()
+            .unwrap()
+            .iter()
+            .map(|s| s.index)
+            .collect::<Vec<_>>(),
+        [2]
+    );
+    assert_eq!(ledger.num_rows().unwrap(), num_rows);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,192 @@
+use ledger_rs::header::{LedgerDescription, LedgerName};
+use ledger_rs::ledger::RowId;
+use ledger_rs::page::PageSchema as _;
+use ledger_rs::utils::DatastoreError;
+use ledger_rs_macros::ledger;
+use std::path::Path;
+
+mod common;
+use common::temp_dir;
+
+#[ledger(page_size = 16)]
+pub struct Trade {
+    pub id: u64,
+    pub price: i64,
+}
+
+const PAGES_PER_SEGMENT: u32 = 2;
+
+fn create(dir: &Path) -> TradeSegmentedLedger {
+    Trade::create_segmented_ledger(dir, "trades", "segments", PAGES_PER_SEGMENT).unwrap()
+}
+
+// two and a half segments
+fn fill(ledger: &mut TradeSegmentedLedger) -> u64 {
+    let num_rows = ledger.rows_per_segment() * 5 / 2;
+    for n in 0..num_rows {
+        assert_eq!(ledger.insert(&Trade::new(n, n as i64)).unwrap(), RowId(n));
+    }
+    num_rows
+}
+
+// files are named after the base64 ledger name, as for a plain ledger
+fn file(dir: &Path, suffix: &str) -> std::path::PathBuf {
+    dir.join(format!("{}.{suffix}", LedgerName::from("trades")))
+}
+
+fn price(ledger: &TradeSegmentedLedger, row_id: u64) -> Option<i64> {
+    ledger
+        .access_row(RowId(row_id))
+        .unwrap()
+        .map(|row| row.price())
+}
+
+#[test]
+fn rolls_over_at_pages_per_segment() {
+    let dir = temp_dir("segments-rollover");
+    let mut ledger = create(&dir);
+    let rows_per_segment = ledger.rows_per_segment();
+    assert_eq!(ledger.segments().unwrap().len(), 1);
+    let num_rows = fill(&mut ledger);
+    //
+    let segments = ledger.segments().unwrap();
+    assert_eq!(
+        segments.iter().map(|s| s.index).collect::<Vec<_>>(),
+        [0, 1, 2]
+    );
+    for segment in &segments {
+        assert_eq!(
+            segment.first_row_id,
+            RowId(segment.index as u64 * rows_per_segment)
+        );
+        assert_eq!(segment.path, file(&dir, &format!("{:04}", segment.index)));
+        assert!(segment.path.exists());
+        let num_pages = ledger
+            .segment(segment.index)
+            .unwrap()
+            .access_header()
+            .unwrap()
+            .num_pages();
+        assert!(num_pages <= PAGES_PER_SEGMENT as u64 + 1);
+    }
+    assert_eq!(segments[0].num_rows, rows_per_segment);
+    assert_eq!(segments[1].num_rows, rows_per_segment);
+    assert_eq!(segments[2].num_rows, rows_per_segment / 2);
+    assert_eq!(ledger.num_rows().unwrap(), num_rows);
+    //
+    for n in 0..num_rows {
+        assert_eq!(price(&ledger, n), Some(n as i64));
+    }
+    let (segment, local) = ledger.split_row_id(RowId(rows_per_segment + 3)).unwrap();
+    assert_eq!((segment, local), (1, RowId(3)));
+    assert_eq!(
+        ledger.to_row_id(segment, local).unwrap(),
+        RowId(rows_per_segment + 3)
+    );
+    assert!(ledger.access_row(RowId(rows_per_segment * 3)).is_err());
+    //
+    ledger
+        .update(RowId(rows_per_segment), |row| row.set_price(-1))
+        .unwrap();
+    ledger.delete(RowId(1)).unwrap();
+    assert_eq!(price(&ledger, rows_per_segment), Some(-1));
+    assert_eq!(price(&ledger, 1), None);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn drops_and_archives_old_segments() {
+    let dir = temp_dir("segments-drop");
+    let mut ledger = create(&dir);
+    let rows_per_segment = ledger.rows_per_segment();
+    let num_rows = fill(&mut ledger);
+    //
+    assert!(matches!(
+        ledger.drop_segment(2),
+        Err(DatastoreError::Error(_))
+    ));
+    assert!(matches!(
+        ledger.drop_segment(7),
+        Err(DatastoreError::Error(_))
+    ));
+    //
+    ledger.drop_segment(0).unwrap();
+    assert!(!file(&dir, "0000").exists());
+    assert!(!file(&dir, "0000.merkle").exists());
+    assert_eq!(price(&ledger, 0), None);
+    assert!(ledger.update(RowId(0), |row| row.set_price(1)).is_err());
+    //
+    let archive = dir.join("archive");
+    let archived = ledger.archive_segment(1, &archive).unwrap();
+    assert_eq!(archived, file(&archive, "0001"));
+    assert!(!file(&dir, "0001").exists());
+    assert_eq!(price(&ledger, rows_per_segment), None);
+    // row ids don't shift, the remaining rows and new ones keep theirs
+    assert_eq!(ledger.num_rows().unwrap(), num_rows);
+    assert_eq!(price(&ledger, num_rows - 1), Some(num_rows as i64 - 1));
+    assert_eq!(ledger.insert(&Trade::new(0, 0)).unwrap(), RowId(num_rows));
+    assert_eq!(
+        ledger
+            .segments()
+            .unwrap()
+            .iter()
+            .map(|s| s.index)
+            .collect::<Vec<_>>(),
+        [2]
+    );
+    //
+    // an archived segment is a plain ledger
+    let segment = TradeLedger::open_file(
+        &archived,
+        LedgerName::from("trades"),
+        LedgerDescription::new("segments"),
+    )
+    .unwrap();
+    assert_eq!(segment.num_rows().unwrap(), rows_per_segment);
+    assert_eq!(
+        segment.access_row(RowId(3)).unwrap().unwrap().price(),
+        (rows_per_segment + 3) as i64
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reopens_from_the_manifest() {
+    let dir = temp_dir("segments-reopen");
+    let mut ledger = create(&dir);
+    let rows_per_segment = ledger.rows_per_segment();
+    let num_rows = fill(&mut ledger);
+    ledger.drop_segment(0).unwrap();
+    drop(ledger);
+    assert!(file(&dir, "segments").exists());
+    //
+    assert!(matches!(
+        Trade::create_segmented_ledger(&dir, "trades", "segments", PAGES_PER_SEGMENT + 1),
+        Err(DatastoreError::Error(_))
+    ));
+    assert!(Trade::create_segmented_ledger(&dir, "trades", "segments", 0).is_err());
+    //
+    let mut ledger = create(&dir);
+    assert_eq!(ledger.pages_per_segment(), PAGES_PER_SEGMENT);
+    assert_eq!(
+        ledger
+            .segments()
+            .unwrap()
+            .iter()
+            .map(|s| s.index)
+            .collect::<Vec<_>>(),
+        [1, 2]
+    );
+    assert_eq!(ledger.num_rows().unwrap(), num_rows);
+    assert_eq!(price(&ledger, 0), None);
+    assert_eq!(
+        price(&ledger, rows_per_segment),
+        Some(rows_per_segment as i64)
+    );
+    // inserts go on in the newest segment and roll over from there
+    for n in num_rows..rows_per_segment * 3 + 1 {
+        assert_eq!(ledger.insert(&Trade::new(n, n as i64)).unwrap(), RowId(n));
+    }
+    assert_eq!(ledger.segments().unwrap().last().unwrap().index, 3);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,88 @@
+use ledger_rs::ledger::RowId;
+use ledger_rs::page::PageSchema as _;
+use ledger_rs_macros::ledger;
+use serde_json::json;
+
+mod common;
+use common::temp_dir;
+
+#[ledger(page_size = 16)]
+pub struct Invoice {
+    pub id: u32,
+    pub amount: i64,
+    pub paid: bool,
+    pub rate: f32,
+    #[max_len(24)]
+    pub customer: String,
+    // past serde's 32 element arrays
+    pub digest: [u8; 40],
+    pub lines: [u16; 3],
+}
+
+fn invoice() -> Invoice {
+    Invoice::new(7, -1250, true, 0.25, "ACME Corp.", [9; 40], [1, 2, 300])
+}
+
+#[test]
+fn serializes_the_logical_fields() {
+    let json = serde_json::to_value(invoice()).unwrap();
+    // no `customer_len`, no padding, the string cut to its length
+    assert_eq!(
+        json,
+        json!({
+            "id": 7,
+            "amount": -1250,
+            "paid": true,
+            "rate": 0.25,
+            "customer": "ACME Corp.",
+            "digest": vec![9; 40],
+            "lines": [1, 2, 300],
+        })
+    );
+    // in declaration order
+    let text = serde_json::to_string(&invoice()).unwrap();
+    assert!(text.starts_with(r#"{"id":7,"amount":-1250,"paid":true,"rate":0.25,"customer":"#));
+}
+
+#[test]
+fn deserializes_what_it_serialized() {
+    let text = serde_json::to_string(&invoice()).unwrap();
+    let row: Invoice = serde_json::from_str(&text).unwrap();
+    assert_eq!(row, invoice());
+    assert_eq!(row.customer().unwrap(), "ACME Corp.");
+    assert_eq!(row.lines(), [1, 2, 300]);
+    //
+    // a row read from a ledger serializes the same
+    let dir = temp_dir("serde-ledger");
+    let mut ledger = Invoice::create_ledger(&dir, "invoices", "serde").unwrap();
+    ledger.insert(&row).unwrap();
+    let stored = ledger.access_row(RowId(0)).unwrap().unwrap();
+    assert_eq!(serde_json::to_string(&*stored).unwrap(), text);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rejects_what_doesnt_fit() {
+    let mut json = serde_json::to_value(invoice()).unwrap();
+    json["customer"] = json!("a customer name past max_len");
+    let e = serde_json::from_value::<Invoice>(json).unwrap_err();
+    assert_eq!(e.to_string(), "customer is 28 bytes, max_len is 24");
+    // exactly max_len is fine
+    let mut json = serde_json::to_value(invoice()).unwrap();
+    json["customer"] = json!("x".repeat(24));
+    let row = serde_json::from_value::<Invoice>(json).unwrap();
+    assert_eq!(row.customer().unwrap(), "x".repeat(24));
+    //
+    let mut json = serde_json::to_value(invoice()).unwrap();
+    json["digest"] = json!([1, 2, 3]);
+    let e = serde_json::from_value::<Invoice>(json).unwrap_err();
+    assert_eq!(e.to_string(), "digest has 3 elements, expected 40");
+    //
+    let mut json = serde_json::to_value(invoice()).unwrap();
+    json.as_object_mut().unwrap().remove("amount");
+    let e = serde_json::from_value::<Invoice>(json).unwrap_err();
+    assert!(e.to_string().contains("missing field `amount`"), "{e}");
+    let mut json = serde_json::to_value(invoice()).unwrap();
+    json["id"] = json!(-1);
+    assert!(serde_json::from_value::<Invoice>(json).is_err());
+}
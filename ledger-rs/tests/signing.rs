@@ -0,0 +1,253 @@
+use ed25519_dalek::Signer;
+use ledger_rs::ledger::RowId;
+use ledger_rs::page::{PageSchema as _, SlottedPage};
+use ledger_rs::signing::{BadCheckpoint, BadCheckpointReason, SigningKey, load};
+use ledger_rs_macros::ledger;
+use std::path::{Path, PathBuf};
+
+mod common;
+use common::temp_dir;
+
+#[ledger(page_size = 16)]
+pub struct Transfer {
+    pub id: u64,
+    pub amount: i64,
+}
+
+// [message version u8][num_rows u64][root][timestamp u64][signature]
+const RECORD_SZ: usize = 1 + 8 + 32 + 8 + 64;
+
+fn max_rows() -> u64 {
+    SlottedPage::<Transfer, { Transfer::PAGE_SZ }, { Transfer::ROWS_PER_PAGE }>::max_rows_per_page()
+        as u64
+}
+
+fn key(seed: u8) -> SigningKey {
+    SigningKey::from_bytes(&[seed; 32])
+}
+
+fn create(dir: &Path, num_rows: u64) -> TransferLedger {
+    let mut ledger = Transfer::create_ledger(dir, "transfers", "signing").unwrap();
+    insert(&mut ledger, num_rows);
+    ledger
+}
+
+fn insert(ledger: &mut TransferLedger, num_rows: u64) {
+    for _ in 0..num_rows {
+        let n = ledger.num_rows().unwrap();
+        ledger.insert(&Transfer::new(n, n as i64 * 100)).unwrap();
+    }
+}
+
+fn sigs_path(ledger: &TransferLedger) -> PathBuf {
+    let mut path = ledger.path().as_os_str().to_os_string();
+    path.push(".sigs");
+    PathBuf::from(path)
+}
+
+#[test]
+fn signs_and_verifies() {
+    let dir = temp_dir("signing-verify");
+    let mut ledger = create(&dir, 0);
+    assert!(ledger.sign_checkpoint().is_err());
+    //
+    // a checkpoint every two filled pages
+    ledger.enable_signing(key(1), 2);
+    insert(&mut ledger, max_rows() * 4 + 1);
+    let checkpoints = ledger.signed_checkpoints().unwrap();
+    assert_eq!(
+        checkpoints.iter().map(|c| c.num_rows).collect::<Vec<_>>(),
+        [max_rows() * 2, max_rows() * 4]
+    );
+    let latest = ledger.sign_checkpoint().unwrap();
+    assert_eq!(latest.num_rows, max_rows() * 4 + 1);
+    assert_eq!(latest.root, ledger.root_hash().unwrap());
+    assert_eq!(latest.version, 2);
+    assert_eq!(
+        ledger.latest_signed_checkpoint().unwrap(),
+        Some(latest.clone())
+    );
+    assert_eq!(
+        ledger.verify_checkpoints(&key(1).verifying_key()).unwrap(),
+        None
+    );
+    //
+    // later rows don't break earlier checkpoints
+    insert(&mut ledger, 3);
+    assert_eq!(
+        ledger.verify_checkpoints(&key(1).verifying_key()).unwrap(),
+        None
+    );
+    //
+    // the file alone still carries the latest
+    std::fs::remove_file(sigs_path(&ledger)).unwrap();
+    assert_eq!(ledger.signed_checkpoints().unwrap(), [latest]);
+    assert_eq!(
+        ledger.verify_checkpoints(&key(1).verifying_key()).unwrap(),
+        None
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rejects_the_wrong_key() {
+    let dir = temp_dir("signing-wrong-key");
+    let mut ledger = create(&dir, 10);
+    ledger.enable_signing(key(1), 0);
+    ledger.sign_checkpoint().unwrap();
+    //
+    let bad = ledger
+        .verify_checkpoints(&key(2).verifying_key())
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        bad,
+        BadCheckpoint {
+            index: 0,
+            num_rows: 10,
+            reason: BadCheckpointReason::Signature,
+        }
+    );
+    assert_eq!(
+        bad.to_string(),
+        "signed checkpoint #0 (10 rows) has an invalid signature"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rejects_tampering() {
+    let dir = temp_dir("signing-tampered");
+    let mut ledger = create(&dir, 10);
+    ledger.enable_signing(key(1), 0);
+    ledger.sign_checkpoint().unwrap();
+    insert(&mut ledger, 10);
+    ledger.sign_checkpoint().unwrap();
+    let public = key(1).verifying_key();
+    //
+    // a signed row changed after the fact
+    let amount = ledger
+        .update(RowId(15), |row| {
+            let amount = row.amount();
+            row.set_amount(-1);
+            amount
+        })
+        .unwrap();
+    let bad = ledger.verify_checkpoints(&public).unwrap().unwrap();
+    assert_eq!(
+        (bad.index, bad.reason),
+        (1, BadCheckpointReason::RootMismatch)
+    );
+    ledger
+        .update(RowId(15), |row| row.set_amount(amount))
+        .unwrap();
+    assert_eq!(ledger.verify_checkpoints(&public).unwrap(), None);
+    //
+    // a root rewritten in the record no longer matches its signature
+    let path = sigs_path(&ledger);
+    let mut sigs = std::fs::read(&path).unwrap();
+    sigs[5 + 9] ^= 1;
+    std::fs::write(&path, &sigs).unwrap();
+    let bad = ledger.verify_checkpoints(&public).unwrap().unwrap();
+    assert_eq!((bad.index, bad.reason), (0, BadCheckpointReason::Signature));
+    //
+    // a record signed for more rows than the ledger holds
+    std::fs::create_dir_all(dir.join("other")).unwrap();
+    let other = create(&dir.join("other"), 5);
+    sigs[5 + 9] ^= 1;
+    std::fs::write(sigs_path(&other), &sigs).unwrap();
+    let bad = other.verify_checkpoints(&public).unwrap().unwrap();
+    assert_eq!(
+        bad.to_string(),
+        "signed checkpoint #0 (10 rows) covers rows the ledger doesn't have"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn ignores_a_torn_trailing_record() {
+    let dir = temp_dir("signing-torn");
+    let mut ledger = create(&dir, 10);
+    ledger.enable_signing(key(1), 0);
+    let first = ledger.sign_checkpoint().unwrap();
+    //
+    // a crash half way through the next append
+    let path = sigs_path(&ledger);
+    let mut sigs = std::fs::read(&path).unwrap();
+    assert_eq!(sigs.len(), 5 + RECORD_SZ);
+    sigs.extend_from_slice(&[0xab; RECORD_SZ / 2]);
+    std::fs::write(&path, &sigs).unwrap();
+    assert_eq!(load(ledger.path()).unwrap(), std::slice::from_ref(&first));
+    assert_eq!(
+        ledger.verify_checkpoints(&key(1).verifying_key()).unwrap(),
+        None
+    );
+    //
+    // the next append cuts it off
+    insert(&mut ledger, 1);
+    let second = ledger.sign_checkpoint().unwrap();
+    assert_eq!(
+        std::fs::metadata(&path).unwrap().len() as usize,
+        5 + 2 * RECORD_SZ
+    );
+    assert_eq!(load(ledger.path()).unwrap(), [first, second]);
+    assert_eq!(
+        ledger.verify_checkpoints(&key(1).verifying_key()).unwrap(),
+        None
+    );
+    //
+    std::fs::write(&path, b"LSI").unwrap();
+    assert!(load(ledger.path()).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reads_and_upgrades_a_legacy_file() {
+    let dir = temp_dir("signing-legacy");
+    let mut ledger = create(&dir, 10);
+    let name = ledger.access_header().unwrap().name();
+    let root = ledger.root_hash().unwrap();
+    //
+    // a version 1 file, a u32 row count and the v1 signing context
+    let mut message = b"ledger-rs signed checkpoint v1".to_vec();
+    message.extend_from_slice(name.as_bytes());
+    message.extend_from_slice(&10_u32.to_le_bytes());
+    message.extend_from_slice(&root);
+    message.extend_from_slice(&1_700_000_000_u64.to_le_bytes());
+    let signature = key(1).sign(&message).to_bytes();
+    let mut sigs = b"LSIG\x01".to_vec();
+    sigs.extend_from_slice(&10_u32.to_le_bytes());
+    sigs.extend_from_slice(&root);
+    sigs.extend_from_slice(&1_700_000_000_u64.to_le_bytes());
+    sigs.extend_from_slice(&signature);
+    // and a torn record
+    sigs.extend_from_slice(&[0; 20]);
+    let path = sigs_path(&ledger);
+    std::fs::write(&path, &sigs).unwrap();
+    //
+    let legacy = load(ledger.path()).unwrap();
+    assert_eq!(legacy.len(), 1);
+    assert_eq!(
+        (legacy[0].version, legacy[0].num_rows, legacy[0].root),
+        (1, 10, root)
+    );
+    assert_eq!(legacy[0].signature, signature);
+    assert_eq!(
+        ledger.verify_checkpoints(&key(1).verifying_key()).unwrap(),
+        None
+    );
+    //
+    // the next append rewrites the file, the old record keeps its message version
+    ledger.enable_signing(key(1), 0);
+    insert(&mut ledger, 2);
+    let latest = ledger.sign_checkpoint().unwrap();
+    let sigs = std::fs::read(&path).unwrap();
+    assert_eq!(&sigs[..5], b"LSIG\x02");
+    assert_eq!(sigs.len(), 5 + 2 * RECORD_SZ);
+    assert_eq!(load(ledger.path()).unwrap(), [legacy[0].clone(), latest]);
+    assert_eq!(
+        ledger.verify_checkpoints(&key(1).verifying_key()).unwrap(),
+        None
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
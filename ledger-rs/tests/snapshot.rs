@@ -0,0 +1,135 @@
+use ledger_rs::ledger::RowId;
+use ledger_rs::page::PageSchema as _;
+use ledger_rs::retention::RetentionPolicy;
+use ledger_rs::snapshot::Snapshot;
+use ledger_rs_macros::ledger;
+use std::path::Path;
+use std::time::Duration;
+
+mod common;
+use common::temp_dir;
+
+#[ledger(page_size = 16)]
+pub struct Event {
+    pub created_at: u64,
+    pub value: i64,
+    #[max_len(16)]
+    pub kind: String,
+}
+
+const NUM_ROWS: u64 = 100;
+
+fn create(dir: &Path) -> EventLedger {
+    let mut ledger = Event::create_ledger(dir, "events", "snapshots").unwrap();
+    for n in 0..NUM_ROWS {
+        ledger
+            .insert(&Event::new(1_000 + n, n as i64, &format!("kind {}", n % 4)))
+            .unwrap();
+    }
+    ledger
+}
+
+fn values<const PAGESZ: usize, const ROWS_PER_PAGE: usize>(
+    snapshot: &Snapshot<Event, PAGESZ, ROWS_PER_PAGE>,
+) -> Vec<Option<i64>> {
+    (0..snapshot.num_rows())
+        .map(|n| {
+            snapshot
+                .access_row(RowId(n))
+                .unwrap()
+                .map(|row| row.value())
+        })
+        .collect()
+}
+
+fn original() -> Vec<Option<i64>> {
+    (0..NUM_ROWS as i64).map(Some).collect()
+}
+
+#[test]
+fn isolated_from_row_writes() {
+    let dir = temp_dir("snapshot-writes");
+    let mut ledger = create(&dir);
+    let snapshot = ledger.snapshot().unwrap();
+    //
+    for n in (0..NUM_ROWS).step_by(7) {
+        ledger.update(RowId(n), |row| row.set_value(-1)).unwrap();
+    }
+    ledger.delete(RowId(3)).unwrap();
+    for n in 0..40 {
+        ledger.insert(&Event::new(5_000 + n, -1, "later")).unwrap();
+    }
+    unsafe { ledger.access_row_unchecked_mut(RowId(10)) }.set_value(-2);
+    //
+    assert_eq!(snapshot.num_rows(), NUM_ROWS);
+    assert_eq!(values(&snapshot), original());
+    assert!(snapshot.access_row(RowId(NUM_ROWS)).is_err());
+    let mut visited = 0;
+    snapshot
+        .for_each_row(|row_id, row| {
+            assert_eq!(row.value(), row_id.get() as i64);
+            visited += 1;
+        })
+        .unwrap();
+    assert_eq!(visited, NUM_ROWS);
+    //
+    // a new snapshot sees the writes
+    let later = ledger.snapshot().unwrap();
+    assert_eq!(later.num_rows(), NUM_ROWS + 40);
+    assert_eq!(later.access_row(RowId(3)).unwrap().map(|r| r.value()), None);
+    assert_eq!(later.access_row(RowId(10)).unwrap().unwrap().value(), -2);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn isolated_from_compression_and_retention() {
+    let dir = temp_dir("snapshot-cold");
+    let mut ledger = create(&dir);
+    let snapshot = ledger.snapshot().unwrap();
+    //
+    assert!(ledger.compress_cold_pages().unwrap().pages > 0);
+    assert_eq!(values(&snapshot), original());
+    // restores a compressed page into the file before changing it
+    ledger.update(RowId(1), |row| row.set_value(-1)).unwrap();
+    assert_eq!(values(&snapshot), original());
+    //
+    ledger.set_retention_policy(Some(RetentionPolicy::new(
+        Duration::from_secs(10),
+        |row: &Event| row.created_at(),
+    )));
+    let stats = ledger.run_retention_at(1_000 + NUM_ROWS + 10).unwrap();
+    assert_eq!(stats.expired_rows, NUM_ROWS);
+    assert!(stats.reclaimed_pages > 0);
+    assert_eq!(ledger.access_row(RowId(0)).unwrap().map(|_| ()), None);
+    assert_eq!(values(&snapshot), original());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reads_on_another_thread_while_writes_continue() {
+    let dir = temp_dir("snapshot-thread");
+    let mut ledger = create(&dir);
+    let snapshot = ledger.snapshot().unwrap();
+    //
+    let reader = std::thread::spawn(move || {
+        for _ in 0..50 {
+            assert_eq!(values(&snapshot), original());
+        }
+        snapshot
+    });
+    for round in 0..20 {
+        for n in (0..NUM_ROWS).step_by(5) {
+            ledger
+                .update(RowId(n), |row| row.set_value(-round))
+                .unwrap();
+        }
+        ledger.insert(&Event::new(9_000, -1, "later")).unwrap();
+    }
+    let snapshot = reader.join().unwrap();
+    assert_eq!(values(&snapshot), original());
+    //
+    // dropped snapshots stop collecting pre-images
+    drop(snapshot);
+    ledger.update(RowId(0), |row| row.set_value(7)).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
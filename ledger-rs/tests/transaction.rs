@@ -0,0 +1,241 @@
+use ledger_rs::ledger::RowId;
+use ledger_rs::page::PageSchema as _;
+use ledger_rs::utils::DatastoreError;
+use ledger_rs_macros::ledger;
+use std::path::{Path, PathBuf};
+
+mod common;
+use common::temp_dir;
+
+#[ledger(page_size = 16)]
+pub struct Transfer {
+    pub id: u64,
+    #[bitmap_index]
+    pub status: u8,
+    pub amount: i64,
+    #[fulltext]
+    #[max_len(24)]
+    pub memo: String,
+}
+
+const NUM_ROWS: u64 = 40;
+
+fn create(dir: &Path) -> TransferLedger {
+    let mut ledger = Transfer::create_ledger(dir, "transfers", "transactions").unwrap();
+    for n in 0..NUM_ROWS {
+        ledger
+            .insert(&Transfer::new(n, 0, n as i64, &format!("rent {n}")))
+            .unwrap();
+    }
+    ledger
+}
+
+fn journal_path(ledger_path: &Path) -> PathBuf {
+    let mut name = ledger_path.as_os_str().to_os_string();
+    name.push(".journal");
+    PathBuf::from(name)
+}
+
+// everything a reader can observe, compared before and after a rollback
+#[derive(Debug, PartialEq)]
+struct State {
+    rows: Vec<Option<(u8, i64, String)>>,
+    file_len: u64,
+    pending: Vec<u64>,
+    rent: Vec<RowId>,
+    bonus: Vec<RowId>,
+}
+
+fn state(ledger: &TransferLedger) -> State {
+    State {
+        rows: (0..ledger.num_rows().unwrap())
+            .map(|n| {
+                ledger
+                    .access_row(RowId(n))
+                    .unwrap()
+                    .map(|row| (row.status, row.amount(), row.memo().unwrap().to_string()))
+            })
+            .collect(),
+        file_len: std::fs::metadata(ledger.path()).unwrap().len(),
+        pending: ledger.rows_where_status_eq(1).unwrap().iter().collect(),
+        rent: ledger.search_memo("rent").unwrap(),
+        bonus: ledger.search_memo("bonus").unwrap(),
+    }
+}
+
+// enough inserts to allocate new pages, then updates and deletes. row 20's page is the last
+// one whose original image reaches the journal
+fn stage(
+    tx: &mut ledger_rs::transaction::Transaction<
+        '_,
+        Transfer,
+        { Transfer::PAGE_SZ },
+        { Transfer::ROWS_PER_PAGE },
+    >,
+) {
+    for n in 0..40 {
+        tx.insert(&Transfer::new(1_000 + n, 1, n as i64, "bonus"))
+            .unwrap();
+    }
+    tx.update(RowId(0), |row| {
+        row.status = 1;
+        row.set_amount(-1);
+    })
+    .unwrap();
+    tx.delete(RowId(5)).unwrap();
+    tx.update(RowId(20), |row| row.set_amount(-20)).unwrap();
+    assert_eq!(tx.access_row(RowId(0)).unwrap().unwrap().amount(), -1);
+}
+
+#[test]
+fn commit_is_durable() {
+    let dir = temp_dir("tx-commit");
+    let mut ledger = create(&dir);
+    let path = ledger.path().to_path_buf();
+    ledger
+        .transaction(|tx| {
+            stage(tx);
+            Ok(())
+        })
+        .unwrap();
+    assert!(!journal_path(&path).exists());
+    let committed = state(&ledger);
+    assert_eq!(committed.rows.len(), NUM_ROWS as usize + 40);
+    assert_eq!(committed.pending.len(), 41);
+    assert_eq!(committed.bonus.len(), 40);
+    let root = ledger.root_hash().unwrap();
+    drop(ledger);
+    //
+    let ledger = Transfer::create_ledger(&dir, "transfers", "transactions").unwrap();
+    assert_eq!(state(&ledger), committed);
+    assert_eq!(ledger.root_hash().unwrap(), root);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rollback_when_the_closure_fails() {
+    let dir = temp_dir("tx-err");
+    let mut ledger = create(&dir);
+    let path = ledger.path().to_path_buf();
+    let before = state(&ledger);
+    let root = ledger.root_hash().unwrap();
+    //
+    let result: Result<(), _> = ledger.transaction(|tx| {
+        stage(tx);
+        Err("changed my mind".into())
+    });
+    assert!(matches!(result, Err(DatastoreError::Error(_))));
+    assert!(!journal_path(&path).exists());
+    // pages allocated inside the transaction are truncated away, indexes and hashes follow
+    assert_eq!(state(&ledger), before);
+    assert_eq!(ledger.root_hash().unwrap(), root);
+    drop(ledger);
+    //
+    let ledger = Transfer::create_ledger(&dir, "transfers", "transactions").unwrap();
+    assert_eq!(state(&ledger), before);
+    assert_eq!(ledger.root_hash().unwrap(), root);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rollback_when_the_closure_panics() {
+    let dir = temp_dir("tx-panic");
+    let mut ledger = create(&dir);
+    let path = ledger.path().to_path_buf();
+    let before = state(&ledger);
+    let root = ledger.root_hash().unwrap();
+    //
+    let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let _: Result<(), _> = ledger.transaction(|tx| {
+            stage(tx);
+            panic!("closure panicked");
+        });
+    }));
+    assert!(panicked.is_err());
+    assert!(!journal_path(&path).exists());
+    assert_eq!(state(&ledger), before);
+    assert_eq!(ledger.root_hash().unwrap(), root);
+    //
+    // the ledger takes transactions again
+    ledger
+        .transaction(|tx| tx.update(RowId(1), |row| row.set_amount(100)))
+        .unwrap();
+    assert_eq!(ledger.access_row(RowId(1)).unwrap().unwrap().amount(), 100);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// the ledger file and its journal as a crash in the middle of `stage` would leave them
+fn crash_image(dir: &Path) -> (PathBuf, State) {
+    let mut ledger = create(dir);
+    let before = state(&ledger);
+    let path = ledger.path().to_path_buf();
+    let crash_dir = dir.join("crash");
+    std::fs::create_dir_all(&crash_dir).unwrap();
+    let crash_path = crash_dir.join(path.file_name().unwrap());
+    //
+    let _: Result<(), _> = ledger.transaction(|tx| {
+        stage(tx);
+        std::fs::copy(&path, &crash_path).unwrap();
+        std::fs::copy(journal_path(&path), journal_path(&crash_path)).unwrap();
+        Err("crashed".into())
+    });
+    (crash_path, before)
+}
+
+#[test]
+fn recovers_a_leftover_journal() {
+    let dir = temp_dir("tx-recover");
+    let (crash_path, before) = crash_image(&dir);
+    assert!(std::fs::metadata(&crash_path).unwrap().len() > before.file_len);
+    //
+    let crash_dir = crash_path.parent().unwrap();
+    let ledger = Transfer::create_ledger(crash_dir, "transfers", "transactions").unwrap();
+    assert!(!journal_path(&crash_path).exists());
+    assert_eq!(state(&ledger), before);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn recovers_a_journal_with_a_torn_record() {
+    let dir = temp_dir("tx-torn");
+    let (crash_path, before) = crash_image(&dir);
+    // half a record that never made it to disk
+    let journal = journal_path(&crash_path);
+    let mut bytes = std::fs::read(&journal).unwrap();
+    bytes.extend_from_slice(&[0xAB; 100]);
+    std::fs::write(&journal, &bytes).unwrap();
+    //
+    let crash_dir = crash_path.parent().unwrap();
+    let ledger = Transfer::create_ledger(crash_dir, "transfers", "transactions").unwrap();
+    assert!(!journal.exists());
+    assert_eq!(state(&ledger), before);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn stops_at_a_record_with_a_bad_checksum() {
+    let dir = temp_dir("tx-checksum");
+    let (crash_path, before) = crash_image(&dir);
+    // a bad checksum on the last record, row 20's page, means it was never fsynced. recovery
+    // treats that page as never modified
+    let journal = journal_path(&crash_path);
+    let mut bytes = std::fs::read(&journal).unwrap();
+    let last = bytes.len() - 20;
+    bytes[last] ^= 0xFF;
+    std::fs::write(&journal, &bytes).unwrap();
+    //
+    let crash_dir = crash_path.parent().unwrap();
+    let ledger = Transfer::create_ledger(crash_dir, "transfers", "transactions").unwrap();
+    assert!(!journal.exists());
+    let recovered = state(&ledger);
+    assert_eq!(recovered.file_len, before.file_len);
+    assert_eq!(recovered.rows.len(), before.rows.len());
+    for (n, row) in recovered.rows.iter().enumerate() {
+        match n {
+            20 => assert_eq!(row.as_ref().unwrap().1, -20),
+            _ => assert_eq!(row, &before.rows[n]),
+        }
+    }
+    assert_eq!(recovered.pending, before.pending);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
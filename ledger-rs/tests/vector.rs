@@ -0,0 +1,129 @@
+use ledger_rs::ledger::RowId;
+use ledger_rs::page::PageSchema as _;
+use ledger_rs::vector::Neighbor;
+use ledger_rs_macros::ledger;
+use rkyv::rend::f32_le;
+
+mod common;
+use common::temp_dir;
+
+#[ledger(page_size = 16)]
+pub struct Point {
+    pub id: u64,
+    #[vector(metric = "cosine")]
+    pub direction: [f32; 2],
+    #[vector(metric = "l2")]
+    pub position: [f32; 3],
+    #[vector(metric = "dot")]
+    pub weights: [f32; 9],
+}
+
+// points on a line: position (n, 0, 0), direction at n degrees, weights all n
+fn create(dir: &std::path::Path, num_rows: u64) -> PointLedger {
+    let mut ledger = Point::create_ledger(dir, "points", "vectors").unwrap();
+    for n in 0..num_rows {
+        let angle = (n as f32).to_radians();
+        ledger
+            .insert(&Point::new(
+                n,
+                [angle.cos(), angle.sin()],
+                [n as f32, 0.0, 0.0],
+                [n as f32; 9],
+            ))
+            .unwrap();
+    }
+    ledger
+}
+
+fn row_ids(neighbors: &[Neighbor]) -> Vec<u64> {
+    neighbors.iter().map(|n| n.row_id.get()).collect()
+}
+
+#[test]
+fn stored_arrays_keep_the_native_layout() {
+    assert_eq!(size_of::<[f32_le; 9]>(), size_of::<[f32; 9]>());
+    assert_eq!(align_of::<[f32_le; 9]>(), align_of::<[f32; 9]>());
+    //
+    let dir = temp_dir("vector-layout");
+    let mut ledger = create(&dir, 3);
+    let row = ledger.access_row(RowId(2)).unwrap().unwrap();
+    assert_eq!(row.position(), [2.0, 0.0, 0.0]);
+    // little endian bytes at the offset the schema page records
+    let schema = Point::SCHEMA;
+    let field = schema.fields.iter().find(|f| f.name == "position").unwrap();
+    let bytes = ledger.row_bytes(RowId(2)).unwrap().unwrap();
+    assert_eq!(bytes[field.offset..field.offset + 4], 2.0_f32.to_le_bytes());
+    drop(row);
+    drop(bytes);
+    //
+    ledger
+        .update(RowId(2), |row| row.set_position([-1.5, 4.0, 0.25]))
+        .unwrap();
+    assert_eq!(
+        ledger.access_row(RowId(2)).unwrap().unwrap().position(),
+        [-1.5, 4.0, 0.25]
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn nearest_by_each_metric() {
+    let dir = temp_dir("vector-metrics");
+    let ledger = create(&dir, 50);
+    //
+    // l2 on positions, ties broken by row id
+    let nearest = ledger.nearest_position(&[20.2, 0.0, 0.0], 3).unwrap();
+    assert_eq!(row_ids(&nearest), [20, 21, 19]);
+    assert!((nearest[0].distance - 0.04).abs() < 1e-3);
+    let nearest = ledger.nearest_position(&[10.0, 0.0, 0.0], 3).unwrap();
+    assert_eq!(row_ids(&nearest), [10, 9, 11]);
+    assert_eq!(nearest[0].distance, 0.0);
+    //
+    // cosine ignores the length of the query
+    let angle = 30_f32.to_radians();
+    let nearest = ledger
+        .nearest_direction(&[angle.cos() * 7.0, angle.sin() * 7.0], 3)
+        .unwrap();
+    assert_eq!(nearest[0].row_id, RowId(30));
+    assert!(nearest[0].distance.abs() < 1e-5);
+    assert_eq!(row_ids(&nearest[1..]).iter().sum::<u64>(), 29 + 31);
+    //
+    // dot prefers the largest weights, the distance is the negated product
+    let nearest = ledger.nearest_weights(&[1.0; 9], 2).unwrap();
+    assert_eq!(row_ids(&nearest), [49, 48]);
+    assert_eq!(nearest[0].distance, -(49.0 * 9.0));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn k_past_the_row_count_and_zero() {
+    let dir = temp_dir("vector-k");
+    let ledger = create(&dir, 5);
+    //
+    let nearest = ledger.nearest_position(&[0.0; 3], 100).unwrap();
+    assert_eq!(row_ids(&nearest), [0, 1, 2, 3, 4]);
+    assert!(nearest.windows(2).all(|w| w[0].distance <= w[1].distance));
+    assert!(ledger.nearest_position(&[0.0; 3], 0).unwrap().is_empty());
+    //
+    let empty_dir = temp_dir("vector-empty");
+    let empty = create(&empty_dir, 0);
+    assert!(empty.nearest_position(&[0.0; 3], 3).unwrap().is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+    std::fs::remove_dir_all(&empty_dir).unwrap();
+}
+
+#[test]
+fn skips_deleted_rows() {
+    let dir = temp_dir("vector-deleted");
+    let mut ledger = create(&dir, 50);
+    ledger.delete(RowId(20)).unwrap();
+    ledger.delete(RowId(49)).unwrap();
+    //
+    let nearest = ledger.nearest_position(&[20.0, 0.0, 0.0], 3).unwrap();
+    assert_eq!(row_ids(&nearest), [19, 21, 18]);
+    let nearest = ledger.nearest_weights(&[1.0; 9], 1).unwrap();
+    assert_eq!(row_ids(&nearest), [48]);
+    let all = ledger.nearest_direction(&[1.0, 0.0], 100).unwrap();
+    assert_eq!(all.len(), 48);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
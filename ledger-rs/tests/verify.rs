@@ -0,0 +1,186 @@
+use ledger_rs::header::LedgerHeader;
+use ledger_rs::inspect::RawLedger;
+use ledger_rs::ledger::{PAGE_HEADER_SZ, RowId};
+use ledger_rs::page::{PageSchema as _, SlottedPage, page_sz};
+use ledger_rs::verify::Anomaly;
+use ledger_rs_macros::ledger;
+use std::path::{Path, PathBuf};
+
+mod common;
+use common::temp_dir;
+
+const LEDGER_HEADER_SZ: usize = size_of::<LedgerHeader>();
+
+#[ledger(page_size = 16)]
+pub struct Account {
+    pub id: u64,
+    pub balance: i64,
+    #[max_len(16)]
+    pub owner: String,
+}
+
+fn max_rows() -> u64 {
+    SlottedPage::<Account, { Account::PAGE_SZ }, { Account::ROWS_PER_PAGE }>::max_rows_per_page()
+        as u64
+}
+
+// three full pages and a few rows on the last one, returns the ledger file
+fn create(dir: &Path) -> PathBuf {
+    let mut ledger = Account::create_ledger(dir, "accounts", "verify").unwrap();
+    for n in 0..max_rows() * 3 + 4 {
+        ledger
+            .insert(&Account::new(n, n as i64 * 10, &format!("owner {n}")))
+            .unwrap();
+    }
+    ledger.delete(RowId(2)).unwrap();
+    ledger.path().to_path_buf()
+}
+
+// edits page `page_id` of the closed ledger file in place
+fn patch(path: &Path, page_id: usize, edit: impl FnOnce(&mut [u8])) {
+    let mut bytes = std::fs::read(path).unwrap();
+    let header_sz = LedgerHeader::access(&bytes[0..LEDGER_HEADER_SZ])
+        .unwrap()
+        .size();
+    let page_size = page_sz::<Account>() as usize;
+    let start = header_sz + page_id * page_size;
+    edit(&mut bytes[start..start + page_size]);
+    std::fs::write(path, bytes).unwrap();
+}
+
+fn slot(page: &[u8], slot: usize) -> u32 {
+    let at = PAGE_HEADER_SZ as usize + slot * 4;
+    u32::from_le_bytes(page[at..at + 4].try_into().unwrap())
+}
+
+fn set_slot(page: &mut [u8], slot: usize, offset: u32) {
+    let at = PAGE_HEADER_SZ as usize + slot * 4;
+    page[at..at + 4].copy_from_slice(&offset.to_le_bytes());
+}
+
+fn anomalies(path: &Path) -> Vec<Anomaly> {
+    let ledger = Account::create_ledger(path.parent().unwrap(), "accounts", "verify").unwrap();
+    let report = ledger.verify().unwrap();
+    assert_eq!(report.pages_checked, 4);
+    report.anomalies
+}
+
+#[test]
+fn a_sound_ledger_has_no_anomalies() {
+    let dir = temp_dir("verify-sound");
+    let path = create(&dir);
+    let ledger = Account::create_ledger(&dir, "accounts", "verify").unwrap();
+    let report = ledger.verify().unwrap();
+    assert!(report.is_ok(), "{:?}", report.anomalies);
+    assert_eq!(report.num_pages, 4);
+    assert_eq!(report.rows, max_rows() * 3 + 3);
+    assert_eq!(report.deleted_rows, 1);
+    assert!(!report.encrypted);
+    assert_eq!(RawLedger::open(&path).unwrap().verify().unwrap(), report);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reports_a_slot_outside_the_heap() {
+    let dir = temp_dir("verify-slot-heap");
+    let path = create(&dir);
+    // into the slot array
+    patch(&path, 1, |page| set_slot(page, 3, PAGE_HEADER_SZ + 4));
+    //
+    let expected = Anomaly::SlotOutOfHeap {
+        page_id: 1,
+        slot: 3,
+        offset: PAGE_HEADER_SZ + 4,
+    };
+    assert_eq!(anomalies(&path), std::slice::from_ref(&expected));
+    assert_eq!(
+        expected.to_string(),
+        format!(
+            "page 1 slot 3 points outside the row heap at {}",
+            PAGE_HEADER_SZ + 4
+        )
+    );
+    // the type-less check finds the same
+    let raw = RawLedger::open(&path).unwrap().verify().unwrap();
+    assert_eq!(raw.anomalies, [expected]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reports_overlapping_rows() {
+    let dir = temp_dir("verify-overlap");
+    let path = create(&dir);
+    let mut offset = 0;
+    patch(&path, 2, |page| {
+        offset = slot(page, 5);
+        set_slot(page, 6, offset);
+    });
+    //
+    let found = anomalies(&path);
+    assert_eq!(found.len(), 1);
+    assert!(
+        matches!(
+            found[0],
+            Anomaly::SlotOverlap { page_id: 2, slot, other_slot, offset: at }
+                if [slot, other_slot].contains(&5) && [slot, other_slot].contains(&6) && at == offset
+        ),
+        "{found:?}"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reports_bad_page_headers() {
+    let dir = temp_dir("verify-page-header");
+    let path = create(&dir);
+    // slot_count sits after the page type, flags and padding
+    patch(&path, 0, |page| {
+        page[4..8].copy_from_slice(&1_u32.to_le_bytes())
+    });
+    patch(&path, 3, |page| page[..PAGE_HEADER_SZ as usize].fill(0));
+    //
+    let found = anomalies(&path);
+    assert_eq!(found.len(), 2, "{found:?}");
+    assert!(matches!(
+        found[0],
+        Anomaly::SlotCount {
+            page_id: 0,
+            slot_count: 1,
+            ..
+        }
+    ));
+    assert_eq!(found[1], Anomaly::ZeroedPage { page_id: 3 });
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reports_a_row_that_fails_validation() {
+    let dir = temp_dir("verify-invalid-row");
+    let path = create(&dir);
+    let schema = Account::SCHEMA;
+    let owner = schema.fields.iter().find(|f| f.name == "owner").unwrap();
+    let (len_offset, offset) = (owner.len_offset.unwrap(), owner.offset);
+    patch(&path, 1, |page| {
+        // a length past max_len on slot 0, bytes that aren't utf8 on slot 1
+        let row = slot(page, 0) as usize;
+        page[row + len_offset] = 17;
+        let row = slot(page, 1) as usize;
+        page[row + offset] = 0xff;
+    });
+    //
+    let found = anomalies(&path);
+    assert_eq!(found.len(), 2, "{found:?}");
+    assert!(matches!(
+        &found[0],
+        Anomaly::InvalidRow { row_id, reason }
+            if *row_id == RowId(max_rows()) && reason.contains("length out of bounds")
+    ));
+    assert!(matches!(
+        &found[1],
+        Anomaly::InvalidRow { row_id, reason }
+            if *row_id == RowId(max_rows() + 1) && reason.contains("not valid utf8")
+    ));
+    // the rows are structurally sound, only the row type can tell
+    assert!(RawLedger::open(&path).unwrap().verify().unwrap().is_ok());
+    std::fs::remove_dir_all(&dir).unwrap();
+}